@@ -173,6 +173,9 @@ impl<'a> EguiMapState {
         for layer in map.layers_mut().iter_mut() {
             layer.set_messenger(Box::new(messenger.clone()));
         }
+        if let Some(terrain) = map.terrain() {
+            terrain.set_messenger(Box::new(messenger.clone()));
+        }
 
         // Set a default size so that render target can be created.
         // This size will be replaced by the UI on the first frame.
@@ -276,7 +279,7 @@ impl<'a> EguiMapState {
             .map
             .layers()
             .iter()
-            .filter_map(|layer| layer.attribution())
+            .flat_map(|layer| layer.attributions())
             .collect();
         if all_layer.is_empty() {
             None
@@ -1,5 +1,5 @@
 //! This examples demonstrates working with the map in a projection different from usual Web
-//! Mercator projection.
+//! Mercator projection. The OSM basemap is loaded in Web Mercator and reprojected on the fly.
 
 use std::sync::Arc;
 
@@ -7,6 +7,7 @@ use data::Country;
 use galileo::control::{EventPropagation, UserEvent, UserEventHandler};
 use galileo::layer::feature_layer::symbol::{SimplePolygonSymbol, Symbol};
 use galileo::layer::feature_layer::{FeatureLayer, FeatureLayerOptions};
+use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
 use galileo::layer::Layer;
 use galileo::render::render_bundle::RenderBundle;
 use galileo::{Map, MapBuilder};
@@ -128,6 +129,11 @@ fn create_countries_layer() -> FeatureLayer<Point2, Country, CountrySymbol, Cart
 }
 
 fn create_map(feature_layer: impl Layer + 'static) -> Map {
+    let raster_layer = RasterTileLayerBuilder::new_osm()
+        .with_file_cache_checked(".tile_cache")
+        .build()
+        .expect("failed to create layer");
+
     MapBuilder::default()
         .with_latlon(52.0, 10.0)
         .with_resolution(10_000.0)
@@ -135,6 +141,7 @@ fn create_map(feature_layer: impl Layer + 'static) -> Map {
             Datum::WGS84,
             ProjectionType::Other("laea lon_0=10 lat_0=52 x_0=4321000 y_0=3210000".into()),
         ))
+        .with_layer(raster_layer)
         .with_layer(feature_layer)
         .build()
 }
@@ -1,4 +1,5 @@
 use std::future::Future;
+use std::time::Duration;
 
 #[cfg(not(target_arch = "wasm32"))]
 use maybe_sync::MaybeSend;
@@ -22,3 +23,31 @@ where
         future.await;
     });
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub async fn sleep(duration: Duration) {
+    use wasm_bindgen::JsCast;
+
+    let millis = duration.as_millis().min(i32::MAX as u128) as i32;
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let result = if let Some(window) = web_sys::window() {
+            window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+        } else if let Ok(global) = js_sys::global().dyn_into::<web_sys::WorkerGlobalScope>() {
+            global.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+        } else {
+            Err("global object is not available".into())
+        };
+
+        if result.is_err() {
+            // Resolve immediately rather than never.
+            let _ = resolve.call0(&wasm_bindgen::JsValue::NULL);
+        }
+    });
+
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
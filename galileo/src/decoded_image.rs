@@ -96,6 +96,15 @@ impl DecodedImage {
     pub fn size(&self) -> Size<u32> {
         Size::new(self.width(), self.height())
     }
+
+    /// Raw RGBA pixels of the image, if they are accessible.
+    pub(crate) fn bitmap_bytes(&self) -> Option<&[u8]> {
+        match &self.0 {
+            DecodedImageType::Bitmap { bytes, .. } => Some(bytes),
+            #[cfg(target_arch = "wasm32")]
+            DecodedImageType::JsImageBitmap { .. } => None,
+        }
+    }
 }
 
 impl DecodedImageType {
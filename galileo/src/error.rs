@@ -21,10 +21,17 @@ pub enum GalileoError {
     /// Item not found.
     #[error("item not found")]
     NotFound,
+    /// Item is not available in the cache, and it cannot be loaded from the source in offline mode.
+    #[error("item is not available offline")]
+    Offline,
     /// Image decoding error.
     #[cfg(feature = "image")]
     #[error("image decode error")]
     ImageDecode,
+    /// TIFF decoding error.
+    #[cfg(feature = "geotiff")]
+    #[error("TIFF decode error: {0}")]
+    TiffDecode(String),
     /// Generic error - details are inside.
     #[error("{0}")]
     Generic(String),
@@ -49,6 +56,13 @@ impl From<std::io::Error> for GalileoError {
     }
 }
 
+#[cfg(feature = "geotiff")]
+impl From<tiff::TiffError> for GalileoError {
+    fn from(value: tiff::TiffError) -> Self {
+        Self::TiffDecode(value.to_string())
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 impl From<wasm_bindgen::JsValue> for GalileoError {
     fn from(value: wasm_bindgen::JsValue) -> Self {
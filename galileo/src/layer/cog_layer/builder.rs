@@ -0,0 +1,162 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use galileo_types::geo::Crs;
+use quick_cache::sync::Cache;
+
+use super::reader::{CogReader, CogSource, ValueMapping};
+use super::{CogLayer, CHUNK_CACHE_SIZE};
+use crate::error::GalileoError;
+use crate::layer::attribution::Attribution;
+use crate::layer::data_provider::HttpRequest;
+use crate::layer::tiles::{RequestSourceId, RetryPolicy, TileRequestQueue};
+use crate::Messenger;
+
+/// Constructor for a [`CogLayer`].
+///
+/// ```no_run
+/// use galileo::layer::cog_layer::CogLayerBuilder;
+///
+/// # async fn load() -> Result<(), galileo::error::GalileoError> {
+/// let layer = CogLayerBuilder::new_url("https://example.com/data/elevation_cog.tif")
+///     .with_value_range(0.0, 4000.0)
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CogLayerBuilder {
+    source: CogSource,
+    crs: Option<Crs>,
+    values: ValueMapping,
+    retry_policy: RetryPolicy,
+    request_queue: Option<TileRequestQueue>,
+    messenger: Option<Box<dyn Messenger>>,
+    attribution: Option<Attribution>,
+}
+
+impl CogLayerBuilder {
+    /// Initializes a builder for a layer that reads a file from the local file system.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_file(path: impl Into<PathBuf>) -> Self {
+        Self::new(CogSource::File(path.into()))
+    }
+
+    /// Initializes a builder for a layer that loads the file from the given url. The server must
+    /// support HTTP range requests.
+    pub fn new_url(request: impl Into<HttpRequest>) -> Self {
+        Self::new(CogSource::Url(request.into()))
+    }
+
+    /// Initializes a builder for a layer that reads the file contents from memory.
+    pub fn new_bytes(bytes: impl Into<Bytes>) -> Self {
+        Self::new(CogSource::Bytes(bytes.into()))
+    }
+
+    fn new(source: CogSource) -> Self {
+        Self {
+            source,
+            crs: None,
+            values: ValueMapping::default(),
+            retry_policy: RetryPolicy::default(),
+            request_queue: None,
+            messenger: None,
+            attribution: None,
+        }
+    }
+
+    /// Sets the CRS of the image.
+    ///
+    /// If not set, the CRS is read from the GeoTIFF keys of the file. Only `EPSG:3857` and
+    /// `EPSG:4326` are recognized, for files in other projections the CRS must be set explicitly.
+    pub fn with_crs(mut self, crs: Crs) -> Self {
+        self.crs = Some(crs);
+        self
+    }
+
+    /// Sets the range of the sample values that is mapped to the full range of colors.
+    ///
+    /// Values outside of the range are clamped. Defaults to the full range of the sample type
+    /// for integer samples, and to `0.0..1.0` for floating point samples. Not used for 8-bit
+    /// and palette images.
+    pub fn with_value_range(mut self, min: f64, max: f64) -> Self {
+        self.values.range = Some((min, max));
+        self
+    }
+
+    /// Sets the value of the pixels that have no data. Such pixels are not drawn.
+    ///
+    /// If not set, the value from the `GDAL_NODATA` tag of the file is used.
+    pub fn with_nodata(mut self, nodata: f64) -> Self {
+        self.values.nodata = Some(nodata);
+        self
+    }
+
+    /// Sets the queue the layer puts its chunk requests into.
+    ///
+    /// By default each layer creates its own [`TileRequestQueue`]. Setting the same queue for
+    /// several layers makes them share the limit of concurrent requests per host.
+    pub fn with_request_queue(mut self, request_queue: TileRequestQueue) -> Self {
+        self.request_queue = Some(request_queue);
+        self
+    }
+
+    /// Sets the way the layer handles chunks that failed to load.
+    ///
+    /// Defaults to [`RetryPolicy::default()`].
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the messenger for the layer.
+    pub fn with_messenger(mut self, messenger: impl Messenger + 'static) -> Self {
+        self.messenger = Some(Box::new(messenger));
+        self
+    }
+
+    /// Sets the attribution of the layer.
+    pub fn with_attribution(mut self, text: String, url: String) -> Self {
+        self.attribution = Some(Attribution::new(text, Some(url)));
+        self
+    }
+
+    /// Consumes the builder, reads the headers of the file and constructs the layer.
+    ///
+    /// Will return an error if the file cannot be loaded, is not a valid GeoTIFF, uses an
+    /// unsupported sample format or rotated georeferencing, or if its CRS is unknown and not set
+    /// with [`CogLayerBuilder::with_crs`].
+    pub async fn build(self) -> Result<CogLayer, GalileoError> {
+        let Self {
+            source,
+            crs,
+            values,
+            retry_policy,
+            request_queue,
+            messenger,
+            attribution,
+        } = self;
+
+        if let Some((min, max)) = values.range {
+            if min.is_nan() || max.is_nan() || min >= max {
+                return Err(GalileoError::Configuration(format!(
+                    "invalid value range: {min}..{max}"
+                )));
+            }
+        }
+
+        let reader = CogReader::open(source, crs, values).await?;
+
+        Ok(CogLayer {
+            reader: Arc::new(reader),
+            chunks: Arc::new(Cache::new(CHUNK_CACHE_SIZE)),
+            retry_policy,
+            request_queue: request_queue.unwrap_or_default(),
+            request_source: RequestSourceId::next_id(),
+            messenger: messenger.map(Arc::from),
+            attribution,
+        })
+    }
+}
@@ -0,0 +1,396 @@
+//! Layer that renders a (cloud optimized) GeoTIFF raster.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use ahash::HashSet;
+use galileo_types::cartesian::{Rect, Vector2};
+use galileo_types::geo::Crs;
+use parking_lot::Mutex;
+use quick_cache::sync::Cache;
+use web_time::Instant;
+
+use super::Layer;
+use crate::decoded_image::DecodedImage;
+use crate::error::GalileoError;
+use crate::layer::attribution::Attribution;
+use crate::layer::tiles::{
+    RequestSlot, RequestSourceId, RetryPolicy, TilePriority, TileRequest, TileRequestQueue,
+};
+use crate::messenger::Messenger;
+use crate::render::render_bundle::RenderBundle;
+use crate::render::{BundleToDraw, Canvas, ImagePaint, PackedBundle, RenderOptions};
+use crate::view::MapView;
+
+mod builder;
+mod reader;
+
+pub use builder::CogLayerBuilder;
+pub use reader::CogSource;
+use reader::{ChunkIndex, CogReader};
+
+/// Number of decoded chunks kept in memory.
+const CHUNK_CACHE_SIZE: usize = 256;
+
+/// Layer that renders a GeoTIFF file, reading only the parts of it that are displayed.
+///
+/// The file is read chunk by chunk (internal tiles or strips), so files of any size can be
+/// displayed. For every view the layer selects the image of the file (the full resolution image
+/// or one of its overviews) with the resolution closest to the view resolution. While the chunks
+/// of the selected image are being loaded, the already loaded chunks of the other images are
+/// displayed instead.
+///
+/// Files that follow the cloud optimized GeoTIFF (COG) layout can be read from a remote server:
+/// the data is loaded with HTTP range requests made through the platform service.
+///
+/// The layer only renders the image if the map CRS is the same as the CRS of the file.
+///
+/// Chunks are requested through a [`TileRequestQueue`], and chunks that failed to load are
+/// retried according to the [`RetryPolicy`].
+///
+/// Use [`CogLayerBuilder`] to create the layer.
+pub struct CogLayer {
+    reader: Arc<CogReader>,
+    chunks: Arc<Cache<ChunkIndex, ChunkState>>,
+    retry_policy: RetryPolicy,
+    request_queue: TileRequestQueue,
+    request_source: RequestSourceId,
+    messenger: Option<Arc<dyn Messenger>>,
+    attribution: Option<Attribution>,
+}
+
+impl Drop for CogLayer {
+    fn drop(&mut self) {
+        self.request_queue.cancel(self.request_source);
+    }
+}
+
+impl std::fmt::Debug for CogLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CogLayer")
+            .field("reader", &self.reader)
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+enum ChunkState {
+    Loading,
+    Loaded(Arc<LoadedChunk>),
+    Empty,
+    Error(Instant),
+}
+
+struct LoadedChunk {
+    image: Arc<DecodedImage>,
+    bbox: Rect,
+    bundle: Mutex<Option<Arc<dyn PackedBundle>>>,
+}
+
+impl CogLayer {
+    /// Bounding box of the image in the CRS of the file.
+    pub fn bounds(&self) -> Rect {
+        self.reader.bounds()
+    }
+
+    /// CRS of the image.
+    pub fn crs(&self) -> &Crs {
+        self.reader.crs()
+    }
+
+    fn visible_chunks(&self, view: &MapView) -> Option<(usize, Vec<ChunkIndex>)> {
+        if view.crs() != self.reader.crs() {
+            return None;
+        }
+
+        let bbox = view.get_bbox()?;
+        let level = self.reader.select_level(view.resolution());
+        Some((level, self.reader.chunks_in_bbox(level, bbox)))
+    }
+
+    fn needs_loading(&self, index: &ChunkIndex) -> bool {
+        match self.chunks.get(index) {
+            None => true,
+            Some(ChunkState::Error(failed_at)) => self.retry_policy.is_error_expired(failed_at),
+            Some(_) => false,
+        }
+    }
+
+    async fn load_chunk(
+        index: ChunkIndex,
+        reader: Arc<CogReader>,
+        chunks: Arc<Cache<ChunkIndex, ChunkState>>,
+        retry_policy: RetryPolicy,
+        messenger: Option<Arc<dyn Messenger>>,
+        slot: RequestSlot,
+    ) {
+        if matches!(chunks.get(&index), Some(ChunkState::Loading)) {
+            return;
+        }
+
+        chunks.insert(index, ChunkState::Loading);
+
+        let result = retry_policy
+            .run(
+                || reader.read_chunk(index),
+                |err| matches!(err, GalileoError::IO | GalileoError::FsIo(_)),
+                Some(&slot),
+            )
+            .await;
+
+        match result {
+            Ok(Some(chunk)) => {
+                chunks.insert(
+                    index,
+                    ChunkState::Loaded(Arc::new(LoadedChunk {
+                        image: Arc::new(chunk.image),
+                        bbox: chunk.bbox,
+                        bundle: Mutex::new(None),
+                    })),
+                );
+
+                if let Some(messenger) = messenger {
+                    messenger.request_redraw();
+                }
+            }
+            Ok(None) => chunks.insert(index, ChunkState::Empty),
+            Err(err @ (GalileoError::IO | GalileoError::FsIo(_))) => {
+                log::debug!("Failed to load GeoTIFF chunk {index:?}: {err}");
+                chunks.insert(index, ChunkState::Error(Instant::now()));
+            }
+            Err(err) => {
+                log::warn!("Failed to decode GeoTIFF chunk {index:?}: {err}");
+                chunks.insert(index, ChunkState::Empty);
+            }
+        }
+    }
+
+    fn loaded_chunk(&self, index: &ChunkIndex) -> Option<Arc<LoadedChunk>> {
+        match self.chunks.get(index)? {
+            ChunkState::Loaded(chunk) => Some(chunk),
+            _ => None,
+        }
+    }
+
+    /// Returns the chunks to draw, from the coarsest to the finest.
+    fn chunks_to_draw(&self, view: &MapView) -> Vec<Arc<LoadedChunk>> {
+        let Some((level, indices)) = self.visible_chunks(view) else {
+            return vec![];
+        };
+
+        let mut missing = vec![];
+        let mut loaded = vec![];
+        for index in indices {
+            match self.chunks.get(&index) {
+                Some(ChunkState::Loaded(chunk)) => loaded.push(chunk),
+                Some(ChunkState::Empty) => {}
+                _ => missing.extend(self.reader.chunk_bbox(index)),
+            }
+        }
+
+        if missing.is_empty() {
+            return loaded;
+        }
+
+        // Fill the gaps with the already loaded chunks of the coarser levels, and of the next
+        // finer level, which is usually the one displayed before zooming out. Only the chunks
+        // covering the gaps are looked up.
+        let mut coarser = vec![];
+        let mut finer = vec![];
+        let mut added = HashSet::default();
+        let fallback_levels = (level + 1..self.reader.levels().len())
+            .rev()
+            .chain(level.checked_sub(1));
+        for fallback_level in fallback_levels {
+            let target = if fallback_level > level {
+                &mut coarser
+            } else {
+                &mut finer
+            };
+
+            for bbox in &missing {
+                for index in self.reader.chunks_in_bbox(fallback_level, *bbox) {
+                    if added.insert(index) {
+                        target.extend(self.loaded_chunk(&index));
+                    }
+                }
+            }
+        }
+
+        coarser.into_iter().chain(loaded).chain(finer).collect()
+    }
+}
+
+impl Layer for CogLayer {
+    fn render(&self, view: &MapView, canvas: &mut dyn Canvas) {
+        let chunks = self.chunks_to_draw(view);
+        if chunks.is_empty() {
+            return;
+        }
+
+        let bundles: Vec<_> = chunks
+            .iter()
+            .map(|chunk| {
+                let bundle = chunk
+                    .bundle
+                    .lock()
+                    .get_or_insert_with(|| {
+                        let mut bundle = RenderBundle::default();
+                        bundle.add_image(
+                            chunk.image.clone(),
+                            Rect::new(0.0, 0.0, chunk.bbox.width(), -chunk.bbox.height())
+                                .into_quadrangle(),
+                            ImagePaint { opacity: 255 },
+                        );
+                        canvas.pack_bundle(&bundle).into()
+                    })
+                    .clone();
+                let offset = Vector2::new(chunk.bbox.x_min() as f32, chunk.bbox.y_max() as f32);
+                (bundle, offset)
+            })
+            .collect();
+
+        let to_draw: Vec<_> = bundles
+            .iter()
+            .map(|(bundle, offset)| BundleToDraw::new(&**bundle, 1.0, *offset))
+            .collect();
+        canvas.draw_bundles(&to_draw, RenderOptions::default());
+    }
+
+    fn prepare(&self, view: &MapView) {
+        let Some((level, indices)) = self.visible_chunks(view) else {
+            return;
+        };
+
+        let host = self.reader.source().request_host();
+        let requests = indices.into_iter().filter_map(|index| {
+            if !self.needs_loading(&index) {
+                return None;
+            }
+
+            Some(TileRequest {
+                index: index.request_index(),
+                priority: TilePriority::for_bbox(
+                    self.reader.chunk_bbox(index)?,
+                    index.level.abs_diff(level) as u32,
+                    view,
+                ),
+                host: host.clone(),
+            })
+        });
+
+        let reader = self.reader.clone();
+        let chunks = self.chunks.clone();
+        let retry_policy = self.retry_policy;
+        let messenger = self.messenger.clone();
+        self.request_queue
+            .request_tiles(self.request_source, requests, move |index, slot| {
+                Self::load_chunk(
+                    ChunkIndex::from_request_index(index),
+                    reader.clone(),
+                    chunks.clone(),
+                    retry_policy,
+                    messenger.clone(),
+                    slot,
+                )
+            });
+    }
+
+    fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
+        self.messenger = Some(Arc::from(messenger));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        self.attribution.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use galileo_types::cartesian::{Point2, Size};
+
+    use super::*;
+    use crate::layer::cog_layer::reader::tests::test_file;
+
+    async fn layer() -> CogLayer {
+        CogLayerBuilder::new_bytes(test_file(4, 4, 3857, None))
+            .build()
+            .await
+            .unwrap()
+    }
+
+    fn view(resolution: f64) -> MapView {
+        MapView::new_projected(&Point2::new(120.0, 180.0), resolution)
+            .with_size(Size::new(40.0 / resolution, 40.0 / resolution))
+    }
+
+    fn load(layer: &CogLayer, level: usize, row: u32) {
+        let index = ChunkIndex {
+            level,
+            column: 0,
+            row,
+        };
+        let image = DecodedImage::from_raw(vec![0; 4], Size::new(1, 1)).unwrap();
+        let bbox = Rect::new(0.0, 0.0, level as f64, row as f64);
+        layer.chunks.insert(
+            index,
+            ChunkState::Loaded(Arc::new(LoadedChunk {
+                image: Arc::new(image),
+                bbox,
+                bundle: Mutex::new(None),
+            })),
+        );
+    }
+
+    #[tokio::test]
+    async fn overview_is_selected_by_view_resolution() {
+        let layer = layer().await;
+
+        let (level, chunks) = layer.visible_chunks(&view(10.0)).unwrap();
+        assert_eq!(level, 0);
+        assert_eq!(chunks.len(), 2);
+
+        let (level, chunks) = layer.visible_chunks(&view(20.0)).unwrap();
+        assert_eq!(level, 1);
+        assert_eq!(chunks.len(), 1);
+
+        let view = MapView::new_projected_with_crs(&Point2::new(120.0, 180.0), 10.0, Crs::WGS84);
+        assert!(layer.visible_chunks(&view).is_none());
+    }
+
+    #[tokio::test]
+    async fn loaded_overview_is_drawn_while_chunks_are_loading() {
+        let layer = layer().await;
+        let view = view(10.0);
+        let drawn = |layer: &CogLayer| {
+            layer
+                .chunks_to_draw(&view)
+                .iter()
+                .map(|chunk| (chunk.bbox.x_max() as usize, chunk.bbox.y_max() as u32))
+                .collect::<Vec<_>>()
+        };
+
+        load(&layer, 1, 0);
+        load(&layer, 0, 1);
+        layer.chunks.insert(
+            ChunkIndex {
+                level: 0,
+                column: 0,
+                row: 0,
+            },
+            ChunkState::Loading,
+        );
+        assert_eq!(drawn(&layer), [(1, 0), (0, 1)]);
+
+        load(&layer, 0, 0);
+        assert_eq!(drawn(&layer), [(0, 0), (0, 1)]);
+    }
+}
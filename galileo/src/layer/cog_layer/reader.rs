@@ -0,0 +1,1063 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use galileo_types::cartesian::{Rect, Size};
+use galileo_types::geo::Crs;
+use parking_lot::Mutex;
+use tiff::decoder::{ChunkType, Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::{ColorType, TiffError, TiffResult};
+
+use crate::decoded_image::DecodedImage;
+use crate::error::GalileoError;
+use crate::layer::data_provider::{url_host, HttpRequest};
+use crate::platform::{slice_range, PlatformService};
+use crate::tile_schema::TileIndex;
+
+/// Number of bytes loaded from the start of the file when it is opened. For cloud optimized
+/// GeoTIFFs this is usually enough to read all the image headers.
+const HEADER_PREFETCH: u64 = 16 * 1024;
+/// Maximum number of additional reads made to load the image headers.
+const MAX_HEADER_READS: usize = 16;
+/// Relative difference between resolutions that is not considered as a difference.
+const RESOLUTION_TOLERANCE: f64 = 0.01;
+
+/// Source of the GeoTIFF data.
+#[derive(Debug, Clone)]
+pub enum CogSource {
+    /// File in the local file system.
+    #[cfg(not(target_arch = "wasm32"))]
+    File(PathBuf),
+    /// Remote file. The data is loaded with HTTP range requests, so the server must support them
+    /// for the layer to load only the parts of the file that are displayed.
+    Url(HttpRequest),
+    /// Contents of the file in memory.
+    Bytes(Bytes),
+}
+
+impl CogSource {
+    async fn read_range(&self, range: Range<u64>) -> Result<Bytes, GalileoError> {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            CogSource::File(path) => {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || read_file_range(&path, range))
+                    .await
+                    .map_err(|_| GalileoError::IO)?
+            }
+            CogSource::Url(request) => {
+                crate::platform::instance()
+                    .load_bytes_range(request, range)
+                    .await
+            }
+            CogSource::Bytes(bytes) => Ok(slice_range(bytes, range)),
+        }
+    }
+
+    /// Host the data is loaded from. Used to limit the number of concurrent requests.
+    pub(crate) fn request_host(&self) -> Option<String> {
+        match self {
+            CogSource::Url(request) => url_host(&request.url).map(str::to_string),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_file_range(path: &std::path::Path, range: Range<u64>) -> Result<Bytes, GalileoError> {
+    let mut file = std::fs::File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+
+    let mut data = vec![];
+    file.take(range.end - range.start).read_to_end(&mut data)?;
+    Ok(data.into())
+}
+
+/// Index of a chunk (tile or strip) in one of the images of the file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ChunkIndex {
+    /// Index of the level in [`CogReader::levels`].
+    pub level: usize,
+    pub column: u32,
+    pub row: u32,
+}
+
+impl ChunkIndex {
+    /// Index the chunk is identified with in a tile request queue.
+    pub(crate) fn request_index(&self) -> TileIndex {
+        TileIndex::new(self.column as i32, self.row as i32, self.level as u32)
+    }
+
+    /// Inverse of [`ChunkIndex::request_index`].
+    pub(crate) fn from_request_index(index: TileIndex) -> Self {
+        Self {
+            level: index.z as usize,
+            column: index.x as u32,
+            row: index.y as u32,
+        }
+    }
+}
+
+/// Decoded chunk of the image.
+pub(crate) struct Chunk {
+    pub image: DecodedImage,
+    pub bbox: Rect,
+}
+
+/// One of the images of the file: the full resolution image or one of its overviews.
+#[derive(Debug, Clone)]
+pub(crate) struct CogLevel {
+    ifd_index: usize,
+    width: u32,
+    height: u32,
+    chunk_width: u32,
+    chunk_height: u32,
+    offsets: Vec<u64>,
+    byte_counts: Vec<u64>,
+    /// Size of a pixel in map units along X and Y axis.
+    resolution: (f64, f64),
+}
+
+impl CogLevel {
+    fn chunks_across(&self) -> u32 {
+        self.width.div_ceil(self.chunk_width)
+    }
+
+    fn chunks_down(&self) -> u32 {
+        self.height.div_ceil(self.chunk_height)
+    }
+}
+
+/// Conversion of the sample values into colors.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ValueMapping {
+    /// Values mapped to 0 and 255 color component values.
+    pub range: Option<(f64, f64)>,
+    /// Pixels with this value are transparent.
+    pub nodata: Option<f64>,
+}
+
+/// Reads the structure of a (cloud optimized) GeoTIFF file and decodes its chunks.
+pub(crate) struct CogReader {
+    source: CogSource,
+    /// Data of the file headers. Decoders are created on top of it.
+    headers: SparseBuffer,
+    /// Decoders that are not used at the moment. Each chunk is decoded by its own decoder, so
+    /// that chunks can be decoded in parallel.
+    decoders: Mutex<Vec<Decoder<SparseBuffer>>>,
+    levels: Vec<CogLevel>,
+    bounds: Rect,
+    crs: Crs,
+    color_type: ColorType,
+    palette: Option<Vec<u16>>,
+    values: ValueMapping,
+}
+
+impl std::fmt::Debug for CogReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CogReader")
+            .field("source", &self.source)
+            .field("bounds", &self.bounds)
+            .field("crs", &self.crs)
+            .finish()
+    }
+}
+
+/// Structure of the file read from its headers.
+struct FileStructure {
+    decoder: Decoder<SparseBuffer>,
+    levels: Vec<CogLevel>,
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    georeference: Option<Georeference>,
+    epsg_code: Option<u16>,
+    nodata: Option<f64>,
+    palette: Option<Vec<u16>>,
+}
+
+/// Position of the top left corner of the image and size of its pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Georeference {
+    x_min: f64,
+    y_max: f64,
+    pixel_width: f64,
+    pixel_height: f64,
+}
+
+impl CogReader {
+    /// Reads the headers of the file.
+    ///
+    /// The CRS of the data is taken from the GeoTIFF keys if not given. Only `EPSG:3857` and
+    /// `EPSG:4326` can be recognized from the keys.
+    pub(crate) async fn open(
+        source: CogSource,
+        crs: Option<Crs>,
+        mut values: ValueMapping,
+    ) -> Result<Self, GalileoError> {
+        let mut buffer = SparseBuffer::default();
+        let mut prefetch = HEADER_PREFETCH;
+        buffer.insert(0, source.read_range(0..prefetch).await?);
+
+        let mut reads = 0;
+        let structure = loop {
+            let err = match read_structure(buffer.clone()) {
+                Ok(structure) => break structure,
+                Err(err) => err,
+            };
+
+            match missing_offset(&err) {
+                Some(offset) if reads < MAX_HEADER_READS => {
+                    let data = source.read_range(offset..offset + prefetch).await?;
+                    if data.is_empty() {
+                        return Err(GalileoError::TiffDecode(
+                            "unexpected end of file".to_string(),
+                        ));
+                    }
+
+                    buffer.insert(offset, data);
+                    prefetch *= 2;
+                    reads += 1;
+                }
+                _ => return Err(err.into()),
+            }
+        };
+
+        let FileStructure {
+            decoder,
+            mut levels,
+            width,
+            height,
+            color_type,
+            georeference,
+            epsg_code,
+            nodata,
+            palette,
+        } = structure;
+
+        let bit_depth = match color_type {
+            ColorType::Gray(bits)
+            | ColorType::GrayA(bits)
+            | ColorType::RGB(bits)
+            | ColorType::RGBA(bits)
+            | ColorType::YCbCr(bits)
+            | ColorType::Palette(bits) => bits,
+            ColorType::Multiband { bit_depth, .. } => bit_depth,
+            _ => 0,
+        };
+        if bit_depth < 8 {
+            return Err(GalileoError::Configuration(format!(
+                "color type {color_type:?} of the file is not supported"
+            )));
+        }
+
+        let georeference = georeference.ok_or_else(|| {
+            GalileoError::Configuration("the file does not contain georeferencing tags".into())
+        })?;
+        let crs = match crs.or_else(|| epsg_code.and_then(crs_from_epsg)) {
+            Some(crs) => crs,
+            None => {
+                return Err(GalileoError::Configuration(match epsg_code {
+                    Some(code) => format!("CRS EPSG:{code} of the file must be set explicitly"),
+                    None => "the file does not specify its CRS, it must be set explicitly".into(),
+                }))
+            }
+        };
+
+        for level in &mut levels {
+            level.resolution = (
+                georeference.pixel_width * width as f64 / level.width as f64,
+                georeference.pixel_height * height as f64 / level.height as f64,
+            );
+        }
+        levels.sort_by(|a, b| a.resolution.0.total_cmp(&b.resolution.0));
+
+        let bounds = Rect::new(
+            georeference.x_min,
+            georeference.y_max - georeference.pixel_height * height as f64,
+            georeference.x_min + georeference.pixel_width * width as f64,
+            georeference.y_max,
+        );
+
+        values.nodata = values.nodata.or(nodata);
+
+        Ok(Self {
+            source,
+            headers: buffer,
+            decoders: Mutex::new(vec![decoder]),
+            levels,
+            bounds,
+            crs,
+            color_type,
+            palette,
+            values,
+        })
+    }
+
+    pub(crate) fn source(&self) -> &CogSource {
+        &self.source
+    }
+
+    pub(crate) fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    pub(crate) fn crs(&self) -> &Crs {
+        &self.crs
+    }
+
+    /// Levels of the file sorted from the finest to the coarsest resolution.
+    pub(crate) fn levels(&self) -> &[CogLevel] {
+        &self.levels
+    }
+
+    /// Returns the index of the coarsest level with pixels not larger than the given resolution.
+    pub(crate) fn select_level(&self, resolution: f64) -> usize {
+        self.levels
+            .iter()
+            .rposition(|level| level.resolution.0 <= resolution * (1.0 + RESOLUTION_TOLERANCE))
+            .unwrap_or(0)
+    }
+
+    /// Returns the chunks of the level that intersect the given bounding box.
+    pub(crate) fn chunks_in_bbox(&self, level_index: usize, bbox: Rect) -> Vec<ChunkIndex> {
+        let Some(level) = self.levels.get(level_index) else {
+            return vec![];
+        };
+        if !bbox.intersects(self.bounds) || level.chunks_across() == 0 || level.chunks_down() == 0 {
+            return vec![];
+        }
+
+        let chunk_width = level.chunk_width as f64 * level.resolution.0;
+        let chunk_height = level.chunk_height as f64 * level.resolution.1;
+        let column_range = |from: f64, to: f64| {
+            let first = ((from - self.bounds.x_min()) / chunk_width)
+                .floor()
+                .max(0.0) as u32;
+            let last = ((to - self.bounds.x_min()) / chunk_width).floor().max(0.0) as u32;
+            first..=last.min(level.chunks_across() - 1)
+        };
+        let row_range = |from: f64, to: f64| {
+            let first = ((self.bounds.y_max() - from) / chunk_height)
+                .floor()
+                .max(0.0) as u32;
+            let last = ((self.bounds.y_max() - to) / chunk_height).floor().max(0.0) as u32;
+            first..=last.min(level.chunks_down() - 1)
+        };
+
+        let rows = row_range(bbox.y_max(), bbox.y_min());
+        column_range(bbox.x_min(), bbox.x_max())
+            .flat_map(|column| {
+                rows.clone().map(move |row| ChunkIndex {
+                    level: level_index,
+                    column,
+                    row,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the bounding box of the chunk, clipped by the bounds of the image.
+    pub(crate) fn chunk_bbox(&self, index: ChunkIndex) -> Option<Rect> {
+        let level = self.levels.get(index.level)?;
+        let chunk_width = level.chunk_width as f64 * level.resolution.0;
+        let chunk_height = level.chunk_height as f64 * level.resolution.1;
+        let x_min = self.bounds.x_min() + index.column as f64 * chunk_width;
+        let y_max = self.bounds.y_max() - index.row as f64 * chunk_height;
+
+        Some(Rect::new(
+            x_min,
+            (y_max - chunk_height).max(self.bounds.y_min()),
+            (x_min + chunk_width).min(self.bounds.x_max()),
+            y_max,
+        ))
+    }
+
+    /// Loads and decodes the chunk. Returns `None` if the chunk is not present in the file.
+    pub(crate) async fn read_chunk(
+        &self,
+        index: ChunkIndex,
+    ) -> Result<Option<Chunk>, GalileoError> {
+        let level = self.levels.get(index.level).ok_or(GalileoError::NotFound)?;
+        if index.column >= level.chunks_across() || index.row >= level.chunks_down() {
+            return Err(GalileoError::NotFound);
+        }
+
+        let chunk_index = index.row * level.chunks_across() + index.column;
+        let (Some(&offset), Some(&byte_count)) = (
+            level.offsets.get(chunk_index as usize),
+            level.byte_counts.get(chunk_index as usize),
+        ) else {
+            return Err(GalileoError::NotFound);
+        };
+
+        if byte_count == 0 {
+            // Sparse files do not store chunks without data.
+            return Ok(None);
+        }
+
+        let data = self.source.read_range(offset..offset + byte_count).await?;
+
+        let decoder = self.decoders.lock().pop();
+        let mut decoder = match decoder {
+            Some(decoder) => decoder,
+            None => Decoder::new(self.headers.clone())?,
+        };
+        decoder.inner().insert(offset, data);
+        let (result, (width, height)) = decode_chunk(&mut decoder, level.ifd_index, chunk_index)?;
+        decoder.inner().remove(offset);
+        self.decoders.lock().push(decoder);
+
+        let rgba = to_rgba(
+            result,
+            self.color_type,
+            self.palette.as_deref(),
+            &self.values,
+        )?;
+        let image = DecodedImage::from_raw(rgba, Size::new(width, height))?;
+
+        let x_min =
+            self.bounds.x_min() + (index.column * level.chunk_width) as f64 * level.resolution.0;
+        let y_max =
+            self.bounds.y_max() - (index.row * level.chunk_height) as f64 * level.resolution.1;
+        let bbox = Rect::new(
+            x_min,
+            y_max - height as f64 * level.resolution.1,
+            x_min + width as f64 * level.resolution.0,
+            y_max,
+        );
+
+        Ok(Some(Chunk { image, bbox }))
+    }
+}
+
+fn decode_chunk(
+    decoder: &mut Decoder<SparseBuffer>,
+    ifd_index: usize,
+    chunk_index: u32,
+) -> TiffResult<(DecodingResult, (u32, u32))> {
+    decoder.seek_to_image(ifd_index)?;
+    let dimensions = decoder.chunk_data_dimensions(chunk_index);
+    Ok((decoder.read_chunk(chunk_index)?, dimensions))
+}
+
+/// Reads the headers of all images in the file.
+fn read_structure(buffer: SparseBuffer) -> TiffResult<FileStructure> {
+    let mut decoder = Decoder::new(buffer)?;
+
+    let (width, height) = decoder.dimensions()?;
+    let color_type = decoder.colortype()?;
+    let georeference = read_georeference(&mut decoder)?;
+    let epsg_code = read_epsg_code(&mut decoder)?;
+    let nodata = decoder
+        .find_tag(Tag::GdalNodata)?
+        .map(|value| value.into_string())
+        .transpose()?
+        .and_then(|value| value.trim_matches(char::from(0)).trim().parse().ok());
+    let palette = match color_type {
+        ColorType::Palette(_) => Some(decoder.get_tag_u16_vec(Tag::ColorMap)?),
+        _ => None,
+    };
+
+    let mut levels = vec![];
+    let mut ifd_index = 0;
+    loop {
+        // Masks are stored as separate images with the bit 2 of the subfile type set.
+        let subfile_type = decoder
+            .find_tag_unsigned::<u32>(Tag::NewSubfileType)?
+            .unwrap_or(0);
+        if subfile_type & 4 == 0 && decoder.colortype()? == color_type {
+            levels.push(read_level(&mut decoder, ifd_index)?);
+        }
+
+        if !decoder.more_images() {
+            break;
+        }
+
+        decoder.next_image()?;
+        ifd_index += 1;
+    }
+
+    Ok(FileStructure {
+        decoder,
+        levels,
+        width,
+        height,
+        color_type,
+        georeference,
+        epsg_code,
+        nodata,
+        palette,
+    })
+}
+
+fn read_level(decoder: &mut Decoder<SparseBuffer>, ifd_index: usize) -> TiffResult<CogLevel> {
+    let (width, height) = decoder.dimensions()?;
+    let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+    if chunk_width == 0 || chunk_height == 0 {
+        return Err(TiffError::FormatError(
+            tiff::TiffFormatError::InvalidDimensions(chunk_width, chunk_height),
+        ));
+    }
+    let (offsets, byte_counts) = match decoder.get_chunk_type() {
+        ChunkType::Strip => (Tag::StripOffsets, Tag::StripByteCounts),
+        ChunkType::Tile => (Tag::TileOffsets, Tag::TileByteCounts),
+    };
+
+    Ok(CogLevel {
+        ifd_index,
+        width,
+        height,
+        chunk_width,
+        chunk_height,
+        offsets: decoder.get_tag_u64_vec(offsets)?,
+        byte_counts: decoder.get_tag_u64_vec(byte_counts)?,
+        resolution: (0.0, 0.0),
+    })
+}
+
+fn read_georeference(decoder: &mut Decoder<SparseBuffer>) -> TiffResult<Option<Georeference>> {
+    let pixel_is_point = read_geo_keys(decoder)?
+        .iter()
+        .any(|&(key, value)| key == GT_RASTER_TYPE_KEY && value == RASTER_PIXEL_IS_POINT);
+
+    let georeference = if let Some(transform) = decoder.find_tag(Tag::ModelTransformationTag)? {
+        let m = transform.into_f64_vec()?;
+        if m.len() < 16 || m[1] != 0.0 || m[4] != 0.0 || m[5] >= 0.0 {
+            return Err(TiffError::UnsupportedError(
+                tiff::TiffUnsupportedError::UnknownInterpretation,
+            ));
+        }
+
+        Georeference {
+            x_min: m[3],
+            y_max: m[7],
+            pixel_width: m[0],
+            pixel_height: -m[5],
+        }
+    } else {
+        let (Some(scale), Some(tie_point)) = (
+            decoder.find_tag(Tag::ModelPixelScaleTag)?,
+            decoder.find_tag(Tag::ModelTiepointTag)?,
+        ) else {
+            return Ok(None);
+        };
+        let scale = scale.into_f64_vec()?;
+        let tie_point = tie_point.into_f64_vec()?;
+        if scale.len() < 2 || tie_point.len() < 6 || scale[1] <= 0.0 {
+            return Err(TiffError::UnsupportedError(
+                tiff::TiffUnsupportedError::UnknownInterpretation,
+            ));
+        }
+
+        Georeference {
+            x_min: tie_point[3] - tie_point[0] * scale[0],
+            y_max: tie_point[4] + tie_point[1] * scale[1],
+            pixel_width: scale[0],
+            pixel_height: scale[1],
+        }
+    };
+
+    Ok(Some(if pixel_is_point {
+        // Coordinates are given for the pixel centers.
+        Georeference {
+            x_min: georeference.x_min - georeference.pixel_width / 2.0,
+            y_max: georeference.y_max + georeference.pixel_height / 2.0,
+            ..georeference
+        }
+    } else {
+        georeference
+    }))
+}
+
+const GT_MODEL_TYPE_KEY: u16 = 1024;
+const GT_RASTER_TYPE_KEY: u16 = 1025;
+const GEOGRAPHIC_TYPE_KEY: u16 = 2048;
+const PROJECTED_CS_TYPE_KEY: u16 = 3072;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+/// Reads GeoTIFF keys with values stored in the key directory itself.
+fn read_geo_keys(decoder: &mut Decoder<SparseBuffer>) -> TiffResult<Vec<(u16, u16)>> {
+    let Some(directory) = decoder.find_tag(Tag::GeoKeyDirectoryTag)? else {
+        return Ok(vec![]);
+    };
+
+    // The directory starts with a header of 4 values, then each key takes 4 values: id, location
+    // of the value (0 for inline values), count and the value itself.
+    let directory = directory.into_u16_vec()?;
+    Ok(directory
+        .chunks_exact(4)
+        .skip(1)
+        .filter(|key| key[1] == 0)
+        .map(|key| (key[0], key[3]))
+        .collect())
+}
+
+fn read_epsg_code(decoder: &mut Decoder<SparseBuffer>) -> TiffResult<Option<u16>> {
+    let keys = read_geo_keys(decoder)?;
+    let key = |id| keys.iter().find(|(key, _)| *key == id).map(|(_, v)| *v);
+
+    Ok(match key(GT_MODEL_TYPE_KEY) {
+        Some(MODEL_TYPE_PROJECTED) => key(PROJECTED_CS_TYPE_KEY),
+        Some(MODEL_TYPE_GEOGRAPHIC) => key(GEOGRAPHIC_TYPE_KEY),
+        _ => key(PROJECTED_CS_TYPE_KEY).or(key(GEOGRAPHIC_TYPE_KEY)),
+    })
+}
+
+fn crs_from_epsg(code: u16) -> Option<Crs> {
+    match code {
+        3857 | 3785 => Some(Crs::EPSG3857),
+        4326 => Some(Crs::WGS84),
+        _ => None,
+    }
+}
+
+/// Converts decoded samples into RGBA pixels.
+fn to_rgba(
+    result: DecodingResult,
+    color_type: ColorType,
+    palette: Option<&[u16]>,
+    values: &ValueMapping,
+) -> Result<Vec<u8>, GalileoError> {
+    let (samples, default_range) = samples(result);
+    let (min, max) = values.range.unwrap_or(default_range);
+    let scale = if max > min { 255.0 / (max - min) } else { 0.0 };
+    let component = |value: f64| ((value - min) * scale).round().clamp(0.0, 255.0) as u8;
+
+    let unsupported =
+        || GalileoError::TiffDecode(format!("color type {color_type:?} is not supported"));
+    let (samples_per_pixel, color_samples) = match color_type {
+        ColorType::Gray(_) | ColorType::Palette(_) => (1, 1),
+        ColorType::GrayA(_) => (2, 1),
+        ColorType::RGB(_) | ColorType::YCbCr(_) => (3, 3),
+        ColorType::RGBA(_) => (4, 3),
+        ColorType::Multiband { num_samples, .. } => {
+            (num_samples as usize, (num_samples as usize).min(3))
+        }
+        _ => return Err(unsupported()),
+    };
+    if samples_per_pixel == 0 {
+        return Err(unsupported());
+    }
+
+    let mut rgba = Vec::with_capacity(samples.len() / samples_per_pixel * 4);
+    for pixel in samples.chunks_exact(samples_per_pixel) {
+        let color = &pixel[..color_samples];
+        let is_nodata = color
+            .iter()
+            .all(|v| v.is_nan() || values.nodata.is_some_and(|nodata| *v == nodata));
+        if is_nodata {
+            rgba.extend([0, 0, 0, 0]);
+            continue;
+        }
+
+        let alpha = match color_type {
+            ColorType::GrayA(_) | ColorType::RGBA(_) => component(pixel[samples_per_pixel - 1]),
+            _ => 255,
+        };
+
+        match (color_type, color_samples) {
+            (ColorType::Palette(bits), _) => {
+                let palette = palette.ok_or_else(unsupported)?;
+                let count = 1usize << bits;
+                let index = color[0] as usize;
+                let entry = |channel: usize| {
+                    palette
+                        .get(channel * count + index)
+                        .map(|v| (v >> 8) as u8)
+                        .unwrap_or(0)
+                };
+                rgba.extend([entry(0), entry(1), entry(2), alpha]);
+            }
+            (ColorType::YCbCr(_), _) => {
+                let (y, cb, cr) = (color[0], color[1] - 128.0, color[2] - 128.0);
+                rgba.extend([
+                    component(y + 1.402 * cr),
+                    component(y - 0.344136 * cb - 0.714136 * cr),
+                    component(y + 1.772 * cb),
+                    alpha,
+                ]);
+            }
+            (_, 1) => {
+                let gray = component(color[0]);
+                rgba.extend([gray, gray, gray, alpha]);
+            }
+            (_, 2) => {
+                let gray = component(color[0]);
+                rgba.extend([gray, gray, gray, component(color[1])]);
+            }
+            _ => rgba.extend([
+                component(color[0]),
+                component(color[1]),
+                component(color[2]),
+                alpha,
+            ]),
+        }
+    }
+
+    Ok(rgba)
+}
+
+/// Returns the samples as floating point values together with the default range of the values.
+fn samples(result: DecodingResult) -> (Vec<f64>, (f64, f64)) {
+    fn convert<T: Copy + Into<f64>>(values: Vec<T>, range: (f64, f64)) -> (Vec<f64>, (f64, f64)) {
+        (values.into_iter().map(Into::into).collect(), range)
+    }
+
+    match result {
+        DecodingResult::U8(v) => convert(v, (0.0, u8::MAX as f64)),
+        DecodingResult::U16(v) => convert(v, (0.0, u16::MAX as f64)),
+        DecodingResult::U32(v) => convert(v, (0.0, u32::MAX as f64)),
+        DecodingResult::U64(v) => (
+            v.into_iter().map(|v| v as f64).collect(),
+            (0.0, u64::MAX as f64),
+        ),
+        DecodingResult::I8(v) => convert(v, (i8::MIN as f64, i8::MAX as f64)),
+        DecodingResult::I16(v) => convert(v, (i16::MIN as f64, i16::MAX as f64)),
+        DecodingResult::I32(v) => convert(v, (i32::MIN as f64, i32::MAX as f64)),
+        DecodingResult::I64(v) => (
+            v.into_iter().map(|v| v as f64).collect(),
+            (i64::MIN as f64, i64::MAX as f64),
+        ),
+        DecodingResult::F16(v) => (v.into_iter().map(|v| v.to_f64()).collect(), (0.0, 1.0)),
+        DecodingResult::F32(v) => convert(v, (0.0, 1.0)),
+        DecodingResult::F64(v) => (v, (0.0, 1.0)),
+    }
+}
+
+/// Returns the offset in the file that must be loaded to continue reading, if the error was
+/// caused by the missing data.
+fn missing_offset(err: &TiffError) -> Option<u64> {
+    let TiffError::IoError(err) = err else {
+        return None;
+    };
+
+    err.get_ref()?
+        .downcast_ref::<MissingRange>()
+        .map(|missing| missing.0)
+}
+
+/// Error returned by [`SparseBuffer`] when the data at the given offset is not loaded.
+#[derive(Debug)]
+struct MissingRange(u64);
+
+impl std::fmt::Display for MissingRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "data at offset {} is not loaded", self.0)
+    }
+}
+
+impl std::error::Error for MissingRange {}
+
+/// Reader over the loaded parts of a file.
+#[derive(Debug, Clone, Default)]
+struct SparseBuffer {
+    ranges: BTreeMap<u64, Bytes>,
+    position: u64,
+}
+
+impl SparseBuffer {
+    fn insert(&mut self, offset: u64, data: Bytes) {
+        self.ranges.insert(offset, data);
+    }
+
+    fn remove(&mut self, offset: u64) {
+        self.ranges.remove(&offset);
+    }
+
+    fn data_at(&self, position: u64) -> Option<&[u8]> {
+        self.ranges
+            .range(..=position)
+            .rev()
+            .filter_map(|(start, data)| data.get((position - start) as usize..))
+            .find(|data| !data.is_empty())
+    }
+}
+
+impl Read for SparseBuffer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let data = self
+            .data_at(self.position)
+            .ok_or_else(|| io::Error::other(MissingRange(self.position)))?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        self.position += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl Seek for SparseBuffer {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(position) => position,
+            SeekFrom::Current(delta) => {
+                self.position.checked_add_signed(delta).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position")
+                })?
+            }
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "size of the file is unknown",
+                ))
+            }
+        };
+
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::io::Cursor;
+
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    use super::*;
+
+    /// Creates a grayscale file with an overview of half the size. Pixel values of the full image
+    /// are `x + y * width`.
+    pub(in super::super) fn test_file(
+        width: u32,
+        height: u32,
+        projected_crs: u16,
+        nodata: Option<&str>,
+    ) -> Bytes {
+        let mut data = Cursor::new(vec![]);
+        let mut tiff = TiffEncoder::new(&mut data).unwrap();
+
+        let pixels: Vec<u8> = (0..width * height).map(|v| v as u8).collect();
+        let mut image = tiff.new_image::<colortype::Gray8>(width, height).unwrap();
+        image.rows_per_strip(2).unwrap();
+        let encoder = image.encoder();
+        encoder
+            .write_tag(Tag::ModelPixelScaleTag, &[10.0, 10.0, 0.0][..])
+            .unwrap();
+        encoder
+            .write_tag(
+                Tag::ModelTiepointTag,
+                &[0.0, 0.0, 0.0, 100.0, 200.0, 0.0][..],
+            )
+            .unwrap();
+        encoder
+            .write_tag(
+                Tag::GeoKeyDirectoryTag,
+                &[1, 1, 0, 2, 1024, 0, 1, 1, 3072, 0, 1, projected_crs][..],
+            )
+            .unwrap();
+        if let Some(nodata) = nodata {
+            encoder.write_tag(Tag::GdalNodata, nodata).unwrap();
+        }
+        image.write_data(&pixels).unwrap();
+
+        let overview: Vec<u8> = (0..width * height / 4)
+            .map(|v| 200u8.wrapping_add(v as u8))
+            .collect();
+        let mut image = tiff
+            .new_image::<colortype::Gray8>(width / 2, height / 2)
+            .unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::NewSubfileType, 1u32)
+            .unwrap();
+        image.write_data(&overview).unwrap();
+
+        data.into_inner().into()
+    }
+
+    async fn open(bytes: Bytes) -> Result<CogReader, GalileoError> {
+        CogReader::open(CogSource::Bytes(bytes), None, ValueMapping::default()).await
+    }
+
+    #[tokio::test]
+    async fn open_reads_levels_and_georeference() {
+        let reader = open(test_file(4, 4, 3857, None)).await.unwrap();
+
+        assert_eq!(reader.bounds(), Rect::new(100.0, 160.0, 140.0, 200.0));
+        assert_eq!(reader.crs(), &Crs::EPSG3857);
+
+        let levels: Vec<_> = reader
+            .levels()
+            .iter()
+            .map(|level| (level.width, level.height, level.resolution))
+            .collect();
+        assert_eq!(levels, [(4, 4, (10.0, 10.0)), (2, 2, (20.0, 20.0))]);
+    }
+
+    #[tokio::test]
+    async fn open_loads_headers_outside_of_prefetched_data() {
+        // Image data is written before the image directories, so they are far from the start of
+        // the file.
+        let bytes = test_file(200, 200, 3857, None);
+        assert!(bytes.len() as u64 > HEADER_PREFETCH * 2);
+
+        let reader = open(bytes).await.unwrap();
+        assert_eq!(reader.levels().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn open_requires_crs_for_unknown_epsg_code() {
+        let result = open(test_file(4, 4, 32633, None)).await;
+        assert!(
+            matches!(&result, Err(GalileoError::Configuration(message)) if message.contains("EPSG:32633")),
+            "{result:?}"
+        );
+
+        let reader = CogReader::open(
+            CogSource::Bytes(test_file(4, 4, 32633, None)),
+            Some(Crs::EPSG3857),
+            ValueMapping::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reader.crs(), &Crs::EPSG3857);
+    }
+
+    #[tokio::test]
+    async fn select_level_by_resolution() {
+        let reader = open(test_file(4, 4, 3857, None)).await.unwrap();
+
+        assert_eq!(reader.select_level(5.0), 0);
+        assert_eq!(reader.select_level(10.0), 0);
+        assert_eq!(reader.select_level(19.0), 0);
+        assert_eq!(reader.select_level(20.0), 1);
+        assert_eq!(reader.select_level(100.0), 1);
+    }
+
+    #[tokio::test]
+    async fn chunks_in_bbox() {
+        let reader = open(test_file(4, 4, 3857, None)).await.unwrap();
+
+        // Strips of 2 rows: row 0 covers y 180..200, row 1 covers y 160..180.
+        let rows = |bbox| {
+            reader
+                .chunks_in_bbox(0, bbox)
+                .iter()
+                .map(|index| (index.column, index.row))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rows(Rect::new(0.0, 0.0, 1000.0, 1000.0)), [(0, 0), (0, 1)]);
+        assert_eq!(rows(Rect::new(110.0, 185.0, 120.0, 190.0)), [(0, 0)]);
+        assert_eq!(rows(Rect::new(110.0, 165.0, 120.0, 170.0)), [(0, 1)]);
+        assert!(rows(Rect::new(0.0, 0.0, 50.0, 50.0)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn chunks_in_bbox_of_empty_level() {
+        let mut reader = open(test_file(4, 4, 3857, None)).await.unwrap();
+        reader.levels[0].width = 0;
+
+        assert!(reader
+            .chunks_in_bbox(0, Rect::new(0.0, 0.0, 1000.0, 1000.0))
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_chunk_converts_to_rgba() {
+        let reader = open(test_file(4, 4, 3857, Some("5"))).await.unwrap();
+
+        let chunk = reader
+            .read_chunk(ChunkIndex {
+                level: 0,
+                column: 0,
+                row: 1,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(chunk.bbox, Rect::new(100.0, 160.0, 140.0, 180.0));
+        assert_eq!(chunk.image.width(), 4);
+        assert_eq!(chunk.image.height(), 2);
+
+        let pixels: Vec<_> = chunk
+            .image
+            .bitmap_bytes()
+            .unwrap()
+            .chunks(4)
+            .map(|p| p.to_vec())
+            .collect();
+        assert_eq!(pixels[0], [8, 8, 8, 255]);
+        assert_eq!(pixels[7], [15, 15, 15, 255]);
+
+        let chunk = reader
+            .read_chunk(ChunkIndex {
+                level: 0,
+                column: 0,
+                row: 0,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let pixels: Vec<_> = chunk
+            .image
+            .bitmap_bytes()
+            .unwrap()
+            .chunks(4)
+            .map(|p| p.to_vec())
+            .collect();
+        assert_eq!(pixels[5], [0, 0, 0, 0], "nodata pixel is transparent");
+
+        let chunk = reader
+            .read_chunk(ChunkIndex {
+                level: 1,
+                column: 0,
+                row: 0,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.bbox, Rect::new(100.0, 160.0, 140.0, 200.0));
+        assert_eq!(
+            &chunk.image.bitmap_bytes().unwrap()[..4],
+            [200, 200, 200, 255]
+        );
+    }
+
+    #[test]
+    fn to_rgba_scales_values() {
+        let values = ValueMapping {
+            range: Some((0.0, 1000.0)),
+            nodata: Some(-1.0),
+        };
+        let rgba = to_rgba(
+            DecodingResult::F32(vec![0.0, 500.0, 2000.0, -1.0, f32::NAN]),
+            ColorType::Gray(32),
+            None,
+            &values,
+        )
+        .unwrap();
+
+        assert_eq!(
+            rgba,
+            [0, 0, 0, 255, 128, 128, 128, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn sparse_buffer_reads_loaded_ranges() {
+        let mut buffer = SparseBuffer::default();
+        buffer.insert(10, Bytes::from_static(&[1, 2, 3, 4]));
+        buffer.insert(12, Bytes::from_static(&[3]));
+
+        let mut data = [0; 2];
+        buffer.seek(SeekFrom::Start(12)).unwrap();
+        buffer.read_exact(&mut data).unwrap();
+        assert_eq!(data, [3, 4]);
+
+        let err = buffer.read_exact(&mut data).unwrap_err();
+        let missing = err.get_ref().unwrap().downcast_ref::<MissingRange>();
+        assert_eq!(missing.map(|missing| missing.0), Some(14));
+    }
+}
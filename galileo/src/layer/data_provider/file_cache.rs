@@ -2,9 +2,19 @@ use std::path::{Path, PathBuf};
 
 use bytes::Bytes;
 use log::debug;
+use parking_lot::Mutex;
+use web_time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::error::GalileoError;
-use crate::layer::data_provider::PersistentCacheController;
+use crate::layer::data_provider::{CacheEntry, CacheMetadata, PersistentCacheController};
+
+mod index;
+use index::{metadata_file_size, CacheIndex};
+
+const METADATA_EXTENSION: &str = "meta";
+
+/// Number of index changes after which the index file is written to the disk.
+const INDEX_SAVE_INTERVAL: usize = 100;
 
 /// Function to modify the default file path of the cache
 pub type FileCachePathModifier = dyn Fn(&str) -> String + Send + Sync;
@@ -17,31 +27,89 @@ pub fn remove_parameters_modifier(path: &str) -> String {
 
 /// Stores the cached data as a set of files in the specified folder. It generates file names from the given urls.
 ///
-/// Currently, there is no eviction mechanism.
+/// Along with every cached file the controller stores a small metadata file (with `.meta` extension
+/// added to the file name), containing the time the file was fetched and the caching headers of the
+/// HTTP response (`ETag`, `Last-Modified`, `Cache-Control`). An entry becomes stale when it is older
+/// than the `max-age` the server sent for it or the [`FileCacheController::with_max_age`] policy,
+/// whichever is smaller. Stale entries are still returned by the controller, but tile loaders
+/// revalidate them with the source with a conditional request. If neither the server nor the
+/// controller specify max age, the entries never become stale.
+///
+/// The size of the cache can be limited with [`FileCacheController::with_max_size`] and
+/// [`FileCacheController::with_max_entries`]. When the cache grows over the limit, the least
+/// recently used entries are removed. To track the usage of entries, the controller keeps an index
+/// file in the root of the cache folder. The index is written to the disk periodically and when
+/// the controller is dropped. To keep access to the controller (e.g. to query its
+/// [usage](FileCacheController::usage)) after giving it to a layer, wrap it into an [`Arc`](std::sync::Arc).
 pub struct FileCacheController {
     folder_path: PathBuf,
     /// Function to modify the default file path of the cache (optional)
     file_path_modifier: Option<Box<FileCachePathModifier>>,
+    max_age: Option<Duration>,
+    max_size: Option<u64>,
+    max_entries: Option<usize>,
+    /// Index is loaded on the first access to the cache.
+    index: Mutex<Option<CacheIndex>>,
+}
+
+/// Amount of data stored in a cache.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CacheUsage {
+    /// Total size of the cached files in bytes.
+    pub size_bytes: u64,
+    /// Number of cached entries.
+    pub entries: usize,
 }
 
 impl PersistentCacheController<str, Bytes> for FileCacheController {
     fn get(&self, key: &str) -> Option<Bytes> {
         let file_path = self.get_file_path(key);
-        if let Ok(bytes) = std::fs::read(file_path) {
-            Some(bytes.into())
-        } else {
-            None
-        }
+        let result = std::fs::read(&file_path).ok().map(Bytes::from);
+        self.update_access(key, &file_path, result.as_ref());
+        result
     }
 
     fn insert(&self, key: &str, data: &Bytes) -> Result<(), GalileoError> {
+        self.insert_entry(key, data, &CacheMetadata::new(SystemTime::now()))
+    }
+
+    fn get_entry(&self, key: &str) -> Option<CacheEntry<Bytes>> {
+        let file_path = self.get_file_path(key);
+        let data = std::fs::read(&file_path).ok().map(Bytes::from);
+        self.update_access(key, &file_path, data.as_ref());
+
+        let data = data?;
+        let metadata = self.read_metadata(&file_path);
+        let is_fresh = metadata.as_ref().is_none_or(|m| self.is_fresh(m));
+
+        Some(CacheEntry {
+            data,
+            metadata,
+            is_fresh,
+        })
+    }
+
+    fn insert_entry(
+        &self,
+        key: &str,
+        data: &Bytes,
+        metadata: &CacheMetadata,
+    ) -> Result<(), GalileoError> {
         let file_path = self.get_file_path(key);
         match file_path.parent() {
             Some(folder) => match ensure_folder_exists(folder) {
                 Ok(()) => {
                     debug!("Saving entry {key} to the cache file {file_path:?}");
                     std::fs::write(&file_path, data)?;
+                    let encoded_metadata = metadata.encode();
+                    std::fs::write(metadata_path(&file_path), &encoded_metadata)?;
                     debug!("Entry {key} saved to cache file {file_path:?}");
+
+                    let size = data.len() as u64 + encoded_metadata.len() as u64;
+                    self.with_index(|index| {
+                        index.insert(&self.relative_path(key), size);
+                        self.evict(index);
+                    });
                     Ok(())
                 }
                 Err(err) => {
@@ -57,6 +125,34 @@ impl PersistentCacheController<str, Bytes> for FileCacheController {
             }
         }
     }
+
+    fn update_metadata(&self, key: &str, metadata: &CacheMetadata) -> Result<(), GalileoError> {
+        let file_path = self.get_file_path(key);
+        if !file_path.exists() {
+            return Err(GalileoError::NotFound);
+        }
+
+        debug!("Updating metadata of the cache entry {key}");
+        let encoded_metadata = metadata.encode();
+        std::fs::write(metadata_path(&file_path), &encoded_metadata)?;
+
+        let data_size = std::fs::metadata(&file_path).map_or(0, |m| m.len());
+        self.with_index(|index| {
+            index.insert(
+                &self.relative_path(key),
+                data_size + encoded_metadata.len() as u64,
+            )
+        });
+        Ok(())
+    }
+}
+
+impl Drop for FileCacheController {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            log::warn!("Failed to save file cache index: {err:?}");
+        }
+    }
 }
 
 impl FileCacheController {
@@ -77,10 +173,222 @@ impl FileCacheController {
         Ok(Self {
             folder_path: path.as_ref().into(),
             file_path_modifier,
+            max_age: None,
+            max_size: None,
+            max_entries: None,
+            index: Mutex::new(None),
         })
     }
 
+    /// Sets the maximum total size of the cached files in bytes. When the cache grows larger, the
+    /// least recently used entries are removed.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use galileo::layer::data_provider::FileCacheController;
+    /// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+    ///
+    /// let cache = Arc::new(
+    ///     FileCacheController::new("target/tile_cache", None)?
+    ///         .with_max_size(200 * 1024 * 1024)
+    ///         .with_max_entries(20_000),
+    /// );
+    /// let layer = RasterTileLayerBuilder::new_osm()
+    ///     .with_cache_controller(cache.clone())
+    ///     .build()?;
+    ///
+    /// println!("Cache size: {} bytes", cache.usage().size_bytes);
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Sets the maximum number of the cached entries. When the cache grows larger, the least
+    /// recently used entries are removed.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Maximum total size of the cached files set by [`FileCacheController::with_max_size`].
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    /// Maximum number of the cached entries set by [`FileCacheController::with_max_entries`].
+    pub fn max_entries(&self) -> Option<usize> {
+        self.max_entries
+    }
+
+    /// Returns the current size of the cache.
+    pub fn usage(&self) -> CacheUsage {
+        self.with_index(|index| CacheUsage {
+            size_bytes: index.total_size(),
+            entries: index.len(),
+        })
+    }
+
+    /// Removes all the entries with urls starting with the given prefix. Returns the amount of
+    /// removed data.
+    ///
+    /// The prefix is converted to the file path the same way as urls of the entries, so for
+    /// example `https://tile.openstreetmap.org/` removes all tiles of the OSM tile server.
+    pub fn clear_prefix(&self, url_prefix: &str) -> Result<CacheUsage, GalileoError> {
+        let prefix = self.relative_path(url_prefix);
+        let removed = self.with_index(|index| {
+            let paths = index.paths_with_prefix(&prefix);
+            self.remove_entries(index, &paths)
+        });
+
+        self.flush()?;
+        Ok(removed)
+    }
+
+    /// Removes all the entries from the cache. Returns the amount of removed data.
+    pub fn clear(&self) -> Result<CacheUsage, GalileoError> {
+        let removed = self.with_index(|index| {
+            let paths = index.paths_with_prefix("");
+            let removed = self.remove_entries(index, &paths);
+            index.clear();
+            removed
+        });
+
+        self.flush()?;
+        Ok(removed)
+    }
+
+    /// Writes the cache index to the disk.
+    ///
+    /// The index is also written automatically from time to time and when the controller is dropped.
+    pub fn flush(&self) -> Result<(), GalileoError> {
+        let mut index = self.index.lock();
+        match &mut *index {
+            Some(index) if index.needs_save() => index.save(&self.folder_path).map_err(|err| {
+                GalileoError::FsIo(format!("failed to save file cache index: {err}"))
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn with_index<T>(&self, f: impl FnOnce(&mut CacheIndex) -> T) -> T {
+        let mut guard = self.index.lock();
+        let index = guard.get_or_insert_with(|| CacheIndex::load(&self.folder_path));
+        let result = f(index);
+
+        if index.unsaved_changes() >= INDEX_SAVE_INTERVAL {
+            if let Err(err) = index.save(&self.folder_path) {
+                log::warn!("Failed to save file cache index: {err:?}");
+            }
+        }
+
+        result
+    }
+
+    fn update_access(&self, key: &str, file_path: &Path, data: Option<&Bytes>) {
+        let relative_path = self.relative_path(key);
+        self.with_index(|index| match data {
+            Some(data) => index.touch(&relative_path, || {
+                data.len() as u64 + metadata_file_size(file_path)
+            }),
+            None => {
+                index.remove(&relative_path);
+            }
+        });
+    }
+
+    fn evict(&self, index: &mut CacheIndex) {
+        let to_evict = index.entries_to_evict(self.max_size, self.max_entries);
+        if !to_evict.is_empty() {
+            let removed = self.remove_entries(index, &to_evict);
+            debug!(
+                "Evicted {} entries ({} bytes) from the file cache",
+                removed.entries, removed.size_bytes
+            );
+        }
+    }
+
+    fn remove_entries(&self, index: &mut CacheIndex, paths: &[String]) -> CacheUsage {
+        let mut removed = CacheUsage::default();
+        for path in paths {
+            let file_path = self.folder_path.join(Path::new(path));
+            if let Err(err) = std::fs::remove_file(&file_path) {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to remove cache file {file_path:?}: {err}");
+                    continue;
+                }
+            }
+            let _ = std::fs::remove_file(metadata_path(&file_path));
+
+            if let Some(size) = index.remove(path) {
+                removed.size_bytes += size;
+                removed.entries += 1;
+            }
+        }
+
+        removed
+    }
+
+    /// Sets the maximum age of cache entries. Entries older than this are revalidated with the
+    /// source even if the server allowed caching them for a longer time.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use galileo::layer::data_provider::FileCacheController;
+    /// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+    ///
+    /// let cache = FileCacheController::new("target/tile_cache", None)?
+    ///     .with_max_age(Duration::from_secs(24 * 60 * 60));
+    /// let layer = RasterTileLayerBuilder::new_osm()
+    ///     .with_cache_controller(cache)
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Maximum age of cache entries set by [`FileCacheController::with_max_age`].
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Returns true if the cache entry with the given metadata can be used without revalidation.
+    pub fn is_fresh(&self, metadata: &CacheMetadata) -> bool {
+        if metadata.no_cache {
+            return false;
+        }
+
+        let lifetime = match (metadata.max_age, self.max_age) {
+            (Some(server), Some(local)) => Some(server.min(local)),
+            (server, local) => server.or(local),
+        };
+
+        lifetime.is_none_or(|lifetime| metadata.age() < lifetime)
+    }
+
+    fn read_metadata(&self, file_path: &Path) -> Option<CacheMetadata> {
+        if let Ok(encoded) = std::fs::read_to_string(metadata_path(file_path)) {
+            if let Some(metadata) = CacheMetadata::decode(&encoded) {
+                return Some(metadata);
+            }
+        }
+
+        // Entries saved without metadata are considered fetched at the time the file was written.
+        let modified = std::fs::metadata(file_path).ok()?.modified().ok()?;
+        let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+        Some(CacheMetadata::new(UNIX_EPOCH + since_epoch))
+    }
+
     fn get_file_path(&self, url: &str) -> PathBuf {
+        self.folder_path.join(Path::new(&self.relative_path(url)))
+    }
+
+    fn relative_path(&self, url: &str) -> String {
         let stripped = if let Some(v) = url.strip_prefix("http://") {
             v
         } else if let Some(v) = url.strip_prefix("https://") {
@@ -89,16 +397,199 @@ impl FileCacheController {
             url
         };
 
-        let path = if let Some(modifier) = &self.file_path_modifier {
+        if let Some(modifier) = &self.file_path_modifier {
             modifier(stripped)
         } else {
             stripped.to_string()
-        };
-
-        self.folder_path.join(Path::new(&path))
+        }
     }
 }
 
+fn metadata_path(file_path: &Path) -> PathBuf {
+    let mut path = file_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(METADATA_EXTENSION);
+    path.into()
+}
+
 fn ensure_folder_exists(folder_path: &Path) -> std::io::Result<()> {
     std::fs::create_dir_all(folder_path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_controller(name: &str) -> FileCacheController {
+        let path = std::env::temp_dir().join(format!("galileo_file_cache_{name}"));
+        let _ = std::fs::remove_dir_all(&path);
+        FileCacheController::new(path, None).expect("failed to create cache")
+    }
+
+    #[test]
+    fn stores_entry_metadata() {
+        let cache = test_controller("stores_entry_metadata");
+        let url = "https://example.com/1/2/3.png";
+        let metadata = CacheMetadata::from_headers(Some("\"abc\""), None, None);
+        cache
+            .insert_entry(url, &Bytes::from_static(b"data"), &metadata)
+            .expect("failed to insert");
+
+        let entry = cache.get_entry(url).expect("entry not found");
+        assert_eq!(&entry.data[..], b"data");
+        assert_eq!(
+            entry.metadata.and_then(|m| m.etag).as_deref(),
+            Some("\"abc\"")
+        );
+        assert!(entry.is_fresh);
+    }
+
+    #[test]
+    fn entries_become_stale_after_max_age() {
+        let cache = test_controller("entries_become_stale").with_max_age(Duration::from_secs(60));
+        let url = "https://example.com/1/2/3.png";
+        let mut metadata = CacheMetadata::new(SystemTime::now() - Duration::from_secs(120));
+        cache
+            .insert_entry(url, &Bytes::from_static(b"data"), &metadata)
+            .expect("failed to insert");
+        assert!(!cache.get_entry(url).expect("entry not found").is_fresh);
+
+        metadata.fetched_at = SystemTime::now();
+        cache
+            .update_metadata(url, &metadata)
+            .expect("failed to update");
+        assert!(cache.get_entry(url).expect("entry not found").is_fresh);
+    }
+
+    #[test]
+    fn server_max_age_limits_freshness() {
+        let cache = test_controller("server_max_age").with_max_age(Duration::from_secs(3600));
+        let mut metadata = CacheMetadata::new(SystemTime::now() - Duration::from_secs(120));
+        assert!(cache.is_fresh(&metadata));
+
+        metadata.max_age = Some(Duration::from_secs(60));
+        assert!(!cache.is_fresh(&metadata));
+
+        metadata.max_age = None;
+        metadata.no_cache = true;
+        assert!(!cache.is_fresh(&metadata));
+    }
+
+    #[test]
+    fn entries_without_metadata_use_file_time() {
+        let cache = test_controller("without_metadata").with_max_age(Duration::from_secs(60));
+        let url = "https://example.com/1/2/3.png";
+        cache
+            .insert(url, &Bytes::from_static(b"data"))
+            .expect("failed to insert");
+        std::fs::remove_file(metadata_path(&cache.get_file_path(url)))
+            .expect("failed to remove metadata");
+
+        let entry = cache.get_entry(url).expect("entry not found");
+        assert!(entry.is_fresh);
+        assert!(entry
+            .metadata
+            .is_some_and(|m| m.age() < Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries() {
+        let cache = test_controller("evicts_lru").with_max_entries(2);
+        let data = Bytes::from_static(b"data");
+        cache
+            .insert("https://example.com/1", &data)
+            .expect("failed to insert");
+        cache
+            .insert("https://example.com/2", &data)
+            .expect("failed to insert");
+        assert!(cache.get("https://example.com/1").is_some());
+
+        cache
+            .insert("https://example.com/3", &data)
+            .expect("failed to insert");
+        assert_eq!(cache.usage().entries, 2);
+        assert!(cache.get("https://example.com/1").is_some());
+        assert!(cache.get("https://example.com/2").is_none());
+        assert!(cache.get("https://example.com/3").is_some());
+    }
+
+    #[test]
+    fn evicts_entries_over_max_size() {
+        let cache = test_controller("evicts_over_size");
+        let entry_size = {
+            cache
+                .insert("https://example.com/0", &Bytes::from_static(b"data"))
+                .expect("failed to insert");
+            cache.usage().size_bytes
+        };
+
+        let cache = cache.with_max_size(entry_size * 3);
+        for i in 1..10 {
+            cache
+                .insert(
+                    &format!("https://example.com/{i}"),
+                    &Bytes::from_static(b"data"),
+                )
+                .expect("failed to insert");
+        }
+
+        assert_eq!(cache.usage().entries, 3);
+        assert_eq!(cache.usage().size_bytes, entry_size * 3);
+        assert!(cache.get("https://example.com/9").is_some());
+        assert!(cache.get("https://example.com/6").is_none());
+    }
+
+    #[test]
+    fn index_is_restored_after_restart() {
+        let path = std::env::temp_dir().join("galileo_file_cache_index_restored");
+        let _ = std::fs::remove_dir_all(&path);
+        let data = Bytes::from_static(b"data");
+        {
+            let cache = FileCacheController::new(&path, None).expect("failed to create cache");
+            cache
+                .insert("https://example.com/1", &data)
+                .expect("failed to insert");
+            cache
+                .insert("https://example.com/2", &data)
+                .expect("failed to insert");
+        }
+
+        let cache = FileCacheController::new(&path, None)
+            .expect("failed to create cache")
+            .with_max_entries(1);
+        assert_eq!(cache.usage().entries, 2);
+
+        cache
+            .insert("https://example.com/3", &data)
+            .expect("failed to insert");
+        assert_eq!(cache.usage().entries, 1);
+        assert!(cache.get("https://example.com/1").is_none());
+    }
+
+    #[test]
+    fn clear_by_prefix() {
+        let cache = test_controller("clear_by_prefix");
+        let data = Bytes::from_static(b"data");
+        cache
+            .insert("https://a.com/1", &data)
+            .expect("failed to insert");
+        cache
+            .insert("https://a.com/2", &data)
+            .expect("failed to insert");
+        cache
+            .insert("https://b.com/1", &data)
+            .expect("failed to insert");
+
+        let removed = cache
+            .clear_prefix("https://a.com/")
+            .expect("failed to clear");
+        assert_eq!(removed.entries, 2);
+        assert_eq!(cache.usage().entries, 1);
+        assert!(cache.get("https://a.com/1").is_none());
+        assert!(cache.get("https://b.com/1").is_some());
+
+        cache.clear().expect("failed to clear");
+        assert_eq!(cache.usage(), CacheUsage::default());
+        assert!(cache.get("https://b.com/1").is_none());
+    }
+}
@@ -0,0 +1,312 @@
+//! Index of the file cache entries used for LRU eviction.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::Path;
+
+use ahash::RandomState;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use super::METADATA_EXTENSION;
+
+/// Name of the index file in the root of the cache folder.
+pub(super) const INDEX_FILE_NAME: &str = ".galileo_cache_index";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexEntry {
+    size: u64,
+    last_access: u64,
+}
+
+/// In-memory copy of the index file. Stores size and last access time of every cache entry.
+///
+/// Entries are identified by their file path relative to the cache folder.
+#[derive(Debug, Default)]
+pub(super) struct CacheIndex {
+    entries: HashMap<String, IndexEntry, RandomState>,
+    /// Entries ordered from the least recently used to the most recently used.
+    by_access: BTreeSet<(u64, String)>,
+    total_size: u64,
+    clock: u64,
+    unsaved_changes: usize,
+    /// Whether access times changed since the index was last saved. Access times are not counted
+    /// as changes, so that reading from the cache doesn't make the index to be saved again and
+    /// again, but they are saved together with the next change or on flush.
+    access_updated: bool,
+}
+
+impl CacheIndex {
+    /// Loads the index from the index file in the given folder. If there is no index file (e.g. the
+    /// cache was created by an older version), the index is built by scanning the folder.
+    pub(super) fn load(folder: &Path) -> Self {
+        match std::fs::read_to_string(folder.join(INDEX_FILE_NAME)) {
+            Ok(contents) => Self::decode(&contents),
+            Err(_) => {
+                let mut index = Self::default();
+                index.scan(folder, folder);
+                index.unsaved_changes = index.entries.len();
+                index
+            }
+        }
+    }
+
+    /// Writes the index to the index file in the given folder.
+    pub(super) fn save(&mut self, folder: &Path) -> std::io::Result<()> {
+        let tmp_path = folder.join(format!("{INDEX_FILE_NAME}.tmp"));
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        for (path, entry) in &self.entries {
+            writeln!(file, "{} {} {path}", entry.last_access, entry.size)?;
+        }
+        file.flush()?;
+        drop(file);
+
+        std::fs::rename(tmp_path, folder.join(INDEX_FILE_NAME))?;
+        self.unsaved_changes = 0;
+        self.access_updated = false;
+        Ok(())
+    }
+
+    /// Number of entries added or removed since the index was last saved.
+    pub(super) fn unsaved_changes(&self) -> usize {
+        self.unsaved_changes
+    }
+
+    /// Returns true if anything changed since the index was last saved, including access times.
+    pub(super) fn needs_save(&self) -> bool {
+        self.unsaved_changes > 0 || self.access_updated
+    }
+
+    pub(super) fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Marks the entry as just used. If the entry is not in the index yet, it is added with the
+    /// size returned by `size`.
+    pub(super) fn touch(&mut self, path: &str, size: impl FnOnce() -> u64) {
+        let last_access = self.next_time();
+        match self.entries.get_mut(path) {
+            Some(entry) => {
+                self.by_access
+                    .remove(&(entry.last_access, path.to_string()));
+                entry.last_access = last_access;
+                self.access_updated = true;
+            }
+            None => {
+                let size = size();
+                self.total_size += size;
+                self.entries
+                    .insert(path.to_string(), IndexEntry { size, last_access });
+                self.unsaved_changes += 1;
+            }
+        }
+
+        self.by_access.insert((last_access, path.to_string()));
+    }
+
+    /// Adds or replaces the entry, marking it as just used.
+    pub(super) fn insert(&mut self, path: &str, size: u64) {
+        self.remove(path);
+        self.touch(path, || size);
+    }
+
+    /// Removes the entry from the index, returning its size.
+    pub(super) fn remove(&mut self, path: &str) -> Option<u64> {
+        let entry = self.entries.remove(path)?;
+        self.by_access
+            .remove(&(entry.last_access, path.to_string()));
+        self.total_size -= entry.size;
+        self.unsaved_changes += 1;
+        Some(entry.size)
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+        self.by_access.clear();
+        self.total_size = 0;
+        self.unsaved_changes += 1;
+    }
+
+    /// Returns paths of the least recently used entries that must be removed for the cache to fit
+    /// into the given limits. The most recently used entry is never returned.
+    pub(super) fn entries_to_evict(
+        &self,
+        max_size: Option<u64>,
+        max_entries: Option<usize>,
+    ) -> Vec<String> {
+        let exceeds = |size: u64, count: usize| {
+            max_size.is_some_and(|max| size > max) || max_entries.is_some_and(|max| count > max)
+        };
+
+        if !exceeds(self.total_size, self.entries.len()) {
+            return vec![];
+        }
+
+        let mut size = self.total_size;
+        let mut count = self.entries.len();
+        let mut result = vec![];
+        for (_, path) in &self.by_access {
+            if count <= 1 || !exceeds(size, count) {
+                break;
+            }
+
+            size -= self.entries.get(path).map_or(0, |entry| entry.size);
+            count -= 1;
+            result.push(path.clone());
+        }
+
+        result
+    }
+
+    /// Returns paths of all entries starting with the given prefix.
+    pub(super) fn paths_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.entries
+            .keys()
+            .filter(|path| path.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    fn next_time(&mut self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.clock = now.max(self.clock + 1);
+        self.clock
+    }
+
+    fn decode(contents: &str) -> Self {
+        let mut index = Self::default();
+        for line in contents.lines() {
+            let mut parts = line.splitn(3, ' ');
+            let (Some(last_access), Some(size), Some(path)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            let (Ok(last_access), Ok(size)) = (last_access.parse(), size.parse()) else {
+                continue;
+            };
+
+            index.clock = index.clock.max(last_access);
+            index.total_size += size;
+            index.by_access.insert((last_access, path.to_string()));
+            index
+                .entries
+                .insert(path.to_string(), IndexEntry { size, last_access });
+        }
+
+        index
+    }
+
+    fn scan(&mut self, root: &Path, folder: &Path) {
+        let Ok(dir) = std::fs::read_dir(folder) else {
+            return;
+        };
+
+        for entry in dir.flatten() {
+            let path = entry.path();
+            let Ok(file_metadata) = entry.metadata() else {
+                continue;
+            };
+
+            if file_metadata.is_dir() {
+                self.scan(root, &path);
+                continue;
+            }
+
+            let is_service_file = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(INDEX_FILE_NAME));
+            let is_metadata = path
+                .extension()
+                .is_some_and(|ext| ext == METADATA_EXTENSION);
+            if is_service_file || is_metadata {
+                continue;
+            }
+
+            let Some(relative) = path
+                .strip_prefix(root)
+                .ok()
+                .and_then(|p| p.to_str())
+                .map(|p| p.replace('\\', "/"))
+            else {
+                continue;
+            };
+
+            let size = file_metadata.len() + metadata_file_size(&path);
+            self.touch(&relative, || size);
+        }
+    }
+}
+
+/// Size of the metadata file stored next to the cache entry file, or 0 if there is none.
+pub(super) fn metadata_file_size(file_path: &Path) -> u64 {
+    std::fs::metadata(super::metadata_path(file_path)).map_or(0, |m| m.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_with(entries: &[(&str, u64)]) -> CacheIndex {
+        let mut index = CacheIndex::default();
+        for (path, size) in entries {
+            index.insert(path, *size);
+        }
+        index
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut index = index_with(&[("a", 10), ("b", 10), ("c", 10)]);
+        index.touch("a", || unreachable!());
+
+        assert!(index.entries_to_evict(Some(30), None).is_empty());
+        assert_eq!(index.entries_to_evict(Some(25), None), vec!["b"]);
+        assert_eq!(index.entries_to_evict(None, Some(1)), vec!["b", "c"]);
+        assert_eq!(index.entries_to_evict(Some(0), None), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn reads_are_not_counted_as_changes() {
+        let mut index = index_with(&[("a", 10)]);
+        let changes = index.unsaved_changes();
+        index.touch("a", || unreachable!());
+        index.touch("a", || unreachable!());
+
+        assert_eq!(index.unsaved_changes(), changes);
+        assert!(index.needs_save());
+    }
+
+    #[test]
+    fn insert_replaces_size() {
+        let mut index = index_with(&[("a", 10), ("b", 10)]);
+        index.insert("a", 5);
+        assert_eq!(index.total_size(), 15);
+        assert_eq!(index.len(), 2);
+
+        assert_eq!(index.remove("b"), Some(10));
+        assert_eq!(index.total_size(), 5);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let folder = std::env::temp_dir().join("galileo_cache_index_encode_decode");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).expect("failed to create folder");
+
+        let mut index = index_with(&[("host/1/2/3.png", 10), ("host/a b.png", 20)]);
+        index.save(&folder).expect("failed to save");
+
+        let loaded = CacheIndex::load(&folder);
+        assert_eq!(loaded.entries, index.entries);
+        assert_eq!(loaded.total_size(), 30);
+        assert_eq!(loaded.unsaved_changes(), 0);
+    }
+}
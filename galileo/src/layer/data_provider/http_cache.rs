@@ -0,0 +1,430 @@
+use std::collections::HashSet;
+use std::sync::{Arc, LazyLock};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use web_time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::GalileoError;
+use crate::layer::data_provider::{HttpRequest, PersistentCacheController};
+use crate::layer::TileDownloadStatus;
+use crate::platform::{ConditionalResponse, PlatformService};
+
+/// Information about a cached resource, used to decide when the resource must be revalidated.
+///
+/// The values are taken from the HTTP response headers the resource was loaded with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheMetadata {
+    /// Time the resource was loaded from (or last revalidated with) the source.
+    pub fetched_at: SystemTime,
+    /// Value of the `ETag` header.
+    pub etag: Option<String>,
+    /// Value of the `Last-Modified` header.
+    pub last_modified: Option<String>,
+    /// Value of the `max-age` directive of the `Cache-Control` header.
+    pub max_age: Option<Duration>,
+    /// Whether the `Cache-Control` header requires revalidating the resource before every use
+    /// (`no-cache` directive).
+    pub no_cache: bool,
+    /// Whether the `Cache-Control` header forbids storing the resource in a cache (`no-store`
+    /// directive).
+    pub no_store: bool,
+}
+
+impl CacheMetadata {
+    /// Creates metadata for a resource fetched at the given time without any caching headers.
+    pub fn new(fetched_at: SystemTime) -> Self {
+        Self {
+            fetched_at,
+            etag: None,
+            last_modified: None,
+            max_age: None,
+            no_cache: false,
+            no_store: false,
+        }
+    }
+
+    /// Creates metadata for a resource fetched right now with the given header values.
+    pub fn from_headers(
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+        cache_control: Option<&str>,
+    ) -> Self {
+        let mut metadata = Self::new(SystemTime::now());
+        metadata.etag = etag.map(str::to_string);
+        metadata.last_modified = last_modified.map(str::to_string);
+
+        for directive in cache_control.unwrap_or_default().split(',') {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+
+            if name.eq_ignore_ascii_case("max-age") {
+                metadata.max_age = value.and_then(|v| v.parse().ok()).map(Duration::from_secs);
+            } else if name.eq_ignore_ascii_case("no-cache") {
+                metadata.no_cache = true;
+            } else if name.eq_ignore_ascii_case("no-store") {
+                metadata.no_store = true;
+            }
+        }
+
+        metadata
+    }
+
+    /// Time passed since the resource was fetched.
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.fetched_at)
+            .unwrap_or_default()
+    }
+
+    /// Returns true if the resource has validators to make a conditional request with.
+    pub fn can_revalidate(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    /// Merges metadata of a `304 Not Modified` response into the metadata of the cached resource.
+    ///
+    /// The server may omit headers in such response, so the values of the previous response are
+    /// kept in this case.
+    pub(crate) fn revalidated(self, previous: CacheMetadata) -> Self {
+        Self {
+            fetched_at: self.fetched_at,
+            etag: self.etag.or(previous.etag),
+            last_modified: self.last_modified.or(previous.last_modified),
+            max_age: self.max_age.or(previous.max_age),
+            no_cache: self.no_cache,
+            no_store: self.no_store,
+        }
+    }
+
+    /// Serializes the metadata into a simple `key=value` line format.
+    pub(crate) fn encode(&self) -> String {
+        let fetched_at = self
+            .fetched_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut result = format!("fetched_at={fetched_at}\n");
+        if let Some(etag) = &self.etag {
+            result += &format!("etag={etag}\n");
+        }
+        if let Some(last_modified) = &self.last_modified {
+            result += &format!("last_modified={last_modified}\n");
+        }
+        if let Some(max_age) = self.max_age {
+            result += &format!("max_age={}\n", max_age.as_secs());
+        }
+        if self.no_cache {
+            result += "no_cache=true\n";
+        }
+
+        result
+    }
+
+    /// Parses metadata serialized with [`CacheMetadata::encode`]. Returns `None` if the fetch
+    /// time is missing or malformed.
+    pub(crate) fn decode(encoded: &str) -> Option<Self> {
+        let mut fetched_at = None;
+        let mut metadata = Self::new(UNIX_EPOCH);
+        for line in encoded.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            match key {
+                "fetched_at" => {
+                    fetched_at = Some(UNIX_EPOCH + Duration::from_secs(value.parse().ok()?))
+                }
+                "etag" => metadata.etag = Some(value.to_string()),
+                "last_modified" => metadata.last_modified = Some(value.to_string()),
+                "max_age" => metadata.max_age = Some(Duration::from_secs(value.parse().ok()?)),
+                "no_cache" => metadata.no_cache = value == "true",
+                _ => {}
+            }
+        }
+
+        metadata.fetched_at = fetched_at?;
+        Some(metadata)
+    }
+}
+
+/// Data item stored in a [`PersistentCacheController`] together with its cache state.
+#[derive(Debug, Clone)]
+pub struct CacheEntry<Data> {
+    /// Cached data.
+    pub data: Data,
+    /// Metadata of the cached resource, if the controller stores it.
+    pub metadata: Option<CacheMetadata>,
+    /// If false, the data can still be used, but it should be revalidated with the source.
+    pub is_fresh: bool,
+}
+
+/// Loads the resource with the given request using the persistent cache. The url of the request is
+/// used as the cache key.
+///
+/// Fresh cache entries are returned without contacting the source. Stale entries are returned
+/// right away too, but a conditional request is sent in background to revalidate them
+/// (stale-while-revalidate). In offline mode cache entries are always used as is, and a missing
+/// entry results in [`GalileoError::Offline`] error, as the resource may still exist in the source.
+pub(crate) async fn load_with_cache(
+    request: &HttpRequest,
+    cache: Option<&Arc<dyn PersistentCacheController<str, Bytes>>>,
+    offline_mode: bool,
+) -> Result<Bytes, GalileoError> {
+    let url = request.url.as_str();
+    if let Some(cache) = cache {
+        if let Some(entry) = cache.get_entry(url) {
+            log::trace!("Cache hit for url {url}");
+            if !entry.is_fresh && !offline_mode {
+                if let Some(guard) = RevalidationGuard::start(cache, url) {
+                    let cache = cache.clone();
+                    let request = request.clone();
+                    crate::async_runtime::spawn(async move {
+                        revalidate(&request, &*cache, entry.metadata).await;
+                        drop(guard);
+                    });
+                }
+            }
+
+            return Ok(entry.data);
+        }
+    }
+
+    if offline_mode {
+        return Err(GalileoError::Offline);
+    }
+
+    log::info!("Loading {url}");
+    match crate::platform::instance()
+        .load_bytes_conditional(request, None)
+        .await?
+    {
+        ConditionalResponse::Modified { data, metadata } => {
+            if let Some(cache) = cache.filter(|_| !metadata.no_store) {
+                if let Err(error) = cache.insert_entry(url, &data, &metadata) {
+                    log::warn!("Failed to write persistent cache entry: {error:?}");
+                }
+            }
+
+            Ok(data)
+        }
+        ConditionalResponse::NotModified { .. } => {
+            log::info!("Failed to load {url}: unexpected Not Modified response");
+            Err(GalileoError::IO)
+        }
+    }
+}
+
+/// Makes sure the resource of the given request is stored in the cache and is fresh, downloading it
+/// if necessary.
+pub(crate) async fn store_in_cache(
+    request: &HttpRequest,
+    cache: Option<&Arc<dyn PersistentCacheController<str, Bytes>>>,
+    offline_mode: bool,
+) -> Result<TileDownloadStatus, GalileoError> {
+    let url = request.url.as_str();
+    let Some(cache) = cache else {
+        return Err(GalileoError::Configuration(
+            "persistent cache is not configured for the loader".into(),
+        ));
+    };
+
+    let entry = cache.get_entry(url);
+    let validators = match &entry {
+        Some(entry) if entry.is_fresh || offline_mode => {
+            return Ok(TileDownloadStatus::Cached {
+                size: entry.data.len() as u64,
+            })
+        }
+        Some(entry) => entry.metadata.as_ref().filter(|m| m.can_revalidate()),
+        None if offline_mode => return Err(GalileoError::Offline),
+        None => None,
+    };
+
+    match crate::platform::instance()
+        .load_bytes_conditional(request, validators)
+        .await?
+    {
+        ConditionalResponse::Modified { data, metadata } => {
+            if metadata.no_store {
+                return Err(GalileoError::Generic(format!(
+                    "resource {url} must not be stored in a cache"
+                )));
+            }
+
+            cache.insert_entry(url, &data, &metadata)?;
+            Ok(TileDownloadStatus::Downloaded {
+                size: data.len() as u64,
+            })
+        }
+        ConditionalResponse::NotModified {
+            metadata: new_metadata,
+        } => {
+            let Some(entry) = entry else {
+                return Err(GalileoError::IO);
+            };
+
+            let metadata = match entry.metadata {
+                Some(previous) => new_metadata.revalidated(previous),
+                None => new_metadata,
+            };
+            cache.update_metadata(url, &metadata)?;
+            Ok(TileDownloadStatus::Cached {
+                size: entry.data.len() as u64,
+            })
+        }
+    }
+}
+
+/// Keys of the cache entries that are being revalidated at the moment. Entries are identified by
+/// the address of the cache controller and the url.
+static REVALIDATING: LazyLock<Mutex<HashSet<(usize, String)>>> = LazyLock::new(Default::default);
+
+/// Marks a cache entry as being revalidated until dropped.
+struct RevalidationGuard {
+    key: (usize, String),
+}
+
+impl RevalidationGuard {
+    /// Returns `None` if the entry is already being revalidated.
+    fn start(cache: &Arc<dyn PersistentCacheController<str, Bytes>>, url: &str) -> Option<Self> {
+        let key = (Arc::as_ptr(cache) as *const () as usize, url.to_string());
+        if !REVALIDATING.lock().insert(key.clone()) {
+            return None;
+        }
+
+        Some(Self { key })
+    }
+}
+
+impl Drop for RevalidationGuard {
+    fn drop(&mut self) {
+        REVALIDATING.lock().remove(&self.key);
+    }
+}
+
+async fn revalidate(
+    request: &HttpRequest,
+    cache: &dyn PersistentCacheController<str, Bytes>,
+    metadata: Option<CacheMetadata>,
+) {
+    let url = request.url.as_str();
+    log::debug!("Revalidating cache entry for {url}");
+    let validators = metadata.as_ref().filter(|m| m.can_revalidate());
+    let response = match crate::platform::instance()
+        .load_bytes_conditional(request, validators)
+        .await
+    {
+        Ok(response) => response,
+        Err(err) => {
+            log::debug!("Failed to revalidate cache entry for {url}: {err}");
+            return;
+        }
+    };
+
+    let result = match response {
+        ConditionalResponse::Modified { metadata, .. } if metadata.no_store => {
+            log::debug!("Not updating cache entry for {url}: the response must not be stored");
+            Ok(())
+        }
+        ConditionalResponse::Modified { data, metadata } => {
+            cache.insert_entry(url, &data, &metadata)
+        }
+        ConditionalResponse::NotModified {
+            metadata: new_metadata,
+        } => {
+            let metadata = match metadata {
+                Some(previous) => new_metadata.revalidated(previous),
+                None => new_metadata,
+            };
+            cache.update_metadata(url, &metadata)
+        }
+    };
+
+    if let Err(error) = result {
+        log::warn!("Failed to write persistent cache entry: {error:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cache_control() {
+        let metadata =
+            CacheMetadata::from_headers(Some("\"abc\""), None, Some("public, max-age=3600"));
+        assert_eq!(metadata.etag.as_deref(), Some("\"abc\""));
+        assert_eq!(metadata.max_age, Some(Duration::from_secs(3600)));
+        assert!(!metadata.no_cache);
+        assert!(metadata.can_revalidate());
+
+        let metadata = CacheMetadata::from_headers(None, None, Some("No-Cache, max-age=\"10\""));
+        assert_eq!(metadata.max_age, Some(Duration::from_secs(10)));
+        assert!(metadata.no_cache);
+        assert!(!metadata.can_revalidate());
+
+        let metadata = CacheMetadata::from_headers(None, None, Some("no-store"));
+        assert!(metadata.no_store);
+        assert!(!metadata.no_cache);
+    }
+
+    #[test]
+    fn encode_decode_metadata() {
+        let metadata = CacheMetadata {
+            fetched_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            etag: Some("W/\"a=b\"".into()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".into()),
+            max_age: Some(Duration::from_secs(60)),
+            no_cache: true,
+            no_store: false,
+        };
+        assert_eq!(CacheMetadata::decode(&metadata.encode()), Some(metadata));
+
+        let metadata = CacheMetadata::new(UNIX_EPOCH + Duration::from_secs(10));
+        assert_eq!(CacheMetadata::decode(&metadata.encode()), Some(metadata));
+
+        assert_eq!(CacheMetadata::decode("etag=abc"), None);
+    }
+
+    #[test]
+    fn revalidated_keeps_previous_validators() {
+        let previous = CacheMetadata::from_headers(Some("abc"), Some("yesterday"), None);
+        let new = CacheMetadata::from_headers(None, None, Some("max-age=5"));
+        let merged = new.clone().revalidated(previous);
+
+        assert_eq!(merged.fetched_at, new.fetched_at);
+        assert_eq!(merged.etag.as_deref(), Some("abc"));
+        assert_eq!(merged.last_modified.as_deref(), Some("yesterday"));
+        assert_eq!(merged.max_age, Some(Duration::from_secs(5)));
+    }
+
+    struct NoopCache;
+
+    impl PersistentCacheController<str, Bytes> for NoopCache {
+        fn get(&self, _key: &str) -> Option<Bytes> {
+            None
+        }
+
+        fn insert(&self, _key: &str, _data: &Bytes) -> Result<(), GalileoError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn entry_is_revalidated_once_at_a_time() {
+        let cache: Arc<dyn PersistentCacheController<str, Bytes>> = Arc::new(NoopCache);
+        let other_cache: Arc<dyn PersistentCacheController<str, Bytes>> = Arc::new(NoopCache);
+
+        let guard = RevalidationGuard::start(&cache, "https://example.com/a");
+        assert!(guard.is_some());
+        assert!(RevalidationGuard::start(&cache, "https://example.com/a").is_none());
+        assert!(RevalidationGuard::start(&cache, "https://example.com/b").is_some());
+        assert!(RevalidationGuard::start(&other_cache, "https://example.com/a").is_some());
+
+        drop(guard);
+        assert!(RevalidationGuard::start(&cache, "https://example.com/a").is_some());
+    }
+}
@@ -0,0 +1,116 @@
+use web_time::Duration;
+
+/// Description of an HTTP GET request made by data loaders.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use galileo::layer::data_provider::HttpRequest;
+///
+/// let request = HttpRequest::new("https://tile.openstreetmap.org/0/0/0.png")
+///     .with_header("User-Agent", "my-app/1.0 (contact@example.com)")
+///     .with_timeout(Duration::from_secs(10));
+///
+/// assert_eq!(request.header("user-agent"), Some("my-app/1.0 (contact@example.com)"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpRequest {
+    /// Url of the requested resource.
+    pub url: String,
+    /// Additional headers sent with the request.
+    pub headers: Vec<(String, String)>,
+    /// Maximum time to wait for the response. If `None`, the platform default is used.
+    pub timeout: Option<Duration>,
+}
+
+impl HttpRequest {
+    /// Creates a request to the given url without additional headers.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            headers: vec![],
+            timeout: None,
+        }
+    }
+
+    /// Adds the header to the request, replacing existing header with the same name (case
+    /// insensitive).
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set_header(name, value);
+        self
+    }
+
+    /// Adds all the headers to the request, replacing existing headers with the same names.
+    pub fn with_headers<N: Into<String>, V: Into<String>>(
+        mut self,
+        headers: impl IntoIterator<Item = (N, V)>,
+    ) -> Self {
+        for (name, value) in headers {
+            self.set_header(name, value);
+        }
+        self
+    }
+
+    /// Sets the maximum time to wait for the response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Adds the header to the request, replacing existing header with the same name (case
+    /// insensitive).
+    pub fn set_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.headers
+            .retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+    }
+
+    /// Returns the value of the header with the given name (case insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl From<String> for HttpRequest {
+    fn from(url: String) -> Self {
+        Self::new(url)
+    }
+}
+
+impl From<&str> for HttpRequest {
+    fn from(url: &str) -> Self {
+        Self::new(url)
+    }
+}
+
+/// Headers and timeout added to every request made by a loader.
+///
+/// Stored as a request template with an empty url.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RequestSettings(HttpRequest);
+
+impl RequestSettings {
+    pub(crate) fn set_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.0.set_header(name, value);
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.0.timeout = Some(timeout);
+    }
+
+    /// Creates a request to the given url with these settings.
+    pub(crate) fn request(&self, url: String) -> HttpRequest {
+        HttpRequest {
+            url,
+            ..self.0.clone()
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.headers.is_empty() && self.0.timeout.is_none()
+    }
+}
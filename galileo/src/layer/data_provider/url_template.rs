@@ -0,0 +1,365 @@
+use std::fmt::Write;
+
+use galileo_types::cartesian::Rect;
+
+use crate::error::GalileoError;
+use crate::layer::data_provider::UrlSource;
+use crate::tile_schema::TileIndex;
+use crate::TileSchema;
+
+/// Parsed template of tile urls, e.g. `https://{s}.tile.example.com/{z}/{x}/{y}{r}.png`.
+///
+/// Supported placeholders:
+/// * `{z}`, `{x}`, `{y}` - tile index;
+/// * `{-y}` - Y index counted in the opposite direction (as in TMS services). If the template
+///   has a tile schema, the index is flipped within the schema bounds, otherwise the Web Mercator
+///   tile grid is assumed. Replaced with an empty string if the index cannot be flipped;
+/// * `{s}` - one of the subdomains set with [`TileUrlTemplateBuilder::with_subdomains`]. The
+///   subdomain is selected based on the tile index, so the same tile always has the same url;
+/// * `{quadkey}` - Bing Maps quadkey of the tile;
+/// * `{bbox}` - bounding box of the tile in the CRS of the tile schema, formatted as
+///   `x_min,y_min,x_max,y_max`. Requires the tile schema to be set;
+/// * `{r}` - retina suffix (`@2x`) if enabled with [`TileUrlTemplateBuilder::with_retina`], or an
+///   empty string.
+///
+/// ```
+/// use galileo::layer::data_provider::TileUrlTemplate;
+/// use galileo::tile_schema::TileIndex;
+///
+/// let template = TileUrlTemplate::builder("https://{s}.tile.example.com/{z}/{x}/{-y}.png")
+///     .with_subdomains(["a", "b", "c"])
+///     .build()?;
+///
+/// assert_eq!(
+///     template.url(&TileIndex::new(1, 0, 1)),
+///     "https://b.tile.example.com/1/1/1.png"
+/// );
+/// # Ok::<(), galileo::error::GalileoError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileUrlTemplate {
+    parts: Vec<TemplatePart>,
+    subdomains: Vec<String>,
+    retina_suffix: &'static str,
+    tile_schema: Option<TileSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Text(String),
+    Z,
+    X,
+    Y,
+    InvertedY,
+    Subdomain,
+    Quadkey,
+    Bbox,
+    Retina,
+}
+
+impl TemplatePart {
+    fn from_placeholder(name: &str) -> Option<Self> {
+        Some(match name {
+            "z" => Self::Z,
+            "x" => Self::X,
+            "y" => Self::Y,
+            "-y" => Self::InvertedY,
+            "s" => Self::Subdomain,
+            "quadkey" => Self::Quadkey,
+            "bbox" => Self::Bbox,
+            "r" => Self::Retina,
+            _ => return None,
+        })
+    }
+}
+
+/// Constructor for a [`TileUrlTemplate`].
+pub struct TileUrlTemplateBuilder {
+    template: String,
+    subdomains: Vec<String>,
+    retina: bool,
+    tile_schema: Option<TileSchema>,
+}
+
+impl TileUrlTemplateBuilder {
+    /// Initializes a builder for the given template string.
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+            subdomains: vec![],
+            retina: false,
+            tile_schema: None,
+        }
+    }
+
+    /// Sets the subdomains substituted into the `{s}` placeholder.
+    pub fn with_subdomains<S: Into<String>>(
+        mut self,
+        subdomains: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.subdomains = subdomains.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Substitutes `@2x` into the `{r}` placeholder to request high resolution tiles.
+    pub fn with_retina(mut self, retina: bool) -> Self {
+        self.retina = retina;
+        self
+    }
+
+    /// Sets the tile schema used to calculate `{bbox}` and `{-y}` placeholders.
+    pub fn with_tile_schema(mut self, tile_schema: TileSchema) -> Self {
+        self.tile_schema = Some(tile_schema);
+        self
+    }
+
+    /// Consumes the builder and parses the template.
+    ///
+    /// Returns an error if the template contains unknown or unclosed placeholders, or if the
+    /// placeholders require settings that were not given.
+    pub fn build(self) -> Result<TileUrlTemplate, GalileoError> {
+        let parts = parse(&self.template)?;
+
+        if parts.contains(&TemplatePart::Subdomain) && self.subdomains.is_empty() {
+            return Err(GalileoError::Configuration(
+                "url template with {s} placeholder requires subdomains".into(),
+            ));
+        }
+
+        if parts.contains(&TemplatePart::Bbox) && self.tile_schema.is_none() {
+            return Err(GalileoError::Configuration(
+                "url template with {bbox} placeholder requires a tile schema".into(),
+            ));
+        }
+
+        Ok(TileUrlTemplate {
+            parts,
+            subdomains: self.subdomains,
+            retina_suffix: if self.retina { "@2x" } else { "" },
+            tile_schema: self.tile_schema,
+        })
+    }
+}
+
+impl TileUrlTemplate {
+    /// Initializes a builder for the template.
+    pub fn builder(template: impl Into<String>) -> TileUrlTemplateBuilder {
+        TileUrlTemplateBuilder::new(template)
+    }
+
+    /// Parses the template that does not require any additional settings.
+    pub fn new(template: impl Into<String>) -> Result<Self, GalileoError> {
+        Self::builder(template).build()
+    }
+
+    /// Returns the url of the tile with the given index.
+    pub fn url(&self, index: &TileIndex) -> String {
+        let mut url = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(text) => url += text,
+                TemplatePart::Z => write_value(&mut url, index.z),
+                TemplatePart::X => write_value(&mut url, index.x),
+                TemplatePart::Y => write_value(&mut url, index.y),
+                TemplatePart::InvertedY => {
+                    if let Some(y) = self.inverted_y(index) {
+                        write_value(&mut url, y);
+                    }
+                }
+                TemplatePart::Subdomain => url += self.subdomain(index),
+                TemplatePart::Quadkey => url += &quadkey(index),
+                TemplatePart::Bbox => {
+                    if let Some(bbox) = self.bbox(index) {
+                        write_value(
+                            &mut url,
+                            format_args!(
+                                "{},{},{},{}",
+                                bbox.x_min(),
+                                bbox.y_min(),
+                                bbox.x_max(),
+                                bbox.y_max()
+                            ),
+                        );
+                    }
+                }
+                TemplatePart::Retina => url += self.retina_suffix,
+            }
+        }
+
+        url
+    }
+
+    /// Converts the template into a url source that can be used by tile layers.
+    ///
+    /// ```
+    /// use galileo::layer::data_provider::TileUrlTemplate;
+    /// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+    ///
+    /// let template = TileUrlTemplate::new("https://tile.openstreetmap.org/{z}/{x}/{y}.png")?;
+    /// let layer = RasterTileLayerBuilder::new_rest(template.into_url_source()).build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn into_url_source(self) -> impl UrlSource<TileIndex> {
+        move |index: &TileIndex| self.url(index)
+    }
+
+    /// Y index counted from the bottom. Without a tile schema, the standard web mercator grid is
+    /// assumed. Returns `None` if the inverted index does not fit into `i32` at the tile level.
+    fn inverted_y(&self, index: &TileIndex) -> Option<i32> {
+        self.tile_schema
+            .as_ref()
+            .and_then(|schema| schema.flip_y(*index))
+            .or_else(|| {
+                let rows = 1i64.checked_shl(index.z).filter(|rows| *rows > 0)?;
+                i32::try_from(rows - 1 - index.y as i64).ok()
+            })
+    }
+
+    fn subdomain(&self, index: &TileIndex) -> &str {
+        let position = (index.x as i64 + index.y as i64).rem_euclid(self.subdomains.len() as i64);
+        &self.subdomains[position as usize]
+    }
+
+    fn bbox(&self, index: &TileIndex) -> Option<Rect> {
+        self.tile_schema.as_ref()?.tile_bbox(index.into_wrapping())
+    }
+}
+
+fn write_value(url: &mut String, value: impl std::fmt::Display) {
+    // Writing into a string never fails.
+    let _ = write!(url, "{value}");
+}
+
+fn parse(template: &str) -> Result<Vec<TemplatePart>, GalileoError> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(TemplatePart::Text(rest[..start].to_string()));
+        }
+
+        let Some(length) = rest[start..].find('}') else {
+            return Err(GalileoError::Configuration(format!(
+                "unclosed placeholder in url template: {template}"
+            )));
+        };
+
+        let name = &rest[start + 1..start + length];
+        let Some(part) = TemplatePart::from_placeholder(name) else {
+            return Err(GalileoError::Configuration(format!(
+                "unknown placeholder {{{name}}} in url template: {template}"
+            )));
+        };
+
+        parts.push(part);
+        rest = &rest[start + length + 1..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Text(rest.to_string()));
+    }
+
+    Ok(parts)
+}
+
+fn quadkey(index: &TileIndex) -> String {
+    (1..=index.z)
+        .rev()
+        .map(|level| {
+            // Tile indices cannot have bits above the 31st, so they are all zeros at higher levels.
+            let mask = 1i32.checked_shl(level - 1).unwrap_or(0);
+            let mut digit = b'0';
+            if index.x & mask != 0 {
+                digit += 1;
+            }
+            if index.y & mask != 0 {
+                digit += 2;
+            }
+            digit as char
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use galileo_types::cartesian::Point2;
+    use galileo_types::geo::Crs;
+    use insta::assert_compact_debug_snapshot;
+
+    use super::*;
+    use crate::lod::Lod;
+    use crate::tile_schema::VerticalDirection;
+
+    #[test]
+    fn substitutes_placeholders() {
+        let template =
+            TileUrlTemplate::builder("https://{s}.example.com/{z}/{x}/{y}/{-y}{r}.png?q={quadkey}")
+                .with_subdomains(["a", "b", "c"])
+                .with_retina(true)
+                .build()
+                .unwrap();
+
+        assert_eq!(
+            template.url(&TileIndex::new(3, 5, 3)),
+            "https://c.example.com/3/3/5/2@2x.png?q=213"
+        );
+        assert_eq!(
+            template.url(&TileIndex::new(0, 0, 0)),
+            "https://a.example.com/0/0/0/0@2x.png?q="
+        );
+    }
+
+    #[test]
+    fn quadkey_at_deep_levels() {
+        assert_eq!(quadkey(&TileIndex::new(3, 5, 3)), "213");
+
+        let key = quadkey(&TileIndex::new(i32::MAX, 1, 40));
+        assert_eq!(key.len(), 40);
+        assert_eq!(&key[..9], "000000000");
+        assert_eq!(&key[9..], "1111111111111111111111111111113");
+    }
+
+    #[test]
+    fn inverted_y_at_deep_levels() {
+        let template = TileUrlTemplate::new("{z}/{-y}").unwrap();
+
+        assert_eq!(template.url(&TileIndex::new(0, 0, 31)), "31/2147483647");
+        assert_eq!(template.url(&TileIndex::new(0, i32::MAX, 31)), "31/0");
+        assert_eq!(template.url(&TileIndex::new(0, 0, 32)), "32/");
+        assert_eq!(template.url(&TileIndex::new(0, 0, 70)), "70/");
+    }
+
+    #[test]
+    fn bbox_in_schema_crs() {
+        let tile_schema = TileSchema {
+            origin: Point2::new(-1024.0, 1024.0),
+            bounds: Rect::new(-1024.0, -1024.0, 1024.0, 1024.0),
+            lods: [Lod::new(4.0, 0).unwrap()].into(),
+            tile_width: 256,
+            tile_height: 256,
+            y_direction: VerticalDirection::TopToBottom,
+            crs: Crs::EPSG3857,
+        };
+        let template = TileUrlTemplate::builder("wms?BBOX={bbox}&Y={-y}")
+            .with_tile_schema(tile_schema)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            template.url(&TileIndex::new(1, 0, 0)),
+            "wms?BBOX=0,0,1024,1024&Y=1"
+        );
+        assert_eq!(
+            template.url(&TileIndex::new(0, 1, 0)),
+            "wms?BBOX=-1024,-1024,0,0&Y=0"
+        );
+    }
+
+    #[test]
+    fn template_errors() {
+        assert_compact_debug_snapshot!(TileUrlTemplate::new("{z}/{x}/{y"), @r#"Err(Configuration("unclosed placeholder in url template: {z}/{x}/{y"))"#);
+        assert_compact_debug_snapshot!(TileUrlTemplate::new("{z}/{col}/{y}"), @r#"Err(Configuration("unknown placeholder {col} in url template: {z}/{col}/{y}"))"#);
+        assert_compact_debug_snapshot!(TileUrlTemplate::new("{s}/{z}/{x}/{y}"), @r#"Err(Configuration("url template with {s} placeholder requires subdomains"))"#);
+        assert_compact_debug_snapshot!(TileUrlTemplate::new("{bbox}"), @r#"Err(Configuration("url template with {bbox} placeholder requires a tile schema"))"#);
+    }
+}
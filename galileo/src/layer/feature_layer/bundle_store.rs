@@ -34,11 +34,11 @@ pub(super) enum UpdateType {
 }
 
 impl UpdateType {
-    fn update_all(&mut self) {
+    pub(super) fn update_all(&mut self) {
         *self = UpdateType::All;
     }
 
-    fn update_feature(&mut self, id: FeatureId) {
+    pub(super) fn update_feature(&mut self, id: FeatureId) {
         if matches!(self, UpdateType::All) {
             return;
         }
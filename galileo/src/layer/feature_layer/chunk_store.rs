@@ -0,0 +1,426 @@
+use ahash::{HashMap, HashMapExt};
+use galileo_types::cartesian::{CartesianPoint2d, CartesianPoint3d, Point2, Point3, Rect};
+use galileo_types::geo::Crs;
+use galileo_types::geometry::Geom;
+use galileo_types::{Contour, MultiContour, MultiPoint, MultiPolygon, Polygon};
+
+use super::bundle_store::{BundleStore, UpdateType};
+use super::FeatureId;
+use crate::render::{BundleToDraw, Canvas};
+
+/// Chunks with bounding rectangles intersecting the view bounding rectangle magnified by this
+/// factor are kept in memory. Others are unloaded.
+const KEEP_LOADED_FACTOR: f64 = 3.0;
+
+/// Index of a chunk in the chunk grid.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub(super) struct ChunkIndex {
+    x: i64,
+    y: i64,
+}
+
+/// Stores features of a layer split into square chunks of the map, each chunk rendered into its
+/// own set of bundles.
+///
+/// A feature belongs to the chunk containing the center of its bounding rectangle in the map CRS.
+/// The bounding rectangle of a chunk includes all its features, so it can be larger than the
+/// chunk cell.
+pub(super) struct ChunkStore {
+    chunk_size: f64,
+    crs: Option<Crs>,
+    bundle_size_limit: usize,
+    chunks: HashMap<ChunkIndex, Chunk>,
+    feature_chunks: HashMap<FeatureId, ChunkIndex>,
+    required_update: UpdateType,
+}
+
+struct Chunk {
+    features: Vec<(FeatureId, Rect)>,
+    bbox: Rect,
+    /// Set when a feature is removed from the chunk, so the bounding rectangle must be
+    /// recalculated to shrink.
+    bbox_outdated: bool,
+    /// Bundles of the chunk, `None` if the chunk was not rendered yet or was unloaded.
+    bundles: Option<BundleStore>,
+}
+
+impl ChunkStore {
+    pub(super) fn new(bundle_size_limit: usize) -> Self {
+        Self {
+            chunk_size: 0.0,
+            crs: None,
+            bundle_size_limit,
+            chunks: HashMap::new(),
+            feature_chunks: HashMap::new(),
+            required_update: UpdateType::All,
+        }
+    }
+
+    pub(super) fn set_bundle_size_limit(&mut self, limit: usize) {
+        self.bundle_size_limit = limit;
+        for bundles in self.chunks.values_mut().filter_map(|c| c.bundles.as_mut()) {
+            bundles.set_bundle_size_limit(limit);
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.chunks.clear();
+        self.feature_chunks.clear();
+        self.required_update.update_all();
+    }
+
+    /// Marks the feature to be placed into the chunk and rendered again.
+    pub(super) fn reset_feature(&mut self, id: FeatureId) {
+        self.required_update.update_feature(id);
+        if let Some(bundles) = self
+            .feature_chunks
+            .get(&id)
+            .and_then(|index| self.chunks.get_mut(index))
+            .and_then(|chunk| chunk.bundles.as_mut())
+        {
+            bundles.reset_feature(id);
+        }
+    }
+
+    /// Returns features that must be (re)assigned to chunks with [`ChunkStore::assign`]. If the
+    /// chunk size or the CRS of the map changed since the last call, all features must be
+    /// assigned again.
+    pub(super) fn required_update(&mut self, chunk_size: f64, crs: &Crs) -> UpdateType {
+        if self.chunk_size != chunk_size || self.crs.as_ref() != Some(crs) {
+            self.clear();
+            self.chunk_size = chunk_size;
+            self.crs = Some(crs.clone());
+        }
+
+        std::mem::take(&mut self.required_update)
+    }
+
+    /// Places the feature into the chunk by its projected bounding rectangle. If the `bbox` is
+    /// `None`, the feature is removed from the store.
+    pub(super) fn assign(&mut self, id: FeatureId, bbox: Option<Rect>) {
+        if let Some(index) = self.feature_chunks.remove(&id) {
+            if let Some(chunk) = self.chunks.get_mut(&index) {
+                chunk.features.retain(|(feature_id, _)| *feature_id != id);
+                if chunk.features.is_empty() {
+                    self.chunks.remove(&index);
+                } else {
+                    chunk.bbox_outdated = true;
+                    if let Some(bundles) = &mut chunk.bundles {
+                        bundles.reset_feature(id);
+                    }
+                }
+            }
+        }
+
+        let Some(bbox) = bbox else {
+            return;
+        };
+
+        let center = bbox.center();
+        let index = ChunkIndex {
+            x: (center.x() / self.chunk_size).floor() as i64,
+            y: (center.y() / self.chunk_size).floor() as i64,
+        };
+        let chunk = self.chunks.entry(index).or_insert_with(|| Chunk {
+            features: vec![],
+            bbox,
+            bbox_outdated: false,
+            bundles: None,
+        });
+        chunk.features.push((id, bbox));
+        chunk.bbox = chunk.bbox.merge(bbox);
+        if let Some(bundles) = &mut chunk.bundles {
+            bundles.reset_feature(id);
+        }
+
+        self.feature_chunks.insert(id, index);
+    }
+
+    /// Returns indices of chunks intersecting the given bounding rectangle and unloads the chunks
+    /// far from it. If `bbox` is `None`, all chunks are returned.
+    pub(super) fn visible_chunks(&mut self, bbox: Option<Rect>) -> Vec<ChunkIndex> {
+        let Some(bbox) = bbox else {
+            for chunk in self.chunks.values_mut() {
+                chunk.update_bbox();
+            }
+            return self.chunks.keys().copied().collect();
+        };
+
+        let keep_loaded = bbox.magnify(KEEP_LOADED_FACTOR);
+        let mut visible = vec![];
+        for (index, chunk) in &mut self.chunks {
+            chunk.update_bbox();
+            if chunk.bbox.intersects(bbox) {
+                visible.push(*index);
+            } else if !chunk.bbox.intersects(keep_loaded) {
+                chunk.bundles = None;
+            }
+        }
+
+        visible
+    }
+
+    /// Renders features of the chunk that were not rendered yet with the `render_feature`
+    /// function.
+    pub(super) fn update_chunk(
+        &mut self,
+        index: ChunkIndex,
+        mut render_feature: impl FnMut(FeatureId, &mut BundleStore),
+    ) {
+        let Some(chunk) = self.chunks.get_mut(&index) else {
+            return;
+        };
+
+        let bundle_size_limit = self.bundle_size_limit;
+        let bundles = chunk
+            .bundles
+            .get_or_insert_with(|| BundleStore::new(bundle_size_limit));
+        match bundles.required_update() {
+            UpdateType::All => {
+                for &(id, _) in &chunk.features {
+                    render_feature(id, bundles);
+                }
+            }
+            UpdateType::Selected(ids) => {
+                for id in ids {
+                    // Feature might have been moved to another chunk.
+                    if self.feature_chunks.get(&id) == Some(&index) {
+                        render_feature(id, bundles);
+                    }
+                }
+            }
+            UpdateType::None => {}
+        }
+    }
+
+    pub(super) fn pack(&mut self, indices: &[ChunkIndex], canvas: &dyn Canvas) {
+        for index in indices {
+            if let Some(bundles) = self
+                .chunks
+                .get_mut(index)
+                .and_then(|chunk| chunk.bundles.as_mut())
+            {
+                bundles.pack(canvas);
+            }
+        }
+    }
+
+    pub(super) fn packed(&self, indices: &[ChunkIndex]) -> Vec<BundleToDraw<'_>> {
+        indices
+            .iter()
+            .filter_map(|index| self.chunks.get(index)?.bundles.as_ref())
+            .flat_map(|bundles| bundles.packed())
+            .collect()
+    }
+}
+
+impl Chunk {
+    fn update_bbox(&mut self) {
+        if !self.bbox_outdated {
+            return;
+        }
+
+        if let Some(bbox) = self
+            .features
+            .iter()
+            .map(|(_, bbox)| *bbox)
+            .reduce(|a, b| a.merge(b))
+        {
+            self.bbox = bbox;
+        }
+        self.bbox_outdated = false;
+    }
+}
+
+/// Bounding rectangles of the features of a layer projected into the map CRS.
+///
+/// The rectangles are shared by the chunk stores of all levels of detail of the layer, so that the
+/// features are not projected again only to be assigned to chunks.
+#[derive(Default)]
+pub(super) struct ProjectedBBoxes {
+    crs: Option<Crs>,
+    bboxes: HashMap<FeatureId, Option<Rect>>,
+}
+
+impl ProjectedBBoxes {
+    /// Drops all rectangles if they were calculated for a different CRS.
+    pub(super) fn set_crs(&mut self, crs: &Crs) {
+        if self.crs.as_ref() != Some(crs) {
+            self.bboxes.clear();
+            self.crs = Some(crs.clone());
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.bboxes.clear();
+    }
+
+    /// Drops the rectangle of the feature, so it is calculated again on the next access.
+    pub(super) fn reset_feature(&mut self, id: FeatureId) {
+        self.bboxes.remove(&id);
+    }
+
+    /// Returns the rectangle of the feature, calculating it with `calculate` if it is not known
+    /// yet.
+    pub(super) fn get_or_calculate(
+        &mut self,
+        id: FeatureId,
+        calculate: impl FnOnce() -> Option<Rect>,
+    ) -> Option<Rect> {
+        *self.bboxes.entry(id).or_insert_with(calculate)
+    }
+}
+
+/// Bounding rectangle of the geometry projected into the map CRS.
+pub(super) fn projected_bbox(geometry: &Geom<Point3>) -> Option<Rect> {
+    let points: Box<dyn Iterator<Item = Point3> + '_> = match geometry {
+        Geom::Point(point) => Box::new(std::iter::once(*point)),
+        Geom::MultiPoint(points) => Box::new(points.iter_points()),
+        Geom::Contour(contour) => Box::new(contour.iter_points()),
+        Geom::MultiContour(contours) => Box::new(
+            contours
+                .contours()
+                .flat_map(|contour| contour.iter_points()),
+        ),
+        Geom::Polygon(polygon) => Box::new(polygon.outer_contour().iter_points()),
+        Geom::MultiPolygon(polygons) => Box::new(
+            polygons
+                .polygons()
+                .flat_map(|polygon| polygon.outer_contour().iter_points()),
+        ),
+    };
+
+    Rect::from_points(points.map(|point| Point2::new(point.x(), point.y())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moved_feature_is_reassigned_to_other_chunk() {
+        let mut store = ChunkStore::new(1000);
+        let id = FeatureId::next();
+        assert!(matches!(
+            store.required_update(10.0, &Crs::EPSG3857),
+            UpdateType::All
+        ));
+
+        store.assign(id, Some(Rect::new(1.0, 1.0, 2.0, 2.0)));
+        store.assign(id, Some(Rect::new(21.0, 1.0, 22.0, 2.0)));
+
+        let visible = store.visible_chunks(Some(Rect::new(0.0, 0.0, 9.0, 9.0)));
+        assert!(visible.is_empty());
+
+        let visible = store.visible_chunks(Some(Rect::new(20.0, 0.0, 29.0, 9.0)));
+        assert_eq!(visible.len(), 1);
+
+        let mut rendered = vec![];
+        store.update_chunk(visible[0], |id, _| rendered.push(id));
+        assert_eq!(rendered, vec![id]);
+    }
+
+    #[test]
+    fn chunk_bbox_shrinks_when_feature_is_moved_out() {
+        let mut store = ChunkStore::new(1000);
+        store.required_update(10.0, &Crs::EPSG3857);
+        let small = FeatureId::next();
+        let large = FeatureId::next();
+
+        store.assign(small, Some(Rect::new(1.0, 1.0, 2.0, 2.0)));
+        store.assign(large, Some(Rect::new(-20.0, -20.0, 25.0, 25.0)));
+        assert_eq!(
+            store
+                .visible_chunks(Some(Rect::new(20.0, 20.0, 21.0, 21.0)))
+                .len(),
+            1
+        );
+
+        store.assign(large, Some(Rect::new(31.0, 1.0, 32.0, 2.0)));
+        assert!(store
+            .visible_chunks(Some(Rect::new(20.0, 20.0, 21.0, 21.0)))
+            .is_empty());
+        assert_eq!(
+            store
+                .visible_chunks(Some(Rect::new(0.0, 0.0, 3.0, 3.0)))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn removed_feature_is_not_rendered() {
+        let mut store = ChunkStore::new(1000);
+        store.required_update(10.0, &Crs::EPSG3857);
+        let kept = FeatureId::next();
+        let removed = FeatureId::next();
+
+        store.assign(kept, Some(Rect::new(1.0, 1.0, 2.0, 2.0)));
+        store.assign(removed, Some(Rect::new(5.0, 5.0, 8.0, 8.0)));
+        store.assign(removed, None);
+
+        assert!(store
+            .visible_chunks(Some(Rect::new(6.0, 6.0, 7.0, 7.0)))
+            .is_empty());
+
+        let visible = store.visible_chunks(None);
+        assert_eq!(visible.len(), 1);
+        let mut rendered = vec![];
+        store.update_chunk(visible[0], |id, _| rendered.push(id));
+        assert_eq!(rendered, vec![kept]);
+
+        store.assign(kept, None);
+        assert!(store.visible_chunks(None).is_empty());
+    }
+
+    #[test]
+    fn updated_feature_is_rendered_again() {
+        let mut store = ChunkStore::new(1000);
+        store.required_update(10.0, &Crs::EPSG3857);
+        let first = FeatureId::next();
+        let second = FeatureId::next();
+
+        store.assign(first, Some(Rect::new(1.0, 1.0, 2.0, 2.0)));
+        store.assign(second, Some(Rect::new(3.0, 3.0, 4.0, 4.0)));
+        let visible = store.visible_chunks(None);
+        let mut rendered = vec![];
+        store.update_chunk(visible[0], |id, _| rendered.push(id));
+        assert_eq!(rendered.len(), 2);
+
+        store.reset_feature(second);
+        assert!(matches!(
+            store.required_update(10.0, &Crs::EPSG3857),
+            UpdateType::Selected(ids) if ids.len() == 1 && ids.contains(&second)
+        ));
+        store.assign(second, Some(Rect::new(4.0, 4.0, 5.0, 5.0)));
+
+        let mut rendered = vec![];
+        store.update_chunk(visible[0], |id, _| rendered.push(id));
+        assert_eq!(rendered, vec![second]);
+    }
+
+    #[test]
+    fn projected_bboxes_are_calculated_once_per_crs() {
+        let mut bboxes = ProjectedBBoxes::default();
+        let id = FeatureId::next();
+        let calculated = std::cell::Cell::new(0);
+        let calculate = || {
+            calculated.set(calculated.get() + 1);
+            Some(Rect::new(0.0, 0.0, 1.0, 1.0))
+        };
+
+        bboxes.set_crs(&Crs::EPSG3857);
+        bboxes.get_or_calculate(id, calculate);
+        bboxes.set_crs(&Crs::EPSG3857);
+        bboxes.get_or_calculate(id, calculate);
+        assert_eq!(calculated.get(), 1);
+
+        bboxes.reset_feature(id);
+        bboxes.get_or_calculate(id, calculate);
+        assert_eq!(calculated.get(), 2);
+
+        bboxes.set_crs(&Crs::WGS84);
+        bboxes.get_or_calculate(id, calculate);
+        assert_eq!(calculated.get(), 3);
+    }
+}
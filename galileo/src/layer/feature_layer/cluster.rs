@@ -0,0 +1,548 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use ahash::{HashMap, HashMapExt};
+use galileo_types::cartesian::{CartesianPoint2d, Point2, Point3};
+use galileo_types::geo::Crs;
+use galileo_types::geometry::Geom;
+use galileo_types::geometry_type::CartesianSpace2d;
+use maybe_sync::{MaybeSend, MaybeSync};
+
+use super::feature_store::VecFeatureStore;
+use super::{Feature, FeatureId, FeatureLayer, FeatureStore, Symbol};
+use crate::layer::attribution::Attribution;
+use crate::layer::Layer;
+use crate::messenger::Messenger;
+use crate::render::render_bundle::RenderBundle;
+use crate::render::Canvas;
+use crate::view::MapView;
+
+/// Default size of the cluster cell in pixels.
+const DEFAULT_CLUSTER_RADIUS: f64 = 40.0;
+
+/// Properties of a [`Cluster`] accumulated from the features it consists of.
+///
+/// The aggregate of a cluster is created with [`Default::default`], and then every feature of the
+/// cluster is added to it. Implement this trait to make data of the features (like the sum of
+/// some value or the most severe category) available to the symbol rendering the clusters.
+pub trait ClusterAggregate<F>: Default {
+    /// Adds the feature to the aggregate.
+    fn add(&mut self, feature: &F);
+}
+
+impl<F> ClusterAggregate<F> for () {
+    fn add(&mut self, _feature: &F) {}
+}
+
+/// A group of features of a [`ClusterLayer`] displayed as a single point.
+///
+/// A cluster is a [`Feature`] with the point geometry, so it can be rendered with any point
+/// [`Symbol`], but a symbol implemented for `Cluster<A>` can also use the number of features in the
+/// cluster and their aggregated properties. See [`ClusterSymbol`](super::symbol::ClusterSymbol).
+#[derive(Debug, Clone)]
+pub struct Cluster<A> {
+    position: Point2,
+    members: Vec<FeatureId>,
+    aggregate: A,
+    level: usize,
+}
+
+impl<A> Cluster<A> {
+    /// Position of the cluster in the CRS of the layer. This is the average position of all the
+    /// features in the cluster.
+    pub fn position(&self) -> Point2 {
+        self.position
+    }
+
+    /// Number of features in the cluster.
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Ids of the features in the cluster.
+    pub fn features(&self) -> &[FeatureId] {
+        &self.members
+    }
+
+    /// Aggregated properties of the features in the cluster.
+    pub fn aggregate(&self) -> &A {
+        &self.aggregate
+    }
+
+    fn recalculate<F>(&mut self, features: &impl FeatureStore<F>)
+    where
+        F: Feature,
+        F::Geom: CartesianPoint2d<Num = f64>,
+        A: ClusterAggregate<F>,
+    {
+        let mut aggregate = A::default();
+        let (mut x, mut y) = (0.0, 0.0);
+        let mut count = 0.0;
+        for feature in self.members.iter().filter_map(|id| features.get(*id)) {
+            let position = feature_position(feature);
+            x += position.x();
+            y += position.y();
+            count += 1.0;
+            aggregate.add(feature);
+        }
+
+        if count > 0.0 {
+            self.position = Point2::new(x / count, y / count);
+        }
+        self.aggregate = aggregate;
+    }
+}
+
+impl<A> Feature for Cluster<A> {
+    type Geom = Point2;
+
+    fn geometry(&self) -> &Self::Geom {
+        &self.position
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+enum ClusterKey {
+    Cell(i64, i64),
+    Feature(FeatureId),
+}
+
+/// Layer that displays point features grouped into clusters.
+///
+/// The layer splits the map into a square grid for each of the given resolutions, and all the
+/// features in the same cell are displayed as a single [`Cluster`]. The size of a cell is
+/// the cluster radius (40 pixels by default, see [`ClusterLayer::with_cluster_radius`]) at the
+/// resolution of the level. At resolutions lower than the lowest given one, every feature is
+/// displayed as a separate cluster with a single feature.
+///
+/// The clusters are updated incrementally when features are added to or removed from the layer,
+/// so only the clusters containing the changed feature are rendered again.
+///
+/// All features of the layer must have point geometries in the CRS of the layer.
+///
+/// ```
+/// use galileo::Color;
+/// use galileo::galileo_types::cartesian::{CartesianPoint2d, Point2};
+/// use galileo::galileo_types::geo::Crs;
+/// use galileo::layer::feature_layer::symbol::ClusterSymbol;
+/// use galileo::layer::feature_layer::{ClusterAggregate, ClusterLayer};
+///
+/// #[derive(Default)]
+/// struct MaxX(f64);
+///
+/// impl ClusterAggregate<Point2> for MaxX {
+///     fn add(&mut self, point: &Point2) {
+///         self.0 = self.0.max(point.x());
+///     }
+/// }
+///
+/// let points = vec![Point2::new(0.0, 0.0), Point2::new(10.0, 10.0), Point2::new(100.0, 100.0)];
+/// let layer = ClusterLayer::<_, MaxX, _>::new(
+///     points,
+///     ClusterSymbol::new(Color::RED, 10.0, 40.0),
+///     Crs::EPSG3857,
+///     &[10.0, 1.0],
+/// );
+///
+/// let cluster = layer.clusters(20.0).next().unwrap();
+/// assert_eq!(cluster.count(), 3);
+/// assert_eq!(cluster.aggregate().0, 100.0);
+///
+/// // At resolution 10 the cluster splits into two.
+/// assert_eq!(layer.expansion_resolution(cluster), Some(10.0));
+/// assert_eq!(layer.clusters(10.0).count(), 2);
+/// ```
+pub struct ClusterLayer<F, A, S> {
+    features: VecFeatureStore<F>,
+    symbol: Arc<S>,
+    crs: Crs,
+    resolutions: Vec<f64>,
+    radius: f64,
+    levels: Vec<ClusterLevel<A, S>>,
+    messenger: Option<Box<dyn Messenger>>,
+}
+
+struct ClusterLevel<A, S> {
+    min_resolution: f64,
+    /// Size of the grid cell in the layer CRS, or `None` if features are not clustered at this
+    /// level.
+    cell_size: Option<f64>,
+    clusters: HashMap<ClusterKey, FeatureId>,
+    layer: FeatureLayer<Point2, Cluster<A>, SharedSymbol<S>, CartesianSpace2d>,
+}
+
+impl<F, A, S> ClusterLayer<F, A, S>
+where
+    F: Feature + MaybeSend + MaybeSync + 'static,
+    F::Geom: CartesianPoint2d<Num = f64>,
+    A: ClusterAggregate<F> + MaybeSend + MaybeSync + 'static,
+    S: Symbol<Cluster<A>> + MaybeSend + MaybeSync + 'static,
+{
+    /// Creates a new layer that clusters the `features` at each of the given `resolutions`.
+    pub fn new(features: Vec<F>, symbol: S, crs: Crs, resolutions: &[f64]) -> Self {
+        let mut resolutions = resolutions.to_vec();
+        resolutions.sort_by(|a, b| b.total_cmp(a));
+
+        let mut layer = Self {
+            features: VecFeatureStore::new(features),
+            symbol: Arc::new(symbol),
+            crs,
+            resolutions,
+            radius: DEFAULT_CLUSTER_RADIUS,
+            levels: vec![],
+            messenger: None,
+        };
+        layer.build_levels();
+
+        layer
+    }
+
+    /// Sets the size of the cluster cell in pixels.
+    pub fn with_cluster_radius(mut self, radius: f64) -> Self {
+        self.radius = radius;
+        self.build_levels();
+        self
+    }
+
+    /// Returns a reference to the features of the layer.
+    ///
+    /// Features cannot be edited in place, as it would make the clusters outdated. To change a
+    /// feature, remove it and add it again.
+    pub fn features(&self) -> &dyn FeatureStore<F> {
+        &self.features
+    }
+
+    /// Returns the CRS of the layer.
+    pub fn crs(&self) -> &Crs {
+        &self.crs
+    }
+
+    /// Adds the feature to the layer, updating the clusters it belongs to.
+    pub fn add(&mut self, feature: F) -> FeatureId {
+        let id = self.features.add(feature);
+        if let Some(feature) = self.features.get(id) {
+            for (index, level) in self.levels.iter_mut().enumerate() {
+                level.add(index, id, feature);
+            }
+        }
+
+        self.request_redraw();
+        id
+    }
+
+    /// Removes the feature from the layer, updating the clusters it belonged to.
+    ///
+    /// If the feature with the given id is not in the layer, returns `None`.
+    pub fn remove(&mut self, id: FeatureId) -> Option<F> {
+        let feature = self.features.remove(id)?;
+        for level in &mut self.levels {
+            level.remove(id, &feature, &self.features);
+        }
+
+        self.request_redraw();
+        Some(feature)
+    }
+
+    /// Returns an iterator over the clusters displayed at the given map resolution.
+    pub fn clusters(&self, resolution: f64) -> impl Iterator<Item = &Cluster<A>> + '_ {
+        self.select_level(resolution)
+            .layer
+            .features()
+            .iter()
+            .map(|(_, cluster)| cluster)
+    }
+
+    /// Returns an iterator over the clusters displayed at the given map resolution that are within
+    /// `tolerance` units from the `point`. The `point` is expected to be set in the layer's CRS.
+    pub fn get_clusters_at<'a>(
+        &'a self,
+        point: &'a impl CartesianPoint2d<Num = f64>,
+        resolution: f64,
+        tolerance: f64,
+    ) -> impl Iterator<Item = &'a Cluster<A>> + 'a {
+        self.select_level(resolution)
+            .layer
+            .get_features_at(point, tolerance)
+            .map(|(_, cluster)| cluster)
+    }
+
+    /// Returns the largest map resolution at which the cluster is displayed split into several
+    /// clusters. Zooming the map to this resolution "expands" the cluster.
+    ///
+    /// Returns `None` if the cluster contains a single feature.
+    pub fn expansion_resolution(&self, cluster: &Cluster<A>) -> Option<f64> {
+        if cluster.count() < 2 {
+            return None;
+        }
+
+        for index in cluster.level + 1..self.levels.len() {
+            let level = &self.levels[index];
+            let mut keys = cluster.members.iter().filter_map(|&id| {
+                let feature = self.features.get(id)?;
+                Some(level.key(id, &feature_position(feature)))
+            });
+            let first = keys.next()?;
+            if keys.any(|key| key != first) {
+                return Some(self.levels[index - 1].min_resolution);
+            }
+        }
+
+        None
+    }
+
+    fn build_levels(&mut self) {
+        let levels = self
+            .resolutions
+            .iter()
+            .map(|&resolution| (resolution, Some(resolution * self.radius)))
+            .chain(std::iter::once((0.0, None)));
+
+        self.levels = levels
+            .enumerate()
+            .map(|(index, (min_resolution, cell_size))| {
+                let mut level = ClusterLevel {
+                    min_resolution,
+                    cell_size,
+                    clusters: HashMap::new(),
+                    layer: FeatureLayer::with_lods(
+                        vec![],
+                        SharedSymbol(self.symbol.clone()),
+                        self.crs.clone(),
+                        &[min_resolution],
+                    ),
+                };
+                for (id, feature) in self.features.iter() {
+                    level.add(index, id, feature);
+                }
+
+                level
+            })
+            .collect();
+    }
+
+    fn select_level(&self, resolution: f64) -> &ClusterLevel<A, S> {
+        self.levels
+            .iter()
+            .find(|level| level.min_resolution < resolution)
+            .unwrap_or(&self.levels[self.levels.len() - 1])
+    }
+
+    fn request_redraw(&self) {
+        if let Some(messenger) = &self.messenger {
+            messenger.request_redraw();
+        }
+    }
+}
+
+impl<A, S> ClusterLevel<A, S>
+where
+    A: MaybeSend + MaybeSync + 'static,
+    S: Symbol<Cluster<A>>,
+{
+    fn key(&self, id: FeatureId, position: &Point2) -> ClusterKey {
+        match self.cell_size {
+            Some(size) => ClusterKey::Cell(
+                (position.x() / size).floor() as i64,
+                (position.y() / size).floor() as i64,
+            ),
+            None => ClusterKey::Feature(id),
+        }
+    }
+
+    fn add<F>(&mut self, level: usize, id: FeatureId, feature: &F)
+    where
+        F: Feature,
+        F::Geom: CartesianPoint2d<Num = f64>,
+        A: ClusterAggregate<F>,
+    {
+        let position = feature_position(feature);
+        let key = self.key(id, &position);
+        let cluster_id = match self.clusters.get(&key) {
+            Some(&cluster_id) => {
+                if let Some(cluster) = self.layer.features_mut().get_mut(cluster_id) {
+                    let count = cluster.members.len() as f64;
+                    cluster.position = Point2::new(
+                        (cluster.position.x() * count + position.x()) / (count + 1.0),
+                        (cluster.position.y() * count + position.y()) / (count + 1.0),
+                    );
+                    cluster.members.push(id);
+                    cluster.aggregate.add(feature);
+                }
+
+                cluster_id
+            }
+            None => {
+                let mut aggregate = A::default();
+                aggregate.add(feature);
+                let cluster_id = self.layer.features_mut().add(Cluster {
+                    position,
+                    members: vec![id],
+                    aggregate,
+                    level,
+                });
+                self.clusters.insert(key, cluster_id);
+
+                cluster_id
+            }
+        };
+
+        self.layer.update_feature(cluster_id);
+    }
+
+    fn remove<F>(&mut self, id: FeatureId, feature: &F, features: &VecFeatureStore<F>)
+    where
+        F: Feature + MaybeSend + MaybeSync,
+        F::Geom: CartesianPoint2d<Num = f64>,
+        A: ClusterAggregate<F>,
+    {
+        let key = self.key(id, &feature_position(feature));
+        let Some(&cluster_id) = self.clusters.get(&key) else {
+            return;
+        };
+
+        let store = self.layer.features_mut();
+        let Some(cluster) = store.get_mut(cluster_id) else {
+            return;
+        };
+
+        cluster.members.retain(|member| *member != id);
+        if cluster.members.is_empty() {
+            store.remove(cluster_id);
+            self.clusters.remove(&key);
+        } else {
+            cluster.recalculate(features);
+        }
+
+        self.layer.update_feature(cluster_id);
+    }
+}
+
+fn feature_position<F>(feature: &F) -> Point2
+where
+    F: Feature,
+    F::Geom: CartesianPoint2d<Num = f64>,
+{
+    let geometry = feature.geometry();
+    Point2::new(geometry.x(), geometry.y())
+}
+
+/// Symbol of the cluster layer shared by the feature layers of all cluster levels.
+struct SharedSymbol<S>(Arc<S>);
+
+impl<A, S> Symbol<Cluster<A>> for SharedSymbol<S>
+where
+    S: Symbol<Cluster<A>>,
+{
+    fn render(
+        &self,
+        feature: &Cluster<A>,
+        geometry: &Geom<Point3>,
+        min_resolution: f64,
+        bundle: &mut RenderBundle,
+    ) {
+        self.0.render(feature, geometry, min_resolution, bundle)
+    }
+}
+
+impl<F, A, S> Layer for ClusterLayer<F, A, S>
+where
+    F: Feature + MaybeSend + MaybeSync + 'static,
+    F::Geom: CartesianPoint2d<Num = f64>,
+    A: ClusterAggregate<F> + MaybeSend + MaybeSync + 'static,
+    S: Symbol<Cluster<A>> + MaybeSend + MaybeSync + 'static,
+{
+    fn render(&self, view: &MapView, canvas: &mut dyn Canvas) {
+        self.select_level(view.resolution())
+            .layer
+            .render(view, canvas);
+    }
+
+    fn prepare(&self, _view: &MapView) {
+        // do nothing
+    }
+
+    fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
+        self.messenger = Some(messenger);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::feature_layer::symbol::CirclePointSymbol;
+    use crate::Color;
+
+    #[derive(Default)]
+    struct SumX(f64);
+
+    impl ClusterAggregate<Point2> for SumX {
+        fn add(&mut self, point: &Point2) {
+            self.0 += point.x();
+        }
+    }
+
+    fn counts(
+        layer: &ClusterLayer<Point2, SumX, CirclePointSymbol>,
+        resolution: f64,
+    ) -> Vec<usize> {
+        let mut counts: Vec<_> = layer.clusters(resolution).map(|c| c.count()).collect();
+        counts.sort();
+        counts
+    }
+
+    #[test]
+    fn clusters_are_updated_incrementally() {
+        let mut layer: ClusterLayer<_, SumX, _> = ClusterLayer::new(
+            vec![
+                Point2::new(0.0, 0.0),
+                Point2::new(10.0, 10.0),
+                Point2::new(100.0, 100.0),
+            ],
+            CirclePointSymbol::new(Color::RED, 10.0),
+            Crs::EPSG3857,
+            &[1.0, 10.0],
+        );
+
+        assert_eq!(counts(&layer, 20.0), vec![3]);
+        assert_eq!(counts(&layer, 5.0), vec![1, 2]);
+        assert_eq!(counts(&layer, 0.5), vec![1, 1, 1]);
+
+        let cluster = layer.clusters(20.0).next().unwrap();
+        assert_eq!(cluster.aggregate().0, 110.0);
+        assert_eq!(layer.expansion_resolution(cluster), Some(10.0));
+
+        let cluster = layer.clusters(5.0).find(|c| c.count() == 2).unwrap();
+        assert_eq!(cluster.position(), Point2::new(5.0, 5.0));
+        assert_eq!(layer.expansion_resolution(cluster), Some(1.0));
+
+        let added = layer.add(Point2::new(20.0, 20.0));
+        assert_eq!(counts(&layer, 5.0), vec![1, 3]);
+        assert_eq!(layer.clusters(20.0).next().unwrap().aggregate().0, 130.0);
+
+        layer.remove(added);
+        let (far_id, _) = layer
+            .features()
+            .iter()
+            .find(|(_, point)| point.x() == 100.0)
+            .unwrap();
+        layer.remove(far_id);
+        assert_eq!(counts(&layer, 5.0), vec![2]);
+        assert_eq!(counts(&layer, 0.5), vec![1, 1]);
+
+        let cluster = layer.clusters(20.0).next().unwrap();
+        assert_eq!(cluster.aggregate().0, 10.0);
+        assert_eq!(layer.expansion_resolution(cluster), Some(1.0));
+    }
+}
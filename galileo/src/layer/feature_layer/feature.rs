@@ -5,6 +5,8 @@ use galileo_types::geometry_type::GeometryType;
 use galileo_types::impls::{Contour, MultiContour, MultiPolygon, Polygon};
 use galileo_types::Disambig;
 
+use crate::layer::vector_tile_layer::style::PropertyValue;
+
 /// A feature is an arbitrary geographic object.
 pub trait Feature {
     /// Type of the geometry the feature returns.
@@ -13,6 +15,15 @@ pub trait Feature {
     fn geometry(&self) -> &Self::Geom;
 }
 
+/// A feature with named properties, that can be used to choose how the feature is rendered by data-driven symbols,
+/// like [`RuleBasedSymbol`](super::symbol::RuleBasedSymbol).
+pub trait FeatureProperties {
+    /// Type of the values of the properties.
+    type Value: PropertyValue + ?Sized;
+    /// Returns the value of the property with the given name, or `None` if the feature doesn't have the property.
+    fn get_property(&self, name: &str) -> Option<&Self::Value>;
+}
+
 macro_rules! impl_feature {
     ($geom:ident) => {
         impl Feature for $geom {
@@ -1,4 +1,7 @@
-use crate::layer::feature_layer::feature::Feature;
+use geojson::JsonValue;
+
+use crate::layer::feature_layer::feature::{Feature, FeatureProperties};
+use crate::layer::vector_tile_layer::style::PropertyValue;
 
 impl Feature for geojson::Feature {
     type Geom = geojson::Geometry;
@@ -9,3 +12,34 @@ impl Feature for geojson::Feature {
             .expect("GeoJSON Feature has no geometry")
     }
 }
+
+impl FeatureProperties for geojson::Feature {
+    type Value = JsonValue;
+
+    fn get_property(&self, name: &str) -> Option<&Self::Value> {
+        self.properties.as_ref()?.get(name)
+    }
+}
+
+impl PropertyValue for JsonValue {
+    fn eq_str(&self, value: &str) -> bool {
+        match self {
+            JsonValue::String(s) => s == value,
+            JsonValue::Number(n) => value.parse::<f64>().ok() == n.as_f64(),
+            JsonValue::Bool(b) => value.parse::<bool>() == Ok(*b),
+            JsonValue::Null | JsonValue::Array(_) | JsonValue::Object(_) => false,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        JsonValue::as_f64(self)
+    }
+
+    fn text(&self) -> String {
+        match self {
+            JsonValue::String(s) => s.clone(),
+            JsonValue::Null => String::new(),
+            other => other.to_string(),
+        }
+    }
+}
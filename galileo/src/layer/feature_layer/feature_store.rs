@@ -1,7 +1,14 @@
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use ahash::{HashMap, HashMapExt};
+use galileo_types::cartesian::{NewCartesianPoint2d, Point2, Rect};
+use galileo_types::geo::impls::projection::IdentityProjection;
+use galileo_types::geometry::{CartesianGeometry2d, Geometry};
+use galileo_types::geometry_type::{CartesianSpace2d, CartesianSpace3d, GeoSpace2d};
 use maybe_sync::{MaybeSend, MaybeSync};
+use num_traits::AsPrimitive;
+
+use super::{Feature, RTreeFeatureStore};
 
 /// Unique identifier of a feature in a feature layer.
 ///
@@ -49,15 +56,85 @@ pub trait FeatureStore<F>: MaybeSend + MaybeSync {
     ///
     /// If the feature with the given id is not in the store, returns `None`.
     fn remove(&mut self, id: FeatureId) -> Option<F>;
+
+    /// Returns an iterator over the features which bounding rectangles intersect `bbox`. The
+    /// `bbox` is set in the coordinates the store is indexed in, usually in the CRS of the layer.
+    ///
+    /// The iterator may also return features outside of `bbox`, so the caller must check the
+    /// geometries of the returned features. Stores without spatial index return all features.
+    fn iter_in_bbox(&self, _bbox: &Rect) -> Box<dyn Iterator<Item = (FeatureId, &F)> + '_> {
+        self.iter()
+    }
+
+    /// Mutable version of [`FeatureStore::iter_in_bbox`].
+    fn iter_mut_in_bbox(
+        &mut self,
+        _bbox: &Rect,
+    ) -> Box<dyn Iterator<Item = (FeatureId, &mut F)> + '_> {
+        self.iter_mut()
+    }
+}
+
+/// Coordinate space of a [`FeatureLayer`](super::FeatureLayer), which defines the feature store the
+/// layer uses by default.
+///
+/// Layers in [`CartesianSpace2d`] keep their features in an [`RTreeFeatureStore`], so that
+/// [`FeatureLayer::get_features_at`](super::FeatureLayer::get_features_at) checks only the
+/// features near the point. Layers in other spaces store features in a plain list.
+pub trait DefaultFeatureStore<P, F> {
+    /// Creates the feature store with the given features.
+    fn feature_store(features: Vec<F>) -> Box<dyn FeatureStore<F>>;
+}
+
+impl<P, F> DefaultFeatureStore<P, F> for CartesianSpace2d
+where
+    P: NewCartesianPoint2d + 'static,
+    P::Num: AsPrimitive<f64>,
+    F: Feature + MaybeSend + MaybeSync + 'static,
+    F::Geom: Geometry<Point = P>,
+{
+    fn feature_store(features: Vec<F>) -> Box<dyn FeatureStore<F>> {
+        Box::new(RTreeFeatureStore::new(features, |feature: &F| {
+            let projection = IdentityProjection::<P, Point2<P::Num>, CartesianSpace2d>::new();
+            let bbox = feature
+                .geometry()
+                .project(&projection)?
+                .bounding_rectangle()?;
+            Some(Rect::new(
+                bbox.x_min().as_(),
+                bbox.y_min().as_(),
+                bbox.x_max().as_(),
+                bbox.y_max().as_(),
+            ))
+        }))
+    }
+}
+
+impl<P, F> DefaultFeatureStore<P, F> for GeoSpace2d
+where
+    F: MaybeSend + MaybeSync + 'static,
+{
+    fn feature_store(features: Vec<F>) -> Box<dyn FeatureStore<F>> {
+        Box::new(VecFeatureStore::new(features))
+    }
 }
 
-pub(super) struct VecFeatureStore<F> {
+impl<P, F> DefaultFeatureStore<P, F> for CartesianSpace3d
+where
+    F: MaybeSend + MaybeSync + 'static,
+{
+    fn feature_store(features: Vec<F>) -> Box<dyn FeatureStore<F>> {
+        Box::new(VecFeatureStore::new(features))
+    }
+}
+
+pub(crate) struct VecFeatureStore<F> {
     features: Vec<(FeatureId, F)>,
     ids: HashMap<FeatureId, usize>,
 }
 
 impl<F> VecFeatureStore<F> {
-    pub(super) fn new(feature_iter: impl IntoIterator<Item = F>) -> Self {
+    pub(crate) fn new(feature_iter: impl IntoIterator<Item = F>) -> Self {
         let mut features = vec![];
         let mut ids = HashMap::new();
         for (index, feature) in feature_iter.into_iter().enumerate() {
@@ -68,6 +145,31 @@ impl<F> VecFeatureStore<F> {
 
         Self { features, ids }
     }
+
+    /// Returns a mutable iterator over the features with the given ids. Ids that are not in the
+    /// store are skipped.
+    pub(crate) fn iter_mut_by_ids(
+        &mut self,
+        ids: impl IntoIterator<Item = FeatureId>,
+    ) -> impl Iterator<Item = (FeatureId, &mut F)> + '_ {
+        let mut indices: Vec<usize> = ids
+            .into_iter()
+            .filter_map(|id| self.ids.get(&id).copied())
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut rest = &mut self.features[..];
+        let mut offset = 0;
+        indices.into_iter().filter_map(move |index| {
+            let (_, tail) = std::mem::take(&mut rest).split_at_mut(index - offset);
+            let ((id, feature), tail) = tail.split_first_mut()?;
+            rest = tail;
+            offset = index + 1;
+
+            Some((*id, feature))
+        })
+    }
 }
 
 impl<F> FeatureStore<F> for VecFeatureStore<F>
@@ -137,4 +239,18 @@ mod tests {
         assert_eq!(store.get(ids[3]), Some(&3));
         assert_eq!(store.get(ids[4]), Some(&4));
     }
+
+    #[test]
+    fn vec_store_iter_mut_by_ids() {
+        let mut store = VecFeatureStore::new([]);
+        let ids: Vec<_> = (0..5).map(|i| store.add(i)).collect();
+
+        let selected = [ids[3], ids[1], FeatureId::next(), ids[3]];
+        for (_, feature) in store.iter_mut_by_ids(selected) {
+            *feature *= 10;
+        }
+
+        let features: Vec<_> = store.iter().map(|(_, f)| *f).collect();
+        assert_eq!(features, vec![0, 10, 2, 30, 4]);
+    }
 }
@@ -4,6 +4,7 @@ use std::any::Any;
 use std::marker::PhantomData;
 use std::ops::Deref;
 
+use ahash::{HashMap, HashMapExt};
 use galileo_types::cartesian::{
     CartesianPoint2d, NewCartesianPoint2d, NewCartesianPoint3d, Point2, Point3, Rect,
 };
@@ -22,15 +23,21 @@ use crate::messenger::Messenger;
 use crate::render::{Canvas, RenderOptions};
 use crate::view::MapView;
 
+mod cluster;
 mod feature;
 mod feature_store;
+mod rtree_feature_store;
 pub mod symbol;
 
 mod bundle_store;
+mod chunk_store;
 use bundle_store::{BundleStore, UpdateType};
-pub use feature::Feature;
-use feature_store::VecFeatureStore;
-pub use feature_store::{FeatureId, FeatureStore};
+use chunk_store::{projected_bbox, ChunkStore, ProjectedBBoxes};
+pub use cluster::{Cluster, ClusterAggregate, ClusterLayer};
+pub use feature::{Feature, FeatureProperties};
+pub(crate) use feature_store::VecFeatureStore;
+pub use feature_store::{DefaultFeatureStore, FeatureId, FeatureStore};
+pub use rtree_feature_store::RTreeFeatureStore;
 pub use symbol::Symbol;
 
 /// Feature layers render a set of [features](Feature) using [symbols](Symbol).
@@ -57,6 +64,8 @@ where
     lods: Vec<Lod>,
     messenger: RwLock<Option<Box<dyn Messenger>>>,
     options: FeatureLayerOptions,
+    chunk_size: Option<f64>,
+    projected_bboxes: Mutex<ProjectedBBoxes>,
 
     space: PhantomData<Space>,
 }
@@ -100,6 +109,7 @@ impl Default for FeatureLayerOptions {
 struct Lod {
     min_resolution: f64,
     bundles: Mutex<BundleStore>,
+    chunks: Mutex<ChunkStore>,
 }
 
 impl Lod {
@@ -107,6 +117,7 @@ impl Lod {
         Self {
             min_resolution,
             bundles: Mutex::new(BundleStore::new(bundle_size_limit)),
+            chunks: Mutex::new(ChunkStore::new(bundle_size_limit)),
         }
     }
 }
@@ -118,15 +129,23 @@ where
     S: Symbol<F>,
 {
     /// Creates a new layer with the given parameters.
-    pub fn new(features: Vec<F>, style: S, crs: Crs) -> Self {
+    ///
+    /// The features are stored in the default store of the layer coordinate space (see
+    /// [`DefaultFeatureStore`]). Use [`FeatureLayer::with_feature_store`] to replace it.
+    pub fn new(features: Vec<F>, style: S, crs: Crs) -> Self
+    where
+        Space: DefaultFeatureStore<P, F>,
+    {
         let options = FeatureLayerOptions::default();
         Self {
-            features: Box::new(VecFeatureStore::new(features)),
+            features: Space::feature_store(features),
             symbol: style,
             crs,
             messenger: RwLock::new(None),
             lods: vec![Lod::new(1.0, options.buffer_size_limit)],
             options,
+            chunk_size: None,
+            projected_bboxes: Default::default(),
             space: Default::default(),
         }
     }
@@ -134,7 +153,10 @@ where
     /// Creates a new layer with specified levels of detail.
     ///
     /// Levels of details specify resolution boundaries at which feature must be rendered separately.
-    pub fn with_lods(features: Vec<F>, style: S, crs: Crs, lods: &[f64]) -> Self {
+    pub fn with_lods(features: Vec<F>, style: S, crs: Crs, lods: &[f64]) -> Self
+    where
+        Space: DefaultFeatureStore<P, F>,
+    {
         let options = FeatureLayerOptions::default();
         let mut lods: Vec<_> = lods
             .iter()
@@ -143,16 +165,28 @@ where
         lods.sort_by(|a, b| b.min_resolution.total_cmp(&a.min_resolution));
 
         Self {
-            features: Box::new(VecFeatureStore::new(features)),
+            features: Space::feature_store(features),
             symbol: style,
             crs,
             messenger: RwLock::new(None),
             lods,
             options,
+            chunk_size: None,
+            projected_bboxes: Default::default(),
             space: Default::default(),
         }
     }
 
+    /// Replaces the features of the layer with the given feature store.
+    ///
+    /// Use this method to store features in a custom store, for example an [`RTreeFeatureStore`]
+    /// indexing features in a CRS other than the CRS of the layer.
+    pub fn with_feature_store(mut self, features: impl FeatureStore<F> + 'static) -> Self {
+        self.features = Box::new(features);
+        self.drop_render_cache();
+        self
+    }
+
     /// Set the rendering options for the layer.
     pub fn with_options(mut self, options: FeatureLayerOptions) -> Self {
         self.options = options;
@@ -160,11 +194,28 @@ where
         for lod in &self.lods {
             let mut store = lod.bundles.lock();
             store.set_bundle_size_limit(options.buffer_size_limit);
+            lod.chunks
+                .lock()
+                .set_bundle_size_limit(options.buffer_size_limit);
         }
 
         self
     }
 
+    /// Splits the features of the layer into square chunks of the given size (in map units), each rendered into
+    /// separate buffers. Only the chunks visible in the map view are rendered, and the buffers of the chunks far
+    /// from the view are released. This makes memory usage and rendering time of layers with millions of features
+    /// depend on the number of features in view rather than on the size of the whole layer.
+    ///
+    /// A feature is placed into the chunk by the center of its bounding rectangle, projected into the map CRS. So the
+    /// chunk size should be chosen so that a few chunks are visible in a typical map view, and most features are
+    /// smaller than a chunk.
+    pub fn with_chunk_size(mut self, chunk_size: f64) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self.drop_render_cache();
+        self
+    }
+
     /// Returns a reference to the feature store.
     pub fn features(&self) -> &dyn FeatureStore<F> {
         &*self.features
@@ -189,8 +240,10 @@ where
 
     /// Marks the feature with the given id to be redrawn on the next render cycles.
     pub fn update_feature(&self, feature_id: FeatureId) {
+        self.projected_bboxes.lock().reset_feature(feature_id);
         for lod in &self.lods {
             lod.bundles.lock().reset_feature(feature_id);
+            lod.chunks.lock().reset_feature(feature_id);
         }
     }
 
@@ -200,9 +253,11 @@ where
     }
 
     fn drop_render_cache(&mut self) {
+        self.projected_bboxes.lock().clear();
         for lod in &mut self.lods {
             let mut bundles = lod.bundles.lock();
             bundles.clear();
+            lod.chunks.lock().clear();
         }
     }
 
@@ -231,6 +286,11 @@ where
         projection: impl Deref<Target = Proj>,
     ) {
         let lod = self.select_lod(view.resolution());
+        if let Some(chunk_size) = self.chunk_size {
+            self.render_chunks(view, canvas, &*projection, lod, chunk_size);
+            return;
+        }
+
         let mut store = lod.bundles.lock();
         let dpi_scale_factor = view.dpi_scale_factor();
 
@@ -280,6 +340,87 @@ where
             },
         );
     }
+
+    fn render_chunks<Proj: Projection<InPoint = P, OutPoint = Point3> + ?Sized>(
+        &self,
+        view: &MapView,
+        canvas: &mut dyn Canvas,
+        projection: &Proj,
+        lod: &Lod,
+        chunk_size: f64,
+    ) {
+        let mut chunks = lod.chunks.lock();
+        let mut bboxes = self.projected_bboxes.lock();
+        bboxes.set_crs(view.crs());
+        let dpi_scale_factor = view.dpi_scale_factor();
+
+        // Geometries projected to calculate the bounding rectangles are kept until the end of the frame to be
+        // rendered without projecting them again. Only the ones in view are kept, as the chunks they are placed into
+        // are sure to be rendered in this frame. Others are projected again if their chunk becomes visible.
+        let view_bbox = view.get_bbox();
+        let mut projected_geometries = HashMap::new();
+        let mut feature_bbox = |id: FeatureId, feature: &F| {
+            bboxes.get_or_calculate(id, || {
+                let projected = feature.geometry().project(projection)?;
+                let bbox = projected_bbox(&projected)?;
+                if view_bbox.is_none_or(|view_bbox| bbox.intersects(view_bbox)) {
+                    projected_geometries.insert(id, projected);
+                }
+
+                Some(bbox)
+            })
+        };
+
+        match chunks.required_update(chunk_size, view.crs()) {
+            UpdateType::All => {
+                for (id, feature) in self.features.iter() {
+                    chunks.assign(id, feature_bbox(id, feature));
+                }
+            }
+            UpdateType::Selected(ids) => {
+                for id in ids {
+                    let bbox = self
+                        .features
+                        .get(id)
+                        .and_then(|feature| feature_bbox(id, feature));
+                    chunks.assign(id, bbox);
+                }
+            }
+            UpdateType::None => {}
+        }
+
+        let visible = chunks.visible_chunks(view_bbox);
+        for &index in &visible {
+            chunks.update_chunk(index, |id, store| {
+                let Some(feature) = self.features.get(id) else {
+                    return;
+                };
+                store.with_bundle(
+                    |bundle| {
+                        let projected = projected_geometries
+                            .remove(&id)
+                            .or_else(|| feature.geometry().project(projection));
+                        if let Some(projected) = projected {
+                            self.symbol
+                                .render(feature, &projected, lod.min_resolution, bundle);
+                        }
+
+                        id
+                    },
+                    dpi_scale_factor,
+                );
+            });
+        }
+
+        chunks.pack(&visible, canvas);
+
+        canvas.draw_bundles(
+            &chunks.packed(&visible),
+            RenderOptions {
+                antialias: self.options.use_antialiasing,
+            },
+        );
+    }
 }
 
 impl<P, F, S> FeatureLayer<P, F, S, GeoSpace2d>
@@ -311,9 +452,9 @@ where
     /// Returns an iterator of features that are within `tolerance` units from the `point`. Note that the `point` is
     /// expected to be set in the layer's CRS.
     ///
-    /// At this moment this method just iterates over all features checking for each one if it is at the point. But
-    /// in future it may be changed into using geo-index to make this more efficient. So this method should be preferred
-    /// to manually checking every feature.
+    /// If the feature store of the layer has a spatial index (like [`RTreeFeatureStore`], which is used by default),
+    /// only the features near the point are checked. Otherwise, the method iterates over all features checking for each one if it is at
+    /// the point. So this method should be preferred to manually checking every feature.
     pub fn get_features_at<'a>(
         &'a self,
         point: &'a impl CartesianPoint2d<Num = P::Num>,
@@ -321,18 +462,18 @@ where
     ) -> impl Iterator<Item = (FeatureId, &'a F)> + 'a
     where
         F::Geom: CartesianGeometry2d<P>,
+        P::Num: AsPrimitive<f64>,
     {
+        let bbox = Self::tolerance_bbox(point, tolerance);
         self.features
-            .iter()
+            .iter_in_bbox(&bbox)
             .filter(move |(_, f)| f.geometry().is_point_inside(point, tolerance))
     }
 
     /// Returns a mutable iterator of features that are within `tolerance` units from the `point`. Note that the `point` is
     /// expected to be set in the layer's CRS.
     ///
-    /// At this moment this method just iterates over all features checking for each one if it is at the point. But
-    /// in future it may be changed into using geo-index to make this more efficient. So this method should be preferred
-    /// to manually checking every feature.
+    /// See [`FeatureLayer::get_features_at`] for details.
     pub fn get_features_at_mut<'a>(
         &'a mut self,
         point: &'a impl CartesianPoint2d<Num = P::Num>,
@@ -340,11 +481,23 @@ where
     ) -> impl Iterator<Item = (FeatureId, &'a mut F)> + 'a
     where
         F::Geom: CartesianGeometry2d<P>,
+        P::Num: AsPrimitive<f64>,
     {
+        let bbox = Self::tolerance_bbox(point, tolerance);
         self.features
-            .iter_mut()
+            .iter_mut_in_bbox(&bbox)
             .filter(move |(_, f)| f.geometry().is_point_inside(point, tolerance))
     }
+
+    fn tolerance_bbox(point: &impl CartesianPoint2d<Num = P::Num>, tolerance: P::Num) -> Rect
+    where
+        P::Num: AsPrimitive<f64>,
+    {
+        let x: f64 = point.x().as_();
+        let y: f64 = point.y().as_();
+        let tolerance: f64 = tolerance.as_();
+        Rect::new(x - tolerance, y - tolerance, x + tolerance, y + tolerance)
+    }
 }
 
 impl<P, F, S> FeatureLayer<P, F, S, GeoSpace2d>
@@ -0,0 +1,371 @@
+use ahash::{HashMap, HashMapExt, HashSet};
+use galileo_types::cartesian::{CartesianPoint2d, Rect};
+use galileo_types::geometry::{CartesianGeometry2d, Geometry};
+use maybe_sync::{MaybeSend, MaybeSync};
+use num_traits::AsPrimitive;
+use parking_lot::RwLock;
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{RTree, AABB};
+
+use super::feature_store::VecFeatureStore;
+use super::{Feature, FeatureId, FeatureStore};
+
+type IndexEntry = GeomWithData<Rectangle<[f64; 2]>, FeatureId>;
+type BboxFn<F> = dyn Fn(&F) -> Option<Rect> + MaybeSend + MaybeSync;
+
+/// [`FeatureStore`] that keeps features in an R-tree index by their bounding rectangles.
+///
+/// The store makes selecting features in an area ([`FeatureLayer::get_features_at`](super::FeatureLayer::get_features_at)
+/// for example) efficient even for layers with hundreds of thousands of features, at the cost
+/// of slower adding and removing of features.
+///
+/// Bounding rectangles of features are calculated by the function given to the store
+/// constructor. Usually this is the bounding rectangle of the feature geometry in the CRS of the
+/// layer (see [`RTreeFeatureStore::new_cartesian`]), but it can also be projected into any other
+/// CRS, in which case all queries to the store must be made in that CRS.
+///
+/// Since a feature can be modified through the references returned by
+/// [`FeatureStore::get_mut`] and [`FeatureStore::iter_mut`] methods, the bounding rectangles of
+/// the features accessed this way are recalculated before the next query to the store, and the
+/// features which rectangles have changed are reindexed.
+///
+/// ```
+/// use galileo::galileo_types::cartesian::{Point2, Rect};
+/// use galileo::layer::feature_layer::{FeatureStore, RTreeFeatureStore};
+///
+/// let store = RTreeFeatureStore::new_cartesian((0..100).map(|i| Point2::new(i as f64, 0.0)));
+///
+/// let found: Vec<_> = store
+///     .iter_in_bbox(&Rect::new(9.5, -1.0, 12.5, 1.0))
+///     .collect();
+/// assert_eq!(found.len(), 3);
+///
+/// let (_, nearest) = store.nearest(&Point2::new(41.8, 3.0), 1).next().unwrap();
+/// assert_eq!(*nearest, Point2::new(42.0, 0.0));
+/// ```
+pub struct RTreeFeatureStore<F> {
+    features: VecFeatureStore<F>,
+    bbox: Box<BboxFn<F>>,
+    index: RwLock<SpatialIndex>,
+}
+
+#[derive(Default)]
+struct SpatialIndex {
+    tree: RTree<IndexEntry>,
+    bboxes: HashMap<FeatureId, Rect>,
+    /// Features that might have been modified since they were indexed.
+    outdated: HashSet<FeatureId>,
+    /// All features might have been modified since they were indexed.
+    all_outdated: bool,
+}
+
+impl<F> RTreeFeatureStore<F>
+where
+    F: MaybeSend + MaybeSync,
+{
+    /// Creates a new store with the given features. The `bbox` function returns the bounding
+    /// rectangle by which the feature is indexed. Features, for which `bbox` returns `None`, are
+    /// stored but never returned by spatial queries.
+    pub fn new(
+        features: impl IntoIterator<Item = F>,
+        bbox: impl Fn(&F) -> Option<Rect> + MaybeSend + MaybeSync + 'static,
+    ) -> Self {
+        let store = Self {
+            features: VecFeatureStore::new(features),
+            bbox: Box::new(bbox),
+            index: RwLock::new(SpatialIndex::default()),
+        };
+        let bboxes = store.bboxes();
+        store.index.write().rebuild(bboxes);
+
+        store
+    }
+
+    /// Creates a new store, that indexes features by the bounding rectangles of their geometries.
+    pub fn new_cartesian<P>(features: impl IntoIterator<Item = F>) -> Self
+    where
+        F: Feature,
+        F::Geom: Geometry<Point = P> + CartesianGeometry2d<P>,
+        P: CartesianPoint2d,
+        P::Num: AsPrimitive<f64>,
+    {
+        Self::new(features, |feature| {
+            let bbox = feature.geometry().bounding_rectangle()?;
+            Some(Rect::new(
+                bbox.x_min().as_(),
+                bbox.y_min().as_(),
+                bbox.x_max().as_(),
+                bbox.y_max().as_(),
+            ))
+        })
+    }
+
+    /// Returns an iterator over features which bounding rectangles contain the `point` or are
+    /// within `tolerance` from it.
+    pub fn iter_at_point(
+        &self,
+        point: &impl CartesianPoint2d<Num = f64>,
+        tolerance: f64,
+    ) -> impl Iterator<Item = (FeatureId, &F)> + '_ {
+        let bbox = Rect::new(
+            point.x() - tolerance,
+            point.y() - tolerance,
+            point.x() + tolerance,
+            point.y() + tolerance,
+        );
+        self.ids_in_bbox(&bbox)
+            .into_iter()
+            .filter_map(|id| Some((id, self.features.get(id)?)))
+    }
+
+    /// Returns `count` indexed features nearest to the `point`, sorted by the distance from
+    /// their bounding rectangles to the point.
+    pub fn nearest(
+        &self,
+        point: &impl CartesianPoint2d<Num = f64>,
+        count: usize,
+    ) -> impl Iterator<Item = (FeatureId, &F)> + '_ {
+        self.update_index();
+
+        let ids: Vec<FeatureId> = self
+            .index
+            .read()
+            .tree
+            .nearest_neighbor_iter(&[point.x(), point.y()])
+            .take(count)
+            .map(|entry| entry.data)
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| Some((id, self.features.get(id)?)))
+    }
+
+    fn ids_in_bbox(&self, bbox: &Rect) -> Vec<FeatureId> {
+        self.update_index();
+
+        let envelope =
+            AABB::from_corners([bbox.x_min(), bbox.y_min()], [bbox.x_max(), bbox.y_max()]);
+        self.index
+            .read()
+            .tree
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|entry| entry.data)
+            .collect()
+    }
+
+    fn bboxes(&self) -> Vec<(FeatureId, Option<Rect>)> {
+        self.features
+            .iter()
+            .map(|(id, feature)| (id, (self.bbox)(feature)))
+            .collect()
+    }
+
+    fn update_index(&self) {
+        {
+            let index = self.index.read();
+            if !index.all_outdated && index.outdated.is_empty() {
+                return;
+            }
+        }
+
+        let mut index = self.index.write();
+        if index.all_outdated {
+            let bboxes = self.bboxes();
+            let changed = bboxes
+                .iter()
+                .filter(|(id, bbox)| index.bboxes.get(id) != bbox.as_ref())
+                .count();
+
+            // Reindexing features one by one is slower than bulk loading of the tree, if many of
+            // them have changed.
+            if changed > bboxes.len() / 2 {
+                index.rebuild(bboxes);
+            } else {
+                for (id, bbox) in bboxes {
+                    index.update(id, bbox);
+                }
+            }
+
+            index.outdated.clear();
+            index.all_outdated = false;
+            return;
+        }
+
+        for id in std::mem::take(&mut index.outdated) {
+            let bbox = self
+                .features
+                .get(id)
+                .and_then(|feature| (self.bbox)(feature));
+            index.update(id, bbox);
+        }
+    }
+}
+
+impl SpatialIndex {
+    fn rebuild(&mut self, bboxes: Vec<(FeatureId, Option<Rect>)>) {
+        self.bboxes = HashMap::new();
+        let mut entries = vec![];
+        for (id, bbox) in bboxes {
+            if let Some(bbox) = bbox {
+                self.bboxes.insert(id, bbox);
+                entries.push(index_entry(id, &bbox));
+            }
+        }
+
+        self.tree = RTree::bulk_load(entries);
+    }
+
+    /// Reindexes the feature if its bounding rectangle has changed.
+    fn update(&mut self, id: FeatureId, bbox: Option<Rect>) {
+        if self.bboxes.get(&id) != bbox.as_ref() {
+            self.remove(id);
+            self.insert(id, bbox);
+        }
+    }
+
+    fn insert(&mut self, id: FeatureId, bbox: Option<Rect>) {
+        if let Some(bbox) = bbox {
+            self.bboxes.insert(id, bbox);
+            self.tree.insert(index_entry(id, &bbox));
+        }
+    }
+
+    fn remove(&mut self, id: FeatureId) {
+        if let Some(bbox) = self.bboxes.remove(&id) {
+            self.tree.remove(&index_entry(id, &bbox));
+        }
+    }
+}
+
+fn index_entry(id: FeatureId, bbox: &Rect) -> IndexEntry {
+    GeomWithData::new(
+        Rectangle::from_corners([bbox.x_min(), bbox.y_min()], [bbox.x_max(), bbox.y_max()]),
+        id,
+    )
+}
+
+impl<F> FeatureStore<F> for RTreeFeatureStore<F>
+where
+    F: MaybeSend + MaybeSync,
+{
+    fn iter(&self) -> Box<dyn Iterator<Item = (FeatureId, &F)> + '_> {
+        self.features.iter()
+    }
+
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (FeatureId, &mut F)> + '_> {
+        self.index.get_mut().all_outdated = true;
+        self.features.iter_mut()
+    }
+
+    fn get(&self, id: FeatureId) -> Option<&F> {
+        self.features.get(id)
+    }
+
+    fn get_mut(&mut self, id: FeatureId) -> Option<&mut F> {
+        let feature = self.features.get_mut(id)?;
+        self.index.get_mut().outdated.insert(id);
+
+        Some(feature)
+    }
+
+    fn add(&mut self, feature: F) -> FeatureId {
+        let bbox = (self.bbox)(&feature);
+        let id = self.features.add(feature);
+        self.index.get_mut().insert(id, bbox);
+
+        id
+    }
+
+    fn remove(&mut self, id: FeatureId) -> Option<F> {
+        let feature = self.features.remove(id)?;
+        let index = self.index.get_mut();
+        index.remove(id);
+        index.outdated.remove(&id);
+
+        Some(feature)
+    }
+
+    fn iter_in_bbox(&self, bbox: &Rect) -> Box<dyn Iterator<Item = (FeatureId, &F)> + '_> {
+        let ids = self.ids_in_bbox(bbox);
+        Box::new(
+            ids.into_iter()
+                .filter_map(|id| Some((id, self.features.get(id)?))),
+        )
+    }
+
+    fn iter_mut_in_bbox(
+        &mut self,
+        bbox: &Rect,
+    ) -> Box<dyn Iterator<Item = (FeatureId, &mut F)> + '_> {
+        let ids = self.ids_in_bbox(bbox);
+        self.index.get_mut().outdated.extend(ids.iter().copied());
+
+        Box::new(self.features.iter_mut_by_ids(ids))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use galileo_types::cartesian::Point2;
+
+    use super::*;
+
+    fn points_in_bbox(store: &RTreeFeatureStore<Point2>, bbox: Rect) -> Vec<Point2> {
+        let mut points: Vec<_> = store.iter_in_bbox(&bbox).map(|(_, point)| *point).collect();
+        points.sort_by(|a, b| a.x().total_cmp(&b.x()));
+        points
+    }
+
+    #[test]
+    fn index_is_updated_on_changes() {
+        let mut store = RTreeFeatureStore::new_cartesian([
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 1.0),
+            Point2::new(2.0, 2.0),
+        ]);
+        let bbox = Rect::new(-0.5, -0.5, 1.5, 1.5);
+        assert_eq!(
+            points_in_bbox(&store, bbox),
+            vec![Point2::new(0.0, 0.0), Point2::new(1.0, 1.0)]
+        );
+
+        let added = store.add(Point2::new(0.5, 0.5));
+        assert_eq!(points_in_bbox(&store, bbox).len(), 3);
+
+        store.remove(added);
+        let (first_id, _) = store.iter().next().unwrap();
+        *store.get_mut(first_id).unwrap() = Point2::new(10.0, 10.0);
+        assert_eq!(points_in_bbox(&store, bbox), vec![Point2::new(1.0, 1.0)]);
+
+        for (_, point) in store.iter_mut() {
+            *point = Point2::new(point.x() - 1.0, point.y() - 1.0);
+        }
+        assert_eq!(
+            points_in_bbox(&store, bbox),
+            vec![Point2::new(0.0, 0.0), Point2::new(1.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn iter_mut_in_bbox_returns_only_features_in_bbox() {
+        let mut store =
+            RTreeFeatureStore::new_cartesian((0..10).map(|i| Point2::new(i as f64, 0.0)));
+
+        let bbox = Rect::new(2.5, -1.0, 5.5, 1.0);
+        let mut moved = 0;
+        for (_, point) in store.iter_mut_in_bbox(&bbox) {
+            *point = Point2::new(point.x() + 10.0, 0.0);
+            moved += 1;
+        }
+        assert_eq!(moved, 3);
+
+        assert!(points_in_bbox(&store, bbox).is_empty());
+        assert_eq!(
+            points_in_bbox(&store, Rect::new(12.5, -1.0, 15.5, 1.0)),
+            vec![
+                Point2::new(13.0, 0.0),
+                Point2::new(14.0, 0.0),
+                Point2::new(15.0, 0.0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use galileo_types::cartesian::Point3;
+use galileo_types::geometry::Geom;
+
+use crate::layer::feature_layer::symbol::Symbol;
+use crate::layer::feature_layer::Cluster;
+use crate::render::point_paint::PointPaint;
+use crate::render::render_bundle::RenderBundle;
+use crate::render::text::TextStyle;
+use crate::Color;
+
+/// Number of features in a cluster at which the circle reaches its maximum size.
+const MAX_SIZE_COUNT: f64 = 10_000.0;
+
+/// Renders a [`Cluster`] as a circle, which size grows with the number of features in the cluster.
+///
+/// The size grows logarithmically from `min_size` for single features to `max_size` for clusters
+/// of 10 000 features and more.
+#[derive(Debug, Clone)]
+pub struct ClusterSymbol {
+    /// Color of the circle.
+    pub color: Color,
+    /// Diameter of the circle for a single feature in pixels.
+    pub min_size: f64,
+    /// Maximum diameter of the circle in pixels.
+    pub max_size: f64,
+    /// Style of the label with the number of features, drawn over clusters of more than one
+    /// feature. If `None`, no label is drawn.
+    pub label_style: Option<TextStyle>,
+}
+
+impl ClusterSymbol {
+    /// Creates a new instance without labels.
+    pub fn new(color: Color, min_size: f64, max_size: f64) -> Self {
+        Self {
+            color,
+            min_size,
+            max_size,
+            label_style: None,
+        }
+    }
+
+    /// Sets the style of the label with the number of features.
+    pub fn with_label(mut self, style: TextStyle) -> Self {
+        self.label_style = Some(style);
+        self
+    }
+}
+
+impl<A> Symbol<Cluster<A>> for ClusterSymbol {
+    fn render(
+        &self,
+        feature: &Cluster<A>,
+        geometry: &Geom<Point3>,
+        min_resolution: f64,
+        bundle: &mut RenderBundle,
+    ) {
+        let Geom::Point(point) = geometry else {
+            return;
+        };
+
+        let count = feature.count();
+        let scale = ((count as f64).log10() / MAX_SIZE_COUNT.log10()).min(1.0);
+        let size = self.min_size + (self.max_size - self.min_size) * scale;
+        bundle.add_point(
+            point,
+            &PointPaint::circle(self.color, size as f32),
+            min_resolution,
+        );
+
+        if let Some(style) = self.label_style.as_ref().filter(|_| count > 1) {
+            let text = count.to_string();
+            bundle.add_point(point, &PointPaint::label(&text, style), min_resolution);
+        }
+    }
+}
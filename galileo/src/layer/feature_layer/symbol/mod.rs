@@ -3,16 +3,20 @@
 //! features it uses. But a few simple implementations are provided for convenience.
 
 mod arbitrary;
+mod cluster;
 mod contour;
 mod point;
 mod polygon;
+mod rule_based;
 
 pub use arbitrary::ArbitraryGeometrySymbol;
+pub use cluster::ClusterSymbol;
 pub use contour::SimpleContourSymbol;
 use galileo_types::cartesian::Point3;
 use galileo_types::geometry::Geom;
 pub use point::{CirclePointSymbol, ImagePointSymbol};
 pub use polygon::SimplePolygonSymbol;
+pub use rule_based::{RuleBasedSymbol, SymbolRule};
 
 use crate::render::render_bundle::RenderBundle;
 
@@ -0,0 +1,340 @@
+use galileo_types::cartesian::Point3;
+use galileo_types::geometry::Geom;
+use galileo_types::{MultiContour, MultiPoint, MultiPolygon};
+use serde::{Deserialize, Serialize};
+
+use crate::layer::feature_layer::symbol::Symbol;
+use crate::layer::feature_layer::FeatureProperties;
+use crate::layer::vector_tile_layer::style::{PropertyFilter, PropertyValue, VectorTileSymbol};
+use crate::render::point_paint::PointPaint;
+use crate::render::render_bundle::RenderBundle;
+use crate::render::{LinePaint, PolygonPaint};
+
+/// Renders features with the symbol of the first rule that matches the properties of a feature.
+///
+/// This is the feature layer counterpart of the
+/// [`VectorTileStyle`](crate::layer::vector_tile_layer::style::VectorTileStyle): the symbol is declarative and
+/// serializable, so the styling of a layer can be loaded from a configuration file instead of being hard-coded.
+/// Features must implement [`FeatureProperties`] to be rendered with this symbol.
+///
+/// ```
+/// use galileo::layer::vector_tile_layer::style::{
+///     PropertyFilter, PropertyFilterOperator, VectorTilePolygonSymbol, VectorTileSymbol,
+/// };
+/// use galileo::symbol::{RuleBasedSymbol, SymbolRule};
+/// use galileo::Color;
+///
+/// let symbol = RuleBasedSymbol {
+///     rules: vec![SymbolRule {
+///         properties: vec![PropertyFilter {
+///             property_name: "landuse".to_string(),
+///             operator: PropertyFilterOperator::Equal("forest".to_string()),
+///         }],
+///         symbol: VectorTileSymbol::Polygon(VectorTilePolygonSymbol {
+///             fill_color: Color::GREEN,
+///         }),
+///     }],
+/// };
+/// ```
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RuleBasedSymbol {
+    /// Rules are traversed in sequence until a rule that corresponds to the feature and its geometry is found, and
+    /// that rule is used for drawing. If no rule corresponds to the feature, the feature is not rendered.
+    pub rules: Vec<SymbolRule>,
+}
+
+/// A rule that specifies what kind of features can be drawn with the given symbol.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SymbolRule {
+    /// Specifies a set of properties of a feature that must have the given values for this rule to be applied.
+    #[serde(default)]
+    pub properties: Vec<PropertyFilter>,
+    /// Symbol to draw a feature with. Points are drawn with point and label symbols, contours with line symbols
+    /// and polygons with polygon symbols.
+    #[serde(default)]
+    pub symbol: VectorTileSymbol,
+}
+
+impl RuleBasedSymbol {
+    /// Get a rule for the given feature with the given geometry.
+    pub fn get_rule<F: FeatureProperties>(
+        &self,
+        feature: &F,
+        geometry: &Geom<Point3>,
+    ) -> Option<&SymbolRule> {
+        self.rules.iter().find(|&rule| {
+            let correct_geometry_type = match geometry {
+                Geom::Point(_) | Geom::MultiPoint(_) => matches!(
+                    rule.symbol,
+                    VectorTileSymbol::Point(_) | VectorTileSymbol::Label(_)
+                ),
+                Geom::Contour(_) | Geom::MultiContour(_) => {
+                    matches!(rule.symbol, VectorTileSymbol::Line(_))
+                }
+                Geom::Polygon(_) | Geom::MultiPolygon(_) => {
+                    matches!(rule.symbol, VectorTileSymbol::Polygon(_))
+                }
+            };
+
+            correct_geometry_type
+                && rule
+                    .properties
+                    .iter()
+                    .all(|filter| filter.matches(feature.get_property(&filter.property_name)))
+        })
+    }
+}
+
+impl<F: FeatureProperties> Symbol<F> for RuleBasedSymbol {
+    fn render(
+        &self,
+        feature: &F,
+        geometry: &Geom<Point3>,
+        min_resolution: f64,
+        bundle: &mut RenderBundle,
+    ) {
+        let Some(rule) = self.get_rule(feature, geometry) else {
+            return;
+        };
+
+        match &rule.symbol {
+            VectorTileSymbol::Point(symbol) => {
+                add_points(geometry, &(*symbol).into(), min_resolution, bundle);
+            }
+            VectorTileSymbol::Label(symbol) => {
+                let text =
+                    symbol.format_text(|name| feature.get_property(name).map(PropertyValue::text));
+                if !text.is_empty() {
+                    let paint = PointPaint::label(&text, &symbol.text_style);
+                    add_points(geometry, &paint, min_resolution, bundle);
+                }
+            }
+            VectorTileSymbol::Line(symbol) => {
+                let paint = LinePaint::from(*symbol);
+                match geometry {
+                    Geom::Contour(contour) => bundle.add_line(contour, &paint, min_resolution),
+                    Geom::MultiContour(contours) => contours.contours().for_each(|contour| {
+                        bundle.add_line(contour, &paint, min_resolution);
+                    }),
+                    _ => {}
+                }
+            }
+            VectorTileSymbol::Polygon(symbol) => {
+                let paint = PolygonPaint::from(*symbol);
+                match geometry {
+                    Geom::Polygon(polygon) => bundle.add_polygon(polygon, &paint, min_resolution),
+                    Geom::MultiPolygon(polygons) => polygons.polygons().for_each(|polygon| {
+                        bundle.add_polygon(polygon, &paint, min_resolution);
+                    }),
+                    _ => {}
+                }
+            }
+            VectorTileSymbol::None => {}
+        }
+    }
+}
+
+fn add_points(
+    geometry: &Geom<Point3>,
+    paint: &PointPaint,
+    min_resolution: f64,
+    bundle: &mut RenderBundle,
+) {
+    match geometry {
+        Geom::Point(point) => bundle.add_point(point, paint, min_resolution),
+        Geom::MultiPoint(points) => points.iter_points().for_each(|p| {
+            bundle.add_point(&p, paint, min_resolution);
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use galileo_types::cartesian::Vector2;
+
+    use super::*;
+    use crate::layer::vector_tile_layer::style::{VectorTileLineSymbol, VectorTilePointSymbol};
+    use crate::render::text::font_provider::FontProvider;
+    use crate::render::text::text_service::FontServiceError;
+    use crate::render::text::{
+        GlyphVertex, TessellatedGlyph, TextRasterizer, TextService, TextShaping, TextStyle,
+    };
+    use crate::Color;
+
+    struct TestFeature(HashMap<String, String>);
+
+    impl FeatureProperties for TestFeature {
+        type Value = str;
+
+        fn get_property(&self, name: &str) -> Option<&str> {
+            self.0.get(name).map(String::as_str)
+        }
+    }
+
+    fn feature(properties: &[(&str, &str)]) -> TestFeature {
+        TestFeature(
+            properties
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn rule_is_chosen_by_properties_and_geometry() {
+        let symbol: RuleBasedSymbol = serde_json::from_value(serde_json::json!({
+            "rules": [
+                {
+                    "properties": [
+                        { "property_name": "population", "operator": { "greater_than": "1000" } }
+                    ],
+                    "symbol": { "point": { "size": 10.0, "color": "#ff0000ff" } }
+                },
+                {
+                    "symbol": { "line": { "width": 2.0, "stroke_color": "#000000ff" } }
+                },
+                {
+                    "symbol": { "point": { "size": 4.0, "color": "#00ff00ff" } }
+                }
+            ]
+        }))
+        .unwrap();
+
+        let point = Geom::Point(Point3::new(0.0, 0.0, 0.0));
+        let city = feature(&[("population", "5000")]);
+        let village = feature(&[("population", "100")]);
+
+        let rule = symbol.get_rule(&city, &point).unwrap();
+        assert_eq!(
+            rule.symbol,
+            VectorTileSymbol::Point(VectorTilePointSymbol {
+                size: 10.0,
+                color: Color::RED,
+            })
+        );
+
+        let rule = symbol.get_rule(&village, &point).unwrap();
+        assert_eq!(
+            rule.symbol,
+            VectorTileSymbol::Point(VectorTilePointSymbol {
+                size: 4.0,
+                color: Color::GREEN,
+            })
+        );
+
+        let line = Geom::Contour(galileo_types::impls::Contour::open(vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+        ]));
+        let rule = symbol.get_rule(&village, &line).unwrap();
+        assert_eq!(
+            rule.symbol,
+            VectorTileSymbol::Line(VectorTileLineSymbol {
+                width: 2.0,
+                stroke_color: Color::BLACK,
+            })
+        );
+
+        let polygon = Geom::Polygon(galileo_types::impls::Polygon::new(
+            galileo_types::impls::ClosedContour::new(vec![]),
+            vec![],
+        ));
+        assert!(symbol.get_rule(&city, &polygon).is_none());
+    }
+
+    /// Rasterizer that records the shaped texts and returns a single triangle for each of them.
+    struct RecordingRasterizer;
+
+    static SHAPED_TEXTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    impl TextRasterizer for RecordingRasterizer {
+        fn shape(
+            &self,
+            text: &str,
+            style: &TextStyle,
+            _offset: Vector2<f32>,
+            _font_provider: &dyn FontProvider,
+            _dpi_scale_factor: f32,
+        ) -> Result<TextShaping, FontServiceError> {
+            SHAPED_TEXTS.lock().unwrap().push(text.to_string());
+            let vertex = |x, y| GlyphVertex {
+                position: [x, y],
+                color: style.font_color,
+            };
+
+            Ok(TextShaping::Tessellation {
+                glyphs: vec![TessellatedGlyph {
+                    vertices: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)],
+                    indices: vec![0, 1, 2],
+                }],
+            })
+        }
+    }
+
+    #[test]
+    fn label_and_polygon_are_rendered_into_bundle() {
+        TextService::initialize(RecordingRasterizer);
+
+        let symbol: RuleBasedSymbol = serde_json::from_value(serde_json::json!({
+            "rules": [
+                {
+                    "symbol": {
+                        "label": {
+                            "pattern": "{name} ({population})",
+                            "text_style": {
+                                "font_family": [],
+                                "font_size": 12.0,
+                                "font_color": "#ff0000ff"
+                            }
+                        }
+                    }
+                },
+                {
+                    "properties": [
+                        { "property_name": "landuse", "operator": { "equal": "forest" } }
+                    ],
+                    "symbol": { "polygon": { "fill_color": "#00ff00ff" } }
+                }
+            ]
+        }))
+        .unwrap();
+
+        let city = feature(&[("name", "Springfield"), ("population", "5000")]);
+        let forest = feature(&[("landuse", "forest")]);
+        let point = Geom::Point(Point3::new(0.0, 0.0, 0.0));
+        let polygon = Geom::Polygon(galileo_types::impls::Polygon::new(
+            galileo_types::impls::ClosedContour::new(vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(10.0, 0.0, 0.0),
+                Point3::new(10.0, 10.0, 0.0),
+            ]),
+            vec![],
+        ));
+
+        let mut bundle = RenderBundle::new(1.0);
+        symbol.render(&city, &point, 1.0, &mut bundle);
+        symbol.render(&forest, &polygon, 1.0, &mut bundle);
+        // The city has no polygon rule, so nothing is added for it.
+        symbol.render(&city, &polygon, 1.0, &mut bundle);
+
+        assert!(SHAPED_TEXTS
+            .lock()
+            .unwrap()
+            .contains(&"Springfield (5000)".to_string()));
+
+        let vertices = &bundle.world_set.poly_tessellation.vertices;
+        let colored = |color: Color| {
+            vertices
+                .iter()
+                .filter(|vertex| vertex.color == color.to_f32_array())
+                .count()
+        };
+        assert_eq!(colored(Color::RED), 3);
+        assert!(colored(Color::GREEN) >= 3);
+        assert_eq!(vertices.len(), colored(Color::RED) + colored(Color::GREEN));
+    }
+}
@@ -0,0 +1,367 @@
+//! Layer that draws the density of point features as a heatmap.
+
+use std::any::Any;
+
+use galileo_types::cartesian::{CartesianPoint2d, CartesianPoint2dFloat, Point2, Point3, Rect};
+use galileo_types::geo::impls::GeoPoint2d;
+use galileo_types::geo::{Crs, GeoPoint, NewGeoPoint};
+use maybe_sync::{MaybeSend, MaybeSync};
+use parking_lot::Mutex;
+
+use super::feature_layer::{Feature, FeatureStore, VecFeatureStore};
+use super::Layer;
+use crate::layer::attribution::Attribution;
+use crate::messenger::Messenger;
+use crate::render::{Canvas, ColorRamp, HeatmapPaint, HeatmapPoint, HeatmapPoints, PackedBundle};
+use crate::reprojection::CrsTransform;
+use crate::view::MapView;
+
+type WeightFn<F> = dyn Fn(&F) -> f32 + MaybeSend + MaybeSync;
+
+/// Radius of the Earth in meters used to convert [`HeatmapRadius::Meters`] into map units.
+const EARTH_RADIUS: f64 = 6_378_137.0;
+
+/// Radius of the kernel of a heatmap point.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HeatmapRadius {
+    /// Radius in pixels. The heatmap looks the same at any map resolution, but the density in an
+    /// area changes when the map is zoomed.
+    Pixels(f32),
+    /// Radius in meters on the ground. It is converted into map units with the scale of the map
+    /// CRS at the center of the view, so in Web Mercator the kernel of a point covers the same
+    /// ground distance at any latitude. The density in an area does not depend on the map
+    /// resolution.
+    Meters(f64),
+    /// Radius in the units of the map CRS. Note that these are not meters in general: in Web
+    /// Mercator a map unit is a meter only at the equator, and it is about half a meter at 60°
+    /// latitude. The density in an area does not depend on the map resolution.
+    MapUnits(f64),
+}
+
+/// Configuration of a [`HeatmapLayer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeatmapOptions {
+    /// Radius of the kernel of each point.
+    pub radius: HeatmapRadius,
+    /// Multiplier of the density. The density is the sum of the kernels of the points, each
+    /// being equal to the weight of the point in its center. The color ramp is applied to the
+    /// density multiplied by this value.
+    pub intensity: f32,
+    /// Colors of the density values from `0.0` to `1.0`.
+    pub ramp: ColorRamp,
+}
+
+impl Default for HeatmapOptions {
+    fn default() -> Self {
+        Self {
+            radius: HeatmapRadius::Pixels(20.0),
+            intensity: 1.0,
+            ramp: ColorRamp::default(),
+        }
+    }
+}
+
+/// Layer that draws point features as a heatmap.
+///
+/// Every point adds a Gaussian kernel, scaled by the weight of the point, to the density of the
+/// area around it. The density is accumulated by the renderer in an offscreen buffer at the
+/// current map resolution, and then converted into colors with the [`ColorRamp`] of the layer.
+///
+/// All features of the layer must have point geometries in the CRS of the layer.
+///
+/// ```
+/// use galileo::galileo_types::cartesian::Point2;
+/// use galileo::galileo_types::geo::Crs;
+/// use galileo::layer::heatmap_layer::{HeatmapOptions, HeatmapRadius};
+/// use galileo::layer::HeatmapLayer;
+///
+/// let incidents = vec![Point2::new(30.3, 59.9), Point2::new(30.31, 59.91)];
+/// let layer = HeatmapLayer::new(incidents, Crs::WGS84).with_options(HeatmapOptions {
+///     radius: HeatmapRadius::Meters(500.0),
+///     intensity: 0.5,
+///     ..Default::default()
+/// });
+/// ```
+pub struct HeatmapLayer<F> {
+    features: Box<dyn FeatureStore<F>>,
+    weight: Box<WeightFn<F>>,
+    crs: Crs,
+    options: HeatmapOptions,
+    packed: Mutex<Option<PackedPoints>>,
+    messenger: Option<Box<dyn Messenger>>,
+}
+
+/// Points projected into the map CRS and packed by the canvas.
+struct PackedPoints {
+    crs: Crs,
+    points: Option<Box<dyn PackedBundle>>,
+}
+
+impl<F> HeatmapLayer<F>
+where
+    F: Feature + MaybeSend + MaybeSync + 'static,
+    F::Geom: CartesianPoint2d<Num = f64>,
+{
+    /// Creates a new layer with the given features. All points have the weight of `1.0`.
+    pub fn new(features: Vec<F>, crs: Crs) -> Self {
+        Self {
+            features: Box::new(VecFeatureStore::new(features)),
+            weight: Box::new(|_| 1.0),
+            crs,
+            options: HeatmapOptions::default(),
+            packed: Mutex::new(None),
+            messenger: None,
+        }
+    }
+
+    /// Replaces the features of the layer with the given feature store.
+    pub fn with_feature_store(mut self, features: impl FeatureStore<F> + 'static) -> Self {
+        self.features = Box::new(features);
+        *self.packed.get_mut() = None;
+        self
+    }
+
+    /// Sets the function that returns the weight of a feature.
+    pub fn with_weight(
+        mut self,
+        weight: impl Fn(&F) -> f32 + MaybeSend + MaybeSync + 'static,
+    ) -> Self {
+        self.weight = Box::new(weight);
+        *self.packed.get_mut() = None;
+        self
+    }
+
+    /// Sets the rendering options of the layer.
+    pub fn with_options(mut self, options: HeatmapOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Rendering options of the layer.
+    pub fn options(&self) -> &HeatmapOptions {
+        &self.options
+    }
+
+    /// Changes the rendering options of the layer. The change is applied on the next redraw.
+    pub fn set_options(&mut self, options: HeatmapOptions) {
+        self.options = options;
+        self.request_redraw();
+    }
+
+    /// Returns a reference to the feature store.
+    pub fn features(&self) -> &dyn FeatureStore<F> {
+        &*self.features
+    }
+
+    /// Returns a mutable reference to the feature store.
+    ///
+    /// As the features may be changed through the returned reference, all the points of the layer
+    /// are projected again on the next redraw.
+    pub fn features_mut(&mut self) -> &mut dyn FeatureStore<F> {
+        *self.packed.get_mut() = None;
+        self.request_redraw();
+        &mut *self.features
+    }
+
+    /// Returns the CRS of the layer.
+    pub fn crs(&self) -> &Crs {
+        &self.crs
+    }
+
+    /// Projects the points into the given CRS, relative to the center of their bounding rectangle.
+    fn project_points(&self, crs: &Crs) -> Option<HeatmapPoints> {
+        let Some(transform) = CrsTransform::new(&self.crs, crs) else {
+            log::warn!("Heatmap points cannot be projected into the map CRS");
+            return None;
+        };
+
+        let projected: Vec<_> = self
+            .features
+            .iter()
+            .filter_map(|(_, feature)| {
+                let geometry = feature.geometry();
+                let position = transform.transform(&Point2::new(geometry.x(), geometry.y()))?;
+                Some((position, (self.weight)(feature)))
+            })
+            .collect();
+        let origin = Rect::from_points(projected.iter().map(|(position, _)| *position))?.center();
+
+        Some(HeatmapPoints {
+            origin: Point3::new(origin.x(), origin.y(), 0.0),
+            points: projected
+                .into_iter()
+                .map(|(position, weight)| HeatmapPoint {
+                    position: [
+                        (position.x() - origin.x()) as f32,
+                        (position.y() - origin.y()) as f32,
+                        0.0,
+                    ],
+                    weight,
+                })
+                .collect(),
+        })
+    }
+
+    /// Radius of the kernel in pixels at the resolution of the view.
+    fn radius_pixels(&self, view: &MapView) -> f32 {
+        match self.options.radius {
+            HeatmapRadius::Pixels(radius) => radius * view.dpi_scale_factor(),
+            HeatmapRadius::Meters(radius) => {
+                // If the scale cannot be calculated, meters are the best guess of the map units.
+                let scale = map_units_per_meter(view).unwrap_or(1.0);
+                (radius * scale / view.resolution()) as f32
+            }
+            HeatmapRadius::MapUnits(radius) => (radius / view.resolution()) as f32,
+        }
+    }
+
+    fn request_redraw(&self) {
+        if let Some(messenger) = &self.messenger {
+            messenger.request_redraw();
+        }
+    }
+}
+
+impl<F> Layer for HeatmapLayer<F>
+where
+    F: Feature + MaybeSend + MaybeSync + 'static,
+    F::Geom: CartesianPoint2d<Num = f64>,
+{
+    fn render(&self, view: &MapView, canvas: &mut dyn Canvas) {
+        let mut packed = self.packed.lock();
+        if packed.as_ref().is_none_or(|p| &p.crs != view.crs()) {
+            *packed = Some(PackedPoints {
+                crs: view.crs().clone(),
+                points: self
+                    .project_points(view.crs())
+                    .and_then(|points| canvas.pack_heatmap(&points)),
+            });
+        }
+
+        let Some(points) = packed.as_ref().and_then(|p| p.points.as_deref()) else {
+            return;
+        };
+        canvas.draw_heatmap(
+            points,
+            HeatmapPaint {
+                radius: self.radius_pixels(view),
+                intensity: self.options.intensity,
+                ramp: &self.options.ramp,
+            },
+        );
+    }
+
+    fn prepare(&self, _view: &MapView) {
+        // do nothing
+    }
+
+    fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
+        self.messenger = Some(messenger);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        None
+    }
+}
+
+/// Number of map units in a meter along the meridian at the center of the view.
+fn map_units_per_meter(view: &MapView) -> Option<f64> {
+    /// Difference of latitudes of the points the scale is measured between, in degrees.
+    const STEP: f64 = 0.01;
+
+    let center = view.position()?;
+    let projection = view.crs().get_projection::<GeoPoint2d, Point2>()?;
+    let lat = center.lat().clamp(-89.0, 89.0);
+    let south = projection.project(&GeoPoint2d::latlon(lat - STEP / 2.0, center.lon()))?;
+    let north = projection.project(&GeoPoint2d::latlon(lat + STEP / 2.0, center.lon()))?;
+
+    Some(south.distance(&north) / (STEP.to_radians() * EARTH_RADIUS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_are_projected_with_weights() {
+        let mut layer = HeatmapLayer::new(
+            vec![Point2::new(0.0, 0.0), Point2::new(10.0, 20.0)],
+            Crs::EPSG3857,
+        )
+        .with_weight(|point: &Point2| point.x() as f32 / 10.0 + 1.0);
+
+        let points = layer.project_points(&Crs::EPSG3857).unwrap();
+        assert_eq!(
+            points,
+            HeatmapPoints {
+                origin: Point3::new(5.0, 10.0, 0.0),
+                points: vec![
+                    HeatmapPoint {
+                        position: [-5.0, -10.0, 0.0],
+                        weight: 1.0
+                    },
+                    HeatmapPoint {
+                        position: [5.0, 10.0, 0.0],
+                        weight: 2.0
+                    },
+                ]
+            }
+        );
+
+        layer.features_mut().add(Point2::new(5.0, 5.0));
+        assert_eq!(
+            layer.project_points(&Crs::EPSG3857).unwrap().points.len(),
+            3
+        );
+    }
+
+    #[test]
+    fn offsets_are_precise_for_large_coordinates() {
+        let layer = HeatmapLayer::new(
+            vec![
+                Point2::new(20_000_000.0, 20_000_000.0),
+                Point2::new(20_000_000.5, 20_000_000.25),
+            ],
+            Crs::EPSG3857,
+        );
+
+        let points = layer.project_points(&Crs::EPSG3857).unwrap();
+        assert_eq!(points.points[0].position, [-0.25, -0.125, 0.0]);
+        assert_eq!(points.points[1].position, [0.25, 0.125, 0.0]);
+    }
+
+    #[test]
+    fn empty_layer_has_no_points() {
+        let layer = HeatmapLayer::<Point2>::new(vec![], Crs::EPSG3857);
+        assert!(layer.project_points(&Crs::EPSG3857).is_none());
+    }
+
+    #[test]
+    fn meters_are_converted_with_the_scale_at_view_center() {
+        let layer =
+            HeatmapLayer::<Point2>::new(vec![], Crs::EPSG3857).with_options(HeatmapOptions {
+                radius: HeatmapRadius::Meters(100.0),
+                ..Default::default()
+            });
+
+        let equator = MapView::new(&GeoPoint2d::latlon(0.0, 30.0), 10.0);
+        assert!((layer.radius_pixels(&equator) - 10.0).abs() < 0.01);
+
+        let north = MapView::new(&GeoPoint2d::latlon(60.0, 30.0), 10.0);
+        assert!((layer.radius_pixels(&north) - 20.0).abs() < 0.01);
+
+        let layer = layer.with_options(HeatmapOptions {
+            radius: HeatmapRadius::MapUnits(100.0),
+            ..Default::default()
+        });
+        assert!((layer.radius_pixels(&north) - 10.0).abs() < 0.01);
+    }
+}
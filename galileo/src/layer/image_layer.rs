@@ -0,0 +1,290 @@
+//! Layer that draws a single georeferenced image.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use galileo_types::cartesian::{CartesianPoint2d, Point2, Rect, Vector2};
+use galileo_types::geo::Crs;
+use parking_lot::Mutex;
+
+use super::Layer;
+use crate::decoded_image::DecodedImage;
+use crate::layer::attribution::Attribution;
+use crate::messenger::Messenger;
+use crate::render::render_bundle::RenderBundle;
+use crate::render::{BundleToDraw, Canvas, ImagePaint, PackedBundle, RenderOptions};
+use crate::reprojection::CrsTransform;
+use crate::view::MapView;
+
+/// Layer that draws an image (e.g. a scanned plan or an aerial photo) pinned to the map by the
+/// positions of its corners.
+///
+/// The corners can be given in any CRS that can be converted into the CRS of the map. The image is
+/// drawn as a mesh with every node projected into the map CRS separately, so it follows the
+/// curvature of non-linear projections.
+///
+/// ```no_run
+/// use galileo::decoded_image::DecodedImage;
+/// use galileo::galileo_types::cartesian::Rect;
+/// use galileo::galileo_types::geo::Crs;
+/// use galileo::layer::ImageLayer;
+///
+/// # fn load() -> Result<(), galileo::error::GalileoError> {
+/// let image = DecodedImage::decode(&std::fs::read("plan.png")?)?;
+/// let layer = ImageLayer::from_bbox(image, Rect::new(10.0, 50.0, 10.1, 50.1), Crs::WGS84)
+///     .with_opacity(0.7);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ImageLayer {
+    image: Arc<DecodedImage>,
+    corners: [Point2; 4],
+    crs: Crs,
+    opacity: f32,
+    projected: Mutex<Option<ProjectedImage>>,
+    messenger: Option<Box<dyn Messenger>>,
+    attribution: Option<Attribution>,
+}
+
+impl std::fmt::Debug for ImageLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImageLayer")
+            .field("corners", &self.corners)
+            .field("crs", &self.crs)
+            .field("opacity", &self.opacity)
+            .finish()
+    }
+}
+
+/// Image packed for drawing on a map with the given CRS.
+struct ProjectedImage {
+    crs: Crs,
+    bundle: Option<(Arc<dyn PackedBundle>, Vector2<f32>)>,
+}
+
+impl ImageLayer {
+    /// Creates a new layer. The corners of the image are given in the order: top-left, top-right,
+    /// bottom-right, bottom-left (as the image is normally viewed), in the coordinates of the
+    /// given CRS.
+    ///
+    /// Coordinates of [`Crs::WGS84`] are longitude and latitude in degrees.
+    pub fn new(image: DecodedImage, corners: [Point2; 4], crs: Crs) -> Self {
+        Self {
+            image: Arc::new(image),
+            corners,
+            crs,
+            opacity: 1.0,
+            projected: Mutex::new(None),
+            messenger: None,
+            attribution: None,
+        }
+    }
+
+    /// Creates a new layer with the image covering the given bounding box, with the top of the
+    /// image at the `y_max` side of the box.
+    pub fn from_bbox(image: DecodedImage, bbox: Rect, crs: Crs) -> Self {
+        let corners = [
+            Point2::new(bbox.x_min(), bbox.y_max()),
+            Point2::new(bbox.x_max(), bbox.y_max()),
+            Point2::new(bbox.x_max(), bbox.y_min()),
+            Point2::new(bbox.x_min(), bbox.y_min()),
+        ];
+        Self::new(image, corners, crs)
+    }
+
+    /// Sets the opacity of the image (from `0.0` to `1.0`). Defaults to `1.0`.
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the attribution of the layer.
+    pub fn with_attribution(mut self, text: String, url: String) -> Self {
+        self.attribution = Some(Attribution::new(text, Some(url)));
+        self
+    }
+
+    /// Opacity of the image.
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    /// Sets the opacity of the image (from `0.0` to `1.0`). The change is applied on the next
+    /// redraw.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        if let Some(messenger) = &self.messenger {
+            messenger.request_redraw();
+        }
+    }
+
+    /// Corners of the image in the order: top-left, top-right, bottom-right, bottom-left.
+    pub fn corners(&self) -> [Point2; 4] {
+        self.corners
+    }
+
+    /// CRS of the corner coordinates.
+    pub fn crs(&self) -> &Crs {
+        &self.crs
+    }
+
+    /// Returns the position of the point of the image with the given relative coordinates
+    /// (`0.0..=1.0` from the top left corner) in the CRS of the corners.
+    fn image_point(&self, u: f64, v: f64) -> Point2 {
+        let [top_left, top_right, bottom_right, bottom_left] = self.corners;
+        let lerp = |a: Point2, b: Point2, k: f64| a + (b - a) * k;
+        lerp(
+            lerp(top_left, top_right, u),
+            lerp(bottom_left, bottom_right, u),
+            v,
+        )
+    }
+
+    fn pack(
+        &self,
+        crs: &Crs,
+        canvas: &dyn Canvas,
+    ) -> Option<(Arc<dyn PackedBundle>, Vector2<f32>)> {
+        let transform = CrsTransform::new(&self.crs, crs)?;
+
+        // Positions are stored relative to the top left corner to keep the precision of `f32`
+        // vertex coordinates.
+        let origin = transform.transform(&self.corners[0])?;
+        let mut bundle = RenderBundle::default();
+        let is_added =
+            bundle.add_warped_image(self.image.clone(), ImagePaint { opacity: 255 }, |u, v| {
+                let position = transform.transform(&self.image_point(u, v))?;
+                Some(Point2::new(
+                    position.x() - origin.x(),
+                    position.y() - origin.y(),
+                ))
+            });
+        if !is_added {
+            log::warn!("Image cannot be projected into the map CRS");
+            return None;
+        }
+
+        Some((
+            canvas.pack_bundle(&bundle).into(),
+            Vector2::new(origin.x() as f32, origin.y() as f32),
+        ))
+    }
+}
+
+impl Layer for ImageLayer {
+    fn render(&self, view: &MapView, canvas: &mut dyn Canvas) {
+        let mut projected = self.projected.lock();
+        if projected.as_ref().is_none_or(|p| &p.crs != view.crs()) {
+            *projected = Some(ProjectedImage {
+                crs: view.crs().clone(),
+                bundle: self.pack(view.crs(), canvas),
+            });
+        }
+
+        let Some((bundle, offset)) = projected.as_ref().and_then(|p| p.bundle.as_ref()) else {
+            return;
+        };
+        canvas.draw_bundles(
+            &[BundleToDraw::new(&**bundle, self.opacity, *offset)],
+            RenderOptions::default(),
+        );
+    }
+
+    fn prepare(&self, _view: &MapView) {
+        // The image is already loaded.
+    }
+
+    fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
+        self.messenger = Some(messenger);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        self.attribution.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use galileo_types::cartesian::Size;
+
+    use super::*;
+
+    fn layer(corners: [Point2; 4]) -> ImageLayer {
+        let image = DecodedImage::from_raw(vec![0; 4], Size::new(1, 1)).unwrap();
+        ImageLayer::new(image, corners, Crs::EPSG3857)
+    }
+
+    #[test]
+    fn image_point_interpolates_corners() {
+        let layer = layer([
+            Point2::new(0.0, 10.0),
+            Point2::new(20.0, 10.0),
+            Point2::new(30.0, 0.0),
+            Point2::new(10.0, 0.0),
+        ]);
+
+        assert_eq!(layer.image_point(0.0, 0.0), Point2::new(0.0, 10.0));
+        assert_eq!(layer.image_point(1.0, 0.0), Point2::new(20.0, 10.0));
+        assert_eq!(layer.image_point(1.0, 1.0), Point2::new(30.0, 0.0));
+        assert_eq!(layer.image_point(0.5, 0.5), Point2::new(15.0, 5.0));
+    }
+
+    #[test]
+    fn from_bbox_sets_corners() {
+        let image = DecodedImage::from_raw(vec![0; 4], Size::new(1, 1)).unwrap();
+        let layer = ImageLayer::from_bbox(image, Rect::new(1.0, 2.0, 3.0, 4.0), Crs::WGS84)
+            .with_opacity(1.5);
+
+        assert_eq!(
+            layer.corners(),
+            [
+                Point2::new(1.0, 4.0),
+                Point2::new(3.0, 4.0),
+                Point2::new(3.0, 2.0),
+                Point2::new(1.0, 2.0),
+            ]
+        );
+        assert_eq!(layer.opacity(), 1.0);
+    }
+
+    #[test]
+    fn warped_mesh_follows_projection() {
+        let image = Arc::new(DecodedImage::from_raw(vec![0; 4], Size::new(1, 1)).unwrap());
+        let transform = CrsTransform::new(&Crs::WGS84, &Crs::EPSG3857).unwrap();
+        let layer = ImageLayer::from_bbox(
+            DecodedImage::from_raw(vec![0; 4], Size::new(1, 1)).unwrap(),
+            Rect::new(0.0, 0.0, 10.0, 80.0),
+            Crs::WGS84,
+        );
+
+        let mut bundle = RenderBundle::default();
+        assert!(
+            bundle.add_warped_image(image, ImagePaint { opacity: 255 }, |u, v| {
+                transform.transform(&layer.image_point(u, v))
+            })
+        );
+
+        // Middle node of the mesh is at latitude 40, which is not in the middle between the
+        // projected top and bottom edges of the image.
+        let crate::render::render_bundle::world_set::ImageVertices::Mesh(vertices) =
+            &bundle.world_set.images[0].vertices
+        else {
+            panic!("image must be added as a mesh");
+        };
+        let middle = vertices[vertices.len() / 2].position;
+        let expected = transform.transform(&Point2::new(5.0, 40.0)).unwrap();
+        assert!((middle[0] as f64 - expected.x()).abs() < 1.0);
+        assert!((middle[1] as f64 - expected.y()).abs() < 1.0);
+
+        let top = vertices[0].position[1] as f64;
+        assert!((middle[1] as f64) < top / 2.0);
+    }
+}
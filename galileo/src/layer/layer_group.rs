@@ -0,0 +1,140 @@
+//! [`LayerGroup`] combines several layers into a single one.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use crate::layer::attribution::Attribution;
+use crate::layer::Layer;
+use crate::map::LayerId;
+use crate::messenger::Messenger;
+use crate::render::Canvas;
+use crate::view::MapView;
+use crate::{LayerCollection, TileSchema};
+
+/// Layer that consists of a [`LayerCollection`] of other layers.
+///
+/// A group is stored in a layer collection as any other layer, so it can be hidden, reordered or
+/// given opacity, blend mode and visibility range as a whole. These settings apply to all the
+/// layers of the group: the children are drawn into a single image, which is then combined with
+/// the layers below the group using the opacity and the blend mode of the group. The children are
+/// displayed only when both the group and the child itself are visible.
+///
+/// Groups can be nested. A layer inside nested groups can be addressed by the path of indices
+/// using the [`LayerCollection::get_by_path`] and [`LayerCollection::group_layers`] methods.
+///
+/// ```
+/// use galileo::LayerCollection;
+/// use galileo::layer::{LayerGroup, TestLayer};
+///
+/// let pipes = LayerGroup::new(vec![TestLayer("Pipes")]);
+/// let mut utilities = LayerGroup::default();
+/// utilities.push(TestLayer("Power lines"));
+/// utilities.push(pipes);
+///
+/// let mut collection = LayerCollection::default();
+/// collection.push(TestLayer("Basemap"));
+/// collection.push(utilities);
+///
+/// let pipes = collection.get_by_path(&[1, 1, 0]).and_then(|layer| layer.as_any().downcast_ref());
+/// assert_eq!(pipes, Some(&TestLayer("Pipes")));
+///
+/// // Hide the power lines.
+/// collection.group_layers_mut(&[1]).unwrap().hide(0);
+/// ```
+///
+/// Only the groups stored in the collection directly (not wrapped into `Arc<RwLock<_>>`) are
+/// considered when addressing layers by path and when applying group opacity.
+#[derive(Default)]
+pub struct LayerGroup {
+    layers: LayerCollection,
+    messenger: Option<Arc<dyn Messenger>>,
+}
+
+impl LayerGroup {
+    /// Creates a new group with the given layers.
+    pub fn new(layers: impl Into<LayerCollection>) -> Self {
+        Self {
+            layers: layers.into(),
+            messenger: None,
+        }
+    }
+
+    /// Layers of the group.
+    pub fn layers(&self) -> &LayerCollection {
+        &self.layers
+    }
+
+    /// Mutable reference to the layers of the group.
+    ///
+    /// Layers added through this reference do not receive the messenger of the group. Use
+    /// [`LayerGroup::push`] to add a layer that should be able to request map redraw.
+    pub fn layers_mut(&mut self) -> &mut LayerCollection {
+        &mut self.layers
+    }
+
+    /// Adds the layer to the top of the group, setting the messenger of the group to it. Returns
+    /// the id assigned to the layer.
+    pub fn push(&mut self, mut layer: impl Layer + 'static) -> LayerId {
+        if let Some(messenger) = &self.messenger {
+            layer.set_messenger(Box::new(SharedMessenger(messenger.clone())));
+        }
+
+        self.layers.push(layer)
+    }
+}
+
+impl Layer for LayerGroup {
+    fn render(&self, view: &MapView, canvas: &mut dyn Canvas) {
+        for layer in self.layers.iter_visible_at(view) {
+            layer.render(view, canvas);
+        }
+    }
+
+    fn prepare(&self, view: &MapView) {
+        for layer in self.layers.iter_visible_at(view) {
+            layer.prepare(view);
+        }
+    }
+
+    fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
+        let messenger: Arc<dyn Messenger> = Arc::from(messenger);
+        for layer in self.layers.iter_mut() {
+            layer.set_messenger(Box::new(SharedMessenger(messenger.clone())));
+        }
+
+        self.messenger = Some(messenger);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn tile_schema(&self) -> Option<TileSchema> {
+        self.layers.iter().find_map(|layer| layer.tile_schema())
+    }
+
+    /// Returns the attribution of the first child that has one. Use [`Layer::attributions`] to get
+    /// the attributions of all children.
+    fn attribution(&self) -> Option<Attribution> {
+        self.attributions().into_iter().next()
+    }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        (0..self.layers.len())
+            .filter(|&index| self.layers.is_visible(index))
+            .flat_map(|index| self.layers[index].attributions())
+            .collect()
+    }
+}
+
+struct SharedMessenger(Arc<dyn Messenger>);
+
+impl Messenger for SharedMessenger {
+    fn request_redraw(&self) {
+        self.0.request_redraw()
+    }
+}
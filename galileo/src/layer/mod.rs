@@ -13,23 +13,47 @@ use crate::view::MapView;
 use crate::TileSchema;
 
 pub mod attribution;
+#[cfg(feature = "geotiff")]
+pub mod cog_layer;
 pub mod data_provider;
 pub mod feature_layer;
+pub mod heatmap_layer;
+pub mod image_layer;
+pub mod layer_group;
 pub mod raster_tile_layer;
 pub(crate) mod tiles;
 pub mod vector_tile_layer;
-
-pub use feature_layer::{FeatureId, FeatureLayer};
+pub mod wms_layer;
+
+#[cfg(feature = "geotiff")]
+pub use cog_layer::CogLayer;
+pub use feature_layer::{ClusterLayer, FeatureId, FeatureLayer};
+pub use heatmap_layer::HeatmapLayer;
+pub use image_layer::ImageLayer;
+pub use layer_group::LayerGroup;
 pub use raster_tile_layer::RasterTileLayer;
+pub use tiles::{
+    AreaDownload, DownloadArea, DownloadProgress, RetryPolicy, TileDownloadStatus, TileDownloader,
+    TileRequestQueue,
+};
 pub use vector_tile_layer::VectorTileLayer;
+pub use wms_layer::WmsLayer;
 
 /// Layers specify a data source and the way the data should be rendered to the map.
 ///
-/// There are currently 3 types of layers:
+/// There are currently 8 types of layers:
 /// * [`RasterTileLayer`] - downloads prerendered tiles from an Internet source and draws them as is.
+/// * [`WmsLayer`] - requests a single image for the whole view from an OGC WMS service.
+/// * `CogLayer` - reads a GeoTIFF raster from a file or, if it is cloud optimized, from a remote server
+///   (requires `geotiff` feature).
 /// * [`VectorTileLayer`] - downloads vector tiles (in MVT format) from an Internet source and draws them using the
 ///   provided stylesheet.
+/// * [`ImageLayer`] - draws a single image pinned to the map by its corners.
 /// * [`FeatureLayer`] - draws custom set of geographic objects with the given [`feature_layer::Symbol`];
+/// * [`ClusterLayer`] - draws point features grouped into clusters depending on the map resolution.
+/// * [`HeatmapLayer`] - draws the density of point features as a heatmap.
+///
+/// Several layers can be combined into a [`LayerGroup`], which is a layer itself.
 pub trait Layer: MaybeSend + MaybeSync {
     /// Renders the layer to the given canvas.
     fn render(&self, view: &MapView, canvas: &mut dyn Canvas);
@@ -49,6 +73,11 @@ pub trait Layer: MaybeSend + MaybeSync {
     }
     /// Returns the attribution of the layer, if available.
     fn attribution(&self) -> Option<Attribution>;
+    /// Returns all attributions of the layer. Layers combining data from several sources (like
+    /// [`LayerGroup`]) return an attribution for each of them.
+    fn attributions(&self) -> Vec<Attribution> {
+        self.attribution().into_iter().collect()
+    }
 }
 
 impl<T: Layer + 'static> Layer for Arc<RwLock<T>> {
@@ -79,6 +108,10 @@ impl<T: Layer + 'static> Layer for Arc<RwLock<T>> {
     fn attribution(&self) -> Option<Attribution> {
         self.read().attribution()
     }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        self.read().attributions()
+    }
 }
 
 /// Used for doc-tests
@@ -1,13 +1,19 @@
 use std::path::{Path, PathBuf};
 
 use bytes::Bytes;
+use web_time::Duration;
 
+#[cfg(feature = "image")]
+use super::{DemEncoding, HillshadeOptions, HillshadeTileLoader};
 use super::{RasterTileLayer, RasterTileLoader, RestTileLoader};
 use crate::error::GalileoError;
 use crate::layer::attribution::Attribution;
 use crate::layer::data_provider::{
-    FileCacheController, FileCachePathModifier, PersistentCacheController, UrlSource,
+    FileCacheController, FileCachePathModifier, PersistentCacheController, RequestSettings,
+    UrlSource,
 };
+use crate::layer::{RetryPolicy, TileRequestQueue};
+use crate::render::ColorAdjustment;
 use crate::tile_schema::TileIndex;
 use crate::{Messenger, TileSchema};
 
@@ -33,11 +39,17 @@ pub struct RasterTileLayerBuilder {
     messenger: Option<Box<dyn Messenger>>,
     cache: CacheType,
     offline_mode: bool,
+    request_settings: RequestSettings,
     attribution: Option<Attribution>,
+    request_queue: Option<TileRequestQueue>,
+    retry_policy: RetryPolicy,
+    color_adjustment: ColorAdjustment,
 }
 
 enum LoaderType {
     Rest(Box<dyn UrlSource<TileIndex>>),
+    #[cfg(feature = "image")]
+    Hillshade(Box<dyn UrlSource<TileIndex>>, DemEncoding, HillshadeOptions),
     Custom(Box<dyn RasterTileLoader>),
 }
 
@@ -69,7 +81,11 @@ impl RasterTileLayerBuilder {
             messenger: None,
             cache: CacheType::None,
             offline_mode: false,
+            request_settings: RequestSettings::default(),
             attribution: None,
+            request_queue: None,
+            retry_policy: RetryPolicy::default(),
+            color_adjustment: ColorAdjustment::default(),
         }
     }
 
@@ -97,10 +113,63 @@ impl RasterTileLayerBuilder {
             messenger: None,
             cache: CacheType::None,
             offline_mode: false,
+            request_settings: RequestSettings::default(),
             attribution: Some(Attribution::new(
                 "© OpenStreetMap contributors".to_string(),
                 Some("https://www.openstreetmap.org/copyright".to_string()),
             )),
+            request_queue: None,
+            retry_policy: RetryPolicy::default(),
+            color_adjustment: ColorAdjustment::default(),
+        }
+    }
+
+    /// Initializes a builder for a layer that renders hillshade from elevation tiles loaded from
+    /// the given url source.
+    ///
+    /// The tile schema of the layer must match the schema of the DEM tiles. Caching, request
+    /// headers and other loading settings of the builder apply to the DEM tiles. See
+    /// [`HillshadeTileLoader`] for details.
+    ///
+    /// ```
+    /// use galileo::layer::raster_tile_layer::{
+    ///     DemEncoding, HillshadeOptions, RasterTileLayerBuilder,
+    /// };
+    ///
+    /// let layer = RasterTileLayerBuilder::new_hillshade(
+    ///     |index| {
+    ///         format!(
+    ///             "https://s3.amazonaws.com/elevation-tiles-prod/terrarium/{}/{}/{}.png",
+    ///             index.z, index.x, index.y
+    ///         )
+    ///     },
+    ///     DemEncoding::Terrarium,
+    ///     HillshadeOptions {
+    ///         exaggeration: 2.0,
+    ///         ..Default::default()
+    ///     },
+    /// )
+    /// .with_tile_schema(galileo::TileSchema::web(15))
+    /// .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    #[cfg(feature = "image")]
+    pub fn new_hillshade(
+        dem_source: impl UrlSource<TileIndex> + 'static,
+        encoding: DemEncoding,
+        options: HillshadeOptions,
+    ) -> Self {
+        Self {
+            loader_type: LoaderType::Hillshade(Box::new(dem_source), encoding, options),
+            tile_schema: None,
+            messenger: None,
+            cache: CacheType::None,
+            offline_mode: false,
+            request_settings: RequestSettings::default(),
+            attribution: None,
+            request_queue: None,
+            retry_policy: RetryPolicy::default(),
+            color_adjustment: ColorAdjustment::default(),
         }
     }
 
@@ -130,7 +199,11 @@ impl RasterTileLayerBuilder {
             messenger: None,
             cache: CacheType::None,
             offline_mode: false,
+            request_settings: RequestSettings::default(),
             attribution: None,
+            request_queue: None,
+            retry_policy: RetryPolicy::default(),
+            color_adjustment: ColorAdjustment::default(),
         }
     }
 
@@ -389,6 +462,107 @@ impl RasterTileLayerBuilder {
         self
     }
 
+    /// Adds the header to every tile request the layer makes.
+    ///
+    /// Some tile servers require requests to identify the application (e.g. with `User-Agent` or
+    /// `Referer` headers) or to carry an API key. Setting a header with the same name again
+    /// replaces the previous value.
+    ///
+    /// Headers can only be set if the layer loads tiles from a url source. Building a layer with a
+    /// custom tile loader and additional headers will return an error.
+    ///
+    /// ```
+    /// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+    ///
+    /// let layer = RasterTileLayerBuilder::new_osm()
+    ///     .with_header("User-Agent", "my-app/1.0 (contact@example.com)")
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request_settings.set_header(name, value);
+        self
+    }
+
+    /// Sets the maximum time to wait for a tile response. Tiles that are not loaded in time are
+    /// treated as failed and are handled according to the retry policy of the layer.
+    ///
+    /// Like headers, the timeout cannot be used together with a custom tile loader.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+    ///
+    /// let layer = RasterTileLayerBuilder::new_osm()
+    ///     .with_request_timeout(Duration::from_secs(10))
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_settings.set_timeout(timeout);
+        self
+    }
+
+    /// Sets the queue the layer puts its tile requests into.
+    ///
+    /// By default each layer creates its own [`TileRequestQueue`]. Setting the same queue for
+    /// several layers makes them share the limit of concurrent requests per host.
+    ///
+    /// ```
+    /// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+    /// use galileo::layer::TileRequestQueue;
+    ///
+    /// let layer = RasterTileLayerBuilder::new_osm()
+    ///     .with_request_queue(TileRequestQueue::new(2))
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_request_queue(mut self, request_queue: TileRequestQueue) -> Self {
+        self.request_queue = Some(request_queue);
+        self
+    }
+
+    /// Sets the way the layer handles tiles that failed to load.
+    ///
+    /// Defaults to [`RetryPolicy::default()`].
+    ///
+    /// ```
+    /// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+    /// use galileo::layer::RetryPolicy;
+    ///
+    /// let layer = RasterTileLayerBuilder::new_osm()
+    ///     .with_retry_policy(RetryPolicy::no_retry())
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the color adjustment applied to the tiles when they are drawn.
+    ///
+    /// The adjustment can be changed later with [`RasterTileLayer::set_color_adjustment`].
+    ///
+    /// ```
+    /// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+    /// use galileo::render::ColorAdjustment;
+    ///
+    /// let layer = RasterTileLayerBuilder::new_osm()
+    ///     .with_color_adjustment(ColorAdjustment {
+    ///         grayscale: 1.0,
+    ///         opacity: 0.7,
+    ///         ..Default::default()
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_color_adjustment(mut self, color_adjustment: ColorAdjustment) -> Self {
+        self.color_adjustment = color_adjustment;
+        self
+    }
+
     /// Consumes the builder and constructs the raster tile layer.
     ///
     /// Will return an error if the layer is configured incorrectly or if the cache controller
@@ -400,10 +574,15 @@ impl RasterTileLayerBuilder {
             messenger,
             cache,
             offline_mode,
+            request_settings,
             attribution,
+            request_queue,
+            retry_policy,
+            color_adjustment,
         } = self;
 
         let tile_schema = tile_schema.unwrap_or_else(|| TileSchema::web(18));
+        let request_queue = request_queue.unwrap_or_default();
 
         let cache_controller: Option<Box<dyn PersistentCacheController<str, Bytes>>> = match cache {
             CacheType::None => None,
@@ -420,11 +599,20 @@ impl RasterTileLayerBuilder {
         }
 
         let provider: Box<dyn RasterTileLoader> = match provider_type {
-            LoaderType::Rest(url_source) => Box::new(RestTileLoader::new(
-                url_source,
-                cache_controller,
-                offline_mode,
-            )),
+            LoaderType::Rest(url_source) => Box::new(
+                RestTileLoader::new(url_source, cache_controller, offline_mode)
+                    .with_request_settings(request_settings),
+            ),
+            #[cfg(feature = "image")]
+            LoaderType::Hillshade(url_source, encoding, options) => {
+                let dem_loader = RestTileLoader::new(url_source, cache_controller, offline_mode)
+                    .with_request_settings(request_settings);
+                Box::new(
+                    HillshadeTileLoader::new(dem_loader, encoding, tile_schema.clone())
+                        .with_options(options)
+                        .with_request_queue(request_queue.clone()),
+                )
+            }
             LoaderType::Custom(raster_tile_provider) => {
                 if cache_controller.is_some() {
                     return Err(GalileoError::Configuration(
@@ -433,6 +621,13 @@ impl RasterTileLayerBuilder {
                     ));
                 }
 
+                if !request_settings.is_empty() {
+                    return Err(GalileoError::Configuration(
+                        "custom tile provider cannot be used together with request headers or timeout"
+                            .into(),
+                    ));
+                }
+
                 raster_tile_provider
             }
         };
@@ -442,6 +637,9 @@ impl RasterTileLayerBuilder {
             tile_schema,
             messenger,
             attribution,
+            request_queue,
+            retry_policy,
+            color_adjustment,
         ))
     }
 }
@@ -527,6 +725,28 @@ mod tests {
         assert_compact_debug_snapshot!(result, @r#"Err(Configuration("offline mode cannot be used without cache"))"#);
     }
 
+    #[test]
+    fn with_header_fails_build_if_custom_provider() {
+        let provider = RestTileLoader::new(|_| unimplemented!(), None, false);
+        let result = RasterTileLayerBuilder::new_with_loader(provider)
+            .with_header("User-Agent", "galileo")
+            .build();
+
+        assert!(result.is_err());
+        assert_compact_debug_snapshot!(result, @r#"Err(Configuration("custom tile provider cannot be used together with request headers or timeout"))"#);
+    }
+
+    #[test]
+    fn with_header_replaces_previous_value() {
+        let builder = RasterTileLayerBuilder::new_rest(|_| unimplemented!())
+            .with_header("User-Agent", "first")
+            .with_header("user-agent", "second")
+            .with_request_timeout(Duration::from_secs(5));
+
+        let request = builder.request_settings.request("url".into());
+        assert_compact_debug_snapshot!(request, @r#"HttpRequest { url: "url", headers: [("user-agent", "second")], timeout: Some(5s) }"#);
+    }
+
     #[test]
     fn default_tile_schema() {
         let layer = RasterTileLayerBuilder::new_rest(|_| unimplemented!())
@@ -535,4 +755,18 @@ mod tests {
 
         assert_eq!(*layer.tile_schema(), TileSchema::web(18));
     }
+
+    #[test]
+    fn with_color_adjustment_sets_layer_adjustment() {
+        let adjustment = ColorAdjustment {
+            saturation: 0.5,
+            ..Default::default()
+        };
+        let layer = RasterTileLayerBuilder::new_osm()
+            .with_color_adjustment(adjustment)
+            .build()
+            .unwrap();
+
+        assert_eq!(layer.color_adjustment(), adjustment);
+    }
 }
@@ -0,0 +1,147 @@
+//! Elevation (DEM) tiles shared by the hillshade layer and the terrain.
+
+use serde::{Deserialize, Serialize};
+
+use super::RestTileLoader;
+use crate::decoded_image::DecodedImage;
+use crate::error::GalileoError;
+use crate::tile_schema::TileIndex;
+
+/// Radius of the Earth used by the Web Mercator projection.
+pub(crate) const EARTH_RADIUS: f64 = 6378137.0;
+/// Length of one degree of a meridian in meters.
+pub(crate) const METERS_PER_DEGREE: f64 = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+
+/// Number of meters on the ground in one unit of the Web Mercator projection at the given `y`
+/// coordinate.
+pub(crate) fn web_mercator_scale(y: f64) -> f64 {
+    (y / EARTH_RADIUS).sinh().atan().cos()
+}
+
+/// Encoding of elevation values in the pixels of DEM tile images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DemEncoding {
+    /// Mapbox terrain-RGB: `-10000 + (R * 65536 + G * 256 + B) * 0.1` meters.
+    TerrainRgb,
+    /// Terrarium (used by AWS elevation tiles): `R * 256 + G + B / 256 - 32768` meters.
+    Terrarium,
+}
+
+impl DemEncoding {
+    /// Decodes the elevation in meters from the pixel color.
+    pub fn elevation(&self, r: u8, g: u8, b: u8) -> f32 {
+        let (r, g, b) = (r as f64, g as f64, b as f64);
+        let elevation = match self {
+            Self::TerrainRgb => -10000.0 + (r * 65536.0 + g * 256.0 + b) * 0.1,
+            Self::Terrarium => r * 256.0 + g + b / 256.0 - 32768.0,
+        };
+
+        elevation as f32
+    }
+}
+
+/// Grid of elevations decoded from a DEM tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemTile {
+    width: u32,
+    height: u32,
+    elevations: Vec<f32>,
+}
+
+impl DemTile {
+    /// Decodes the elevations from the image pixels.
+    ///
+    /// Returns an error if the pixels of the image are not accessible (e.g. images decoded by the
+    /// browser).
+    pub fn from_image(image: &DecodedImage, encoding: DemEncoding) -> Result<Self, GalileoError> {
+        let Some(bytes) = image.bitmap_bytes() else {
+            return Err(GalileoError::Generic(
+                "pixels of the DEM tile image are not accessible".into(),
+            ));
+        };
+
+        let elevations = bytes
+            .chunks_exact(4)
+            .map(|pixel| encoding.elevation(pixel[0], pixel[1], pixel[2]))
+            .collect();
+
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            elevations,
+        })
+    }
+
+    /// Creates a grid from the elevations given row by row from the top.
+    #[cfg(test)]
+    pub(crate) fn new(width: u32, height: u32, elevations: Vec<f32>) -> Self {
+        assert_eq!(elevations.len(), (width * height) as usize);
+        Self {
+            width,
+            height,
+            elevations,
+        }
+    }
+
+    /// Downloads the DEM tile with the loader and decodes the elevations. Returns `None` if the
+    /// tile does not exist.
+    pub(crate) async fn load(
+        loader: &RestTileLoader,
+        index: TileIndex,
+        encoding: DemEncoding,
+    ) -> Result<Option<Self>, GalileoError> {
+        let bytes = match loader.download_tile(index).await {
+            Ok(bytes) => bytes,
+            Err(GalileoError::NotFound) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let image = DecodedImage::decode(&bytes)?;
+        Ok(Some(Self::from_image(&image, encoding)?))
+    }
+
+    /// Width of the grid.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the grid.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Elevation in meters at the given cell. Rows are counted from the top.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is outside of the grid.
+    pub fn elevation(&self, x: u32, y: u32) -> f32 {
+        self.elevations[(y * self.width + x) as usize]
+    }
+
+    /// All elevations of the grid, row by row from the top.
+    pub fn elevations(&self) -> &[f32] {
+        &self.elevations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use galileo_types::cartesian::Size;
+
+    use super::*;
+
+    #[test]
+    fn decode_elevations() {
+        assert_eq!(DemEncoding::TerrainRgb.elevation(1, 134, 160), 0.0);
+        assert_eq!(DemEncoding::Terrarium.elevation(128, 0, 0), 0.0);
+        assert_eq!(DemEncoding::Terrarium.elevation(128, 100, 128), 100.5);
+
+        let image =
+            DecodedImage::from_raw(vec![1, 134, 160, 255, 1, 134, 170, 255], Size::new(2, 1))
+                .unwrap();
+        let tile = DemTile::from_image(&image, DemEncoding::TerrainRgb).unwrap();
+        assert_eq!(tile.elevation(0, 0), 0.0);
+        assert_eq!(tile.elevation(1, 0), 1.0);
+    }
+}
@@ -0,0 +1,443 @@
+//! Hillshade rendering of elevation (DEM) tiles.
+
+use std::sync::Arc;
+
+use futures::lock::Mutex;
+use galileo_types::cartesian::{Rect, Size};
+use galileo_types::geo::Crs;
+use quick_cache::sync::Cache;
+use serde::{Deserialize, Serialize};
+
+use super::dem::{web_mercator_scale, DemEncoding, DemTile, METERS_PER_DEGREE};
+use super::{RasterTileLoader, RestTileLoader};
+use crate::decoded_image::DecodedImage;
+use crate::error::GalileoError;
+use crate::layer::tiles::TileRequestQueue;
+use crate::tile_schema::TileIndex;
+use crate::{Color, TileSchema};
+
+/// Number of decoded DEM tiles kept in memory to be reused as neighbors of other tiles.
+const DEM_CACHE_SIZE: usize = 256;
+
+/// Parameters of hillshade rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HillshadeOptions {
+    /// Direction the light comes from, in degrees clockwise from north. Defaults to 315
+    /// (north-west).
+    pub azimuth: f64,
+    /// Angle of the light source above the horizon, in degrees. Defaults to 45.
+    pub altitude: f64,
+    /// Multiplier of elevation differences. Defaults to 1.0.
+    pub exaggeration: f64,
+    /// Color of the slopes facing away from the light. The alpha of the color is reached on
+    /// surfaces that get no light at all. Defaults to black.
+    pub shadow_color: Color,
+    /// Color of the slopes facing the light. The alpha of the color is reached on surfaces
+    /// perpendicular to the light direction. Defaults to semi-transparent white.
+    pub highlight_color: Color,
+}
+
+impl Default for HillshadeOptions {
+    fn default() -> Self {
+        Self {
+            azimuth: 315.0,
+            altitude: 45.0,
+            exaggeration: 1.0,
+            shadow_color: Color::BLACK,
+            highlight_color: Color::WHITE.with_alpha(128),
+        }
+    }
+}
+
+/// Raster tile loader that renders hillshade from elevation tiles.
+///
+/// The loader requests DEM tiles with the given [`RestTileLoader`], decodes elevations from them
+/// and renders shading of the relief into an image. Flat areas are fully transparent, so the
+/// layer can be drawn on top of other layers.
+///
+/// To calculate slopes at the edges of a tile, the neighboring DEM tiles are loaded as well. The
+/// decoded tiles are cached in memory, so the neighbors are requested only once. If a neighbor
+/// does not exist or fails to load, the edge of the tile is extended instead.
+///
+/// When the loader is created by the layer builder, the neighbors are loaded through the request
+/// queue of the layer: they use free slots of the queue if there are any, and otherwise they are
+/// loaded one by one in the slot of the tile being rendered.
+///
+/// Usually the layer is created with [`RasterTileLayerBuilder::new_hillshade`](super::RasterTileLayerBuilder::new_hillshade).
+pub struct HillshadeTileLoader {
+    dem_loader: RestTileLoader,
+    encoding: DemEncoding,
+    tile_schema: TileSchema,
+    options: HillshadeOptions,
+    dem_tiles: Cache<TileIndex, Option<Arc<DemTile>>>,
+    request_queue: Option<TileRequestQueue>,
+}
+
+impl HillshadeTileLoader {
+    /// Creates a new loader. The tile schema must be the schema of the DEM tiles.
+    pub fn new(dem_loader: RestTileLoader, encoding: DemEncoding, tile_schema: TileSchema) -> Self {
+        Self {
+            dem_loader,
+            encoding,
+            tile_schema,
+            options: HillshadeOptions::default(),
+            dem_tiles: Cache::new(DEM_CACHE_SIZE),
+            request_queue: None,
+        }
+    }
+
+    /// Sets the hillshade rendering parameters.
+    pub fn with_options(mut self, options: HillshadeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the queue the neighbor tiles are loaded through. Must be the queue of the layer.
+    pub(crate) fn with_request_queue(mut self, request_queue: TileRequestQueue) -> Self {
+        self.request_queue = Some(request_queue);
+        self
+    }
+
+    /// Loads the neighbor DEM tile. `own_slot` is the slot of the tile being rendered, which is
+    /// used if the queue has no free slots.
+    async fn neighbor_tile(&self, index: TileIndex, own_slot: &Mutex<()>) -> Option<Arc<DemTile>> {
+        let load = async {
+            let Some(queue) = &self.request_queue else {
+                return self.load_dem_tile(index).await;
+            };
+
+            let host = self.dem_loader.request_host(index);
+            match queue.try_take_slot(host.as_deref()) {
+                Some(_slot) => self.load_dem_tile(index).await,
+                None => {
+                    let _slot = own_slot.lock().await;
+                    self.load_dem_tile(index).await
+                }
+            }
+        };
+
+        self.dem_tiles
+            .get_or_insert_async(&index, load)
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Loads and decodes the DEM tile, using the cached one if available. Returns `None` if the
+    /// tile does not exist.
+    async fn dem_tile(&self, index: TileIndex) -> Result<Option<Arc<DemTile>>, GalileoError> {
+        self.dem_tiles
+            .get_or_insert_async(&index, self.load_dem_tile(index))
+            .await
+    }
+
+    async fn load_dem_tile(&self, index: TileIndex) -> Result<Option<Arc<DemTile>>, GalileoError> {
+        let tile = DemTile::load(&self.dem_loader, index, self.encoding).await?;
+        Ok(tile.map(Arc::new))
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+impl RasterTileLoader for HillshadeTileLoader {
+    async fn load(&self, index: TileIndex) -> Result<DecodedImage, GalileoError> {
+        let tile_bbox = self
+            .tile_schema
+            .tile_bbox(index.into_wrapping())
+            .ok_or(GalileoError::NotFound)?;
+        let center = self.dem_tile(index).await?.ok_or(GalileoError::NotFound)?;
+
+        let own_slot = Mutex::new(());
+        let neighbors = futures::future::join_all(NEIGHBOR_OFFSETS.iter().map(|(dx, dy)| async {
+            let neighbor = self.tile_schema.neighbor(index, *dx, *dy)?;
+            self.neighbor_tile(neighbor, &own_slot).await
+        }))
+        .await;
+
+        let grid = PaddedGrid::new(&center, &neighbors);
+        let cell_size = CellSize::new(&self.tile_schema.crs, tile_bbox, &center);
+
+        hillshade(&grid, &cell_size, &self.options)
+    }
+
+    fn request_host(&self, index: TileIndex) -> Option<String> {
+        self.dem_loader.request_host(index)
+    }
+}
+
+/// Offsets of the neighbor tiles in the order they are stored in [`PaddedGrid`].
+const NEIGHBOR_OFFSETS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// Elevations of a tile with one cell wide border taken from the neighboring tiles.
+struct PaddedGrid {
+    width: u32,
+    height: u32,
+    elevations: Vec<f32>,
+}
+
+impl PaddedGrid {
+    fn new(center: &DemTile, neighbors: &[Option<Arc<DemTile>>]) -> Self {
+        let (w, h) = (center.width() as i32, center.height() as i32);
+        let neighbor = |dx: i32, dy: i32| {
+            let position = NEIGHBOR_OFFSETS.iter().position(|o| *o == (dx, dy))?;
+            neighbors
+                .get(position)?
+                .as_deref()
+                .filter(|tile| tile.width() == center.width() && tile.height() == center.height())
+        };
+
+        let mut elevations = Vec::with_capacity(((w + 2) * (h + 2)) as usize);
+        for y in -1..=h {
+            for x in -1..=w {
+                let dx = if x < 0 { -1 } else { (x >= w) as i32 };
+                let dy = if y < 0 { -1 } else { (y >= h) as i32 };
+
+                let elevation = match (dx, dy) {
+                    (0, 0) => center.elevation(x as u32, y as u32),
+                    _ => match neighbor(dx, dy) {
+                        Some(tile) => tile.elevation((x - dx * w) as u32, (y - dy * h) as u32),
+                        None => {
+                            center.elevation(x.clamp(0, w - 1) as u32, y.clamp(0, h - 1) as u32)
+                        }
+                    },
+                };
+                elevations.push(elevation);
+            }
+        }
+
+        Self {
+            width: center.width(),
+            height: center.height(),
+            elevations,
+        }
+    }
+
+    /// Elevation at the cell of the center tile. Coordinates from -1 to width (height) inclusive
+    /// are allowed.
+    fn get(&self, x: i32, y: i32) -> f64 {
+        self.elevations[((y + 1) * (self.width as i32 + 2) + x + 1) as usize] as f64
+    }
+}
+
+/// Ground size of the DEM cells in meters.
+struct CellSize {
+    width: f64,
+    height: f64,
+    /// Coordinate of the top edge of the tile in the CRS of the tile schema.
+    y_max: f64,
+    kind: CellSizeKind,
+}
+
+enum CellSizeKind {
+    /// Cells are in meters, but the scale depends on latitude.
+    WebMercator,
+    /// Cells are in degrees.
+    Geographic,
+    /// Cells are in meters with constant scale.
+    Projected,
+}
+
+impl CellSize {
+    fn new(crs: &Crs, tile_bbox: Rect, tile: &DemTile) -> Self {
+        let kind = if *crs == Crs::EPSG3857 {
+            CellSizeKind::WebMercator
+        } else if *crs == Crs::WGS84 {
+            CellSizeKind::Geographic
+        } else {
+            CellSizeKind::Projected
+        };
+
+        Self {
+            width: tile_bbox.width() / tile.width() as f64,
+            height: tile_bbox.height() / tile.height() as f64,
+            y_max: tile_bbox.y_max(),
+            kind,
+        }
+    }
+
+    /// Returns width and height of the cell in the given row in meters.
+    fn at_row(&self, row: u32) -> (f64, f64) {
+        let y = self.y_max - (row as f64 + 0.5) * self.height;
+        match self.kind {
+            CellSizeKind::WebMercator => {
+                let scale = web_mercator_scale(y);
+                (self.width * scale, self.height * scale)
+            }
+            CellSizeKind::Geographic => {
+                let scale = y.to_radians().cos();
+                (
+                    self.width * METERS_PER_DEGREE * scale,
+                    self.height * METERS_PER_DEGREE,
+                )
+            }
+            CellSizeKind::Projected => (self.width, self.height),
+        }
+    }
+}
+
+/// Renders the hillshade image of the center tile of the grid.
+fn hillshade(
+    grid: &PaddedGrid,
+    cell_size: &CellSize,
+    options: &HillshadeOptions,
+) -> Result<DecodedImage, GalileoError> {
+    let azimuth = options.azimuth.to_radians();
+    let altitude = options.altitude.to_radians();
+    let light = [
+        azimuth.sin() * altitude.cos(),
+        azimuth.cos() * altitude.cos(),
+        altitude.sin(),
+    ];
+    let flat = light[2].max(f64::EPSILON);
+
+    let shadow = options.shadow_color.to_u8_array();
+    let highlight = options.highlight_color.to_u8_array();
+
+    let mut bytes = Vec::with_capacity((grid.width * grid.height * 4) as usize);
+    for y in 0..grid.height {
+        let (cell_w, cell_h) = cell_size.at_row(y);
+        let y = y as i32;
+
+        for x in 0..grid.width as i32 {
+            let e = |dx: i32, dy: i32| grid.get(x + dx, y + dy);
+
+            // Horn's method. Y axis is directed to the north.
+            let dz_dx = ((e(1, -1) + 2.0 * e(1, 0) + e(1, 1))
+                - (e(-1, -1) + 2.0 * e(-1, 0) + e(-1, 1)))
+                / (8.0 * cell_w);
+            let dz_dy = ((e(-1, -1) + 2.0 * e(0, -1) + e(1, -1))
+                - (e(-1, 1) + 2.0 * e(0, 1) + e(1, 1)))
+                / (8.0 * cell_h);
+
+            let normal = [
+                -dz_dx * options.exaggeration,
+                -dz_dy * options.exaggeration,
+                1.0,
+            ];
+            let length = (normal[0] * normal[0] + normal[1] * normal[1] + 1.0).sqrt();
+            let shade = ((normal[0] * light[0] + normal[1] * light[1] + normal[2] * light[2])
+                / length)
+                .max(0.0);
+
+            let (color, strength) = if shade < flat {
+                (shadow, (flat - shade) / flat)
+            } else {
+                (highlight, (shade - flat) / (1.0 - flat).max(f64::EPSILON))
+            };
+
+            let alpha = (color[3] as f64 * strength.clamp(0.0, 1.0)).round() as u8;
+            bytes.extend_from_slice(&[color[0], color[1], color[2], alpha]);
+        }
+    }
+
+    DecodedImage::from_raw(bytes, Size::new(grid.width, grid.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dem_tile(width: u32, height: u32, elevation: impl Fn(u32, u32) -> f32) -> DemTile {
+        DemTile::new(
+            width,
+            height,
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| elevation(x, y))
+                .collect(),
+        )
+    }
+
+    fn pixel(image: &DecodedImage, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * image.width() + x) * 4) as usize;
+        let bytes = image.bitmap_bytes().expect("bitmap image");
+        [
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ]
+    }
+
+    fn alpha(image: &DecodedImage, x: u32, y: u32) -> u8 {
+        pixel(image, x, y)[3]
+    }
+
+    fn cell_size() -> CellSize {
+        CellSize {
+            width: 10.0,
+            height: 10.0,
+            y_max: 0.0,
+            kind: CellSizeKind::Projected,
+        }
+    }
+
+    #[test]
+    fn flat_area_is_transparent() {
+        let tile = dem_tile(4, 4, |_, _| 100.0);
+        let grid = PaddedGrid::new(&tile, &[]);
+        let image = hillshade(&grid, &cell_size(), &HillshadeOptions::default()).unwrap();
+
+        for (x, y) in [(0, 0), (1, 2), (3, 3)] {
+            assert_eq!(alpha(&image, x, y), 0);
+        }
+    }
+
+    #[test]
+    fn slope_facing_light_is_highlighted() {
+        let options = HillshadeOptions {
+            azimuth: 90.0,
+            ..Default::default()
+        };
+
+        // Elevation grows to the west, so the slope faces the light coming from the east.
+        let tile = dem_tile(4, 4, |x, _| 40.0 - x as f32 * 10.0);
+        let grid = PaddedGrid::new(&tile, &[]);
+        let image = hillshade(&grid, &cell_size(), &options).unwrap();
+        let [r, g, b, a] = pixel(&image, 1, 1);
+        assert_eq!([r, g, b], [255, 255, 255]);
+        assert!(a > 0);
+
+        let tile = dem_tile(4, 4, |x, _| x as f32 * 10.0);
+        let grid = PaddedGrid::new(&tile, &[]);
+        let image = hillshade(&grid, &cell_size(), &options).unwrap();
+        let [r, g, b, a] = pixel(&image, 1, 1);
+        assert_eq!([r, g, b], [0, 0, 0]);
+        assert!(a > 0);
+    }
+
+    #[test]
+    fn edges_use_neighbor_tiles() {
+        // A plane sloping to the east, continuous across tiles.
+        let slope = |tile_x: i32| move |x: u32, _| (tile_x * 4 + x as i32) as f32 * 5.0;
+        let center = dem_tile(4, 4, slope(0));
+        let neighbors: Vec<_> = NEIGHBOR_OFFSETS
+            .iter()
+            .map(|(dx, _)| Some(Arc::new(dem_tile(4, 4, slope(*dx)))))
+            .collect();
+
+        let options = HillshadeOptions::default();
+        let grid = PaddedGrid::new(&center, &neighbors);
+        let image = hillshade(&grid, &cell_size(), &options).unwrap();
+        let interior = alpha(&image, 1, 1);
+        assert!(interior > 0);
+        for (x, y) in [(0, 0), (3, 0), (0, 3), (3, 3), (0, 1)] {
+            assert_eq!(alpha(&image, x, y), interior);
+        }
+
+        // Without neighbors the slope at the edges is underestimated.
+        let grid = PaddedGrid::new(&center, &[]);
+        let image = hillshade(&grid, &cell_size(), &options).unwrap();
+        assert_ne!(alpha(&image, 0, 1), interior);
+    }
+}
@@ -3,16 +3,21 @@
 use std::any::Any;
 use std::sync::Arc;
 
-use galileo_types::cartesian::Vector2;
+use galileo_types::cartesian::{CartesianPoint2d, Point2, Vector2};
 use provider::RasterTileProvider;
 use web_time::Duration;
 
-use super::tiles::TilesContainer;
+use super::tiles::{
+    RequestSlot, RequestSourceId, RetryPolicy, TilePriority, TileRequest, TileRequestQueue,
+    TilesContainer,
+};
 use super::Layer;
+use crate::error::GalileoError;
 use crate::layer::attribution::Attribution;
 use crate::messenger::Messenger;
-use crate::render::{BundleToDraw, Canvas, RenderOptions};
-use crate::tile_schema::{TileIndex, TileSchema};
+use crate::render::{BundleToDraw, Canvas, ColorAdjustment, RenderOptions};
+use crate::reprojection::CrsTransform;
+use crate::tile_schema::{TileIndex, TileSchema, WrappingTileIndex};
 use crate::view::MapView;
 
 mod provider;
@@ -21,6 +26,22 @@ pub use provider::{RasterTileLoader, RestTileLoader};
 mod builder;
 pub use builder::RasterTileLayerBuilder;
 
+#[cfg(feature = "image")]
+pub(crate) mod dem;
+#[cfg(feature = "image")]
+pub use dem::{DemEncoding, DemTile};
+
+#[cfg(feature = "image")]
+mod hillshade;
+#[cfg(feature = "image")]
+pub use hillshade::{HillshadeOptions, HillshadeTileLoader};
+
+mod wmts;
+pub use wmts::{
+    TileMatrix, TileMatrixLimits, TileMatrixSet, TileMatrixSetLink, WmtsCapabilities,
+    WmtsLayerInfo, WmtsResourceUrl, WmtsStyle,
+};
+
 /// Raster tile layers load prerendered tile sets using [tile loader](RasterTileLoader) and render them to the map.
 pub struct RasterTileLayer {
     tile_loader: Arc<dyn RasterTileLoader>,
@@ -28,6 +49,9 @@ pub struct RasterTileLayer {
     tile_schema: TileSchema,
     messenger: Option<Arc<dyn Messenger>>,
     attribution: Option<Attribution>,
+    request_queue: TileRequestQueue,
+    request_source: RequestSourceId,
+    color_adjustment: ColorAdjustment,
 }
 
 impl std::fmt::Debug for RasterTileLayer {
@@ -49,11 +73,14 @@ impl RasterTileLayer {
             tile_loader: Arc::new(tile_loader),
             tile_container: Arc::new(TilesContainer::new(
                 tile_schema.clone(),
-                RasterTileProvider::new(tile_schema.clone()),
+                RasterTileProvider::new(tile_schema.clone(), RetryPolicy::default()),
             )),
             tile_schema,
             messenger,
             attribution: None,
+            request_queue: TileRequestQueue::default(),
+            request_source: RequestSourceId::next_id(),
+            color_adjustment: ColorAdjustment::default(),
         }
     }
 
@@ -62,16 +89,22 @@ impl RasterTileLayer {
         tile_schema: TileSchema,
         messenger: Option<Box<dyn Messenger>>,
         attribution: Option<Attribution>,
+        request_queue: TileRequestQueue,
+        retry_policy: RetryPolicy,
+        color_adjustment: ColorAdjustment,
     ) -> Self {
         Self {
             tile_loader: tile_loader.into(),
             tile_container: Arc::new(TilesContainer::new(
                 tile_schema.clone(),
-                RasterTileProvider::new(tile_schema.clone()),
+                RasterTileProvider::new(tile_schema.clone(), retry_policy),
             )),
             tile_schema,
             messenger: messenger.map(|m| m.into()),
             attribution,
+            request_queue,
+            request_source: RequestSourceId::next_id(),
+            color_adjustment,
         }
     }
 
@@ -80,12 +113,44 @@ impl RasterTileLayer {
         self.tile_container.set_fade_in_duration(duration);
     }
 
+    /// Returns the color adjustment applied to the tiles.
+    pub fn color_adjustment(&self) -> ColorAdjustment {
+        self.color_adjustment
+    }
+
+    /// Sets the color adjustment applied to the tiles. The change is applied on the next redraw
+    /// without reloading the tiles.
+    pub fn set_color_adjustment(&mut self, color_adjustment: ColorAdjustment) {
+        self.color_adjustment = color_adjustment;
+        if let Some(messenger) = &self.messenger {
+            messenger.request_redraw();
+        }
+    }
+
+    /// Returns the indices of the tiles needed to display the view.
+    ///
+    /// If the view CRS is different from the CRS of the tile schema, the tiles covering the view
+    /// are selected in the schema CRS and then warped into the view CRS.
+    fn needed_tiles(&self, view: &MapView) -> Option<Vec<WrappingTileIndex>> {
+        if *view.crs() == self.tile_schema.crs {
+            Some(self.tile_schema.iter_tiles(view)?.collect())
+        } else {
+            Some(self.tile_schema.iter_tiles_reprojected(view)?.collect())
+        }
+    }
+
     fn update_displayed_tiles(&self, view: &MapView, canvas: &dyn Canvas) {
-        let Some(tile_iter) = self.tile_schema.iter_tiles(view) else {
+        let crs_changed = self.tile_container.tile_provider.set_target_crs(view.crs());
+        if crs_changed {
+            // Tiles rendered for another CRS would be drawn at wrong positions. They are rendered
+            // again from the decoded images below.
+            self.tile_container.tiles.lock().clear();
+        }
+
+        let Some(needed_indices) = self.needed_tiles(view) else {
             return;
         };
 
-        let needed_indices: Vec<_> = tile_iter.collect();
         let mut to_pack: Vec<TileIndex> = needed_indices.iter().map(|t| (*t).into()).collect();
         to_pack.dedup();
 
@@ -95,6 +160,10 @@ impl RasterTileLayer {
         let requires_redraw = self
             .tile_container
             .update_displayed_tiles(needed_indices, ());
+        if crs_changed {
+            // The tiles were already displayed before the change, so they should not fade in.
+            self.tile_container.skip_fade_in();
+        }
 
         if requires_redraw {
             if let Some(messenger) = &self.messenger {
@@ -108,13 +177,22 @@ impl RasterTileLayer {
         tile_loader: Arc<dyn RasterTileLoader>,
         tiles: Arc<TilesContainer<(), RasterTileProvider>>,
         messenger: Option<Arc<dyn Messenger>>,
+        slot: Option<RequestSlot>,
     ) {
         if tiles.tile_provider.set_loading(index) {
             // Already loading
             return;
         }
 
-        let load_result = tile_loader.load(index).await;
+        let load_result = tiles
+            .tile_provider
+            .retry_policy()
+            .run(
+                || tile_loader.load(index),
+                |err| !matches!(err, GalileoError::NotFound | GalileoError::Offline),
+                slot.as_ref(),
+            )
+            .await;
 
         match load_result {
             Ok(decoded_image) => {
@@ -124,6 +202,14 @@ impl RasterTileLayer {
                     messenger.request_redraw();
                 }
             }
+            Err(GalileoError::NotFound) => {
+                log::debug!("Tile {index:?} does not exist");
+                tiles.tile_provider.set_empty(index);
+
+                if let Some(messenger) = messenger {
+                    messenger.request_redraw();
+                }
+            }
             Err(err) => {
                 log::debug!("Failed to load tile: {err}");
                 tiles.tile_provider.set_error(index);
@@ -133,8 +219,8 @@ impl RasterTileLayer {
 
     /// Preload tiles for the given `view`.
     pub async fn load_tiles(&self, view: &MapView) {
-        if let Some(iter) = self.tile_schema.iter_tiles(view) {
-            for index in iter {
+        if let Some(indices) = self.needed_tiles(view) {
+            for index in indices {
                 let tile_provider = self.tile_loader.clone();
                 let messenger = self.messenger.clone();
                 Self::load_tile(
@@ -142,6 +228,7 @@ impl RasterTileLayer {
                     tile_provider,
                     self.tile_container.clone(),
                     messenger,
+                    None,
                 )
                 .await;
             }
@@ -154,35 +241,76 @@ impl RasterTileLayer {
     }
 }
 
+impl Drop for RasterTileLayer {
+    fn drop(&mut self) {
+        self.request_queue.cancel(self.request_source);
+    }
+}
+
 impl Layer for RasterTileLayer {
     fn render(&self, view: &MapView, canvas: &mut dyn Canvas) {
         self.update_displayed_tiles(view, canvas);
 
+        // Bundles are positioned relative to the top left corners of the tiles.
+        let Some(transform) = CrsTransform::new(&self.tile_schema.crs, view.crs()) else {
+            return;
+        };
+
         let displayed_tiles = self.tile_container.tiles.lock();
         let to_render: Vec<_> = displayed_tiles
             .iter()
             .filter_map(|v| {
                 let tile_bbox = self.tile_schema.tile_bbox(v.index)?;
-                let offset = Vector2::new(tile_bbox.x_min() as f32, tile_bbox.y_max() as f32);
+                let top_left =
+                    transform.transform(&Point2::new(tile_bbox.x_min(), tile_bbox.y_max()))?;
+                let offset = Vector2::new(top_left.x() as f32, top_left.y() as f32);
 
-                Some(BundleToDraw::new(&*v.bundle, v.opacity, offset))
+                Some(BundleToDraw::new(
+                    &*v.bundle,
+                    v.opacity * self.color_adjustment.opacity,
+                    offset,
+                ))
             })
             .collect();
 
-        canvas.draw_bundles(&to_render, RenderOptions::default());
+        canvas.draw_bundles_with_color_adjustment(
+            &to_render,
+            RenderOptions::default(),
+            &self.color_adjustment,
+        );
     }
 
     fn prepare(&self, view: &MapView) {
-        if let Some(iter) = self.tile_schema.iter_tiles(view) {
-            for index in iter {
-                let tile_provider = self.tile_loader.clone();
-                let container = self.tile_container.clone();
-                let messenger = self.messenger.clone();
-                crate::async_runtime::spawn(async move {
-                    Self::load_tile(index.into(), tile_provider, container, messenger).await;
-                });
+        let Some(indices) = self.needed_tiles(view) else {
+            return;
+        };
+
+        let requests = indices.into_iter().filter_map(|index| {
+            let tile_index = TileIndex::from(index);
+            if self.tile_container.tile_provider.contains(tile_index) {
+                return None;
             }
-        }
+
+            Some(TileRequest {
+                index: tile_index,
+                priority: TilePriority::new(index, &self.tile_schema, view),
+                host: self.tile_loader.request_host(tile_index),
+            })
+        });
+
+        let tile_loader = self.tile_loader.clone();
+        let container = self.tile_container.clone();
+        let messenger = self.messenger.clone();
+        self.request_queue
+            .request_tiles(self.request_source, requests, move |index, slot| {
+                Self::load_tile(
+                    index,
+                    tile_loader.clone(),
+                    container.clone(),
+                    messenger.clone(),
+                    Some(slot),
+                )
+            });
     }
 
     fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
@@ -205,3 +333,214 @@ impl Layer for RasterTileLayer {
         self.attribution.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use galileo_types::cartesian::Size;
+    use galileo_types::geo::Crs;
+
+    use super::provider::TileState;
+    use super::*;
+    use crate::decoded_image::DecodedImage;
+    use crate::layer::tiles::TileProvider;
+    use crate::render::render_bundle::RenderBundle;
+    use crate::render::PackedBundle;
+
+    struct NoopBundle;
+
+    impl PackedBundle for NoopBundle {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    struct NoopCanvas;
+
+    impl Canvas for NoopCanvas {
+        fn size(&self) -> Size {
+            Size::new(256.0, 256.0)
+        }
+
+        fn pack_bundle(&self, _bundle: &RenderBundle) -> Box<dyn PackedBundle> {
+            Box::new(NoopBundle)
+        }
+
+        fn draw_bundles(&mut self, _bundles: &[BundleToDraw], _options: RenderOptions) {}
+
+        fn draw_screen_sets(&mut self) -> bool {
+            false
+        }
+    }
+
+    struct FlakyLoader {
+        failures: u32,
+        error: GalileoError,
+        attempts: Arc<AtomicU32>,
+    }
+
+    #[async_trait::async_trait]
+    impl RasterTileLoader for FlakyLoader {
+        async fn load(&self, _index: TileIndex) -> Result<DecodedImage, GalileoError> {
+            let attempt = self.attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            if attempt <= self.failures {
+                Err(self.error.clone())
+            } else {
+                DecodedImage::from_raw(vec![0; 4], Size::new(1, 1))
+            }
+        }
+    }
+
+    fn test_layer(
+        failures: u32,
+        error: GalileoError,
+        retry_policy: RetryPolicy,
+    ) -> (RasterTileLayer, Arc<AtomicU32>) {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let loader = FlakyLoader {
+            failures,
+            error,
+            attempts: attempts.clone(),
+        };
+        let layer = RasterTileLayer::new_raw(
+            Box::new(loader),
+            TileSchema::web(18),
+            None,
+            None,
+            TileRequestQueue::default(),
+            retry_policy,
+            ColorAdjustment::default(),
+        );
+
+        (layer, attempts)
+    }
+
+    fn fast_retry(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    async fn load(layer: &RasterTileLayer, index: TileIndex) {
+        RasterTileLayer::load_tile(
+            index,
+            layer.tile_loader.clone(),
+            layer.tile_container.clone(),
+            None,
+            None,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let (layer, attempts) = test_layer(2, GalileoError::IO, fast_retry(3));
+        let index = TileIndex::new(0, 0, 0);
+        load(&layer, index).await;
+
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+        let tiles = layer.tile_container.tile_provider.tiles.lock();
+        assert!(matches!(tiles.peek(&index), Some(TileState::Loaded(_))));
+    }
+
+    #[tokio::test]
+    async fn stops_retrying_after_max_retries() {
+        let (layer, attempts) = test_layer(10, GalileoError::IO, fast_retry(3));
+        let index = TileIndex::new(0, 0, 0);
+        load(&layer, index).await;
+
+        assert_eq!(attempts.load(Ordering::Relaxed), 4);
+        let tiles = layer.tile_container.tile_provider.tiles.lock();
+        assert!(matches!(tiles.peek(&index), Some(TileState::Error(_))));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_missing_tiles() {
+        let (layer, attempts) = test_layer(10, GalileoError::NotFound, fast_retry(3));
+        let index = TileIndex::new(0, 0, 0);
+        load(&layer, index).await;
+
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+        assert!(layer.tile_container.tile_provider.contains(index));
+        let tiles = layer.tile_container.tile_provider.tiles.lock();
+        assert!(matches!(tiles.peek(&index), Some(TileState::Empty)));
+    }
+
+    #[tokio::test]
+    async fn offline_cache_miss_is_not_stored_as_empty_tile() {
+        let (layer, attempts) = test_layer(10, GalileoError::Offline, fast_retry(3));
+        let index = TileIndex::new(0, 0, 0);
+        load(&layer, index).await;
+
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+        let tiles = layer.tile_container.tile_provider.tiles.lock();
+        assert!(matches!(tiles.peek(&index), Some(TileState::Error(_))));
+    }
+
+    #[tokio::test]
+    async fn reloads_failed_tile_after_error_expiry() {
+        let retry_policy = RetryPolicy {
+            error_expiry: Some(Duration::ZERO),
+            ..fast_retry(0)
+        };
+        let (layer, attempts) = test_layer(1, GalileoError::IO, retry_policy);
+        let index = TileIndex::new(0, 0, 0);
+        load(&layer, index).await;
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+        assert!(!layer.tile_container.tile_provider.contains(index));
+
+        load(&layer, index).await;
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+        let tiles = layer.tile_container.tile_provider.tiles.lock();
+        assert!(matches!(tiles.peek(&index), Some(TileState::Loaded(_))));
+    }
+
+    #[tokio::test]
+    async fn does_not_reload_failed_tile_before_error_expiry() {
+        let (layer, attempts) = test_layer(1, GalileoError::IO, fast_retry(0));
+        let index = TileIndex::new(0, 0, 0);
+        load(&layer, index).await;
+        load(&layer, index).await;
+
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+        assert!(layer.tile_container.tile_provider.contains(index));
+    }
+
+    #[tokio::test]
+    async fn changing_map_crs_keeps_decoded_tiles() {
+        let (layer, attempts) = test_layer(0, GalileoError::IO, fast_retry(0));
+        let index = TileIndex::new(0, 0, 0);
+        load(&layer, index).await;
+
+        let provider = &layer.tile_container.tile_provider;
+        provider.pack_tiles(&[index], &NoopCanvas);
+        assert!(provider.get_tile(index, ()).is_some());
+        assert!(!provider.set_target_crs(&Crs::EPSG3857));
+        assert!(provider.get_tile(index, ()).is_some());
+
+        assert!(provider.set_target_crs(&Crs::WGS84));
+        assert!(provider.contains(index));
+        assert!(provider.get_tile(index, ()).is_none());
+
+        provider.pack_tiles(&[index], &NoopCanvas);
+        assert!(provider.get_tile(index, ()).is_some());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn tiles_are_selected_for_view_in_other_crs() {
+        let (layer, _) = test_layer(0, GalileoError::IO, fast_retry(0));
+        let view = MapView::new_projected_with_crs(&Point2::new(10.0, 50.0), 0.01, Crs::WGS84)
+            .with_size(Size::new(256.0, 256.0));
+
+        let tiles = layer.needed_tiles(&view).unwrap();
+        assert!(!tiles.is_empty());
+        // A pixel of 0.01 degrees at 50 degrees of latitude is about 1113 x 1732 meters in Web
+        // Mercator, so z-level 7 (1223 m) is selected.
+        assert!(tiles.iter().all(|index| index.z == 7));
+    }
+}
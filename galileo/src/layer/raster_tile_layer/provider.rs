@@ -1,19 +1,26 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use bytes::Bytes;
-use galileo_types::cartesian::Rect;
+use galileo_types::cartesian::{CartesianPoint2d, Point2, Rect};
+use galileo_types::geo::Crs;
 use maybe_sync::{MaybeSend, MaybeSync};
 use parking_lot::Mutex;
 use quick_cache::sync::Cache;
 use quick_cache::GuardResult;
+use web_time::Duration;
 
 use crate::decoded_image::DecodedImage;
 use crate::error::GalileoError;
-use crate::layer::data_provider::{PersistentCacheController, UrlSource};
-use crate::layer::tiles::TileProvider;
+use crate::layer::data_provider::{
+    load_with_cache, store_in_cache, url_host, HttpRequest, PersistentCacheController,
+    RequestSettings, UrlSource,
+};
+use crate::layer::tiles::{RetryPolicy, TileDownloadStatus, TileDownloader, TileProvider};
 use crate::platform::PlatformService;
 use crate::render::render_bundle::RenderBundle;
 use crate::render::{Canvas, ImagePaint, PackedBundle};
+use crate::reprojection::CrsTransform;
 use crate::tile_schema::TileIndex;
 use crate::TileSchema;
 
@@ -23,6 +30,15 @@ use crate::TileSchema;
 pub trait RasterTileLoader: MaybeSend + MaybeSync {
     /// Loads the tile with the given index.
     async fn load(&self, index: TileIndex) -> Result<DecodedImage, GalileoError>;
+
+    /// Returns the host the tile with the given index is requested from.
+    ///
+    /// The value is used by [`TileRequestQueue`](crate::layer::TileRequestQueue) to limit the
+    /// number of concurrent requests to a single host. Loaders that do not load tiles over network
+    /// can return `None`.
+    fn request_host(&self, _index: TileIndex) -> Option<String> {
+        None
+    }
 }
 
 /// Raster tile loader that loads tiles one by one with REST HTTP GET requests.
@@ -35,12 +51,16 @@ pub trait RasterTileLoader: MaybeSend + MaybeSync {
 /// * etc.
 ///
 /// If constructed with a [`PersistentCacheController`] it will cache the loaded tiles and only
-/// request new tiles from the source url if they are not in the cache.
+/// request new tiles from the source url if they are not in the cache. Stale cache entries are
+/// used right away and revalidated with the source in background.
 ///
 /// If configured to use offline mode, it will only use tiles from the cache without attempting to
 /// load them from the source. Nevertheless, even in this case url source must be correct to
 /// identify the correct files to retrieve from the cache.
 ///
+/// Additional headers (e.g. `Authorization` or `User-Agent`) and request timeout can be set with
+/// [`RestTileLoader::with_header`] and [`RestTileLoader::with_timeout`].
+///
 /// # Example
 ///
 /// ```no_run
@@ -56,7 +76,8 @@ pub trait RasterTileLoader: MaybeSend + MaybeSync {
 ///     },
 ///     None,
 ///     false
-///     );
+///     )
+///     .with_header("User-Agent", "my-app/1.0 (contact@example.com)");
 ///
 /// # tokio_test::block_on(async {
 /// let tile = loader.load(TileIndex::new(3, 5, 3)).await.expect("failed to load tile");
@@ -64,10 +85,15 @@ pub trait RasterTileLoader: MaybeSend + MaybeSync {
 /// ```
 pub struct RestTileLoader {
     url_source: Box<dyn UrlSource<TileIndex>>,
-    cache: Option<Box<dyn PersistentCacheController<str, Bytes>>>,
+    cache: Option<Arc<dyn PersistentCacheController<str, Bytes>>>,
     offline_mode: bool,
+    request_settings: RequestSettings,
+    tile_filter: Option<Box<TileFilter>>,
 }
 
+/// Function that returns false for tiles that do not exist in the source.
+type TileFilter = dyn Fn(TileIndex) -> bool + MaybeSend + MaybeSync;
+
 impl RestTileLoader {
     /// Creates a new instance of the provider.
     pub fn new(
@@ -77,36 +103,57 @@ impl RestTileLoader {
     ) -> Self {
         Self {
             url_source: Box::new(url_source),
-            cache,
+            cache: cache.map(Arc::from),
             offline_mode,
+            request_settings: RequestSettings::default(),
+            tile_filter: None,
         }
     }
 
-    async fn download_tile(&self, index: TileIndex) -> Result<Bytes, GalileoError> {
-        let url = (self.url_source)(&index);
+    /// Adds the header to every tile request, replacing previously set header with the same name.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request_settings.set_header(name, value);
+        self
+    }
 
-        if let Some(cache) = &self.cache {
-            if let Some(data) = cache.get(&url) {
-                return Ok(data);
-            }
-        }
+    /// Sets the maximum time to wait for a tile response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.request_settings.set_timeout(timeout);
+        self
+    }
 
-        if self.offline_mode {
-            return Err(GalileoError::NotFound);
-        }
+    pub(crate) fn with_request_settings(mut self, request_settings: RequestSettings) -> Self {
+        self.request_settings = request_settings;
+        self
+    }
+
+    /// Sets the function that decides whether a tile exists in the source. Tiles rejected by the
+    /// filter are reported as not found without making a request.
+    pub(crate) fn with_tile_filter(
+        mut self,
+        filter: impl Fn(TileIndex) -> bool + MaybeSend + MaybeSync + 'static,
+    ) -> Self {
+        self.tile_filter = Some(Box::new(filter));
+        self
+    }
 
-        log::info!("Loading {url}");
-        let data = crate::platform::instance()
-            .load_bytes_from_url(&url)
-            .await?;
+    pub(crate) fn is_filtered_out(&self, index: TileIndex) -> bool {
+        self.tile_filter
+            .as_ref()
+            .is_some_and(|filter| !filter(index))
+    }
 
-        if let Some(cache) = &self.cache {
-            if let Err(error) = cache.insert(&url, &data) {
-                log::warn!("Failed to write persistent cache entry: {error:?}");
-            }
+    /// Returns the request the loader makes to load the tile with the given index.
+    pub fn request(&self, index: TileIndex) -> HttpRequest {
+        self.request_settings.request((self.url_source)(&index))
+    }
+
+    pub(crate) async fn download_tile(&self, index: TileIndex) -> Result<Bytes, GalileoError> {
+        if self.is_filtered_out(index) {
+            return Err(GalileoError::NotFound);
         }
 
-        Ok(data)
+        load_with_cache(&self.request(index), self.cache.as_ref(), self.offline_mode).await
     }
 }
 
@@ -117,40 +164,102 @@ impl RasterTileLoader for RestTileLoader {
         let bytes = self.download_tile(index).await?;
         crate::platform::instance().decode_image(bytes).await
     }
+
+    fn request_host(&self, index: TileIndex) -> Option<String> {
+        url_host(&(self.url_source)(&index)).map(str::to_string)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+impl TileDownloader for RestTileLoader {
+    async fn download_to_cache(
+        &self,
+        index: TileIndex,
+    ) -> Result<TileDownloadStatus, GalileoError> {
+        if self.is_filtered_out(index) {
+            return Err(GalileoError::NotFound);
+        }
+
+        store_in_cache(&self.request(index), self.cache.as_ref(), self.offline_mode).await
+    }
 }
 
 #[derive(Clone)]
-enum TileState {
+pub(super) enum TileState {
     Loading,
     Loaded(Arc<DecodedImage>),
-    Rendered(Arc<dyn PackedBundle>),
-    Error,
+    Empty,
+    Rendered(RenderedTile),
+    Error(web_time::Instant),
+}
+
+/// Tile packed for drawing. The decoded image is kept to render the tile again if the target CRS
+/// changes.
+#[derive(Clone)]
+pub(super) struct RenderedTile {
+    /// `None` for empty tiles.
+    image: Option<Arc<DecodedImage>>,
+    bundle: Arc<dyn PackedBundle>,
+    /// Value of [`RasterTileProvider::crs_revision`] the tile was rendered for.
+    crs_revision: u64,
 }
 
 #[derive(Debug)]
 pub(crate) struct RasterTileProvider {
-    tiles: Mutex<Cache<TileIndex, TileState>>,
+    pub(super) tiles: Mutex<Cache<TileIndex, TileState>>,
     tile_schema: TileSchema,
+    retry_policy: RetryPolicy,
+    /// CRS the tiles are rendered in.
+    target_crs: Mutex<Crs>,
+    /// Incremented every time the target CRS changes.
+    crs_revision: AtomicU64,
 }
 
 impl RasterTileProvider {
-    pub(crate) fn new(tile_schema: TileSchema) -> Self {
+    pub(crate) fn new(tile_schema: TileSchema, retry_policy: RetryPolicy) -> Self {
         Self {
+            target_crs: Mutex::new(tile_schema.crs.clone()),
             tile_schema,
             tiles: Mutex::new(Cache::new(5000)),
+            retry_policy,
+            crs_revision: AtomicU64::new(0),
         }
     }
 }
 
 impl RasterTileProvider {
+    pub(crate) fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
     pub(crate) fn set_loading(&self, index: TileIndex) -> bool {
-        match self.tiles.lock().get_value_or_guard(&index, None) {
+        let tiles = self.tiles.lock();
+        let is_loading = match tiles.get_value_or_guard(&index, None) {
+            GuardResult::Value(TileState::Error(failed_at)) => {
+                !self.retry_policy.is_error_expired(failed_at)
+            }
             GuardResult::Value(_) => true,
-            GuardResult::Guard(guard) => guard.insert(TileState::Loading).is_err(),
+            GuardResult::Guard(guard) => return guard.insert(TileState::Loading).is_err(),
             GuardResult::Timeout => {
                 log::error!("Raster tile provider is deadlocked");
                 true
             }
+        };
+
+        if !is_loading {
+            tiles.insert(index, TileState::Loading);
+        }
+
+        is_loading
+    }
+
+    /// Returns true if the tile is loaded, is being loaded or failed to load recently.
+    pub(crate) fn contains(&self, index: TileIndex) -> bool {
+        match self.tiles.lock().peek(&index) {
+            Some(TileState::Error(failed_at)) => !self.retry_policy.is_error_expired(failed_at),
+            Some(_) => true,
+            None => false,
         }
     }
 
@@ -160,30 +269,124 @@ impl RasterTileProvider {
             .insert(index, TileState::Loaded(Arc::new(image)));
     }
 
+    /// Marks the tile as not existing in the tile source, so it is displayed as an empty tile.
+    pub(crate) fn set_empty(&self, index: TileIndex) {
+        self.tiles.lock().insert(index, TileState::Empty);
+    }
+
     pub(crate) fn set_error(&self, index: TileIndex) {
-        self.tiles.lock().insert(index, TileState::Error);
+        self.tiles
+            .lock()
+            .insert(index, TileState::Error(web_time::Instant::now()));
+    }
+
+    /// Sets the CRS the tiles are rendered in.
+    ///
+    /// Returns true if the CRS is changed. Tiles rendered in the previous CRS are rendered again
+    /// from their decoded images by the next [`RasterTileProvider::pack_tiles`] call.
+    pub(crate) fn set_target_crs(&self, crs: &Crs) -> bool {
+        let mut target_crs = self.target_crs.lock();
+        if *target_crs == *crs {
+            return false;
+        }
+
+        *target_crs = crs.clone();
+        self.crs_revision.fetch_add(1, Ordering::Relaxed);
+        true
     }
 
     pub(crate) fn pack_tiles(&self, indices: &[TileIndex], canvas: &dyn Canvas) {
+        let target_crs = self.target_crs.lock().clone();
+        let transform = if target_crs == self.tile_schema.crs {
+            None
+        } else {
+            let Some(transform) = CrsTransform::new(&self.tile_schema.crs, &target_crs) else {
+                return;
+            };
+            Some(transform)
+        };
+
+        let crs_revision = self.crs_revision.load(Ordering::Relaxed);
         let tiles = self.tiles.lock();
         for index in indices {
-            if let Some(TileState::Loaded(image)) = tiles.get(index) {
-                let Some(resolution) = self.tile_schema.lod_resolution(index.z) else {
-                    continue;
-                };
-                let width = self.tile_schema.tile_width() as f64;
-                let height = self.tile_schema.tile_height() as f64;
-                let tile_bbox = Rect::new(0.0, 0.0, width * resolution, -height * resolution);
-
-                let mut bundle = RenderBundle::default();
-                bundle.add_image(
-                    image.clone(),
-                    tile_bbox.into_quadrangle(),
-                    ImagePaint { opacity: 255 },
-                );
-                let packed = canvas.pack_bundle(&bundle);
-                tiles.insert(*index, TileState::Rendered(packed.into()));
-            }
+            let image = match tiles.get(index) {
+                Some(TileState::Loaded(image)) => Some(image),
+                Some(TileState::Empty) => None,
+                Some(TileState::Rendered(rendered)) if rendered.crs_revision != crs_revision => {
+                    rendered.image
+                }
+                _ => continue,
+            };
+
+            let bundle = match &image {
+                Some(image) => {
+                    let Some(resolution) = self.tile_schema.lod_resolution(index.z) else {
+                        continue;
+                    };
+                    let width = self.tile_schema.tile_width() as f64;
+                    let height = self.tile_schema.tile_height() as f64;
+                    let tile_bbox = Rect::new(0.0, 0.0, width * resolution, -height * resolution);
+
+                    let mut bundle = RenderBundle::default();
+                    match &transform {
+                        None => bundle.add_image(
+                            image.clone(),
+                            tile_bbox.into_quadrangle(),
+                            ImagePaint { opacity: 255 },
+                        ),
+                        Some(transform) => {
+                            self.add_reprojected_tile(&mut bundle, image.clone(), *index, transform)
+                        }
+                    }
+                    bundle
+                }
+                None => RenderBundle::default(),
+            };
+
+            tiles.insert(
+                *index,
+                TileState::Rendered(RenderedTile {
+                    image,
+                    bundle: canvas.pack_bundle(&bundle).into(),
+                    crs_revision,
+                }),
+            );
+        }
+    }
+}
+
+impl RasterTileProvider {
+    /// Adds the tile image warped into the target CRS to the bundle. The mesh is positioned
+    /// relative to the projected top left corner of the tile. If the tile cannot be projected,
+    /// nothing is added.
+    fn add_reprojected_tile(
+        &self,
+        bundle: &mut RenderBundle,
+        image: Arc<DecodedImage>,
+        index: TileIndex,
+        transform: &CrsTransform,
+    ) {
+        let Some(bbox) = self.tile_schema.tile_bbox(index.into_wrapping()) else {
+            return;
+        };
+        let Some(origin) = transform.transform(&Point2::new(bbox.x_min(), bbox.y_max())) else {
+            return;
+        };
+
+        let is_added = bundle.add_warped_image(image, ImagePaint { opacity: 255 }, |u, v| {
+            let point = Point2::new(
+                bbox.x_min() + bbox.width() * u,
+                bbox.y_max() - bbox.height() * v,
+            );
+            let projected = transform.transform(&point)?;
+            Some(Point2::new(
+                projected.x() - origin.x(),
+                projected.y() - origin.y(),
+            ))
+        });
+
+        if !is_added {
+            log::debug!("Tile {index:?} cannot be projected into the map CRS");
         }
     }
 }
@@ -191,7 +394,11 @@ impl RasterTileProvider {
 impl TileProvider<()> for RasterTileProvider {
     fn get_tile(&self, index: TileIndex, _style_id: ()) -> Option<Arc<dyn PackedBundle>> {
         match self.tiles.lock().get(&index) {
-            Some(TileState::Rendered(bundle)) => Some(bundle),
+            Some(TileState::Rendered(rendered))
+                if rendered.crs_revision == self.crs_revision.load(Ordering::Relaxed) =>
+            {
+                Some(rendered.bundle)
+            }
             _ => None,
         }
     }
@@ -0,0 +1,844 @@
+//! Parsing of OGC WMTS capabilities documents.
+
+use bytes::Bytes;
+use galileo_types::cartesian::{CartesianPoint2d, Point2, Rect};
+use galileo_types::geo::Crs;
+use roxmltree::Node;
+
+use super::RestTileLoader;
+use crate::error::GalileoError;
+use crate::layer::data_provider::{PersistentCacheController, UrlSource};
+use crate::lod::Lod;
+use crate::platform::PlatformService;
+use crate::tile_schema::{TileIndex, TileSchema, VerticalDirection};
+
+/// Size of a rendering pixel in meters, as defined by the WMTS standard.
+const STANDARD_PIXEL_SIZE: f64 = 0.00028;
+/// Length of one degree of the equator in meters, used for CRSs in geographic coordinates.
+const METERS_PER_DEGREE: f64 = 6378137.0 * 2.0 * std::f64::consts::PI / 360.0;
+/// Maximum difference from a whole number for a tile offset between matrix origins.
+const OFFSET_TOLERANCE: f64 = 0.001;
+
+/// Contents of a WMTS `GetCapabilities` response.
+///
+/// ```no_run
+/// use galileo::layer::raster_tile_layer::{RasterTileLayerBuilder, WmtsCapabilities};
+///
+/// # async fn example() -> Result<(), galileo::error::GalileoError> {
+/// let capabilities =
+///     WmtsCapabilities::load("https://wmts.example.com/WMTSCapabilities.xml").await?;
+/// let layer = RasterTileLayerBuilder::new_with_loader(
+///     capabilities
+///         .tile_loader("roads", None, "GoogleMapsCompatible", None, false)?
+///         .with_header("User-Agent", "galileo"),
+/// )
+/// .with_tile_schema(capabilities.tile_schema("GoogleMapsCompatible")?)
+/// .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmtsCapabilities {
+    /// Layers provided by the service.
+    pub layers: Vec<WmtsLayerInfo>,
+    /// Tile matrix sets defined by the service.
+    pub tile_matrix_sets: Vec<TileMatrixSet>,
+    /// Url for KVP encoded `GetTile` requests, if the service supports them.
+    pub get_tile_url: Option<String>,
+}
+
+/// Description of a WMTS layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmtsLayerInfo {
+    /// Identifier of the layer used in requests.
+    pub identifier: String,
+    /// Human readable name of the layer.
+    pub title: Option<String>,
+    /// Styles the layer can be rendered with.
+    pub styles: Vec<WmtsStyle>,
+    /// Image formats the tiles are available in.
+    pub formats: Vec<String>,
+    /// Tile matrix sets the layer is available in.
+    pub tile_matrix_set_links: Vec<TileMatrixSetLink>,
+    /// Templates for RESTful tile requests.
+    pub resource_urls: Vec<WmtsResourceUrl>,
+    /// Extra dimensions of the layer (e.g. time) with their default values.
+    pub dimensions: Vec<(String, String)>,
+}
+
+/// Style of a WMTS layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WmtsStyle {
+    /// Identifier of the style used in requests.
+    pub identifier: String,
+    /// Human readable name of the style.
+    pub title: Option<String>,
+    /// Whether this style is used if no style is specified.
+    pub is_default: bool,
+}
+
+/// Reference from a layer to a tile matrix set the layer is available in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileMatrixSetLink {
+    /// Identifier of the tile matrix set.
+    pub tile_matrix_set: String,
+    /// Ranges of tiles that exist for the layer, if the layer does not cover the whole set.
+    pub limits: Vec<TileMatrixLimits>,
+}
+
+/// Range of tiles of a single tile matrix that exist for a layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileMatrixLimits {
+    /// Identifier of the tile matrix.
+    pub tile_matrix: String,
+    /// Minimum row index.
+    pub min_row: i32,
+    /// Maximum row index.
+    pub max_row: i32,
+    /// Minimum column index.
+    pub min_col: i32,
+    /// Maximum column index.
+    pub max_col: i32,
+}
+
+/// Template for RESTful requests of a layer resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WmtsResourceUrl {
+    /// Format of the returned resource.
+    pub format: String,
+    /// Type of the resource (`tile` or `FeatureInfo`).
+    pub resource_type: String,
+    /// Url template, e.g. `https://example.com/{TileMatrixSet}/{TileMatrix}/{TileCol}/{TileRow}.png`.
+    pub template: String,
+}
+
+/// Set of tile matrices defining a tile grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrixSet {
+    /// Identifier of the set used in requests.
+    pub identifier: String,
+    /// Identifier of the CRS of the set, e.g. `urn:ogc:def:crs:EPSG::3857`.
+    pub supported_crs: String,
+    /// Tile matrices (levels of detail) of the set.
+    pub tile_matrices: Vec<TileMatrix>,
+}
+
+/// Single level of detail of a tile matrix set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrix {
+    /// Identifier of the tile matrix used in requests.
+    pub identifier: String,
+    /// Scale denominator of the level, assuming 0.28 mm pixel size.
+    pub scale_denominator: f64,
+    /// Position of the top left corner of the matrix, in the axis order of the CRS.
+    pub top_left_corner: Point2,
+    /// Width of a tile in pixels.
+    pub tile_width: u32,
+    /// Height of a tile in pixels.
+    pub tile_height: u32,
+    /// Number of tile columns.
+    pub matrix_width: u32,
+    /// Number of tile rows.
+    pub matrix_height: u32,
+}
+
+/// Position of a tile matrix in the grid of the tile schema.
+#[derive(Debug, Clone)]
+struct MatrixGrid {
+    identifier: String,
+    col_offset: i32,
+    row_offset: i32,
+    matrix_width: i32,
+    matrix_height: i32,
+}
+
+impl WmtsCapabilities {
+    /// Loads and parses the capabilities document from the given url.
+    pub async fn load(url: &str) -> Result<Self, GalileoError> {
+        let bytes = crate::platform::instance().load_bytes_from_url(url).await?;
+        let xml = std::str::from_utf8(&bytes)
+            .map_err(|_| GalileoError::Generic("WMTS capabilities are not valid UTF-8".into()))?;
+        Self::parse(xml)
+    }
+
+    /// Parses the capabilities document.
+    pub fn parse(xml: &str) -> Result<Self, GalileoError> {
+        let document = roxmltree::Document::parse(xml)
+            .map_err(|err| GalileoError::Generic(format!("invalid WMTS capabilities: {err}")))?;
+        let root = document.root_element();
+        let contents = child(root, "Contents").ok_or_else(|| {
+            GalileoError::Generic("invalid WMTS capabilities: no Contents element".into())
+        })?;
+
+        let layers = children(contents, "Layer")
+            .map(parse_layer)
+            .collect::<Result<_, _>>()?;
+        let tile_matrix_sets = children(contents, "TileMatrixSet")
+            .map(parse_tile_matrix_set)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            layers,
+            tile_matrix_sets,
+            get_tile_url: parse_get_tile_url(root),
+        })
+    }
+
+    /// Returns the layer with the given identifier.
+    pub fn layer(&self, identifier: &str) -> Option<&WmtsLayerInfo> {
+        self.layers.iter().find(|l| l.identifier == identifier)
+    }
+
+    /// Returns the tile matrix set with the given identifier.
+    pub fn tile_matrix_set(&self, identifier: &str) -> Option<&TileMatrixSet> {
+        self.tile_matrix_sets
+            .iter()
+            .find(|set| set.identifier == identifier)
+    }
+
+    /// Creates the tile schema for the given tile matrix set.
+    ///
+    /// Z-levels of the schema correspond to the tile matrices of the set sorted from the smallest
+    /// scale to the largest. If matrices have different origins, the origin of the first one is
+    /// used, and tile indices of the others are shifted accordingly by the loader created with
+    /// [`WmtsCapabilities::tile_loader`]. This requires the origins to be shifted by a whole number
+    /// of tiles.
+    ///
+    /// Only `EPSG:3857` (and its aliases), `EPSG:4326` and `CRS:84` CRSs are supported.
+    pub fn tile_schema(&self, tile_matrix_set: &str) -> Result<TileSchema, GalileoError> {
+        Ok(self.grid(tile_matrix_set)?.0)
+    }
+
+    /// Creates the url source for tiles of the given layer, style and tile matrix set.
+    ///
+    /// If `style` is `None`, the default style of the layer is used. RESTful requests are used if
+    /// the layer provides a tile resource template, otherwise KVP `GetTile` requests are made.
+    pub fn url_source(
+        &self,
+        layer: &str,
+        style: Option<&str>,
+        tile_matrix_set: &str,
+    ) -> Result<impl UrlSource<TileIndex>, GalileoError> {
+        let (_, grid) = self.grid(tile_matrix_set)?;
+        let template = self.url_template(layer, style, tile_matrix_set)?;
+
+        Ok(move |index: &TileIndex| match grid.get(index.z as usize) {
+            Some(matrix) => template
+                .replace("{TileMatrix}", &matrix.identifier)
+                .replace("{TileCol}", &(index.x - matrix.col_offset).to_string())
+                .replace("{TileRow}", &(index.y - matrix.row_offset).to_string()),
+            None => String::new(),
+        })
+    }
+
+    /// Creates the tile loader for the given layer, style and tile matrix set. The loader must be
+    /// used with the tile schema returned by [`WmtsCapabilities::tile_schema`].
+    ///
+    /// Tiles outside the tile matrices or the layer limits are reported as not found without
+    /// making requests.
+    ///
+    /// `cache` and `offline_mode` have the same meaning as in [`RestTileLoader::new`]. Request
+    /// headers and timeout can be set on the returned loader with [`RestTileLoader::with_header`]
+    /// and [`RestTileLoader::with_timeout`].
+    pub fn tile_loader(
+        &self,
+        layer: &str,
+        style: Option<&str>,
+        tile_matrix_set: &str,
+        cache: Option<Box<dyn PersistentCacheController<str, Bytes>>>,
+        offline_mode: bool,
+    ) -> Result<RestTileLoader, GalileoError> {
+        let (_, grid) = self.grid(tile_matrix_set)?;
+        let limits = self
+            .find_layer(layer)?
+            .tile_matrix_set_links
+            .iter()
+            .find(|link| link.tile_matrix_set == tile_matrix_set)
+            .map(|link| link.limits.clone())
+            .unwrap_or_default();
+
+        let url_source = self.url_source(layer, style, tile_matrix_set)?;
+        Ok(
+            RestTileLoader::new(url_source, cache, offline_mode).with_tile_filter(move |index| {
+                let Some(matrix) = grid.get(index.z as usize) else {
+                    return false;
+                };
+
+                let col = index.x - matrix.col_offset;
+                let row = index.y - matrix.row_offset;
+                let in_matrix = (0..matrix.matrix_width).contains(&col)
+                    && (0..matrix.matrix_height).contains(&row);
+                let in_limits = limits
+                    .iter()
+                    .find(|l| l.tile_matrix == matrix.identifier)
+                    .is_none_or(|l| {
+                        (l.min_col..=l.max_col).contains(&col)
+                            && (l.min_row..=l.max_row).contains(&row)
+                    });
+
+                in_matrix && in_limits
+            }),
+        )
+    }
+
+    fn find_layer(&self, layer: &str) -> Result<&WmtsLayerInfo, GalileoError> {
+        self.layer(layer)
+            .ok_or_else(|| GalileoError::Configuration(format!("WMTS layer {layer} is not found")))
+    }
+
+    /// Returns the url template with all the placeholders except for tile matrix, row and column
+    /// filled in.
+    fn url_template(
+        &self,
+        layer: &str,
+        style: Option<&str>,
+        tile_matrix_set: &str,
+    ) -> Result<String, GalileoError> {
+        let layer_info = self.find_layer(layer)?;
+        if !layer_info
+            .tile_matrix_set_links
+            .iter()
+            .any(|link| link.tile_matrix_set == tile_matrix_set)
+        {
+            return Err(GalileoError::Configuration(format!(
+                "WMTS layer {layer} is not available in tile matrix set {tile_matrix_set}"
+            )));
+        }
+
+        let style = match style {
+            Some(style) => {
+                if !layer_info.styles.iter().any(|s| s.identifier == style) {
+                    return Err(GalileoError::Configuration(format!(
+                        "WMTS layer {layer} does not have style {style}"
+                    )));
+                }
+                style.to_string()
+            }
+            None => layer_info
+                .styles
+                .iter()
+                .find(|s| s.is_default)
+                .or(layer_info.styles.first())
+                .map(|s| s.identifier.clone())
+                .unwrap_or_else(|| "default".to_string()),
+        };
+
+        let resource = layer_info
+            .resource_urls
+            .iter()
+            .find(|r| r.resource_type.eq_ignore_ascii_case("tile"));
+        let template = match (resource, &self.get_tile_url) {
+            (Some(resource), _) => resource.template.clone(),
+            (None, Some(get_tile_url)) => {
+                let format = layer_info
+                    .formats
+                    .first()
+                    .map(String::as_str)
+                    .unwrap_or("image/png");
+                let separator = match get_tile_url.contains('?') {
+                    true if get_tile_url.ends_with('?') || get_tile_url.ends_with('&') => "",
+                    true => "&",
+                    false => "?",
+                };
+                format!(
+                    "{get_tile_url}{separator}SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&\
+                    LAYER={layer}&STYLE={{Style}}&FORMAT={format}&TILEMATRIXSET={{TileMatrixSet}}&\
+                    TILEMATRIX={{TileMatrix}}&TILEROW={{TileRow}}&TILECOL={{TileCol}}"
+                )
+            }
+            (None, None) => {
+                return Err(GalileoError::Configuration(format!(
+                    "WMTS layer {layer} has no tile resource url and the service does not \
+                    support GetTile requests"
+                )))
+            }
+        };
+
+        let mut values = vec![
+            ("style".to_string(), style),
+            ("tilematrixset".to_string(), tile_matrix_set.to_string()),
+        ];
+        values.extend(
+            layer_info
+                .dimensions
+                .iter()
+                .map(|(name, value)| (name.to_lowercase(), value.clone())),
+        );
+
+        fill_template(&template, &values)
+    }
+
+    /// Creates the tile schema and the positions of the matrices of the tile matrix set in the
+    /// schema grid (indexed by z-level).
+    fn grid(&self, tile_matrix_set: &str) -> Result<(TileSchema, Vec<MatrixGrid>), GalileoError> {
+        let set = self.tile_matrix_set(tile_matrix_set).ok_or_else(|| {
+            GalileoError::Configuration(format!(
+                "WMTS tile matrix set {tile_matrix_set} is not found"
+            ))
+        })?;
+        let (crs, meters_per_unit, swap_axes) = crs_info(&set.supported_crs)?;
+
+        let mut matrices: Vec<_> = set.tile_matrices.iter().collect();
+        matrices.sort_by(|a, b| b.scale_denominator.total_cmp(&a.scale_denominator));
+        let Some(first) = matrices.first() else {
+            return Err(GalileoError::Configuration(format!(
+                "WMTS tile matrix set {tile_matrix_set} has no tile matrices"
+            )));
+        };
+
+        let corner = |matrix: &TileMatrix| match swap_axes {
+            true => Point2::new(matrix.top_left_corner.y(), matrix.top_left_corner.x()),
+            false => matrix.top_left_corner,
+        };
+        let origin = corner(first);
+        let (tile_width, tile_height) = (first.tile_width, first.tile_height);
+
+        let mut lods = vec![];
+        let mut grid = vec![];
+        let mut bounds: Option<Rect> = None;
+        for (z, matrix) in matrices.into_iter().enumerate() {
+            if matrix.tile_width != tile_width || matrix.tile_height != tile_height {
+                return Err(GalileoError::Configuration(format!(
+                    "WMTS tile matrix set {tile_matrix_set} has different tile sizes on different \
+                    levels"
+                )));
+            }
+
+            let resolution = matrix.scale_denominator * STANDARD_PIXEL_SIZE / meters_per_unit;
+            let lod = Lod::new(resolution, z as u32).ok_or_else(|| {
+                GalileoError::Configuration(format!(
+                    "invalid scale denominator of WMTS tile matrix {}",
+                    matrix.identifier
+                ))
+            })?;
+            lods.push(lod);
+
+            let tile_w = tile_width as f64 * resolution;
+            let tile_h = tile_height as f64 * resolution;
+            let matrix_origin = corner(matrix);
+            let col_offset = (matrix_origin.x() - origin.x()) / tile_w;
+            let row_offset = (origin.y() - matrix_origin.y()) / tile_h;
+            if (col_offset - col_offset.round()).abs() > OFFSET_TOLERANCE
+                || (row_offset - row_offset.round()).abs() > OFFSET_TOLERANCE
+            {
+                return Err(GalileoError::Configuration(format!(
+                    "origin of WMTS tile matrix {} is not aligned with the tile grid of the first \
+                    matrix",
+                    matrix.identifier
+                )));
+            }
+
+            let matrix_bounds = Rect::new(
+                matrix_origin.x(),
+                matrix_origin.y() - matrix.matrix_height as f64 * tile_h,
+                matrix_origin.x() + matrix.matrix_width as f64 * tile_w,
+                matrix_origin.y(),
+            );
+            bounds = Some(match bounds {
+                Some(bounds) => bounds.merge(matrix_bounds),
+                None => matrix_bounds,
+            });
+
+            grid.push(MatrixGrid {
+                identifier: matrix.identifier.clone(),
+                col_offset: col_offset.round() as i32,
+                row_offset: row_offset.round() as i32,
+                matrix_width: matrix.matrix_width as i32,
+                matrix_height: matrix.matrix_height as i32,
+            });
+        }
+
+        let schema = TileSchema {
+            origin,
+            bounds: bounds.unwrap_or_default(),
+            lods: lods.into_iter().collect(),
+            tile_width,
+            tile_height,
+            y_direction: VerticalDirection::TopToBottom,
+            crs,
+        };
+
+        Ok((schema, grid))
+    }
+}
+
+/// Returns the CRS, the number of meters in a CRS unit and whether the first axis of the CRS is
+/// latitude (northing) for the given CRS identifier.
+fn crs_info(identifier: &str) -> Result<(Crs, f64, bool), GalileoError> {
+    let code = identifier.rsplit(':').next().unwrap_or_default();
+    match code {
+        "3857" | "900913" | "3785" | "102100" | "102113" => Ok((Crs::EPSG3857, 1.0, false)),
+        "4326" => Ok((Crs::WGS84, METERS_PER_DEGREE, true)),
+        "CRS84" | "84" => Ok((Crs::WGS84, METERS_PER_DEGREE, false)),
+        _ => Err(GalileoError::Configuration(format!(
+            "unsupported WMTS CRS: {identifier}"
+        ))),
+    }
+}
+
+/// Replaces `{Name}` placeholders of the template with the values (matched case-insensitively by
+/// lowercase name). Tile matrix, row and column placeholders are left as is.
+fn fill_template(template: &str, values: &[(String, String)]) -> Result<String, GalileoError> {
+    const TILE_PLACEHOLDERS: [&str; 3] = ["{TileMatrix}", "{TileRow}", "{TileCol}"];
+
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result += &rest[..start];
+        let Some(length) = rest[start..].find('}') else {
+            return Err(GalileoError::Configuration(format!(
+                "unclosed placeholder in WMTS url template: {template}"
+            )));
+        };
+
+        let placeholder = &rest[start..start + length + 1];
+        let name = placeholder[1..placeholder.len() - 1].to_lowercase();
+        if let Some(tile_placeholder) = TILE_PLACEHOLDERS
+            .iter()
+            .find(|p| p.eq_ignore_ascii_case(placeholder))
+        {
+            result += tile_placeholder;
+        } else if let Some((_, value)) = values.iter().find(|(n, _)| *n == name) {
+            result += value;
+        } else {
+            return Err(GalileoError::Configuration(format!(
+                "unknown placeholder {placeholder} in WMTS url template: {template}"
+            )));
+        }
+
+        rest = &rest[start + length + 1..];
+    }
+
+    result += rest;
+    Ok(result)
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+fn child_text(node: Node, name: &'static str) -> Option<String> {
+    child(node, name)
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+}
+
+fn required_text(node: Node, name: &'static str) -> Result<String, GalileoError> {
+    child_text(node, name).ok_or_else(|| {
+        GalileoError::Generic(format!(
+            "invalid WMTS capabilities: {} element has no {name}",
+            node.tag_name().name()
+        ))
+    })
+}
+
+fn required_number<T: std::str::FromStr>(
+    node: Node,
+    name: &'static str,
+) -> Result<T, GalileoError> {
+    let text = required_text(node, name)?;
+    text.parse().map_err(|_| {
+        GalileoError::Generic(format!(
+            "invalid WMTS capabilities: invalid {name} value {text}"
+        ))
+    })
+}
+
+fn parse_layer(node: Node) -> Result<WmtsLayerInfo, GalileoError> {
+    let styles = children(node, "Style")
+        .map(|style| {
+            Ok(WmtsStyle {
+                identifier: required_text(style, "Identifier")?,
+                title: child_text(style, "Title"),
+                is_default: style.attribute("isDefault") == Some("true"),
+            })
+        })
+        .collect::<Result<_, GalileoError>>()?;
+
+    let tile_matrix_set_links = children(node, "TileMatrixSetLink")
+        .map(|link| {
+            let limits = child(link, "TileMatrixSetLimits")
+                .into_iter()
+                .flat_map(|limits| children(limits, "TileMatrixLimits"))
+                .map(|limits| {
+                    Ok(TileMatrixLimits {
+                        tile_matrix: required_text(limits, "TileMatrix")?,
+                        min_row: required_number(limits, "MinTileRow")?,
+                        max_row: required_number(limits, "MaxTileRow")?,
+                        min_col: required_number(limits, "MinTileCol")?,
+                        max_col: required_number(limits, "MaxTileCol")?,
+                    })
+                })
+                .collect::<Result<_, GalileoError>>()?;
+
+            Ok(TileMatrixSetLink {
+                tile_matrix_set: required_text(link, "TileMatrixSet")?,
+                limits,
+            })
+        })
+        .collect::<Result<_, GalileoError>>()?;
+
+    let resource_urls = children(node, "ResourceURL")
+        .filter_map(|resource| {
+            Some(WmtsResourceUrl {
+                format: resource.attribute("format").unwrap_or_default().to_string(),
+                resource_type: resource
+                    .attribute("resourceType")
+                    .unwrap_or_default()
+                    .to_string(),
+                template: resource.attribute("template")?.to_string(),
+            })
+        })
+        .collect();
+
+    let dimensions = children(node, "Dimension")
+        .filter_map(|dimension| {
+            Some((
+                child_text(dimension, "Identifier")?,
+                child_text(dimension, "Default")?,
+            ))
+        })
+        .collect();
+
+    Ok(WmtsLayerInfo {
+        identifier: required_text(node, "Identifier")?,
+        title: child_text(node, "Title"),
+        styles,
+        formats: children(node, "Format")
+            .filter_map(|f| f.text())
+            .map(|f| f.trim().to_string())
+            .collect(),
+        tile_matrix_set_links,
+        resource_urls,
+        dimensions,
+    })
+}
+
+fn parse_tile_matrix_set(node: Node) -> Result<TileMatrixSet, GalileoError> {
+    let tile_matrices = children(node, "TileMatrix")
+        .map(|matrix| {
+            let corner = required_text(matrix, "TopLeftCorner")?;
+            let mut coords = corner.split_whitespace().map(str::parse::<f64>);
+            let (Some(Ok(x)), Some(Ok(y))) = (coords.next(), coords.next()) else {
+                return Err(GalileoError::Generic(format!(
+                    "invalid WMTS capabilities: invalid TopLeftCorner value {corner}"
+                )));
+            };
+
+            Ok(TileMatrix {
+                identifier: required_text(matrix, "Identifier")?,
+                scale_denominator: required_number(matrix, "ScaleDenominator")?,
+                top_left_corner: Point2::new(x, y),
+                tile_width: required_number(matrix, "TileWidth")?,
+                tile_height: required_number(matrix, "TileHeight")?,
+                matrix_width: required_number(matrix, "MatrixWidth")?,
+                matrix_height: required_number(matrix, "MatrixHeight")?,
+            })
+        })
+        .collect::<Result<_, GalileoError>>()?;
+
+    Ok(TileMatrixSet {
+        identifier: required_text(node, "Identifier")?,
+        supported_crs: required_text(node, "SupportedCRS")?,
+        tile_matrices,
+    })
+}
+
+/// Returns the url of KVP encoded `GetTile` requests from the operations metadata.
+fn parse_get_tile_url(root: Node) -> Option<String> {
+    let operation = children(child(root, "OperationsMetadata")?, "Operation")
+        .find(|op| op.attribute("name") == Some("GetTile"))?;
+
+    operation
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "Get")
+        .find(|get| {
+            // If the encoding is not specified, KVP is assumed.
+            let encodings: Vec<_> = get
+                .descendants()
+                .filter(|n| n.is_element() && n.tag_name().name() == "Value")
+                .filter_map(|n| n.text())
+                .collect();
+            encodings.is_empty() || encodings.iter().any(|e| e.trim() == "KVP")
+        })
+        .and_then(|get| {
+            get.attributes()
+                .find(|a| a.name() == "href")
+                .map(|a| a.value().to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_compact_debug_snapshot;
+
+    use super::*;
+
+    const CAPABILITIES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Capabilities xmlns="http://www.opengis.net/wmts/1.0" xmlns:ows="http://www.opengis.net/ows/1.1"
+    xmlns:xlink="http://www.w3.org/1999/xlink" version="1.0.0">
+  <ows:OperationsMetadata>
+    <ows:Operation name="GetTile">
+      <ows:DCP><ows:HTTP>
+        <ows:Get xlink:href="https://example.com/wmts?">
+          <ows:Constraint name="GetEncoding"><ows:AllowedValues><ows:Value>KVP</ows:Value></ows:AllowedValues></ows:Constraint>
+        </ows:Get>
+      </ows:HTTP></ows:DCP>
+    </ows:Operation>
+  </ows:OperationsMetadata>
+  <Contents>
+    <Layer>
+      <ows:Title>Roads</ows:Title>
+      <ows:Identifier>roads</ows:Identifier>
+      <Style isDefault="true"><ows:Identifier>default</ows:Identifier></Style>
+      <Style><ows:Identifier>night</ows:Identifier></Style>
+      <Format>image/png</Format>
+      <Dimension><ows:Identifier>Time</ows:Identifier><Default>2024</Default></Dimension>
+      <TileMatrixSetLink>
+        <TileMatrixSet>local</TileMatrixSet>
+        <TileMatrixSetLimits>
+          <TileMatrixLimits>
+            <TileMatrix>L1</TileMatrix>
+            <MinTileRow>0</MinTileRow><MaxTileRow>1</MaxTileRow>
+            <MinTileCol>0</MinTileCol><MaxTileCol>0</MaxTileCol>
+          </TileMatrixLimits>
+        </TileMatrixSetLimits>
+      </TileMatrixSetLink>
+      <ResourceURL format="image/png" resourceType="tile"
+          template="https://example.com/tiles/{Time}/{style}/{TileMatrixSet}/{TileMatrix}/{TileRow}/{TileCol}.png"/>
+    </Layer>
+    <Layer>
+      <ows:Identifier>rivers</ows:Identifier>
+      <Format>image/jpeg</Format>
+      <TileMatrixSetLink><TileMatrixSet>local</TileMatrixSet></TileMatrixSetLink>
+    </Layer>
+    <TileMatrixSet>
+      <ows:Identifier>local</ows:Identifier>
+      <ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>
+      <TileMatrix>
+        <ows:Identifier>L1</ows:Identifier>
+        <ScaleDenominator>14285.714285714286</ScaleDenominator>
+        <TopLeftCorner>-1024 1024</TopLeftCorner>
+        <TileWidth>256</TileWidth><TileHeight>256</TileHeight>
+        <MatrixWidth>2</MatrixWidth><MatrixHeight>2</MatrixHeight>
+      </TileMatrix>
+      <TileMatrix>
+        <ows:Identifier>L0</ows:Identifier>
+        <ScaleDenominator>28571.428571428572</ScaleDenominator>
+        <TopLeftCorner>-2048 2048</TopLeftCorner>
+        <TileWidth>256</TileWidth><TileHeight>256</TileHeight>
+        <MatrixWidth>1</MatrixWidth><MatrixHeight>1</MatrixHeight>
+      </TileMatrix>
+    </TileMatrixSet>
+  </Contents>
+</Capabilities>"#;
+
+    #[test]
+    fn parse_capabilities() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        assert_eq!(
+            capabilities.get_tile_url.as_deref(),
+            Some("https://example.com/wmts?")
+        );
+
+        let roads = capabilities.layer("roads").unwrap();
+        assert_eq!(roads.title.as_deref(), Some("Roads"));
+        assert_eq!(roads.styles.len(), 2);
+        assert!(roads.styles[0].is_default);
+        assert_eq!(roads.dimensions, vec![("Time".into(), "2024".into())]);
+        assert_eq!(roads.tile_matrix_set_links[0].limits.len(), 1);
+
+        let set = capabilities.tile_matrix_set("local").unwrap();
+        assert_eq!(set.tile_matrices.len(), 2);
+        assert_eq!(
+            set.tile_matrices[0].top_left_corner,
+            Point2::new(-1024.0, 1024.0)
+        );
+    }
+
+    #[test]
+    fn tile_schema_with_different_origins() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        let schema = capabilities.tile_schema("local").unwrap();
+
+        assert_eq!(schema.origin, Point2::new(-2048.0, 2048.0));
+        assert_eq!(schema.bounds, Rect::new(-2048.0, -1024.0, 1024.0, 2048.0));
+        assert_eq!(schema.tile_width, 256);
+        assert_eq!(schema.lods.len(), 2);
+        approx::assert_abs_diff_eq!(schema.lod_resolution(0).unwrap(), 8.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(schema.lod_resolution(1).unwrap(), 4.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn rest_url_source() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        let loader = capabilities
+            .tile_loader("roads", Some("night"), "local", None, false)
+            .unwrap();
+
+        // Level L1 starts one tile to the right and down of the schema origin.
+        assert_eq!(
+            loader.request(TileIndex::new(1, 2, 1)).url,
+            "https://example.com/tiles/2024/night/local/L1/1/0.png"
+        );
+    }
+
+    #[test]
+    fn loader_with_header() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        let loader = capabilities
+            .tile_loader("roads", None, "local", None, false)
+            .unwrap()
+            .with_header("Authorization", "token");
+
+        let request = loader.request(TileIndex::new(1, 2, 1));
+        assert_eq!(
+            request.headers,
+            vec![("Authorization".to_string(), "token".to_string())]
+        );
+    }
+
+    #[test]
+    fn kvp_url_source() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        let loader = capabilities
+            .tile_loader("rivers", None, "local", None, false)
+            .unwrap();
+
+        assert_eq!(
+            loader.request(TileIndex::new(0, 0, 0)).url,
+            "https://example.com/wmts?SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&LAYER=rivers&\
+            STYLE=default&FORMAT=image/jpeg&TILEMATRIXSET=local&TILEMATRIX=L0&TILEROW=0&TILECOL=0"
+        );
+    }
+
+    #[test]
+    fn tiles_outside_of_limits_are_not_requested() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        let loader = capabilities
+            .tile_loader("roads", None, "local", None, false)
+            .unwrap();
+
+        assert!(loader.is_filtered_out(TileIndex::new(0, 0, 1)));
+        assert!(loader.is_filtered_out(TileIndex::new(2, 1, 1)));
+        assert!(!loader.is_filtered_out(TileIndex::new(1, 1, 1)));
+        assert!(!loader.is_filtered_out(TileIndex::new(1, 2, 1)));
+        assert!(loader.is_filtered_out(TileIndex::new(1, 3, 1)));
+    }
+
+    #[test]
+    fn configuration_errors() {
+        let capabilities = WmtsCapabilities::parse(CAPABILITIES).unwrap();
+        assert_compact_debug_snapshot!(capabilities.tile_loader("lakes", None, "local", None, false).map(|_| ()), @r#"Err(Configuration("WMTS layer lakes is not found"))"#);
+        assert_compact_debug_snapshot!(capabilities.tile_loader("roads", Some("day"), "local", None, false).map(|_| ()), @r#"Err(Configuration("WMTS layer roads does not have style day"))"#);
+        assert_compact_debug_snapshot!(capabilities.tile_schema("web").map(|_| ()), @r#"Err(Configuration("WMTS tile matrix set web is not found"))"#);
+    }
+}
@@ -8,6 +8,16 @@ use crate::render::PackedBundle;
 use crate::tile_schema::{TileIndex, WrappingTileIndex};
 use crate::TileSchema;
 
+mod area_download;
+mod request_queue;
+mod retry;
+pub use area_download::{
+    AreaDownload, DownloadArea, DownloadProgress, TileDownloadStatus, TileDownloader,
+};
+pub use request_queue::TileRequestQueue;
+pub(crate) use request_queue::{RequestSlot, RequestSourceId, TilePriority, TileRequest};
+pub use retry::RetryPolicy;
+
 const DEFAULT_FADE_IN_DURATION: Duration = Duration::from_millis(300);
 
 #[derive(Clone)]
@@ -136,6 +146,13 @@ where
         requires_redraw
     }
 
+    /// Shows all displayed tiles at full opacity, skipping their fade-in animation.
+    pub(crate) fn skip_fade_in(&self) {
+        for displayed in self.tiles.lock().iter_mut() {
+            displayed.opacity = 1.0;
+        }
+    }
+
     pub fn fade_in_duration(&self) -> Duration {
         Duration::from_millis(self.fade_in_duration.load(Ordering::Relaxed))
     }
@@ -0,0 +1,546 @@
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use ahash::RandomState;
+use futures::StreamExt;
+use galileo_types::cartesian::{CartesianPolygon, Point2, Rect};
+use galileo_types::geo::impls::GeoPoint2d;
+use galileo_types::geo::Crs;
+use galileo_types::impls::{ClosedContour, Polygon};
+use galileo_types::Polygon as _;
+use maybe_sync::{MaybeSend, MaybeSync};
+use parking_lot::Mutex;
+
+use crate::error::GalileoError;
+use crate::tile_schema::{TileIndex, TileSchema};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Loader that can store tiles in a persistent cache without decoding them.
+///
+/// Used by [`AreaDownload`] to prepare tiles for offline use.
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+pub trait TileDownloader: MaybeSend + MaybeSync {
+    /// Makes sure the tile with the given index is stored in the persistent cache, downloading it
+    /// if it is not there or if the cached version is stale.
+    ///
+    /// Returns [`GalileoError::NotFound`] if the tile does not exist in the source.
+    async fn download_to_cache(&self, index: TileIndex)
+        -> Result<TileDownloadStatus, GalileoError>;
+}
+
+/// Result of [`TileDownloader::download_to_cache`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TileDownloadStatus {
+    /// The tile was downloaded from the source.
+    Downloaded {
+        /// Size of the tile data in bytes.
+        size: u64,
+    },
+    /// The tile was already in the cache.
+    Cached {
+        /// Size of the tile data in bytes.
+        size: u64,
+    },
+}
+
+/// Area to be downloaded by [`AreaDownload`].
+///
+/// Coordinates of the area must be in the CRS of the tile schema of the downloaded layer.
+#[derive(Debug, Clone)]
+pub enum DownloadArea {
+    /// Rectangular area.
+    BoundingBox(Rect),
+    /// Area inside a polygon.
+    Polygon(Polygon<Point2>),
+}
+
+impl DownloadArea {
+    /// Creates a polygon area from geographic coordinates, projecting them into the given CRS.
+    ///
+    /// Returns `None` if the points cannot be projected into the CRS.
+    pub fn from_geo_polygon(points: &[GeoPoint2d], crs: &Crs) -> Option<Self> {
+        let projection = crs.get_projection::<GeoPoint2d, Point2>()?;
+        let points = points
+            .iter()
+            .map(|p| projection.project(p))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self::Polygon(Polygon::new(
+            ClosedContour::new(points),
+            vec![],
+        )))
+    }
+
+    fn bounding_box(&self) -> Option<Rect> {
+        match self {
+            Self::BoundingBox(rect) => Some(*rect),
+            Self::Polygon(polygon) => {
+                Rect::from_points(polygon.outer_contour().points.iter().copied())
+            }
+        }
+    }
+
+    fn intersects(&self, tile_bbox: Rect) -> bool {
+        match self {
+            Self::BoundingBox(rect) => rect.intersects(tile_bbox),
+            Self::Polygon(polygon) => {
+                if polygon.contains_point(&tile_bbox.center()) {
+                    return true;
+                }
+
+                let corners = tile_bbox.into_quadrangle();
+                let tile_segments =
+                    (0..4).map(|i| galileo_types::Segment(corners[i], corners[(i + 1) % 4]));
+
+                polygon
+                    .iter_contours()
+                    .flat_map(|contour| contour.points.iter())
+                    .any(|p| tile_bbox.contains(p))
+                    || tile_segments.clone().any(|tile_segment| {
+                        polygon
+                            .iter_segments()
+                            .any(|segment| segment.intersects(&tile_segment))
+                    })
+            }
+        }
+    }
+}
+
+/// Progress of an [`AreaDownload`].
+#[derive(Debug, Clone, Default)]
+pub struct DownloadProgress {
+    /// Total number of tiles in the area.
+    pub total_tiles: usize,
+    /// Number of tiles downloaded from the source.
+    pub downloaded_tiles: usize,
+    /// Number of tiles that were already in the cache.
+    pub cached_tiles: usize,
+    /// Number of tiles that do not exist in the source.
+    pub missing_tiles: usize,
+    /// Tiles that failed to download, with the errors.
+    pub failed_tiles: Vec<(TileIndex, GalileoError)>,
+    /// Total size of the downloaded tiles in bytes.
+    pub downloaded_bytes: u64,
+    /// Total size of the tiles that were already in the cache in bytes.
+    pub cached_bytes: u64,
+}
+
+impl DownloadProgress {
+    /// Number of tiles that were processed, successfully or not.
+    pub fn processed_tiles(&self) -> usize {
+        self.downloaded_tiles + self.cached_tiles + self.missing_tiles + self.failed_tiles.len()
+    }
+
+    /// Returns true if all the tiles of the area are stored in the cache.
+    pub fn is_complete(&self) -> bool {
+        self.processed_tiles() == self.total_tiles && self.failed_tiles.is_empty()
+    }
+
+    /// Estimated size of all the tiles of the area in bytes, based on the average size of the
+    /// tiles processed so far. Returns `None` if no tiles were stored yet.
+    pub fn estimated_total_bytes(&self) -> Option<u64> {
+        let stored = (self.downloaded_tiles + self.cached_tiles) as u64;
+        if stored == 0 {
+            return None;
+        }
+
+        let average = (self.downloaded_bytes + self.cached_bytes) as f64 / stored as f64;
+        let expected_tiles = self.total_tiles.saturating_sub(self.missing_tiles);
+        Some((average * expected_tiles as f64).round() as u64)
+    }
+}
+
+type ProgressCallback = dyn Fn(&DownloadProgress) + MaybeSend + MaybeSync;
+
+/// Downloads all tiles of an area into a persistent cache, so that they can be used in offline
+/// mode.
+///
+/// The download is done by a [`TileDownloader`] (e.g. a
+/// [`RestTileLoader`](crate::layer::raster_tile_layer::RestTileLoader) configured with the same
+/// url source and cache as the layer) for every z-level of the given range. Up to
+/// [`concurrency`](AreaDownload::with_concurrency) tiles are downloaded at the same time.
+///
+/// The download can be stopped with [`AreaDownload::cancel`] and resumed by calling
+/// [`AreaDownload::run`] again. The tiles that were already processed are skipped, and the tiles
+/// that failed to download are retried. Tiles stored in the cache by a previous instance (e.g.
+/// before the application was restarted) are not downloaded again unless they are stale.
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use galileo::layer::data_provider::FileCacheController;
+/// use galileo::layer::raster_tile_layer::RestTileLoader;
+/// use galileo::layer::{AreaDownload, DownloadArea};
+/// use galileo::tile_schema::TileSchema;
+/// use galileo_types::cartesian::Rect;
+///
+/// let cache = FileCacheController::new("target/tile_cache", None)?;
+/// let loader = RestTileLoader::new(
+///     |index| {
+///         format!(
+///             "https://tile.openstreetmap.org/{}/{}/{}.png",
+///             index.z, index.x, index.y
+///         )
+///     },
+///     Some(Box::new(cache)),
+///     false,
+/// );
+///
+/// let download = AreaDownload::new(
+///     Arc::new(loader),
+///     TileSchema::web(18),
+///     DownloadArea::BoundingBox(Rect::new(1_000_000.0, 6_000_000.0, 1_010_000.0, 6_010_000.0)),
+///     10..=16,
+/// )
+/// .with_progress_callback(|progress| {
+///     println!("{}/{}", progress.processed_tiles(), progress.total_tiles)
+/// });
+///
+/// # tokio_test::block_on(async {
+/// let result = download.run().await;
+/// assert!(result.is_complete());
+/// # });
+/// # Ok::<(), galileo::error::GalileoError>(())
+/// ```
+pub struct AreaDownload {
+    downloader: Arc<dyn TileDownloader>,
+    tile_schema: TileSchema,
+    area: DownloadArea,
+    z_levels: RangeInclusive<u32>,
+    concurrency: usize,
+    on_progress: Option<Box<ProgressCallback>>,
+    cancelled: Arc<AtomicBool>,
+    state: Mutex<DownloadState>,
+}
+
+#[derive(Default)]
+struct DownloadState {
+    progress: DownloadProgress,
+    processed: HashSet<TileIndex, RandomState>,
+    total_counted: bool,
+}
+
+impl std::fmt::Debug for AreaDownload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AreaDownload")
+            .field("area", &self.area)
+            .field("z_levels", &self.z_levels)
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+}
+
+impl AreaDownload {
+    /// Creates a new download of the given area for the given z-levels.
+    pub fn new(
+        downloader: Arc<dyn TileDownloader>,
+        tile_schema: TileSchema,
+        area: DownloadArea,
+        z_levels: RangeInclusive<u32>,
+    ) -> Self {
+        Self {
+            downloader,
+            tile_schema,
+            area,
+            z_levels,
+            concurrency: DEFAULT_CONCURRENCY,
+            on_progress: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            state: Mutex::new(DownloadState::default()),
+        }
+    }
+
+    /// Sets the maximum number of tiles downloaded at the same time.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the function called every time a tile is processed.
+    pub fn with_progress_callback(
+        mut self,
+        callback: impl Fn(&DownloadProgress) + MaybeSend + MaybeSync + 'static,
+    ) -> Self {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Iterates over indices of all tiles in the area.
+    pub fn tiles(&self) -> impl Iterator<Item = TileIndex> + '_ {
+        let bbox = self.area.bounding_box();
+        self.z_levels
+            .clone()
+            .filter_map(move |z| self.tile_schema.iter_tiles_in_bbox(z, bbox?))
+            .flatten()
+            .filter(|index| {
+                self.tile_schema
+                    .tile_bbox(index.into_wrapping())
+                    .is_some_and(|tile_bbox| self.area.intersects(tile_bbox))
+            })
+    }
+
+    /// Number of tiles in the area.
+    ///
+    /// For a bounding box area the tiles are counted from the index ranges of every z-level. For
+    /// a polygon area every tile of the polygon bounding box has to be checked for intersection,
+    /// so the tiles are enumerated.
+    pub fn count_tiles(&self) -> usize {
+        let Some(bbox) = self.area.bounding_box() else {
+            return 0;
+        };
+
+        match self.area {
+            DownloadArea::BoundingBox(_) => self
+                .z_levels
+                .clone()
+                .filter_map(|z| self.tile_schema.count_tiles_in_bbox(z, bbox))
+                .sum(),
+            DownloadArea::Polygon(_) => self.tiles().count(),
+        }
+    }
+
+    /// Current progress of the download.
+    ///
+    /// The total number of tiles is counted on the first call to this method or to
+    /// [`AreaDownload::run`].
+    pub fn progress(&self) -> DownloadProgress {
+        let mut state = self.state.lock();
+        self.count_total(&mut state);
+        state.progress.clone()
+    }
+
+    fn count_total(&self, state: &mut DownloadState) {
+        if !state.total_counted {
+            state.progress.total_tiles = self.count_tiles();
+            state.total_counted = true;
+        }
+    }
+
+    /// Stops the running download. Tiles that are being downloaded at the moment are dropped.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if the download was cancelled and was not resumed since.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Downloads the tiles that were not processed yet, and retries the tiles that failed.
+    ///
+    /// Returns when all tiles are processed or the download is cancelled.
+    pub async fn run(&self) -> DownloadProgress {
+        self.cancelled.store(false, Ordering::Relaxed);
+        let to_retry: Vec<_> = {
+            let mut state = self.state.lock();
+            self.count_total(&mut state);
+            std::mem::take(&mut state.progress.failed_tiles)
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect()
+        };
+        {
+            let mut state = self.state.lock();
+            for index in &to_retry {
+                state.processed.remove(index);
+            }
+        }
+
+        let pending = self
+            .tiles()
+            .filter(|index| !self.state.lock().processed.contains(index));
+        let mut results = futures::stream::iter(pending)
+            .map(|index| async move {
+                let result = self.downloader.download_to_cache(index).await;
+                (index, result)
+            })
+            .buffer_unordered(self.concurrency);
+
+        while let Some((index, result)) = results.next().await {
+            let progress = {
+                let mut state = self.state.lock();
+                state.processed.insert(index);
+
+                let progress = &mut state.progress;
+                match result {
+                    Ok(TileDownloadStatus::Downloaded { size }) => {
+                        progress.downloaded_tiles += 1;
+                        progress.downloaded_bytes += size;
+                    }
+                    Ok(TileDownloadStatus::Cached { size }) => {
+                        progress.cached_tiles += 1;
+                        progress.cached_bytes += size;
+                    }
+                    Err(GalileoError::NotFound) => progress.missing_tiles += 1,
+                    Err(err) => {
+                        log::debug!("Failed to download tile {index:?}: {err}");
+                        progress.failed_tiles.push((index, err));
+                    }
+                }
+
+                progress.clone()
+            };
+
+            if let Some(callback) = &self.on_progress {
+                callback(&progress);
+            }
+
+            if self.is_cancelled() {
+                log::debug!("Area download cancelled");
+                break;
+            }
+        }
+
+        self.progress()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct TestDownloader {
+        calls: AtomicUsize,
+        fail: Mutex<HashSet<TileIndex>>,
+        missing: HashSet<TileIndex>,
+        cancel_after: Option<(usize, Arc<AtomicBool>)>,
+    }
+
+    #[async_trait::async_trait]
+    impl TileDownloader for TestDownloader {
+        async fn download_to_cache(
+            &self,
+            index: TileIndex,
+        ) -> Result<TileDownloadStatus, GalileoError> {
+            let calls = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some((count, flag)) = &self.cancel_after {
+                if calls == *count {
+                    flag.store(true, Ordering::Relaxed);
+                }
+            }
+
+            if self.missing.contains(&index) {
+                Err(GalileoError::NotFound)
+            } else if self.fail.lock().remove(&index) {
+                Err(GalileoError::IO)
+            } else {
+                Ok(TileDownloadStatus::Downloaded { size: 100 })
+            }
+        }
+    }
+
+    fn world() -> DownloadArea {
+        DownloadArea::BoundingBox(TileSchema::web(18).bounds)
+    }
+
+    #[test]
+    fn enumerates_tiles_in_polygon() {
+        let schema = TileSchema::web(18);
+        let bbox = DownloadArea::BoundingBox(Rect::new(1.0, 1.0, 10_000_000.0, 10_000_000.0));
+        let download = AreaDownload::new(
+            Arc::new(TestDownloader::default()),
+            schema.clone(),
+            bbox,
+            2..=2,
+        );
+        let tiles: Vec<_> = download.tiles().collect();
+        assert_eq!(tiles, vec![TileIndex::new(2, 1, 2)]);
+        assert_eq!(download.progress().total_tiles, 1);
+
+        // Triangle in the south-eastern half of the north-eastern quarter of the world.
+        let triangle = DownloadArea::Polygon(Polygon::new(
+            ClosedContour::new(vec![
+                Point2::new(1_000_000.0, 100.0),
+                Point2::new(20_000_000.0, 100.0),
+                Point2::new(20_000_000.0, 19_000_000.0),
+            ]),
+            vec![],
+        ));
+        let download =
+            AreaDownload::new(Arc::new(TestDownloader::default()), schema, triangle, 3..=3);
+        let tiles: HashSet<_> = download.tiles().collect();
+        assert!(tiles.len() < 16);
+        assert!(tiles.contains(&TileIndex::new(7, 3, 3)));
+        assert!(tiles.contains(&TileIndex::new(7, 0, 3)));
+        assert!(!tiles.contains(&TileIndex::new(4, 0, 3)));
+        assert!(!tiles.contains(&TileIndex::new(7, 4, 3)));
+        assert_eq!(download.progress().total_tiles, tiles.len());
+    }
+
+    #[test]
+    fn counts_bbox_tiles_without_enumerating() {
+        let download = AreaDownload::new(
+            Arc::new(TestDownloader::default()),
+            TileSchema::web(18),
+            world(),
+            0..=17,
+        );
+        let expected = (0..=17).map(|z| 4usize.pow(z)).sum::<usize>();
+        assert_eq!(download.progress().total_tiles, expected);
+    }
+
+    #[tokio::test]
+    async fn downloads_all_tiles() {
+        let downloader = TestDownloader {
+            missing: [TileIndex::new(0, 0, 1)].into(),
+            ..Default::default()
+        };
+        let download = AreaDownload::new(Arc::new(downloader), TileSchema::web(18), world(), 0..=1);
+
+        let progress = download.run().await;
+        assert_eq!(progress.total_tiles, 5);
+        assert_eq!(progress.downloaded_tiles, 4);
+        assert_eq!(progress.missing_tiles, 1);
+        assert_eq!(progress.downloaded_bytes, 400);
+        assert_eq!(progress.estimated_total_bytes(), Some(400));
+        assert!(progress.is_complete());
+    }
+
+    #[tokio::test]
+    async fn resume_retries_failed_tiles() {
+        let downloader = Arc::new(TestDownloader {
+            fail: Mutex::new([TileIndex::new(1, 1, 1)].into()),
+            ..Default::default()
+        });
+        let download = AreaDownload::new(downloader.clone(), TileSchema::web(18), world(), 0..=1);
+
+        let progress = download.run().await;
+        assert_eq!(progress.failed_tiles.len(), 1);
+        assert!(!progress.is_complete());
+
+        let progress = download.run().await;
+        assert!(progress.failed_tiles.is_empty());
+        assert!(progress.is_complete());
+        assert_eq!(downloader.calls.load(Ordering::Relaxed), 6);
+    }
+
+    #[tokio::test]
+    async fn cancel_and_resume() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let downloader = Arc::new(TestDownloader {
+            cancel_after: Some((3, cancelled.clone())),
+            ..Default::default()
+        });
+        let mut download =
+            AreaDownload::new(downloader.clone(), TileSchema::web(18), world(), 0..=2)
+                .with_concurrency(1);
+        download.cancelled = cancelled;
+
+        let progress = download.run().await;
+        assert!(download.is_cancelled());
+        assert_eq!(progress.processed_tiles(), 3);
+
+        let progress = download.run().await;
+        assert!(!download.is_cancelled());
+        assert!(progress.is_complete());
+        assert_eq!(progress.downloaded_tiles, 21);
+        assert_eq!(downloader.calls.load(Ordering::Relaxed), 21);
+    }
+}
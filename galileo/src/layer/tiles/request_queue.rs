@@ -0,0 +1,627 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use futures::channel::oneshot;
+use galileo_types::cartesian::CartesianPoint2d;
+use parking_lot::Mutex;
+
+use crate::tile_schema::{TileIndex, WrappingTileIndex};
+use crate::view::MapView;
+use crate::TileSchema;
+
+/// Default maximum number of requests to a single host that can be run concurrently.
+const DEFAULT_MAX_REQUESTS_PER_HOST: usize = 6;
+
+#[cfg(not(target_arch = "wasm32"))]
+type RequestTask = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+#[cfg(target_arch = "wasm32")]
+type RequestTask = Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+/// Identifier of a source of tile requests (usually a tile layer) in a [`TileRequestQueue`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct RequestSourceId(u32);
+
+impl RequestSourceId {
+    pub(crate) fn next_id() -> Self {
+        static ID: AtomicU32 = AtomicU32::new(0);
+        Self(ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Priority of a tile request. Requests with smaller values are started first.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub(crate) struct TilePriority {
+    /// Difference between the z-level of the tile and the z-level currently displayed.
+    z_distance: u32,
+    /// Distance from the center of the view to the center of the tile in tile sizes.
+    center_distance: f64,
+}
+
+impl TilePriority {
+    /// Calculates priority of the tile with the given index for the given view.
+    pub(crate) fn new(index: WrappingTileIndex, tile_schema: &TileSchema, view: &MapView) -> Self {
+        let view_parameters = tile_schema.view_center_and_resolution(view);
+        let z_distance = view_parameters
+            .and_then(|(_, resolution)| tile_schema.select_lod(resolution))
+            .map(|lod| lod.z_index().abs_diff(index.z))
+            .unwrap_or_default();
+
+        let center_distance = match (tile_schema.tile_bbox(index), view_parameters) {
+            (Some(bbox), Some((position, _))) => {
+                let center = bbox.center();
+                let dx = (center.x() - position.x()) / bbox.width();
+                let dy = (center.y() - position.y()) / bbox.height();
+                (dx * dx + dy * dy).sqrt()
+            }
+            _ => f64::MAX,
+        };
+
+        Self {
+            z_distance,
+            center_distance,
+        }
+    }
+
+    /// Calculates priority of a part of a non-tiled image (e.g. a chunk of a GeoTIFF file) with
+    /// the given bounding box. `z_distance` is the difference between the level of the image the
+    /// part belongs to and the level currently displayed.
+    #[cfg(feature = "geotiff")]
+    pub(crate) fn for_bbox(
+        bbox: galileo_types::cartesian::Rect,
+        z_distance: u32,
+        view: &MapView,
+    ) -> Self {
+        use galileo_types::cartesian::CartesianPoint3d;
+
+        let center_distance = match view.projected_position() {
+            Some(position) => {
+                let center = bbox.center();
+                let dx = (center.x() - position.x()) / bbox.width();
+                let dy = (center.y() - position.y()) / bbox.height();
+                (dx * dx + dy * dy).sqrt()
+            }
+            None => f64::MAX,
+        };
+
+        Self {
+            z_distance,
+            center_distance,
+        }
+    }
+}
+
+/// A single tile request to be put into [`TileRequestQueue`].
+pub(crate) struct TileRequest {
+    pub(crate) index: TileIndex,
+    pub(crate) priority: TilePriority,
+    pub(crate) host: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct RequestKey {
+    source: RequestSourceId,
+    index: TileIndex,
+}
+
+struct PendingRequest {
+    key: RequestKey,
+    priority: TilePriority,
+    host: String,
+    task: RequestTask,
+}
+
+/// Running request that released its slot and waits to get it back.
+struct ResumingRequest {
+    key: RequestKey,
+    host: String,
+    sender: oneshot::Sender<()>,
+}
+
+struct QueueState {
+    max_requests_per_host: usize,
+    pending: Vec<PendingRequest>,
+    active: HashMap<RequestKey, String, ahash::RandomState>,
+    active_per_host: HashMap<String, usize, ahash::RandomState>,
+    /// Running requests that do not hold a slot at the moment.
+    released: HashSet<RequestKey, ahash::RandomState>,
+    resuming: Vec<ResumingRequest>,
+}
+
+/// Slot taken by a running request in a [`TileRequestQueue`].
+///
+/// A request holds its slot until it completes. If the request has to wait for something that
+/// does not involve the host (e.g. a delay before retrying a failed load), it can let other
+/// requests use the slot while waiting with [`RequestSlot::release_while`].
+pub(crate) struct RequestSlot {
+    queue: TileRequestQueue,
+    key: RequestKey,
+}
+
+impl RequestSlot {
+    /// Releases the slot, awaits the `future` and then waits until the slot can be taken again.
+    pub(crate) async fn release_while<T>(&self, future: impl Future<Output = T>) -> T {
+        self.queue.release(self.key);
+        let output = future.await;
+        self.queue.reacquire(self.key).await;
+        output
+    }
+}
+
+/// Slot of a [`TileRequestQueue`] taken by a running request for additional downloads it needs
+/// (e.g. neighboring tiles). The slot is freed when the value is dropped.
+pub(crate) struct ExtraSlot {
+    queue: TileRequestQueue,
+    host: String,
+}
+
+impl Drop for ExtraSlot {
+    fn drop(&mut self) {
+        self.queue.state.lock().free_slot(&self.host);
+        self.queue.start_ready();
+    }
+}
+
+/// Queue of tile download requests.
+///
+/// Tile layers put requests for all tiles they need for the current view into the queue. The queue
+/// then runs them in the order of their priority: tiles of the currently displayed z-level come
+/// first, and among them the ones closest to the center of the view. When a layer updates its
+/// requests for a new view, all its pending requests for tiles that are not needed anymore are
+/// dropped. Requests that have already been started are not interrupted, so their results end up
+/// in the caches of the layer.
+///
+/// Number of requests that are run concurrently is limited per host. Requests from loaders that
+/// do not report a host are counted as requests to the same host.
+///
+/// By default every tile layer creates its own queue. To make the per-host limit apply to several
+/// layers, create one queue and give its clones to all of them:
+///
+/// ```
+/// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+/// use galileo::layer::vector_tile_layer::VectorTileLayerBuilder;
+/// use galileo::layer::TileRequestQueue;
+///
+/// let queue = TileRequestQueue::new(4);
+///
+/// let raster_layer = RasterTileLayerBuilder::new_osm()
+///     .with_request_queue(queue.clone())
+///     .build()?;
+/// let vector_layer = VectorTileLayerBuilder::new_rest(|index| {
+///         format!(
+///             "https://vector_tiles.example.com/{}/{}/{}.pbf",
+///             index.z, index.x, index.y
+///         )
+///     })
+///     .with_request_queue(queue)
+///     .build()?;
+/// # Ok::<(), galileo::error::GalileoError>(())
+/// ```
+#[derive(Clone)]
+pub struct TileRequestQueue {
+    state: Arc<Mutex<QueueState>>,
+}
+
+impl std::fmt::Debug for TileRequestQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock();
+        f.debug_struct("TileRequestQueue")
+            .field("max_requests_per_host", &state.max_requests_per_host)
+            .field("pending", &state.pending.len())
+            .field("active", &state.active.len())
+            .finish()
+    }
+}
+
+impl Default for TileRequestQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_REQUESTS_PER_HOST)
+    }
+}
+
+impl TileRequestQueue {
+    /// Creates a new queue that runs at most `max_requests_per_host` concurrent requests to one
+    /// host.
+    ///
+    /// If `max_requests_per_host` is 0, the limit of 1 request is used.
+    pub fn new(max_requests_per_host: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(QueueState::new(max_requests_per_host.max(1)))),
+        }
+    }
+
+    /// Maximum number of concurrent requests to one host.
+    pub fn max_requests_per_host(&self) -> usize {
+        self.state.lock().max_requests_per_host
+    }
+
+    /// Number of requests waiting to be started.
+    pub fn pending_count(&self) -> usize {
+        self.state.lock().pending.len()
+    }
+
+    /// Number of requests that are currently running.
+    pub fn active_count(&self) -> usize {
+        self.state.lock().active.len()
+    }
+
+    /// Replaces all pending requests of the `source` with the given set of requests and starts
+    /// as many requests as the per-host limit allows.
+    ///
+    /// Requests that are already pending get their priority updated, and the ones that are
+    /// already running are skipped. `create_task` is called only for newly added requests. It
+    /// receives the slot the request will hold while running.
+    pub(crate) fn request_tiles<F, Fut>(
+        &self,
+        source: RequestSourceId,
+        requests: impl IntoIterator<Item = TileRequest>,
+        create_task: F,
+    ) where
+        F: Fn(TileIndex, RequestSlot) -> Fut,
+        Fut: Future<Output = ()> + maybe_sync::MaybeSend + 'static,
+    {
+        {
+            let mut state = self.state.lock();
+            state.update_requests(source, requests, |index| {
+                let slot = RequestSlot {
+                    queue: self.clone(),
+                    key: RequestKey { source, index },
+                };
+                Box::pin(create_task(index, slot))
+            });
+        }
+
+        self.start_ready();
+    }
+
+    /// Takes a slot for the `host` if one is free.
+    ///
+    /// Used by running requests that need to download additional data. Such downloads must not
+    /// wait for a slot while the request holds its own one, as this can block the queue, so they
+    /// should fall back to the slot of the request if this returns `None`.
+    pub(crate) fn try_take_slot(&self, host: Option<&str>) -> Option<ExtraSlot> {
+        let host = host.unwrap_or_default();
+        let mut state = self.state.lock();
+        let has_waiting = state.resuming.iter().any(|request| request.host == host);
+        if has_waiting || !state.has_free_slot(host) {
+            return None;
+        }
+
+        state.take_slot(host);
+        Some(ExtraSlot {
+            queue: self.clone(),
+            host: host.to_string(),
+        })
+    }
+
+    /// Drops all pending requests of the `source`.
+    pub(crate) fn cancel(&self, source: RequestSourceId) {
+        self.state
+            .lock()
+            .pending
+            .retain(|request| request.key.source != source);
+    }
+
+    fn start_ready(&self) {
+        let ready = self.state.lock().take_ready();
+        for request in ready {
+            let queue = self.clone();
+            let key = request.key;
+            crate::async_runtime::spawn(async move {
+                request.task.await;
+                queue.complete(key);
+            });
+        }
+    }
+
+    fn complete(&self, key: RequestKey) {
+        self.state.lock().complete(key);
+        self.start_ready();
+    }
+
+    fn release(&self, key: RequestKey) {
+        self.state.lock().release(key);
+        self.start_ready();
+    }
+
+    async fn reacquire(&self, key: RequestKey) {
+        let receiver = self.state.lock().reacquire(key);
+        if let Some(receiver) = receiver {
+            let _ = receiver.await;
+        }
+    }
+}
+
+impl QueueState {
+    fn new(max_requests_per_host: usize) -> Self {
+        Self {
+            max_requests_per_host,
+            pending: vec![],
+            active: HashMap::default(),
+            active_per_host: HashMap::default(),
+            released: HashSet::default(),
+            resuming: vec![],
+        }
+    }
+
+    fn update_requests(
+        &mut self,
+        source: RequestSourceId,
+        requests: impl IntoIterator<Item = TileRequest>,
+        create_task: impl Fn(TileIndex) -> RequestTask,
+    ) {
+        let mut needed = HashSet::<_, ahash::RandomState>::default();
+        for request in requests {
+            let key = RequestKey {
+                source,
+                index: request.index,
+            };
+
+            if !needed.insert(key) || self.active.contains_key(&key) {
+                continue;
+            }
+
+            if let Some(pending) = self.pending.iter_mut().find(|p| p.key == key) {
+                pending.priority = request.priority;
+                continue;
+            }
+
+            self.pending.push(PendingRequest {
+                key,
+                priority: request.priority,
+                host: request.host.unwrap_or_default(),
+                task: create_task(request.index),
+            });
+        }
+
+        self.pending
+            .retain(|request| request.key.source != source || needed.contains(&request.key));
+    }
+
+    fn take_ready(&mut self) -> Vec<PendingRequest> {
+        // Sorting in reverse order allows popping the most important requests from the end.
+        self.pending.sort_by(|a, b| {
+            b.priority
+                .partial_cmp(&a.priority)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        // Requests that were already running before releasing their slots go first.
+        let mut index = 0;
+        while index < self.resuming.len() {
+            if !self.has_free_slot(&self.resuming[index].host) {
+                index += 1;
+                continue;
+            }
+
+            let request = self.resuming.remove(index);
+            if request.sender.send(()).is_ok() {
+                self.released.remove(&request.key);
+                self.take_slot(&request.host);
+            }
+        }
+
+        let mut ready = vec![];
+        let mut index = self.pending.len();
+        while index > 0 {
+            index -= 1;
+
+            if !self.has_free_slot(&self.pending[index].host) {
+                continue;
+            }
+
+            let request = self.pending.remove(index);
+            self.take_slot(&request.host);
+            self.active.insert(request.key, request.host.clone());
+            ready.push(request);
+        }
+
+        ready
+    }
+
+    fn complete(&mut self, key: RequestKey) {
+        let Some(host) = self.active.remove(&key) else {
+            return;
+        };
+
+        if !self.released.remove(&key) {
+            self.free_slot(&host);
+        }
+    }
+
+    fn release(&mut self, key: RequestKey) {
+        let Some(host) = self.active.get(&key).cloned() else {
+            return;
+        };
+
+        if self.released.insert(key) {
+            self.free_slot(&host);
+        }
+    }
+
+    /// Takes the slot back for a released request if the host has a free one. Otherwise returns
+    /// a receiver that is notified when the slot is taken.
+    fn reacquire(&mut self, key: RequestKey) -> Option<oneshot::Receiver<()>> {
+        let host = self.active.get(&key)?.clone();
+        if !self.released.contains(&key) {
+            return None;
+        }
+
+        let has_waiting = self.resuming.iter().any(|request| request.host == host);
+        if !has_waiting && self.has_free_slot(&host) {
+            self.released.remove(&key);
+            self.take_slot(&host);
+            return None;
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.resuming.push(ResumingRequest { key, host, sender });
+        Some(receiver)
+    }
+
+    fn has_free_slot(&self, host: &str) -> bool {
+        self.active_per_host.get(host).copied().unwrap_or_default() < self.max_requests_per_host
+    }
+
+    fn take_slot(&mut self, host: &str) {
+        *self.active_per_host.entry(host.to_string()).or_default() += 1;
+    }
+
+    fn free_slot(&mut self, host: &str) {
+        if let Some(count) = self.active_per_host.get_mut(host) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.active_per_host.remove(host);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(x: i32, center_distance: f64, host: &str) -> TileRequest {
+        TileRequest {
+            index: TileIndex::new(x, 0, 10),
+            priority: TilePriority {
+                z_distance: 0,
+                center_distance,
+            },
+            host: Some(host.to_string()),
+        }
+    }
+
+    fn noop_task(_index: TileIndex) -> RequestTask {
+        Box::pin(async {})
+    }
+
+    fn started_x(ready: &[PendingRequest]) -> Vec<i32> {
+        ready.iter().map(|r| r.key.index.x).collect()
+    }
+
+    #[test]
+    fn starts_closest_tiles_first() {
+        let mut state = QueueState::new(2);
+        let source = RequestSourceId::next_id();
+        state.update_requests(
+            source,
+            [
+                request(0, 3.0, "a"),
+                request(1, 1.0, "a"),
+                request(2, 2.0, "a"),
+                request(3, 0.5, "a"),
+            ],
+            noop_task,
+        );
+
+        let ready = state.take_ready();
+        assert_eq!(started_x(&ready), vec![3, 1]);
+        assert_eq!(state.pending.len(), 2);
+    }
+
+    #[test]
+    fn current_z_level_goes_before_closer_tiles() {
+        let mut state = QueueState::new(1);
+        let source = RequestSourceId::next_id();
+        let mut other_z = request(0, 0.0, "a");
+        other_z.priority.z_distance = 1;
+        state.update_requests(source, [other_z, request(1, 5.0, "a")], noop_task);
+
+        let ready = state.take_ready();
+        assert_eq!(started_x(&ready), vec![1]);
+    }
+
+    #[test]
+    fn limits_requests_per_host() {
+        let mut state = QueueState::new(1);
+        let source = RequestSourceId::next_id();
+        state.update_requests(
+            source,
+            [
+                request(0, 0.0, "a"),
+                request(1, 1.0, "a"),
+                request(2, 2.0, "b"),
+            ],
+            noop_task,
+        );
+
+        let ready = state.take_ready();
+        assert_eq!(started_x(&ready), vec![0, 2]);
+        assert!(state.take_ready().is_empty());
+
+        state.complete(ready[0].key);
+        let ready = state.take_ready();
+        assert_eq!(started_x(&ready), vec![1]);
+    }
+
+    #[test]
+    fn drops_requests_that_are_not_needed() {
+        let mut state = QueueState::new(1);
+        let source = RequestSourceId::next_id();
+        let other_source = RequestSourceId::next_id();
+        state.update_requests(
+            source,
+            [request(0, 0.0, "a"), request(1, 1.0, "a")],
+            noop_task,
+        );
+        state.update_requests(other_source, [request(5, 1.0, "a")], noop_task);
+        state.update_requests(source, [request(2, 1.0, "a")], noop_task);
+
+        let pending: Vec<_> = state.pending.iter().map(|r| r.key.index.x).collect();
+        assert_eq!(pending, vec![5, 2]);
+    }
+
+    #[test]
+    fn does_not_repeat_active_requests() {
+        let mut state = QueueState::new(1);
+        let source = RequestSourceId::next_id();
+        state.update_requests(source, [request(0, 0.0, "a")], noop_task);
+        let ready = state.take_ready();
+        assert_eq!(ready.len(), 1);
+
+        state.update_requests(source, [request(0, 0.0, "a")], noop_task);
+        assert!(state.pending.is_empty());
+    }
+
+    #[test]
+    fn released_slot_is_used_by_other_requests() {
+        let mut state = QueueState::new(1);
+        let source = RequestSourceId::next_id();
+        state.update_requests(
+            source,
+            [request(0, 0.0, "a"), request(1, 1.0, "a")],
+            noop_task,
+        );
+        let first = state.take_ready().remove(0).key;
+
+        state.release(first);
+        let second = state.take_ready().remove(0).key;
+        assert_eq!(second.index.x, 1);
+
+        let mut receiver = state.reacquire(first).expect("host has no free slots");
+        assert_eq!(receiver.try_recv(), Ok(None));
+
+        state.complete(second);
+        assert!(state.take_ready().is_empty());
+        assert_eq!(receiver.try_recv(), Ok(Some(())));
+
+        state.complete(first);
+        assert!(state.active.is_empty());
+        assert!(state.active_per_host.is_empty());
+    }
+
+    #[test]
+    fn extra_slot_counts_toward_host_limit() {
+        let queue = TileRequestQueue::new(2);
+        let first = queue.try_take_slot(Some("a")).expect("free slot");
+        let _second = queue.try_take_slot(Some("a")).expect("free slot");
+        assert!(queue.try_take_slot(Some("a")).is_none());
+        assert!(queue.try_take_slot(Some("b")).is_some());
+
+        drop(first);
+        assert!(queue.try_take_slot(Some("a")).is_some());
+    }
+}
@@ -0,0 +1,170 @@
+use std::future::Future;
+
+use web_time::{Duration, Instant};
+
+use super::request_queue::RequestSlot;
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_BACKOFF_FACTOR: f64 = 2.0;
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+const DEFAULT_ERROR_EXPIRY: Duration = Duration::from_secs(30);
+
+/// Specifies how tile layers handle tiles that failed to load.
+///
+/// When a tile fails to load because of a transient error (e.g. a network failure), the loading
+/// is retried up to `max_retries` times. The first retry is done after `initial_delay`, and every
+/// next delay is `backoff_factor` times larger than the previous one, but not larger than
+/// `max_delay`.
+///
+/// If all the attempts fail, the tile is marked as failed and is not requested again until
+/// `error_expiry` duration passes. Tiles that do not exist in the source (the loader returned
+/// "not found" error, e.g. HTTP 404) are treated as empty tiles and are never retried. Tiles that
+/// are missing from the cache in offline mode are treated as failed, so they are requested again
+/// after `error_expiry`.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use galileo::layer::raster_tile_layer::RasterTileLayerBuilder;
+/// use galileo::layer::RetryPolicy;
+///
+/// let layer = RasterTileLayerBuilder::new_osm()
+///     .with_retry_policy(RetryPolicy {
+///         max_retries: 5,
+///         error_expiry: Some(Duration::from_secs(60)),
+///         ..Default::default()
+///     })
+///     .build()?;
+/// # Ok::<(), galileo::error::GalileoError>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts to load a tile after the first one failed.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Multiplier applied to the delay after every retry.
+    pub backoff_factor: f64,
+    /// Maximum delay between two attempts.
+    pub max_delay: Duration,
+    /// Duration after which a failed tile is requested again. If `None`, failed tiles are never
+    /// requested again.
+    pub error_expiry: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_delay: DEFAULT_INITIAL_DELAY,
+            backoff_factor: DEFAULT_BACKOFF_FACTOR,
+            max_delay: DEFAULT_MAX_DELAY,
+            error_expiry: Some(DEFAULT_ERROR_EXPIRY),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries failed requests and never requests failed tiles again.
+    pub fn no_retry() -> Self {
+        Self {
+            max_retries: 0,
+            error_expiry: None,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before the retry with the given number (starting from 0).
+    pub fn retry_delay(&self, retry: u32) -> Duration {
+        let factor = self
+            .backoff_factor
+            .max(1.0)
+            .powi(retry.min(i32::MAX as u32) as i32);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+        if delay.is_finite() && delay < self.max_delay.as_secs_f64() {
+            Duration::from_secs_f64(delay)
+        } else {
+            self.max_delay
+        }
+    }
+
+    /// Returns true if the tile that failed to load at `failed_at` should be requested again.
+    pub fn is_error_expired(&self, failed_at: Instant) -> bool {
+        match self.error_expiry {
+            Some(expiry) => failed_at.elapsed() >= expiry,
+            None => false,
+        }
+    }
+
+    /// Runs the `attempt` until it succeeds, returns a non-transient error or the number of
+    /// retries is exhausted.
+    ///
+    /// If the attempts are run in a slot of a request queue, the slot is released for the time of
+    /// the delays between the attempts.
+    pub(crate) async fn run<T, E, Fut>(
+        &self,
+        mut attempt: impl FnMut() -> Fut,
+        is_transient: impl Fn(&E) -> bool,
+        slot: Option<&RequestSlot>,
+    ) -> Result<T, E>
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut retry = 0;
+        loop {
+            match attempt().await {
+                Err(err) if retry < self.max_retries && is_transient(&err) => {
+                    let delay = self.retry_delay(retry);
+                    log::debug!("Tile loading failed, retrying in {delay:?}");
+                    let sleep = crate::async_runtime::sleep(delay);
+                    match slot {
+                        Some(slot) => slot.release_while(sleep).await,
+                        None => sleep.await,
+                    }
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_millis(100),
+            backoff_factor: 2.0,
+            max_delay: Duration::from_millis(500),
+            ..Default::default()
+        };
+
+        assert_eq!(policy.retry_delay(0), Duration::from_millis(100));
+        assert_eq!(policy.retry_delay(1), Duration::from_millis(200));
+        assert_eq!(policy.retry_delay(2), Duration::from_millis(400));
+        assert_eq!(policy.retry_delay(3), Duration::from_millis(500));
+        assert_eq!(policy.retry_delay(100), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn error_expiry() {
+        let failed_at = Instant::now() - Duration::from_secs(10);
+        let policy = RetryPolicy {
+            error_expiry: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        assert!(policy.is_error_expired(failed_at));
+
+        let policy = RetryPolicy {
+            error_expiry: Some(Duration::from_secs(20)),
+            ..Default::default()
+        };
+        assert!(!policy.is_error_expired(failed_at));
+
+        assert!(!RetryPolicy::no_retry().is_error_expired(failed_at));
+    }
+}
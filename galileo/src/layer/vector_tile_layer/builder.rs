@@ -2,6 +2,7 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use bytes::Bytes;
+use web_time::Duration;
 
 use super::style::{
     StyleRule, VectorTileLineSymbol, VectorTilePolygonSymbol, VectorTileStyle, VectorTileSymbol,
@@ -13,9 +14,10 @@ use super::VectorTileLayer;
 use crate::error::GalileoError;
 use crate::layer::attribution::Attribution;
 use crate::layer::data_provider::{
-    FileCacheController, FileCachePathModifier, PersistentCacheController, UrlSource,
+    FileCacheController, FileCachePathModifier, PersistentCacheController, RequestSettings,
+    UrlSource,
 };
-use crate::layer::Layer;
+use crate::layer::{Layer, RetryPolicy, TileRequestQueue};
 use crate::tile_schema::TileIndex;
 use crate::{Color, Messenger, TileSchema};
 
@@ -51,7 +53,10 @@ pub struct VectorTileLayerBuilder {
     messenger: Option<Box<dyn Messenger>>,
     cache: CacheType,
     offline_mode: bool,
+    request_settings: RequestSettings,
     attribution: Option<Attribution>,
+    request_queue: Option<TileRequestQueue>,
+    retry_policy: RetryPolicy,
 }
 
 enum ProviderType {
@@ -88,7 +93,10 @@ impl VectorTileLayerBuilder {
             messenger: None,
             cache: CacheType::None,
             offline_mode: false,
+            request_settings: RequestSettings::default(),
             attribution: None,
+            request_queue: None,
+            retry_policy: RetryPolicy::default(),
         }
     }
 
@@ -116,7 +124,10 @@ impl VectorTileLayerBuilder {
             messenger: None,
             cache: CacheType::None,
             offline_mode: false,
+            request_settings: RequestSettings::default(),
             attribution: None,
+            request_queue: None,
+            retry_policy: RetryPolicy::default(),
         }
     }
 
@@ -373,6 +384,102 @@ impl VectorTileLayerBuilder {
         self
     }
 
+    /// Adds the header to every tile request the layer makes.
+    ///
+    /// Some tile servers require requests to identify the application (e.g. with `User-Agent` or
+    /// `Referer` headers) or to carry an API key. Setting a header with the same name again
+    /// replaces the previous value.
+    ///
+    /// Headers can only be set if the layer loads tiles from a url source. Building a layer with a
+    /// custom tile loader and additional headers will return an error.
+    ///
+    /// ```
+    /// use galileo::layer::vector_tile_layer::VectorTileLayerBuilder;
+    ///
+    /// let layer = VectorTileLayerBuilder::new_rest(|index| {
+    ///     format!("https://vector_tiles.example.com/{}/{}/{}.pbf", index.z, index.x, index.y)
+    /// })
+    ///     .with_header("User-Agent", "my-app/1.0 (contact@example.com)")
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request_settings.set_header(name, value);
+        self
+    }
+
+    /// Sets the maximum time to wait for a tile response. Tiles that are not loaded in time are
+    /// treated as failed and are handled according to the retry policy of the layer.
+    ///
+    /// Like headers, the timeout cannot be used together with a custom tile loader.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use galileo::layer::vector_tile_layer::VectorTileLayerBuilder;
+    ///
+    /// let layer = VectorTileLayerBuilder::new_rest(|index| {
+    ///     format!("https://vector_tiles.example.com/{}/{}/{}.pbf", index.z, index.x, index.y)
+    /// })
+    ///     .with_request_timeout(Duration::from_secs(10))
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_settings.set_timeout(timeout);
+        self
+    }
+
+    /// Sets the queue the layer puts its tile requests into.
+    ///
+    /// By default each layer creates its own [`TileRequestQueue`]. Setting the same queue for
+    /// several layers makes them share the limit of concurrent requests per host.
+    ///
+    /// ```
+    /// use galileo::layer::vector_tile_layer::VectorTileLayerBuilder;
+    /// use galileo::layer::TileRequestQueue;
+    ///
+    /// let layer = VectorTileLayerBuilder::new_rest(
+    ///     |index| {
+    ///         format!(
+    ///             "https://vector_tiles.example.com/{}/{}/{}.png",
+    ///             index.z, index.x, index.y
+    ///         )
+    ///     })
+    ///     .with_request_queue(TileRequestQueue::new(2))
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_request_queue(mut self, request_queue: TileRequestQueue) -> Self {
+        self.request_queue = Some(request_queue);
+        self
+    }
+
+    /// Sets the way the layer handles tiles that failed to load.
+    ///
+    /// Defaults to [`RetryPolicy::default()`]. If the layer is created with a custom provider,
+    /// this policy replaces the one set for the provider.
+    ///
+    /// ```
+    /// use galileo::layer::vector_tile_layer::VectorTileLayerBuilder;
+    /// use galileo::layer::RetryPolicy;
+    ///
+    /// let layer = VectorTileLayerBuilder::new_rest(
+    ///     |index| {
+    ///         format!(
+    ///             "https://vector_tiles.example.com/{}/{}/{}.png",
+    ///             index.z, index.x, index.y
+    ///         )
+    ///     })
+    ///     .with_retry_policy(RetryPolicy::no_retry())
+    ///     .build()?;
+    /// # Ok::<(), galileo::error::GalileoError>(())
+    /// ```
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
     /// Consumes the builder and constructs the vector tile layer.
     ///
     /// Will return an error if the layer is configured incorrectly or if the cache controller
@@ -385,7 +492,10 @@ impl VectorTileLayerBuilder {
             messenger,
             cache,
             offline_mode,
+            request_settings,
             attribution,
+            request_queue,
+            retry_policy,
         } = self;
 
         let tile_schema = tile_schema.unwrap_or_else(|| TileSchema::web(18));
@@ -406,9 +516,10 @@ impl VectorTileLayerBuilder {
 
         let processor = Self::create_processor(tile_schema.clone());
 
-        let provider = match provider_type {
+        let mut provider = match provider_type {
             ProviderType::Rest(url_source) => {
-                let loader = WebVtLoader::new(cache_controller, url_source, offline_mode);
+                let loader = WebVtLoader::new(cache_controller, url_source, offline_mode)
+                    .with_request_settings(request_settings);
 
                 VectorTileProvider::new(Arc::new(loader), Arc::new(processor))
             }
@@ -420,13 +531,28 @@ impl VectorTileLayerBuilder {
                     ));
                 }
 
+                if !request_settings.is_empty() {
+                    return Err(GalileoError::Configuration(
+                        "custom tile provider cannot be used together with request headers or timeout"
+                            .into(),
+                    ));
+                }
+
                 raster_tile_provider
             }
         };
 
+        provider.set_retry_policy(retry_policy);
+
         let style = style.unwrap_or_else(Self::default_style);
 
-        let mut layer = VectorTileLayer::new(provider, style, tile_schema, attribution);
+        let mut layer = VectorTileLayer::new_raw(
+            provider,
+            style,
+            tile_schema,
+            attribution,
+            request_queue.unwrap_or_default(),
+        );
         if let Some(messenger) = messenger {
             layer.set_messenger(messenger);
         }
@@ -540,6 +666,17 @@ mod tests {
         assert_compact_debug_snapshot!(result, @r#"Err(Configuration("custom tile provider cannot be used together with a cache controller"))"#);
     }
 
+    #[test]
+    fn with_request_timeout_fails_build_if_custom_provider() {
+        let provider = custom_provider();
+        let result = VectorTileLayerBuilder::new_with_provider(provider)
+            .with_request_timeout(Duration::from_secs(5))
+            .build();
+
+        assert!(result.is_err());
+        assert_compact_debug_snapshot!(result, @r#"Err(Configuration("custom tile provider cannot be used together with request headers or timeout"))"#);
+    }
+
     #[test]
     fn with_offline_mode_incompatible_with_custom_provider() {
         let provider = custom_provider();
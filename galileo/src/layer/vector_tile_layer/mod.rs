@@ -21,7 +21,8 @@ use crate::layer::Layer;
 use crate::messenger::Messenger;
 use crate::render::render_bundle::RenderBundle;
 use crate::render::{BundleToDraw, Canvas, PackedBundle, PolygonPaint, RenderOptions};
-use crate::tile_schema::{TileIndex, TileSchema};
+use crate::reprojection::CrsTransform;
+use crate::tile_schema::{TileIndex, TileSchema, WrappingTileIndex};
 use crate::view::MapView;
 use crate::Color;
 
@@ -31,7 +32,7 @@ pub mod tile_provider;
 mod vector_tile;
 pub use builder::VectorTileLayerBuilder;
 
-use super::tiles::TilesContainer;
+use super::tiles::{RequestSourceId, TilePriority, TileRequest, TileRequestQueue, TilesContainer};
 
 /// Vector tile layers use [tile providers](VectorTileProvider) to load prepared vector tiles, and then render them using
 /// specified [styles](VectorTileStyle).
@@ -42,6 +43,8 @@ pub struct VectorTileLayer {
     displayed_tiles: TilesContainer<VtStyleId, VectorTileProvider>,
     prev_background: Mutex<Option<PreviousBackground>>,
     attribution: Option<Attribution>,
+    request_queue: TileRequestQueue,
+    request_source: RequestSourceId,
 }
 
 impl std::fmt::Debug for VectorTileLayer {
@@ -59,6 +62,12 @@ struct PreviousBackground {
     replaced_at: web_time::Instant,
 }
 
+impl Drop for VectorTileLayer {
+    fn drop(&mut self) {
+        self.request_queue.cancel(self.request_source);
+    }
+}
+
 impl Layer for VectorTileLayer {
     fn render(&self, view: &MapView, canvas: &mut dyn Canvas) {
         self.update_displayed_tiles(view, canvas);
@@ -68,15 +77,21 @@ impl Layer for VectorTileLayer {
             return;
         };
 
+        // Bundles are positioned relative to the top left corners of the tiles.
+        let Some(transform) = CrsTransform::new(&self.tile_schema.crs, view.crs()) else {
+            return;
+        };
+
         let displayed_tiles = self.displayed_tiles.tiles.lock();
         let to_render: Vec<_> =
             std::iter::once(BundleToDraw::with_opacity(&*background_bundle, 1.0))
                 .chain(displayed_tiles.iter().filter_map(|v| {
                     let bbox = self.tile_schema.tile_bbox(v.index)?;
+                    let top_left = transform.transform(&Point2::new(bbox.x_min(), bbox.y_max()))?;
                     Some(BundleToDraw::new(
                         &*v.bundle,
                         v.opacity,
-                        Vector2::new(bbox.x_min() as f32, bbox.y_max() as f32),
+                        Vector2::new(top_left.x() as f32, top_left.y() as f32),
                     ))
                 }))
                 .collect();
@@ -85,11 +100,29 @@ impl Layer for VectorTileLayer {
     }
 
     fn prepare(&self, view: &MapView) {
-        if let Some(iter) = self.tile_schema.iter_tiles(view) {
-            for index in iter {
-                self.tile_provider.load_tile(index.into(), self.style_id);
+        let Some(indices) = self.needed_tiles(view) else {
+            return;
+        };
+
+        let requests = indices.into_iter().filter_map(|index| {
+            let tile_index = TileIndex::from(index);
+            if !self.tile_provider.needs_loading(tile_index, self.style_id) {
+                return None;
             }
-        }
+
+            Some(TileRequest {
+                index: tile_index,
+                priority: TilePriority::new(index, &self.tile_schema, view),
+                host: self.tile_provider.request_host(tile_index),
+            })
+        });
+
+        let provider = self.tile_provider.clone();
+        let style_id = self.style_id;
+        self.request_queue
+            .request_tiles(self.request_source, requests, move |index, slot| {
+                provider.load_tile_task(index, style_id, Some(slot))
+            });
     }
 
     fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
@@ -123,10 +156,26 @@ impl VectorTileLayer {
 
     /// Creates a new layer with the given url source.
     pub fn new(
+        tile_provider: VectorTileProvider,
+        style: VectorTileStyle,
+        tile_schema: TileSchema,
+        attribution: Option<Attribution>,
+    ) -> Self {
+        Self::new_raw(
+            tile_provider,
+            style,
+            tile_schema,
+            attribution,
+            TileRequestQueue::default(),
+        )
+    }
+
+    fn new_raw(
         mut tile_provider: VectorTileProvider,
         style: VectorTileStyle,
         tile_schema: TileSchema,
         attribution: Option<Attribution>,
+        request_queue: TileRequestQueue,
     ) -> Self {
         let style_id = tile_provider.add_style(style);
         Self {
@@ -136,15 +185,35 @@ impl VectorTileLayer {
             displayed_tiles: TilesContainer::new(tile_schema, tile_provider),
             prev_background: Default::default(),
             attribution,
+            request_queue,
+            request_source: RequestSourceId::next_id(),
+        }
+    }
+
+    /// Returns the indices of the tiles needed to display the view.
+    ///
+    /// If the view CRS is different from the CRS of the tile schema, the tiles covering the view
+    /// footprint in the schema CRS are selected.
+    fn needed_tiles(&self, view: &MapView) -> Option<Vec<WrappingTileIndex>> {
+        if *view.crs() == self.tile_schema.crs {
+            Some(self.tile_schema.iter_tiles(view)?.collect())
+        } else {
+            Some(self.tile_schema.iter_tiles_reprojected(view)?.collect())
         }
     }
 
     fn update_displayed_tiles(&self, view: &MapView, canvas: &dyn Canvas) {
-        let Some(tile_iter) = self.tile_schema.iter_tiles(view) else {
+        let target_crs = (*view.crs() != self.tile_schema.crs).then(|| view.crs());
+        if self.tile_provider.set_target_crs(target_crs) {
+            // Tiles prepared for another CRS would be drawn at wrong positions.
+            self.displayed_tiles.tiles.lock().clear();
+            self.tile_provider.request_redraw();
+        }
+
+        let Some(needed_indices) = self.needed_tiles(view) else {
             return;
         };
 
-        let needed_indices: Vec<_> = tile_iter.collect();
         let mut to_pack: Vec<TileIndex> = needed_indices.iter().map(|t| (*t).into()).collect();
         to_pack.dedup();
 
@@ -174,6 +243,9 @@ impl VectorTileLayer {
         }
         self.tile_provider.drop_style(self.style_id);
         self.style_id = new_style_id;
+
+        // Pending requests were created for the old style, so there is no point in running them.
+        self.request_queue.cancel(self.request_source);
     }
 
     /// Returns features, visible in the layer at the given point with the given map view.
@@ -183,17 +255,26 @@ impl VectorTileLayer {
         view: &MapView,
     ) -> Vec<(String, MvtFeature)> {
         const PIXEL_TOLERANCE: f64 = 2.0;
-        let view_resolution = view.resolution();
+
+        // Tile geometries are searched in the CRS of the tile schema.
+        let Some(point) = CrsTransform::new(view.crs(), &self.tile_schema.crs)
+            .and_then(|transform| transform.transform(&Point2::new(point.x(), point.y())))
+        else {
+            return vec![];
+        };
+        let Some((_, view_resolution)) = self.tile_schema.view_center_and_resolution(view) else {
+            return vec![];
+        };
         let res_tolerance = view_resolution * PIXEL_TOLERANCE;
 
         let mut features = vec![];
-        if let Some(iter) = self.tile_schema.iter_tiles(view) {
-            for index in iter {
+        if let Some(indices) = self.needed_tiles(view) {
+            for index in indices {
                 let Some(tile_bbox) = self.tile_schema.tile_bbox(index) else {
                     continue;
                 };
 
-                if !tile_bbox.shrink(-res_tolerance).contains(point) {
+                if !tile_bbox.shrink(-res_tolerance).contains(&point) {
                     continue;
                 }
 
@@ -208,7 +289,7 @@ impl VectorTileLayer {
                     ((tile_bbox.y_max() - point.y()) / tile_resolution) as f32,
                 );
 
-                let tolerance = ((view.resolution() / tile_resolution) * PIXEL_TOLERANCE) as f32;
+                let tolerance = ((view_resolution / tile_resolution) * PIXEL_TOLERANCE) as f32;
 
                 if let Some(mvt_tile) = self.tile_provider.get_mvt_tile(index.into()) {
                     for layer in &mvt_tile.layers {
@@ -298,6 +379,9 @@ impl VectorTileLayer {
 
 #[cfg(test)]
 mod tests {
+    use galileo_types::cartesian::Size;
+    use galileo_types::geo::Crs;
+
     use super::*;
     use crate::platform::native::vt_processor::ThreadVtProcessor;
     use crate::tests::TestTileLoader;
@@ -317,6 +401,8 @@ mod tests {
             displayed_tiles: TilesContainer::new(tile_schema, provider),
             prev_background: Default::default(),
             attribution: None,
+            request_queue: TileRequestQueue::default(),
+            request_source: RequestSourceId::next_id(),
         }
     }
 
@@ -331,4 +417,15 @@ mod tests {
         assert!(layer.tile_provider.get_style(new_style_id).is_some());
         assert!(layer.tile_provider.get_style(style_id).is_none());
     }
+
+    #[test]
+    fn tiles_are_selected_for_view_in_other_crs() {
+        let layer = test_layer();
+        let view = MapView::new_projected_with_crs(&Point2::new(0.0, 0.0), 0.01, Crs::WGS84)
+            .with_size(Size::new(256.0, 256.0));
+
+        let tiles = layer.needed_tiles(&view).unwrap();
+        assert_eq!(tiles.len(), 4);
+        assert!(tiles.iter().all(|index| index.z == 8));
+    }
 }
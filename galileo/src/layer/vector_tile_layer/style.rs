@@ -1,6 +1,9 @@
 //! See [`VectorTileStyle`].
 
-use galileo_mvt::{MvtFeature, MvtGeometry};
+use std::sync::LazyLock;
+
+use galileo_mvt::{MvtFeature, MvtGeometry, MvtValue};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use crate::render::point_paint::PointPaint;
@@ -51,43 +54,56 @@ impl VectorTileStyle {
                 return false;
             }
 
-            let filter_check_passed = rule.properties.iter().all(|filter| {
-                let value = feature.properties.get(&filter.property_name);
-                match (&filter.operator, value) {
-                    (PropertyFilterOperator::Equal(value), Some(v)) => v.eq_str(value),
-                    (PropertyFilterOperator::NotEqual(value), Some(v)) => !v.eq_str(value),
-                    (PropertyFilterOperator::NotEqual(_), None) => true,
-                    (PropertyFilterOperator::GreaterThan(value), Some(v)) => {
-                        compare_numeric(v, value, |a, b| a > b)
-                    }
-                    (PropertyFilterOperator::LessThan(value), Some(v)) => {
-                        compare_numeric(v, value, |a, b| a < b)
-                    }
-                    (PropertyFilterOperator::GreaterThanOrEqual(value), Some(v)) => {
-                        compare_numeric(v, value, |a, b| a >= b)
-                    }
-                    (PropertyFilterOperator::LessThanOrEqual(value), Some(v)) => {
-                        compare_numeric(v, value, |a, b| a <= b)
-                    }
-                    (PropertyFilterOperator::OneOf(values), Some(v)) => {
-                        values.iter().any(|candidate| v.eq_str(candidate))
-                    }
-                    (PropertyFilterOperator::NotOneOf(values), Some(v)) => {
-                        !values.iter().any(|candidate| v.eq_str(candidate))
-                    }
-                    (PropertyFilterOperator::Exist, Some(_)) => true,
-                    (PropertyFilterOperator::NotExist, None) => true,
-
-                    _ => false,
-                }
-            });
-
-            filter_check_passed
+            rule.properties
+                .iter()
+                .all(|filter| filter.matches(feature.properties.get(&filter.property_name)))
         })
     }
 }
 
-fn compare_numeric(a: &galileo_mvt::MvtValue, b: &str, cmp: impl Fn(f64, f64) -> bool) -> bool {
+/// Value of a feature property that can be checked by a [`PropertyFilter`].
+pub trait PropertyValue {
+    /// Returns true if the value is equal to the value given as a string.
+    fn eq_str(&self, value: &str) -> bool;
+    /// Returns the value as a number, if it is numeric.
+    fn as_f64(&self) -> Option<f64>;
+    /// Returns the text representation of the value, used to substitute the value into label patterns.
+    fn text(&self) -> String;
+}
+
+impl PropertyValue for MvtValue {
+    fn eq_str(&self, value: &str) -> bool {
+        MvtValue::eq_str(self, value)
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        MvtValue::as_f64(self)
+    }
+
+    fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl PropertyValue for str {
+    fn eq_str(&self, value: &str) -> bool {
+        self == value
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        self.parse().ok()
+    }
+
+    fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+fn compare_numeric<V: PropertyValue + ?Sized>(
+    a: &V,
+    b: &str,
+    cmp: impl Fn(f64, f64) -> bool,
+) -> bool {
     if let Some(a_num) = a.as_f64() {
         if let Ok(b_num) = b.parse::<f64>() {
             return cmp(a_num, b_num);
@@ -119,6 +135,40 @@ pub struct PropertyFilter {
     pub operator: PropertyFilterOperator,
 }
 
+impl PropertyFilter {
+    /// Checks if the given value of the property passes the filter. `None` means that the feature doesn't have
+    /// the property.
+    pub fn matches<V: PropertyValue + ?Sized>(&self, value: Option<&V>) -> bool {
+        match (&self.operator, value) {
+            (PropertyFilterOperator::Equal(value), Some(v)) => v.eq_str(value),
+            (PropertyFilterOperator::NotEqual(value), Some(v)) => !v.eq_str(value),
+            (PropertyFilterOperator::NotEqual(_), None) => true,
+            (PropertyFilterOperator::GreaterThan(value), Some(v)) => {
+                compare_numeric(v, value, |a, b| a > b)
+            }
+            (PropertyFilterOperator::LessThan(value), Some(v)) => {
+                compare_numeric(v, value, |a, b| a < b)
+            }
+            (PropertyFilterOperator::GreaterThanOrEqual(value), Some(v)) => {
+                compare_numeric(v, value, |a, b| a >= b)
+            }
+            (PropertyFilterOperator::LessThanOrEqual(value), Some(v)) => {
+                compare_numeric(v, value, |a, b| a <= b)
+            }
+            (PropertyFilterOperator::OneOf(values), Some(v)) => {
+                values.iter().any(|candidate| v.eq_str(candidate))
+            }
+            (PropertyFilterOperator::NotOneOf(values), Some(v)) => {
+                !values.iter().any(|candidate| v.eq_str(candidate))
+            }
+            (PropertyFilterOperator::Exist, Some(_)) => true,
+            (PropertyFilterOperator::NotExist, None) => true,
+
+            _ => false,
+        }
+    }
+}
+
 /// Operators for filtering feature properties.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
@@ -309,6 +359,21 @@ pub struct VectorTileLabelSymbol {
     pub text_style: TextStyle,
 }
 
+impl VectorTileLabelSymbol {
+    /// Substitutes the values of the feature properties into the pattern. Properties that the feature doesn't
+    /// have are replaced with empty strings.
+    pub(crate) fn format_text(&self, property: impl Fn(&str) -> Option<String>) -> String {
+        static PROPERTY_RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"\{(?<name>[^}]+)\}").expect("valid regex"));
+
+        PROPERTY_RE
+            .replace_all(&self.pattern, |captures: &regex::Captures| {
+                property(&captures["name"]).unwrap_or_default()
+            })
+            .into_owned()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -327,6 +392,31 @@ mod tests {
         assert!(value.as_object().unwrap().get("polygon").is_none());
     }
 
+    #[test]
+    fn format_text_substitutes_every_property() {
+        let symbol = VectorTileLabelSymbol {
+            pattern: "{a} {b} {missing}!".to_string(),
+            text_style: TextStyle {
+                font_family: vec![],
+                font_size: 12.0,
+                font_color: Color::BLACK,
+                horizontal_alignment: Default::default(),
+                vertical_alignment: Default::default(),
+                weight: Default::default(),
+                style: Default::default(),
+                outline_width: Default::default(),
+                outline_color: Default::default(),
+            },
+        };
+
+        let text = symbol.format_text(|name| match name {
+            "a" => Some("first".to_string()),
+            "b" => Some("second".to_string()),
+            _ => None,
+        });
+        assert_eq!(text, "first second !");
+    }
+
     #[test]
     fn serialize_with_bincode() {
         let rule = StyleRule {
@@ -1,12 +1,18 @@
 //! Vector tile loader stuff.
 
+use std::sync::Arc;
+
 use bytes::Bytes;
 use galileo_mvt::MvtTile;
 use maybe_sync::{MaybeSend, MaybeSync};
+use web_time::Duration;
 
 use crate::error::GalileoError;
-use crate::layer::data_provider::{PersistentCacheController, UrlSource};
-use crate::platform::PlatformService;
+use crate::layer::data_provider::{
+    load_with_cache, store_in_cache, url_host, HttpRequest, PersistentCacheController,
+    RequestSettings, UrlSource,
+};
+use crate::layer::{TileDownloadStatus, TileDownloader};
 use crate::tile_schema::TileIndex;
 
 /// Error that can occur when trying to load a vector tile.
@@ -25,13 +31,23 @@ pub enum TileLoadError {
 pub trait VectorTileLoader: MaybeSend + MaybeSync {
     /// Load tile with the given index.
     async fn load(&self, index: TileIndex) -> Result<MvtTile, TileLoadError>;
+
+    /// Returns the host the tile with the given index is requested from.
+    ///
+    /// The value is used by [`TileRequestQueue`](crate::layer::TileRequestQueue) to limit the
+    /// number of concurrent requests to a single host. Loaders that do not load tiles over network
+    /// can return `None`.
+    fn request_host(&self, _index: TileIndex) -> Option<String> {
+        None
+    }
 }
 
 /// Load the tile from the Web.
 pub struct WebVtLoader {
-    cache: Option<Box<dyn PersistentCacheController<str, Bytes>>>,
+    cache: Option<Arc<dyn PersistentCacheController<str, Bytes>>>,
     url_source: Box<dyn UrlSource<TileIndex>>,
     offline_mode: bool,
+    request_settings: RequestSettings,
 }
 
 impl WebVtLoader {
@@ -42,39 +58,42 @@ impl WebVtLoader {
         offline_mode: bool,
     ) -> Self {
         Self {
-            cache,
+            cache: cache.map(Arc::from),
             url_source: Box::new(url_source),
             offline_mode,
+            request_settings: RequestSettings::default(),
         }
     }
 
-    async fn load_raw(&self, url: &str) -> Result<Bytes, TileLoadError> {
-        if let Some(data) = self.cache.as_ref().and_then(|cache| cache.get(url)) {
-            log::trace!("Cache hit for url {url}");
-            return Ok(data);
-        }
+    /// Adds the header to every tile request, replacing previously set header with the same name.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request_settings.set_header(name, value);
+        self
+    }
 
-        if self.offline_mode {
-            return Err(TileLoadError::DoesNotExist);
-        }
+    /// Sets the maximum time to wait for a tile response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.request_settings.set_timeout(timeout);
+        self
+    }
+
+    pub(crate) fn with_request_settings(mut self, request_settings: RequestSettings) -> Self {
+        self.request_settings = request_settings;
+        self
+    }
+
+    /// Returns the request the loader makes to load the tile with the given index.
+    pub fn request(&self, index: TileIndex) -> HttpRequest {
+        self.request_settings.request((self.url_source)(&index))
+    }
 
-        let bytes = crate::platform::instance()
-            .load_bytes_from_url(url)
+    async fn load_raw(&self, request: &HttpRequest) -> Result<Bytes, TileLoadError> {
+        load_with_cache(request, self.cache.as_ref(), self.offline_mode)
             .await
             .map_err(|err| match err {
                 GalileoError::NotFound => TileLoadError::DoesNotExist,
                 _ => TileLoadError::Network,
-            })?;
-
-        log::info!("Loaded tile from url: {url}");
-
-        if let Some(cache) = &self.cache {
-            if let Err(error) = cache.insert(url, &bytes) {
-                log::warn!("Failed to write persistent cache entry: {error:?}");
-            }
-        }
-
-        Ok(bytes)
+            })
     }
 }
 
@@ -82,10 +101,10 @@ impl WebVtLoader {
 #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
 impl VectorTileLoader for WebVtLoader {
     async fn load(&self, index: TileIndex) -> Result<MvtTile, TileLoadError> {
-        let url = (self.url_source)(&index);
+        let request = self.request(index);
 
-        log::trace!("Loading tile {index:?} from url {url}");
-        let bytes = self.load_raw(&url).await?;
+        log::trace!("Loading tile {index:?} from url {}", request.url);
+        let bytes = self.load_raw(&request).await?;
 
         log::trace!("Tile {index:?} loaded. Byte size: {}", bytes.len());
 
@@ -95,4 +114,19 @@ impl VectorTileLoader for WebVtLoader {
 
         Ok(mvt)
     }
+
+    fn request_host(&self, index: TileIndex) -> Option<String> {
+        url_host(&(self.url_source)(&index)).map(str::to_string)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+impl TileDownloader for WebVtLoader {
+    async fn download_to_cache(
+        &self,
+        index: TileIndex,
+    ) -> Result<TileDownloadStatus, GalileoError> {
+        store_in_cache(&self.request(index), self.cache.as_ref(), self.offline_mode).await
+    }
 }
@@ -4,11 +4,12 @@ use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use galileo_mvt::MvtTile;
-use loader::VectorTileLoader;
+use galileo_types::geo::Crs;
+use loader::{TileLoadError, VectorTileLoader};
 use parking_lot::RwLock;
 use processor::VectorTileProcessor;
 
-use crate::layer::tiles::TileProvider;
+use crate::layer::tiles::{RequestSlot, RetryPolicy, TileProvider};
 use crate::layer::vector_tile_layer::style::VectorTileStyle;
 use crate::messenger::Messenger;
 use crate::render::{Canvas, PackedBundle};
@@ -99,45 +100,77 @@ impl VectorTileProvider {
             return;
         }
 
-        let tile_store = self.tiles.clone();
-        if tile_store.read().contains(index, style_id) {
+        if !self.needs_loading(index, style_id) {
             return;
         }
 
+        crate::async_runtime::spawn(self.load_tile_task(index, style_id, None));
+    }
+
+    /// Returns true if the tile with the given index and style has not been requested yet.
+    pub(crate) fn needs_loading(&self, index: TileIndex, style_id: VtStyleId) -> bool {
+        !self.tiles.read().contains(index, style_id)
+    }
+
+    /// Returns the host the tile with the given index is loaded from.
+    pub(crate) fn request_host(&self, index: TileIndex) -> Option<String> {
+        self.loader.request_host(index)
+    }
+
+    /// Creates a future that loads and pre-renders the tile with the given index.
+    ///
+    /// If the task is run by a request queue, `slot` is the slot of the request in the queue.
+    pub(crate) fn load_tile_task(
+        &self,
+        index: TileIndex,
+        style_id: VtStyleId,
+        slot: Option<RequestSlot>,
+    ) -> impl std::future::Future<Output = ()> + maybe_sync::MaybeSend + 'static {
         log::debug!("Loading vector tile {index:?}");
 
+        let tile_store = self.tiles.clone();
         let processor = self.processor.clone();
         let data_provider = self.loader.clone();
         let messenger = self.messenger.clone();
 
-        crate::async_runtime::spawn(async move {
-            let cell = {
+        async move {
+            let (cell, target_crs, crs_id) = {
                 let mut store = tile_store.write();
                 if store.contains(index, style_id) {
                     return;
                 }
 
-                store.start_loading_tile(index, style_id)
+                let target_crs = store.target_crs().cloned();
+                let crs_id = store.target_crs_id();
+                (
+                    store.start_loading_tile(index, style_id),
+                    target_crs,
+                    crs_id,
+                )
             };
 
+            let retry_policy = tile_store.read().retry_policy();
             let tile_state = cell
-                .get_or_init(|| async { Self::download(index, data_provider).await })
+                .get_or_init(|| async {
+                    Self::download(index, data_provider, retry_policy, slot.as_ref()).await
+                })
                 .await;
 
             log::debug!("Tile {index:?} is loaded. Preparing.");
 
-            let tile_state = Self::prepare_tile(tile_state, index, style_id, processor).await;
+            let tile_state =
+                Self::prepare_tile(tile_state, index, style_id, target_crs, processor).await;
 
             log::debug!("tile {index:?} is prepared.");
 
             tile_store
                 .write()
-                .store_tile(index, style_id, cell, tile_state);
+                .store_tile_for_crs(index, style_id, crs_id, cell, tile_state);
 
             if let Some(messenger) = messenger {
                 messenger.request_redraw();
             }
-        });
+        }
     }
 
     /// Move the pre-renderred tile data into GPU memory.
@@ -171,6 +204,19 @@ impl VectorTileProvider {
         self.tiles.read().get_mvt_tile(index)
     }
 
+    /// Sets the CRS the tiles are prepared in. `None` means the CRS of the tile schema.
+    ///
+    /// Returns true if the CRS is changed. Tiles prepared in different CRSs are stored
+    /// separately, and the loaded raw tiles are reused.
+    pub fn set_target_crs(&self, crs: Option<&Crs>) -> bool {
+        self.tiles.write().set_target_crs(crs)
+    }
+
+    /// Sets the way the provider handles tiles that failed to load.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.tiles.write().set_retry_policy(retry_policy);
+    }
+
     /// Set messenger to use to notify about tile updates.
     pub fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
         self.messenger = Some(messenger.into());
@@ -184,10 +230,27 @@ impl VectorTileProvider {
         }
     }
 
-    async fn download(tile_index: TileIndex, loader: Arc<dyn VectorTileLoader>) -> MvtTileState {
-        match loader.load(tile_index).await {
+    async fn download(
+        tile_index: TileIndex,
+        loader: Arc<dyn VectorTileLoader>,
+        retry_policy: RetryPolicy,
+        slot: Option<&RequestSlot>,
+    ) -> MvtTileState {
+        let result = retry_policy
+            .run(
+                || loader.load(tile_index),
+                |err| matches!(err, TileLoadError::Network),
+                slot,
+            )
+            .await;
+
+        match result {
             Ok(mvt_tile) => MvtTileState::Loaded(Arc::new(mvt_tile)),
-            Err(_) => MvtTileState::Error(),
+            Err(TileLoadError::DoesNotExist) => {
+                log::debug!("Tile {tile_index:?} does not exist");
+                MvtTileState::Loaded(Arc::new(MvtTile { layers: vec![] }))
+            }
+            Err(_) => MvtTileState::Error(web_time::Instant::now()),
         }
     }
 
@@ -195,19 +258,30 @@ impl VectorTileProvider {
         mvt_tile_state: &MvtTileState,
         index: TileIndex,
         style_id: VtStyleId,
+        target_crs: Option<Crs>,
         processor: Arc<dyn VectorTileProcessor>,
     ) -> PreparedTileState {
         match mvt_tile_state {
             MvtTileState::Loaded(mvt_tile) => {
-                match processor
-                    .process_tile(mvt_tile.clone(), index, style_id)
-                    .await
-                {
+                let result = match target_crs {
+                    Some(crs) => {
+                        processor
+                            .process_tile_in_crs(mvt_tile.clone(), index, style_id, crs)
+                            .await
+                    }
+                    None => {
+                        processor
+                            .process_tile(mvt_tile.clone(), index, style_id)
+                            .await
+                    }
+                };
+
+                match result {
                     Ok(render_bundle) => PreparedTileState::Loaded(Arc::new(render_bundle)),
-                    Err(_) => PreparedTileState::Error,
+                    Err(_) => PreparedTileState::Error(web_time::Instant::now()),
                 }
             }
-            MvtTileState::Error() => PreparedTileState::Error,
+            MvtTileState::Error(failed_at) => PreparedTileState::Error(*failed_at),
         }
     }
 }
@@ -3,6 +3,7 @@
 use std::sync::Arc;
 
 use galileo_mvt::MvtTile;
+use galileo_types::geo::Crs;
 use maybe_sync::{MaybeSend, MaybeSync};
 use serde::{Deserialize, Serialize};
 
@@ -45,4 +46,19 @@ pub trait VectorTileProcessor: MaybeSend + MaybeSync {
         index: TileIndex,
         style_id: VtStyleId,
     ) -> Result<RenderBundle, TileProcessingError>;
+    /// Convert the tile into render bundle using the given style, projecting the geometries of the
+    /// tile into the `target_crs`.
+    ///
+    /// Used when the map is displayed in a CRS other than the CRS of the tile schema. By default
+    /// projection is not supported and an error is returned.
+    async fn process_tile_in_crs(
+        &self,
+        tile: Arc<MvtTile>,
+        index: TileIndex,
+        style_id: VtStyleId,
+        target_crs: Crs,
+    ) -> Result<RenderBundle, TileProcessingError> {
+        let _ = (tile, index, style_id, target_crs);
+        Err(TileProcessingError::Rendering)
+    }
 }
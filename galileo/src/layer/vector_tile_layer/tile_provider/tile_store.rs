@@ -3,10 +3,12 @@ use std::fmt::{Debug, Formatter};
 use std::sync::{Arc, Weak};
 
 use galileo_mvt::MvtTile;
+use galileo_types::geo::Crs;
 use quick_cache::unsync::Cache;
 use quick_cache::{DefaultHashBuilder, Lifecycle, Weighter};
 use tokio::sync::OnceCell;
 
+use crate::layer::tiles::RetryPolicy;
 use crate::layer::vector_tile_layer::tile_provider::VtStyleId;
 use crate::render::render_bundle::RenderBundle;
 use crate::render::PackedBundle;
@@ -15,10 +17,17 @@ use crate::tile_schema::TileIndex;
 const DEFAULT_CACHE_CAPACITY: usize = 50 * 2usize.pow(20);
 const AVG_TILE_SIZE: usize = 2 * 2usize.pow(20);
 
+/// Identifier of a CRS the tiles are prepared in, unique within a [`TileStore`]. `None` stands for
+/// the CRS of the tile schema.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(super) struct TargetCrsId(Option<usize>);
+
+type TileKey = (TileIndex, VtStyleId, TargetCrsId);
+
 #[derive(Debug, Clone)]
 pub enum MvtTileState {
     Loaded(Arc<MvtTile>),
-    Error(),
+    Error(web_time::Instant),
 }
 
 #[derive(Clone)]
@@ -26,7 +35,7 @@ pub enum PreparedTileState {
     Loading,
     Loaded(Arc<RenderBundle>),
     Packed(Arc<dyn PackedBundle>),
-    Error,
+    Error(web_time::Instant),
 }
 
 impl Debug for PreparedTileState {
@@ -35,7 +44,7 @@ impl Debug for PreparedTileState {
             PreparedTileState::Loading => write!(f, "PreparedTileState::Loading"),
             PreparedTileState::Loaded(_) => write!(f, "PreparedTileState::Loaded"),
             PreparedTileState::Packed(_) => write!(f, "PreparedTileState::Packed"),
-            PreparedTileState::Error => write!(f, "PreparedTileState::Error"),
+            PreparedTileState::Error(_) => write!(f, "PreparedTileState::Error"),
         }
     }
 }
@@ -47,13 +56,11 @@ struct TileStoreEntry {
 
 pub(super) struct TileStore {
     mvt_tiles: HashMap<TileIndex, Weak<OnceCell<MvtTileState>>, ahash::RandomState>,
-    processed: Cache<
-        (TileIndex, VtStyleId),
-        TileStoreEntry,
-        TileWeighter,
-        DefaultHashBuilder,
-        TileStoreLc,
-    >,
+    processed: Cache<TileKey, TileStoreEntry, TileWeighter, DefaultHashBuilder, TileStoreLc>,
+    retry_policy: RetryPolicy,
+    /// CRSs the tiles were prepared in, indexed by [`TargetCrsId`].
+    known_crs: Vec<Crs>,
+    target_crs: TargetCrsId,
 }
 
 impl Default for TileStore {
@@ -67,14 +74,17 @@ impl Default for TileStore {
                 DefaultHashBuilder::default(),
                 TileStoreLc,
             ),
+            retry_policy: RetryPolicy::default(),
+            known_crs: vec![],
+            target_crs: TargetCrsId(None),
         }
     }
 }
 
 struct TileWeighter;
 
-impl Weighter<(TileIndex, VtStyleId), TileStoreEntry> for TileWeighter {
-    fn weight(&self, _key: &(TileIndex, VtStyleId), val: &TileStoreEntry) -> u32 {
+impl Weighter<TileKey, TileStoreEntry> for TileWeighter {
+    fn weight(&self, _key: &TileKey, val: &TileStoreEntry) -> u32 {
         match &val.prepared_tile {
             PreparedTileState::Loaded(v) => v.world_set.approx_buffer_size() as u32,
             _ => AVG_TILE_SIZE as u32,
@@ -89,19 +99,14 @@ struct TileStoreLcState {
     evicted: Vec<TileIndex>,
 }
 
-impl Lifecycle<(TileIndex, VtStyleId), TileStoreEntry> for TileStoreLc {
+impl Lifecycle<TileKey, TileStoreEntry> for TileStoreLc {
     type RequestState = TileStoreLcState;
 
     fn begin_request(&self) -> Self::RequestState {
         TileStoreLcState::default()
     }
 
-    fn on_evict(
-        &self,
-        state: &mut Self::RequestState,
-        key: (TileIndex, VtStyleId),
-        _val: TileStoreEntry,
-    ) {
+    fn on_evict(&self, state: &mut Self::RequestState, key: TileKey, _val: TileStoreEntry) {
         state.evicted.push(key.0)
     }
 }
@@ -121,8 +126,56 @@ impl TileStore {
         }
     }
 
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// CRS the tiles are prepared in. `None` means the CRS of the tile schema.
+    pub fn target_crs(&self) -> Option<&Crs> {
+        self.known_crs.get(self.target_crs.0?)
+    }
+
+    pub fn target_crs_id(&self) -> TargetCrsId {
+        self.target_crs
+    }
+
+    /// Sets the CRS the tiles are prepared in. `None` means the CRS of the tile schema.
+    ///
+    /// Returns true if the CRS is changed. Tiles are stored separately for each CRS, so the tiles
+    /// prepared before are used again when the CRS is changed back.
+    pub fn set_target_crs(&mut self, crs: Option<&Crs>) -> bool {
+        let id = TargetCrsId(crs.map(|crs| {
+            self.known_crs
+                .iter()
+                .position(|known| known == crs)
+                .unwrap_or_else(|| {
+                    self.known_crs.push(crs.clone());
+                    self.known_crs.len() - 1
+                })
+        }));
+
+        let changed = id != self.target_crs;
+        self.target_crs = id;
+        changed
+    }
+
+    /// Returns true if the tile is loaded, is being loaded or failed to load recently.
     pub fn contains(&self, tile_index: TileIndex, style_id: VtStyleId) -> bool {
-        self.processed.peek(&(tile_index, style_id)).is_some()
+        match self
+            .processed
+            .peek(&(tile_index, style_id, self.target_crs))
+        {
+            Some(TileStoreEntry {
+                prepared_tile: PreparedTileState::Error(failed_at),
+                ..
+            }) => !self.retry_policy.is_error_expired(*failed_at),
+            Some(_) => true,
+            None => false,
+        }
     }
 
     pub fn start_loading_tile(
@@ -134,6 +187,8 @@ impl TileStore {
             .mvt_tiles
             .get(&index)
             .and_then(|v| v.upgrade())
+            // Failed tile must be downloaded again
+            .filter(|cell| !matches!(cell.get(), Some(MvtTileState::Error(_))))
             .unwrap_or_default();
         self.mvt_tiles.insert(index, Arc::downgrade(&tile_cell));
 
@@ -142,24 +197,37 @@ impl TileStore {
             prepared_tile: PreparedTileState::Loading,
         };
 
-        self.insert_entry(index, style_id, entry);
+        self.insert_entry((index, style_id, self.target_crs), entry);
 
         tile_cell
     }
 
+    /// Stores the tile prepared in the current target CRS.
     pub fn store_tile(
         &mut self,
         tile_index: TileIndex,
         style_id: VtStyleId,
         mvt_tile: Arc<OnceCell<MvtTileState>>,
         tile_state: PreparedTileState,
+    ) {
+        self.store_tile_for_crs(tile_index, style_id, self.target_crs, mvt_tile, tile_state);
+    }
+
+    /// Stores the tile prepared in the CRS with the given id.
+    pub fn store_tile_for_crs(
+        &mut self,
+        tile_index: TileIndex,
+        style_id: VtStyleId,
+        crs_id: TargetCrsId,
+        mvt_tile: Arc<OnceCell<MvtTileState>>,
+        tile_state: PreparedTileState,
     ) {
         let entry = TileStoreEntry {
             mvt_tile,
             prepared_tile: tile_state,
         };
 
-        self.insert_entry(tile_index, style_id, entry);
+        self.insert_entry((tile_index, style_id, crs_id), entry);
     }
 
     pub fn get_prepared(
@@ -167,7 +235,8 @@ impl TileStore {
         index: TileIndex,
         style_id: VtStyleId,
     ) -> Option<(Arc<RenderBundle>, Arc<OnceCell<MvtTileState>>)> {
-        self.processed.get(&(index, style_id)).and_then(|entry| {
+        let key = (index, style_id, self.target_crs);
+        self.processed.get(&key).and_then(|entry| {
             if let PreparedTileState::Loaded(tile) = &entry.prepared_tile {
                 Some((tile.clone(), entry.mvt_tile.clone()))
             } else {
@@ -181,7 +250,8 @@ impl TileStore {
         index: TileIndex,
         style_id: VtStyleId,
     ) -> Option<Arc<dyn PackedBundle>> {
-        self.processed.get(&(index, style_id)).and_then(|entry| {
+        let key = (index, style_id, self.target_crs);
+        self.processed.get(&key).and_then(|entry| {
             if let PreparedTileState::Packed(tile) = &entry.prepared_tile {
                 Some(tile.clone())
             } else {
@@ -202,10 +272,8 @@ impl TileStore {
         }
     }
 
-    fn insert_entry(&mut self, index: TileIndex, style_id: VtStyleId, entry: TileStoreEntry) {
-        let lc = self
-            .processed
-            .insert_with_lifecycle((index, style_id), entry);
+    fn insert_entry(&mut self, key: TileKey, entry: TileStoreEntry) {
+        let lc = self.processed.insert_with_lifecycle(key, entry);
 
         for index in lc.evicted {
             self.on_bundle_evicted(index)
@@ -251,6 +319,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn failed_tiles_are_reloaded_after_expiry() {
+        let mut store = TileStore::with_capacity(DEFAULT_CACHE_CAPACITY);
+        let style_id = VtStyleId::next_id();
+        let index = TileIndex::new(0, 0, 0);
+        let failed_at = web_time::Instant::now();
+
+        let mvt_cell = store.start_loading_tile(index, style_id);
+        mvt_cell
+            .set(MvtTileState::Error(failed_at))
+            .expect("cell is empty");
+        store.store_tile(
+            index,
+            style_id,
+            mvt_cell.clone(),
+            PreparedTileState::Error(failed_at),
+        );
+        assert!(store.contains(index, style_id));
+
+        store.set_retry_policy(RetryPolicy {
+            error_expiry: Some(web_time::Duration::ZERO),
+            ..Default::default()
+        });
+        assert!(!store.contains(index, style_id));
+
+        let new_cell = store.start_loading_tile(index, style_id);
+        assert!(!Arc::ptr_eq(&mvt_cell, &new_cell));
+        assert!(new_cell.get().is_none());
+    }
+
+    #[test]
+    fn prepared_tiles_are_kept_per_target_crs() {
+        let mut store = TileStore::with_capacity(DEFAULT_CACHE_CAPACITY);
+        let style_id = VtStyleId::next_id();
+        let index = TileIndex::new(0, 0, 0);
+
+        assert!(!store.set_target_crs(None));
+        let mvt_cell = store.start_loading_tile(index, style_id);
+        store.store_tile(index, style_id, mvt_cell.clone(), tile_with_size(100));
+        assert!(store.contains(index, style_id));
+
+        assert!(store.set_target_crs(Some(&Crs::WGS84)));
+        assert!(!store.set_target_crs(Some(&Crs::WGS84)));
+        assert!(!store.contains(index, style_id));
+        assert_eq!(store.target_crs(), Some(&Crs::WGS84));
+
+        // The raw tile is shared between the CRSs.
+        let wgs84_cell = store.start_loading_tile(index, style_id);
+        assert!(Arc::ptr_eq(&mvt_cell, &wgs84_cell));
+
+        assert!(store.set_target_crs(None));
+        assert!(store.contains(index, style_id));
+        assert!(store.get_prepared(index, style_id).is_some());
+    }
+
     #[test]
     fn evicts_old_tiles() {
         const CAPACITY: u64 = 1_000_000;
@@ -330,7 +453,7 @@ mod tests {
             }
         }
 
-        for index in store.processed.iter().map(|((index, _), _)| index) {
+        for index in store.processed.iter().map(|((index, _, _), _)| index) {
             assert!(
                 store.mvt_tiles.contains_key(index),
                 "Mvt tiles does not contain index {index:?}"
@@ -342,7 +465,7 @@ mod tests {
                 store
                     .processed
                     .iter()
-                    .any(|((index, _), _)| mvt_index == index),
+                    .any(|((index, _, _), _)| mvt_index == index),
                 "Index {mvt_index:?} is in mvt store, but not in processed"
             );
         }
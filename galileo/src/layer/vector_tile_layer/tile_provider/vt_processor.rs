@@ -1,18 +1,23 @@
 use galileo_mvt::{MvtFeature, MvtGeometry, MvtPolygon, MvtTile};
-use galileo_types::cartesian::{CartesianPoint2d, Point3, Rect, Vector2};
+use galileo_types::cartesian::{CartesianPoint2d, CartesianPoint3d, Point2, Point3, Rect, Vector2};
+use galileo_types::geo::Crs;
 use galileo_types::impls::{ClosedContour, Polygon};
 use galileo_types::{Contour, MultiContour, MultiPolygon, Polygon as PolygonTrait};
 use num_traits::ToPrimitive;
-use regex::Regex;
 
 use crate::error::GalileoError;
 use crate::layer::vector_tile_layer::style::{StyleRule, VectorTileLabelSymbol, VectorTileStyle};
 use crate::render::point_paint::{PointPaint, PointShape};
 use crate::render::render_bundle::RenderBundle;
 use crate::render::{LinePaint, PolygonPaint};
+use crate::reprojection::CrsTransform;
 use crate::tile_schema::TileIndex;
 use crate::TileSchema;
 
+/// Number of segments each side of the tile boundary is split into when it is projected into
+/// another CRS. Edges of the tile geometries are split into segments of the same length.
+const BOUNDARY_SEGMENTS: usize = 16;
+
 /// Data processor that decodes vector tiles.
 pub struct VtProcessor {}
 
@@ -30,33 +35,100 @@ pub struct VectorTileDecodeContext {
 
 impl VtProcessor {
     /// Pre-render the given tile into the given `bundle`.
+    ///
+    /// Geometries are positioned relative to the top left corner of the tile.
     pub fn prepare(
         mvt_tile: &MvtTile,
         bundle: &mut RenderBundle,
         index: TileIndex,
         style: &VectorTileStyle,
         tile_schema: &TileSchema,
+    ) -> Result<(), GalileoError> {
+        Self::prepare_projected(mvt_tile, bundle, index, style, tile_schema, None)
+    }
+
+    /// Pre-render the given tile into the given `bundle`, projecting it into the `target_crs`.
+    ///
+    /// Geometries are positioned relative to the projected top left corner of the tile. Edges of
+    /// lines and polygons are split into shorter segments to follow the curvature of the
+    /// projection, and the parts of the geometries that cannot be projected are cut off.
+    pub fn prepare_in_crs(
+        mvt_tile: &MvtTile,
+        bundle: &mut RenderBundle,
+        index: TileIndex,
+        style: &VectorTileStyle,
+        tile_schema: &TileSchema,
+        target_crs: &Crs,
+    ) -> Result<(), GalileoError> {
+        Self::prepare_projected(
+            mvt_tile,
+            bundle,
+            index,
+            style,
+            tile_schema,
+            Some(target_crs),
+        )
+    }
+
+    fn prepare_projected(
+        mvt_tile: &MvtTile,
+        bundle: &mut RenderBundle,
+        index: TileIndex,
+        style: &VectorTileStyle,
+        tile_schema: &TileSchema,
+        target_crs: Option<&Crs>,
     ) -> Result<(), GalileoError> {
         let lod_resolution = tile_schema.lod_resolution(index.z).ok_or_else(|| {
             GalileoError::Generic(format!("cannot get lod resolution for lod {}", index.z))
         })?;
         let tile_resolution = lod_resolution * tile_schema.tile_width() as f64;
 
+        let projection = match target_crs {
+            Some(crs) if *crs != tile_schema.crs => {
+                let projection = TileProjection::new(index, tile_schema, crs);
+                if projection.is_none() {
+                    // Tile is outside of the area where the target CRS is defined.
+                    log::debug!("Tile {index:?} cannot be projected into the target CRS");
+                    return Ok(());
+                }
+
+                projection
+            }
+            _ => None,
+        };
+
         let width = tile_schema.tile_width() as f64;
         let height = tile_schema.tile_height() as f64;
         let bbox = Rect::new(0.0, 0.0, width * lod_resolution, -height * lod_resolution);
 
-        let bounds = Polygon::new(
-            ClosedContour::new(vec![
-                Point3::new(bbox.x_min(), bbox.y_min(), 0.0),
-                Point3::new(bbox.x_min(), bbox.y_max(), 0.0),
-                Point3::new(bbox.x_max(), bbox.y_max(), 0.0),
-                Point3::new(bbox.x_max(), bbox.y_min(), 0.0),
-            ]),
-            vec![],
-        );
+        let bounds = match &projection {
+            Some(projection) => {
+                let Some(bounds) = projection.project_boundary(&bbox) else {
+                    log::debug!("Bounds of tile {index:?} cannot be projected into the target CRS");
+                    return Ok(());
+                };
+
+                bounds
+            }
+            None => Polygon::new(
+                ClosedContour::new(vec![
+                    Point3::new(bbox.x_min(), bbox.y_min(), 0.0),
+                    Point3::new(bbox.x_min(), bbox.y_max(), 0.0),
+                    Point3::new(bbox.x_max(), bbox.y_max(), 0.0),
+                    Point3::new(bbox.x_max(), bbox.y_min(), 0.0),
+                ]),
+                vec![],
+            ),
+        };
         bundle.world_set.clip_area(&bounds);
 
+        // Resolution in the target CRS is used to simplify geometries.
+        let lod_resolution = match &projection {
+            Some(projection) => lod_resolution * projection.scale,
+            None => lod_resolution,
+        };
+        let projection = projection.as_ref();
+
         for layer in mvt_tile.layers.iter().rev() {
             for feature in &layer.features {
                 let Some(rule) = style.get_style_rule(&layer.name, feature) else {
@@ -70,7 +142,12 @@ impl VtProcessor {
                         };
 
                         for point in points {
-                            let position = Self::transform_point(point, tile_resolution);
+                            let Some(position) = Self::project(
+                                Self::transform_point(point, tile_resolution),
+                                projection,
+                            ) else {
+                                continue;
+                            };
                             match &paint.shape {
                                 PointShape::Label { text, style } => {
                                     if !text.is_empty() {
@@ -92,28 +169,31 @@ impl VtProcessor {
                     MvtGeometry::LineString(contours) => {
                         if let Some(paint) = Self::get_line_symbol(rule, feature) {
                             for contour in contours.contours() {
-                                bundle.add_line(
-                                    &galileo_types::impls::Contour::new(
-                                        contour
-                                            .iter_points()
-                                            .map(|p| Self::transform_point(&p, tile_resolution))
-                                            .collect(),
-                                        false,
-                                    ),
-                                    &paint,
-                                    lod_resolution,
-                                );
+                                let points: Vec<_> = contour
+                                    .iter_points()
+                                    .map(|p| Self::transform_point(&p, tile_resolution))
+                                    .collect();
+
+                                for part in Self::project_line(points, projection) {
+                                    bundle.add_line(
+                                        &galileo_types::impls::Contour::new(part, false),
+                                        &paint,
+                                        lod_resolution,
+                                    );
+                                }
                             }
                         }
                     }
                     MvtGeometry::Polygon(polygons) => {
                         if let Some(paint) = Self::get_polygon_symbol(rule, feature) {
                             for polygon in polygons.polygons() {
-                                bundle.add_polygon(
-                                    &Self::transform_polygon(polygon, tile_resolution),
-                                    &paint,
-                                    lod_resolution,
-                                );
+                                let Some(polygon) =
+                                    Self::transform_polygon(polygon, tile_resolution, projection)
+                                else {
+                                    continue;
+                                };
+
+                                bundle.add_polygon(&polygon, &paint, lod_resolution);
                             }
                         }
                     }
@@ -140,18 +220,8 @@ impl VtProcessor {
         label_symbol: &VectorTileLabelSymbol,
         feature: &MvtFeature,
     ) -> Option<PointPaint<'a>> {
-        let re = Regex::new("\\{(?<name>.+)}").ok()?;
-        let mut text = label_symbol.pattern.to_string();
-        for m in re.captures_iter(&label_symbol.pattern) {
-            let prop_name = &m["name"];
-            let prop = feature
-                .properties
-                .get(prop_name)
-                .map(|v| v.to_string())
-                .unwrap_or_default();
-
-            text = text.replace(&format!("{{{prop_name}}}"), &prop);
-        }
+        let text =
+            label_symbol.format_text(|name| feature.properties.get(name).map(|v| v.to_string()));
         Some(PointPaint::label_owned(
             text,
             label_symbol.text_style.clone(),
@@ -166,22 +236,62 @@ impl VtProcessor {
         rule.symbol.polygon().map(|&s| s.into())
     }
 
-    fn transform_polygon(mvt_polygon: &MvtPolygon, tile_resolution: f64) -> Polygon<Point3> {
-        let cast = |p| Self::transform_point(&p, tile_resolution);
+    fn transform_polygon(
+        mvt_polygon: &MvtPolygon,
+        tile_resolution: f64,
+        projection: Option<&TileProjection>,
+    ) -> Option<Polygon<Point3>> {
+        let cast = |contour: &<MvtPolygon as PolygonTrait>::Contour| {
+            let points: Vec<Point3> = contour
+                .iter_points()
+                .map(|p| Self::transform_point(&p, tile_resolution))
+                .collect();
+            match projection {
+                Some(projection) => projection.project_ring(&points),
+                None => Some(ClosedContour::new(points)),
+            }
+        };
 
-        Polygon {
-            outer_contour: ClosedContour::new(
-                mvt_polygon
-                    .outer_contour()
-                    .iter_points()
-                    .map(&cast)
-                    .collect(),
-            ),
-            inner_contours: mvt_polygon
-                .inner_contours()
-                .map(|c| ClosedContour::new(c.iter_points().map(&cast).collect()))
-                .collect(),
+        Some(Polygon {
+            outer_contour: cast(mvt_polygon.outer_contour())?,
+            inner_contours: mvt_polygon.inner_contours().filter_map(cast).collect(),
+        })
+    }
+
+    fn project(point: Point3, projection: Option<&TileProjection>) -> Option<Point3> {
+        match projection {
+            Some(projection) => projection.project(&point),
+            None => Some(point),
+        }
+    }
+
+    /// Projects the line. The line is split into several parts if some of its points cannot be
+    /// projected.
+    fn project_line(points: Vec<Point3>, projection: Option<&TileProjection>) -> Vec<Vec<Point3>> {
+        let Some(projection) = projection else {
+            return vec![points];
+        };
+
+        let mut parts = vec![];
+        let mut part = vec![];
+        for point in projection.densify(&points, false) {
+            match projection.project(&point) {
+                Some(projected) => part.push(projected),
+                None => {
+                    if part.len() > 1 {
+                        parts.push(std::mem::take(&mut part));
+                    } else {
+                        part.clear();
+                    }
+                }
+            }
+        }
+
+        if part.len() > 1 {
+            parts.push(part);
         }
+
+        parts
     }
 
     fn transform_point<Num: num_traits::Float + ToPrimitive>(
@@ -193,3 +303,201 @@ impl VtProcessor {
         Point3::new(x, y, 0.0)
     }
 }
+
+/// Projection of points positioned relative to the top left corner of a tile into another CRS.
+/// Projected points are positioned relative to the projected top left corner of the tile.
+struct TileProjection {
+    transform: CrsTransform,
+    /// Top left corner of the tile in the tile schema CRS.
+    source_origin: Point2,
+    /// Top left corner of the tile in the target CRS.
+    target_origin: Point2,
+    /// Approximate ratio between lengths in the target CRS and in the tile schema CRS.
+    scale: f64,
+    /// Maximum length of a segment in the tile schema CRS that is projected as a straight line.
+    max_segment_length: f64,
+}
+
+impl TileProjection {
+    fn new(index: TileIndex, tile_schema: &TileSchema, target_crs: &Crs) -> Option<Self> {
+        let transform = CrsTransform::new(&tile_schema.crs, target_crs)?;
+        let bbox = tile_schema.tile_bbox(index.into_wrapping())?;
+        let source_origin = Point2::new(bbox.x_min(), bbox.y_max());
+        let target_origin = transform.transform(&source_origin)?;
+
+        // Geometric mean of the scales along the tile sides keeps the area the same.
+        let center = bbox.center();
+        let projected_center = transform.transform(&center)?;
+        let step = bbox.width() / 2.0;
+        let dx = transform.transform(&Point2::new(center.x() + step, center.y()))?;
+        let dy = transform.transform(&Point2::new(center.x(), center.y() + step))?;
+        let scale = (dx.distance_sq(&projected_center) * dy.distance_sq(&projected_center))
+            .powf(0.25)
+            / step;
+        if !scale.is_finite() || scale <= 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            transform,
+            source_origin,
+            target_origin,
+            scale,
+            max_segment_length: bbox.width() / BOUNDARY_SEGMENTS as f64,
+        })
+    }
+
+    fn project(&self, point: &Point3) -> Option<Point3> {
+        let projected = self.transform.transform(&Point2::new(
+            self.source_origin.x() + point.x(),
+            self.source_origin.y() + point.y(),
+        ))?;
+
+        Some(Point3::new(
+            projected.x() - self.target_origin.x(),
+            projected.y() - self.target_origin.y(),
+            point.z(),
+        ))
+    }
+
+    /// Projects the tile boundary given relative to the top left corner. Sides of the boundary are
+    /// split into segments, so that they follow the curvature of the projection.
+    fn project_boundary(&self, bbox: &Rect) -> Option<Polygon<Point3>> {
+        let corners = vec![
+            Point3::new(bbox.x_min(), bbox.y_min(), 0.0),
+            Point3::new(bbox.x_min(), bbox.y_max(), 0.0),
+            Point3::new(bbox.x_max(), bbox.y_max(), 0.0),
+            Point3::new(bbox.x_max(), bbox.y_min(), 0.0),
+        ];
+
+        Some(Polygon::new(self.project_ring(&corners)?, vec![]))
+    }
+
+    /// Projects a closed contour. Points that cannot be projected are skipped, which cuts off the
+    /// part of the contour outside of the area where the projection is defined. Returns `None` if
+    /// less than 3 points are left.
+    fn project_ring(&self, points: &[Point3]) -> Option<ClosedContour<Point3>> {
+        let projected: Vec<_> = self
+            .densify(points, true)
+            .iter()
+            .filter_map(|point| self.project(point))
+            .collect();
+
+        (projected.len() > 2).then(|| ClosedContour::new(projected))
+    }
+
+    /// Splits the edges of the contour into segments not longer than `max_segment_length`.
+    fn densify(&self, points: &[Point3], closed: bool) -> Vec<Point3> {
+        let mut densified = Vec::with_capacity(points.len());
+        let closing = closed
+            .then(|| Some((*points.last()?, *points.first()?)))
+            .flatten();
+        let edges = points
+            .windows(2)
+            .map(|edge| (edge[0], edge[1]))
+            .chain(closing);
+
+        for (from, to) in edges {
+            let (dx, dy) = (to.x() - from.x(), to.y() - from.y());
+            let length = (dx * dx + dy * dy).sqrt();
+            let segments = (length / self.max_segment_length).ceil().max(1.0) as usize;
+            for segment in 0..segments {
+                let k = segment as f64 / segments as f64;
+                densified.push(Point3::new(from.x() + dx * k, from.y() + dy * k, from.z()));
+            }
+        }
+
+        if !closed {
+            densified.extend(points.last());
+        }
+
+        densified
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn tile_projection_positions_points_relative_to_tile_corner() {
+        let tile_schema = TileSchema::web(18);
+        let index = TileIndex::new(0, 0, 1);
+        let projection = TileProjection::new(index, &tile_schema, &Crs::WGS84).unwrap();
+
+        assert_abs_diff_eq!(projection.target_origin.x(), -180.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(projection.target_origin.y(), 85.0511, epsilon = 1e-4);
+
+        let corner = projection.project(&Point3::new(0.0, 0.0, 0.0)).unwrap();
+        assert_abs_diff_eq!(corner.x(), 0.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(corner.y(), 0.0, epsilon = 1e-6);
+
+        let bbox = tile_schema.tile_bbox(index.into_wrapping()).unwrap();
+        let opposite = projection
+            .project(&Point3::new(bbox.width(), -bbox.height(), 0.0))
+            .unwrap();
+        assert_abs_diff_eq!(opposite.x(), 180.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(opposite.y(), -85.0511, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn edges_are_densified() {
+        let tile_schema = TileSchema::web(18);
+        let index = TileIndex::new(0, 0, 1);
+        let projection = TileProjection::new(index, &tile_schema, &Crs::WGS84).unwrap();
+        let width = tile_schema
+            .tile_bbox(index.into_wrapping())
+            .unwrap()
+            .width();
+
+        let line = [Point3::new(0.0, 0.0, 0.0), Point3::new(width, 0.0, 0.0)];
+        let parts = VtProcessor::project_line(line.to_vec(), Some(&projection));
+        assert_eq!(parts.len(), 1);
+        assert!(parts[0].len() > BOUNDARY_SEGMENTS);
+
+        let ring = [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, -width, 0.0),
+            Point3::new(width, -width, 0.0),
+        ];
+        let projected = projection.project_ring(&ring).unwrap();
+        assert!(projected.points.len() > 3 * BOUNDARY_SEGMENTS);
+
+        // Middle of the left edge of the tile is at 66.5 degrees, not in the middle between 0 and
+        // 85 degrees.
+        let expected = 66.51326 - 85.05113;
+        assert!(projected
+            .points
+            .iter()
+            .any(|p| p.x().abs() < 1e-6 && (p.y() - expected).abs() < 1e-4));
+    }
+
+    #[test]
+    fn unprojectable_parts_are_cut_off() {
+        let tile_schema = TileSchema {
+            origin: Point2::new(-180.0, 90.0),
+            bounds: Rect::new(-180.0, -90.0, 180.0, 90.0),
+            lods: [crate::lod::Lod::new(180.0 / 256.0, 0).unwrap()].into(),
+            tile_width: 256,
+            tile_height: 256,
+            y_direction: crate::tile_schema::VerticalDirection::TopToBottom,
+            crs: Crs::WGS84,
+        };
+        let index = TileIndex::new(0, 0, 0);
+        let projection = TileProjection::new(index, &tile_schema, &Crs::EPSG3857).unwrap();
+
+        // The line goes over the pole, where Web Mercator is not defined.
+        let line = [
+            Point3::new(45.0, -90.0, 0.0),
+            Point3::new(90.0, 9.0, 0.0),
+            Point3::new(135.0, -90.0, 0.0),
+        ];
+        let parts = VtProcessor::project_line(line.to_vec(), Some(&projection));
+        assert_eq!(parts.len(), 2);
+
+        let ring = projection.project_ring(&line).unwrap();
+        assert!(ring.points.len() > 3);
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use galileo_types::geo::Crs;
+use parking_lot::Mutex;
+use web_time::Duration;
+
+use super::{WmsLayer, WmsParameters, WmsState, WmsVersion};
+use crate::error::GalileoError;
+use crate::layer::attribution::Attribution;
+use crate::layer::data_provider::RequestSettings;
+use crate::Messenger;
+
+/// Constructor for a [`WmsLayer`].
+///
+/// ```
+/// use galileo::layer::wms_layer::WmsLayerBuilder;
+///
+/// let layer = WmsLayerBuilder::new("https://ows.example.com/wms", ["roads", "rivers"])
+///     .with_format("image/jpeg")
+///     .with_transparent(false)
+///     .build()?;
+/// # Ok::<(), galileo::error::GalileoError>(())
+/// ```
+pub struct WmsLayerBuilder {
+    url: String,
+    layers: Vec<String>,
+    styles: Vec<String>,
+    version: WmsVersion,
+    format: String,
+    transparent: bool,
+    crs: Crs,
+    crs_code: String,
+    info_format: String,
+    extra_params: Vec<(String, String)>,
+    request_settings: RequestSettings,
+    debounce: Duration,
+    max_image_size: u32,
+    messenger: Option<Box<dyn Messenger>>,
+    attribution: Option<Attribution>,
+}
+
+impl WmsLayerBuilder {
+    /// Initializes a builder for a layer that requests the given WMS layers from the service at the
+    /// given url.
+    ///
+    /// The url may contain query parameters (e.g. `map=...` for MapServer), the WMS parameters are
+    /// appended to them.
+    pub fn new<S: Into<String>>(
+        url: impl Into<String>,
+        layers: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            layers: layers.into_iter().map(Into::into).collect(),
+            styles: vec![],
+            version: WmsVersion::default(),
+            format: "image/png".into(),
+            transparent: true,
+            crs: Crs::EPSG3857,
+            crs_code: "EPSG:3857".into(),
+            info_format: "text/plain".into(),
+            extra_params: vec![],
+            request_settings: RequestSettings::default(),
+            debounce: Duration::from_millis(300),
+            max_image_size: 4096,
+            messenger: None,
+            attribution: None,
+        }
+    }
+
+    /// Sets the styles of the requested layers. If not set, default styles are used.
+    pub fn with_styles<S: Into<String>>(mut self, styles: impl IntoIterator<Item = S>) -> Self {
+        self.styles = styles.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the version of the WMS protocol. Defaults to [`WmsVersion::V1_3_0`].
+    pub fn with_version(mut self, version: WmsVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Sets the image format to request. Defaults to `image/png`.
+    pub fn with_format(mut self, format: impl Into<String>) -> Self {
+        self.format = format.into();
+        self
+    }
+
+    /// Sets whether the service should render the background transparent. Defaults to `true`.
+    pub fn with_transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// Sets the CRS the images are requested in, and the code of the CRS the service knows it by
+    /// (e.g. `EPSG:3857`).
+    ///
+    /// The layer is only displayed if the map uses the same CRS. Defaults to [`Crs::EPSG3857`].
+    pub fn with_crs(mut self, crs: Crs, code: impl Into<String>) -> Self {
+        self.crs = crs;
+        self.crs_code = code.into();
+        self
+    }
+
+    /// Sets the format of `GetFeatureInfo` responses. Defaults to `text/plain`.
+    pub fn with_info_format(mut self, info_format: impl Into<String>) -> Self {
+        self.info_format = info_format.into();
+        self
+    }
+
+    /// Adds a vendor-specific parameter (e.g. `TIME` or `DPI`) to every request.
+    pub fn with_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extra_params.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds the header to every request the layer makes.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request_settings.set_header(name, value);
+        self
+    }
+
+    /// Sets the maximum time to wait for the service response.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_settings.set_timeout(timeout);
+        self
+    }
+
+    /// Sets the time the view must stay unchanged before a new image is requested. Defaults to
+    /// 300 ms.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Sets the maximum width and height of the requested image. If the view is larger, a smaller
+    /// image is requested and stretched over the view. Defaults to 4096.
+    pub fn with_max_image_size(mut self, max_image_size: u32) -> Self {
+        self.max_image_size = max_image_size;
+        self
+    }
+
+    /// Sets the messenger for the layer.
+    pub fn with_messenger(mut self, messenger: impl Messenger + 'static) -> Self {
+        self.messenger = Some(Box::new(messenger));
+        self
+    }
+
+    /// Sets the attribution of the layer.
+    pub fn with_attribution(mut self, text: String, url: String) -> Self {
+        self.attribution = Some(Attribution::new(text, Some(url)));
+        self
+    }
+
+    /// Consumes the builder and constructs the WMS layer.
+    ///
+    /// Will return an error if no layers are given, if the number of styles does not match the
+    /// number of layers, or if the url is not an http(s) url.
+    pub fn build(self) -> Result<WmsLayer, GalileoError> {
+        let Self {
+            url,
+            layers,
+            styles,
+            version,
+            format,
+            transparent,
+            crs,
+            crs_code,
+            info_format,
+            extra_params,
+            request_settings,
+            debounce,
+            max_image_size,
+            messenger,
+            attribution,
+        } = self;
+
+        if layers.is_empty() {
+            return Err(GalileoError::Configuration(
+                "WMS layer requires at least one layer name".into(),
+            ));
+        }
+
+        if !styles.is_empty() && styles.len() != layers.len() {
+            return Err(GalileoError::Configuration(format!(
+                "WMS layer has {} layers but {} styles",
+                layers.len(),
+                styles.len()
+            )));
+        }
+
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(GalileoError::Configuration(format!(
+                "invalid WMS service url: {url}"
+            )));
+        }
+
+        if max_image_size == 0 {
+            return Err(GalileoError::Configuration(
+                "maximum WMS image size must be positive".into(),
+            ));
+        }
+
+        Ok(WmsLayer {
+            params: Arc::new(WmsParameters {
+                url,
+                version,
+                layers,
+                styles,
+                format,
+                transparent,
+                crs_code,
+                info_format,
+                extra_params,
+                request_settings,
+            }),
+            state: Arc::new(Mutex::new(WmsState::default())),
+            crs,
+            debounce,
+            max_image_size,
+            messenger: messenger.map(Arc::from),
+            attribution,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_compact_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn build_errors() {
+        let no_layers =
+            WmsLayerBuilder::new("https://example.com/wms", Vec::<String>::new()).build();
+        assert_compact_debug_snapshot!(no_layers.map(|_| ()), @r#"Err(Configuration("WMS layer requires at least one layer name"))"#);
+
+        let styles = WmsLayerBuilder::new("https://example.com/wms", ["a", "b"])
+            .with_styles(["default"])
+            .build();
+        assert_compact_debug_snapshot!(styles.map(|_| ()), @r#"Err(Configuration("WMS layer has 2 layers but 1 styles"))"#);
+
+        let url = WmsLayerBuilder::new("example.com/wms", ["a"]).build();
+        assert_compact_debug_snapshot!(url.map(|_| ()), @r#"Err(Configuration("invalid WMS service url: example.com/wms"))"#);
+    }
+}
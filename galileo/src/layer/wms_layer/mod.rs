@@ -0,0 +1,600 @@
+//! Layer that renders images of an OGC Web Map Service.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use galileo_types::cartesian::{CartesianPoint2d, Point2, Rect, Vector2};
+use galileo_types::geo::Crs;
+use parking_lot::Mutex;
+use web_time::Duration;
+
+use super::Layer;
+use crate::decoded_image::DecodedImage;
+use crate::error::GalileoError;
+use crate::layer::attribution::Attribution;
+use crate::layer::data_provider::{HttpRequest, RequestSettings};
+use crate::messenger::Messenger;
+use crate::platform::PlatformService;
+use crate::render::render_bundle::RenderBundle;
+use crate::render::{BundleToDraw, Canvas, ImagePaint, PackedBundle, RenderOptions};
+use crate::view::MapView;
+
+mod builder;
+pub use builder::WmsLayerBuilder;
+
+/// Version of the WMS protocol.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum WmsVersion {
+    /// Version 1.1.1. Uses `SRS` parameter for the CRS and `X`, `Y` for feature info pixel.
+    V1_1_1,
+    /// Version 1.3.0. Uses `CRS` parameter for the CRS and `I`, `J` for feature info pixel.
+    ///
+    /// For `EPSG:4326` the bounding box is sent in latitude-longitude order, as required by the
+    /// standard.
+    #[default]
+    V1_3_0,
+}
+
+impl WmsVersion {
+    fn as_str(&self) -> &'static str {
+        match self {
+            WmsVersion::V1_1_1 => "1.1.1",
+            WmsVersion::V1_3_0 => "1.3.0",
+        }
+    }
+}
+
+/// Layer that requests a single image from a WMS service for the whole map view.
+///
+/// On every [`Layer::prepare`] call the layer checks if the view has changed since the last
+/// request. If it has, a `GetMap` request for the new view is sent after a short delay (so that
+/// no requests are made while the user is dragging or zooming the map). The previous image is
+/// displayed until the new one is loaded.
+///
+/// The layer only renders the image if the map CRS is the same as the CRS of the layer.
+///
+/// Use [`WmsLayerBuilder`] to create the layer.
+pub struct WmsLayer {
+    params: Arc<WmsParameters>,
+    state: Arc<Mutex<WmsState>>,
+    crs: Crs,
+    debounce: Duration,
+    max_image_size: u32,
+    messenger: Option<Arc<dyn Messenger>>,
+    attribution: Option<Attribution>,
+}
+
+impl std::fmt::Debug for WmsLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WmsLayer")
+            .field("url", &self.params.url)
+            .field("layers", &self.params.layers)
+            .finish()
+    }
+}
+
+/// Parameters of the requests sent to the service.
+#[derive(Debug, Clone)]
+struct WmsParameters {
+    url: String,
+    version: WmsVersion,
+    layers: Vec<String>,
+    styles: Vec<String>,
+    format: String,
+    transparent: bool,
+    crs_code: String,
+    info_format: String,
+    extra_params: Vec<(String, String)>,
+    request_settings: RequestSettings,
+}
+
+/// Area and pixel size of a map image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ImageExtent {
+    bbox: Rect,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Default)]
+struct WmsState {
+    requested: Option<ImageExtent>,
+    generation: u64,
+    displayed: Option<DisplayedImage>,
+}
+
+struct DisplayedImage {
+    extent: ImageExtent,
+    generation: u64,
+    image: Arc<DecodedImage>,
+    bundle: Option<Arc<dyn PackedBundle>>,
+}
+
+impl WmsLayer {
+    /// Returns the `GetFeatureInfo` request for the given point of the screen, or `None` if the map
+    /// cannot be displayed by the layer with the given view.
+    pub fn feature_info_request(
+        &self,
+        view: &MapView,
+        screen_position: Point2,
+    ) -> Option<HttpRequest> {
+        let extent = self.image_extent(view)?;
+        let map_position = view.screen_to_map(screen_position)?;
+        if !extent.bbox.contains(&map_position) {
+            return None;
+        }
+
+        let i =
+            (map_position.x() - extent.bbox.x_min()) / extent.bbox.width() * extent.width as f64;
+        let j =
+            (extent.bbox.y_max() - map_position.y()) / extent.bbox.height() * extent.height as f64;
+
+        Some(self.params.feature_info_request(
+            extent,
+            (i.floor() as u32).min(extent.width - 1),
+            (j.floor() as u32).min(extent.height - 1),
+        ))
+    }
+
+    /// Requests information about the features at the given point of the screen using the
+    /// `GetFeatureInfo` request.
+    ///
+    /// The response is returned as text in the format set with
+    /// [`WmsLayerBuilder::with_info_format`].
+    pub async fn get_feature_info(
+        &self,
+        view: &MapView,
+        screen_position: Point2,
+    ) -> Result<String, GalileoError> {
+        let request = self
+            .feature_info_request(view, screen_position)
+            .ok_or_else(|| {
+                GalileoError::Generic("the point is outside of the layer image".into())
+            })?;
+        let bytes = crate::platform::instance().load_bytes(&request).await?;
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Returns the `GetMap` request the layer would make for the given view.
+    pub fn map_request(&self, view: &MapView) -> Option<HttpRequest> {
+        Some(self.params.map_request(self.image_extent(view)?))
+    }
+
+    fn image_extent(&self, view: &MapView) -> Option<ImageExtent> {
+        if view.crs() != &self.crs {
+            return None;
+        }
+
+        // Snapping the bbox to the pixel grid prevents new requests caused by the floating point
+        // noise in the view calculations.
+        let resolution = view.resolution();
+        let snap = |v: f64| (v / resolution).round() * resolution;
+        let bbox = view.get_bbox()?;
+        let bbox = Rect::new(
+            snap(bbox.x_min()),
+            snap(bbox.y_min()),
+            snap(bbox.x_max()),
+            snap(bbox.y_max()),
+        );
+        let mut width = bbox.width() / resolution;
+        let mut height = bbox.height() / resolution;
+
+        let max_size = self.max_image_size as f64;
+        if width > max_size || height > max_size {
+            let k = max_size / width.max(height);
+            width *= k;
+            height *= k;
+        }
+
+        let width = width.round() as u32;
+        let height = height.round() as u32;
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        Some(ImageExtent {
+            bbox,
+            width,
+            height,
+        })
+    }
+
+    async fn load_image(
+        params: Arc<WmsParameters>,
+        state: Arc<Mutex<WmsState>>,
+        messenger: Option<Arc<dyn Messenger>>,
+        extent: ImageExtent,
+        generation: u64,
+        debounce: Duration,
+    ) {
+        if !debounce.is_zero() {
+            crate::async_runtime::sleep(debounce).await;
+        }
+
+        if state.lock().generation != generation {
+            // The view was changed while waiting, so a newer request will be made.
+            return;
+        }
+
+        let request = params.map_request(extent);
+        log::debug!("Loading WMS image from {}", request.url);
+
+        let platform = crate::platform::instance();
+        let image = match platform.load_bytes(&request).await {
+            Ok(bytes) => platform.decode_image(bytes).await,
+            Err(err) => Err(err),
+        };
+
+        let image = match image {
+            Ok(image) => image,
+            Err(err) => {
+                log::warn!("Failed to load WMS image: {err}");
+                return;
+            }
+        };
+
+        {
+            let mut state = state.lock();
+            if state
+                .displayed
+                .as_ref()
+                .is_some_and(|displayed| displayed.generation > generation)
+            {
+                return;
+            }
+
+            state.displayed = Some(DisplayedImage {
+                extent,
+                generation,
+                image: Arc::new(image),
+                bundle: None,
+            });
+        }
+
+        if let Some(messenger) = messenger {
+            messenger.request_redraw();
+        }
+    }
+}
+
+impl WmsParameters {
+    fn map_request(&self, extent: ImageExtent) -> HttpRequest {
+        let mut params = self.common_params("GetMap", extent);
+        params.push(("FORMAT", self.format.clone()));
+        params.push((
+            "TRANSPARENT",
+            if self.transparent { "TRUE" } else { "FALSE" }.to_string(),
+        ));
+
+        self.request_settings.request(self.build_url(&params))
+    }
+
+    fn feature_info_request(&self, extent: ImageExtent, i: u32, j: u32) -> HttpRequest {
+        let mut params = self.common_params("GetFeatureInfo", extent);
+        params.push(("QUERY_LAYERS", self.layers.join(",")));
+        params.push(("INFO_FORMAT", self.info_format.clone()));
+
+        let (i_name, j_name) = match self.version {
+            WmsVersion::V1_1_1 => ("X", "Y"),
+            WmsVersion::V1_3_0 => ("I", "J"),
+        };
+        params.push((i_name, i.to_string()));
+        params.push((j_name, j.to_string()));
+
+        self.request_settings.request(self.build_url(&params))
+    }
+
+    fn common_params(&self, request: &str, extent: ImageExtent) -> Vec<(&str, String)> {
+        let crs_param = match self.version {
+            WmsVersion::V1_1_1 => "SRS",
+            WmsVersion::V1_3_0 => "CRS",
+        };
+
+        vec![
+            ("SERVICE", "WMS".to_string()),
+            ("VERSION", self.version.as_str().to_string()),
+            ("REQUEST", request.to_string()),
+            ("LAYERS", self.layers.join(",")),
+            ("STYLES", self.styles.join(",")),
+            (crs_param, self.crs_code.clone()),
+            ("BBOX", self.format_bbox(extent.bbox)),
+            ("WIDTH", extent.width.to_string()),
+            ("HEIGHT", extent.height.to_string()),
+        ]
+    }
+
+    fn format_bbox(&self, bbox: Rect) -> String {
+        let swap_axes =
+            self.version == WmsVersion::V1_3_0 && self.crs_code.eq_ignore_ascii_case("EPSG:4326");
+        if swap_axes {
+            format!(
+                "{},{},{},{}",
+                bbox.y_min(),
+                bbox.x_min(),
+                bbox.y_max(),
+                bbox.x_max()
+            )
+        } else {
+            format!(
+                "{},{},{},{}",
+                bbox.x_min(),
+                bbox.y_min(),
+                bbox.x_max(),
+                bbox.y_max()
+            )
+        }
+    }
+
+    fn build_url(&self, params: &[(&str, String)]) -> String {
+        let mut url = self.url.clone();
+        if !url.contains('?') {
+            url.push('?');
+        } else if !url.ends_with('?') && !url.ends_with('&') {
+            url.push('&');
+        }
+
+        let extra = self
+            .extra_params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+        let params = params
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .chain(extra);
+
+        for (index, (name, value)) in params.enumerate() {
+            if index > 0 {
+                url.push('&');
+            }
+            url += &encode_component(name);
+            url.push('=');
+            url += &encode_component(value);
+        }
+
+        url
+    }
+}
+
+/// Percent-encodes the characters that cannot appear in a query parameter. Commas, colons and
+/// slashes are left as is for readability, since WMS servers accept them unencoded.
+fn encode_component(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => result.push(byte as char),
+            b'-' | b'_' | b'.' | b'~' | b',' | b':' | b'/' => result.push(byte as char),
+            _ => result += &format!("%{byte:02X}"),
+        }
+    }
+
+    result
+}
+
+impl Layer for WmsLayer {
+    fn render(&self, view: &MapView, canvas: &mut dyn Canvas) {
+        if view.crs() != &self.crs {
+            return;
+        }
+
+        let mut state = self.state.lock();
+        let Some(displayed) = &mut state.displayed else {
+            return;
+        };
+
+        let bbox = displayed.extent.bbox;
+        let bundle = displayed.bundle.get_or_insert_with(|| {
+            let mut bundle = RenderBundle::default();
+            bundle.add_image(
+                displayed.image.clone(),
+                Rect::new(0.0, 0.0, bbox.width(), -bbox.height()).into_quadrangle(),
+                ImagePaint { opacity: 255 },
+            );
+            canvas.pack_bundle(&bundle).into()
+        });
+
+        let offset = Vector2::new(bbox.x_min() as f32, bbox.y_max() as f32);
+        canvas.draw_bundles(
+            &[BundleToDraw::new(&**bundle, 1.0, offset)],
+            RenderOptions::default(),
+        );
+    }
+
+    fn prepare(&self, view: &MapView) {
+        let Some(extent) = self.image_extent(view) else {
+            return;
+        };
+
+        let generation = {
+            let mut state = self.state.lock();
+            if state.requested == Some(extent) {
+                return;
+            }
+
+            state.requested = Some(extent);
+            state.generation += 1;
+            state.generation
+        };
+
+        crate::async_runtime::spawn(Self::load_image(
+            self.params.clone(),
+            self.state.clone(),
+            self.messenger.clone(),
+            extent,
+            generation,
+            self.debounce,
+        ));
+    }
+
+    fn set_messenger(&mut self, messenger: Box<dyn Messenger>) {
+        self.messenger = Some(Arc::from(messenger));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        self.attribution.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use galileo_types::cartesian::Size;
+
+    use super::*;
+
+    fn test_view() -> MapView {
+        MapView::new_projected(&Point2::new(0.0, 0.0), 10.0).with_size(Size::new(200.0, 100.0))
+    }
+
+    #[test]
+    fn get_map_url() {
+        let layer = WmsLayerBuilder::new("https://example.com/wms?map=test", ["roads", "rivers"])
+            .with_styles(["", "blue"])
+            .with_param("dim_time", "2024-01-01T00:00:00Z")
+            .build()
+            .unwrap();
+
+        let request = layer.map_request(&test_view()).unwrap();
+        assert_eq!(
+            request.url,
+            "https://example.com/wms?map=test&SERVICE=WMS&VERSION=1.3.0&REQUEST=GetMap&\
+            LAYERS=roads,rivers&STYLES=,blue&CRS=EPSG:3857&BBOX=-1000,-500,1000,500&WIDTH=200&\
+            HEIGHT=100&FORMAT=image/png&TRANSPARENT=TRUE&dim_time=2024-01-01T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn feature_info_url() {
+        let layer = WmsLayerBuilder::new("https://example.com/wms", ["roads"])
+            .with_version(WmsVersion::V1_1_1)
+            .with_info_format("application/json")
+            .build()
+            .unwrap();
+
+        let request = layer
+            .feature_info_request(&test_view(), Point2::new(150.0, 20.0))
+            .unwrap();
+        assert_eq!(
+            request.url,
+            "https://example.com/wms?SERVICE=WMS&VERSION=1.1.1&REQUEST=GetFeatureInfo&\
+            LAYERS=roads&STYLES=&SRS=EPSG:3857&BBOX=-1000,-500,1000,500&WIDTH=200&HEIGHT=100&\
+            QUERY_LAYERS=roads&INFO_FORMAT=application/json&X=150&Y=20"
+        );
+    }
+
+    #[test]
+    fn no_requests_for_other_crs() {
+        let layer = WmsLayerBuilder::new("https://example.com/wms", ["roads"])
+            .build()
+            .unwrap();
+        let view = MapView::new_projected_with_crs(&Point2::new(0.0, 0.0), 10.0, Crs::WGS84)
+            .with_size(Size::new(200.0, 100.0));
+
+        assert!(layer.map_request(&view).is_none());
+    }
+
+    #[cfg(feature = "image")]
+    /// Starts a stand-in WMS server that responds to `GetMap` requests with a 2x2 PNG image and
+    /// to `GetFeatureInfo` requests with a text. Returns the address of the server and the list
+    /// of received request paths.
+    fn start_server() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+        let address = format!("http://{}/wms", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let mut png = std::io::Cursor::new(vec![]);
+        image::DynamicImage::ImageRgba8(image::RgbaImage::new(2, 2))
+            .write_to(&mut png, image::ImageFormat::Png)
+            .expect("failed to encode image");
+        let png = png.into_inner();
+
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) <= 2 {
+                        break;
+                    }
+                }
+
+                let path = request_line
+                    .split(' ')
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let (content_type, body) = if path.contains("GetFeatureInfo") {
+                    ("text/plain", b"roads: 1".to_vec())
+                } else {
+                    ("image/png", png.clone())
+                };
+                received.lock().push(path);
+
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+
+        (address, requests)
+    }
+
+    #[cfg(feature = "image")]
+    #[tokio::test]
+    async fn loads_image_from_server() {
+        let (url, requests) = start_server();
+        let layer = WmsLayerBuilder::new(url, ["roads"])
+            .with_debounce(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        let view = test_view();
+        layer.prepare(&view.translate(Vector2::new(100.0, 0.0)));
+        layer.prepare(&view);
+        layer.prepare(&view);
+
+        for _ in 0..500 {
+            if layer.state.lock().displayed.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        {
+            let state = layer.state.lock();
+            let displayed = state.displayed.as_ref().expect("image is not loaded");
+            assert_eq!(displayed.extent, layer.image_extent(&view).unwrap());
+            assert_eq!(displayed.image.size(), Size::new(2, 2));
+        }
+
+        let requests = requests.lock().clone();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].contains("BBOX=-1000,-500,1000,500"));
+
+        let info = layer
+            .get_feature_info(&view, Point2::new(10.0, 10.0))
+            .await
+            .unwrap();
+        assert_eq!(info, "roads: 1");
+    }
+}
@@ -91,6 +91,8 @@ mod map;
 mod messenger;
 pub mod platform;
 pub mod render;
+mod reprojection;
+pub mod terrain;
 pub mod tile_schema;
 mod view;
 
@@ -105,7 +107,7 @@ pub use color::Color;
 pub use galileo_types;
 pub use layer::feature_layer::symbol;
 pub use lod::Lod;
-pub use map::{LayerCollection, Map, MapBuilder};
+pub use map::{LayerCollection, LayerCollectionChange, LayerId, Map, MapBuilder, VisibilityRange};
 pub use messenger::{DummyMessenger, Messenger};
 pub use tile_schema::TileSchema;
 pub use view::MapView;
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use galileo_types::cartesian::{CartesianPoint2d, Point2};
 use galileo_types::geo::impls::GeoPoint2d;
 use galileo_types::geo::{Crs, GeoPoint};
@@ -5,6 +7,7 @@ use galileo_types::latlon;
 
 use super::Map;
 use crate::layer::Layer;
+use crate::terrain::Terrain;
 use crate::{MapView, Messenger};
 
 // z-level 4 on the standard web tile scheme
@@ -35,6 +38,7 @@ pub struct MapBuilder {
     crs: Option<Crs>,
     layers: Vec<Box<dyn Layer>>,
     messenger: Option<Box<dyn Messenger>>,
+    terrain: Option<Arc<dyn Terrain>>,
 }
 
 impl MapBuilder {
@@ -203,6 +207,12 @@ impl MapBuilder {
         self
     }
 
+    /// Sets the [terrain](crate::terrain) that elevates the map surface.
+    pub fn with_terrain(mut self, terrain: impl Terrain + 'static) -> Self {
+        self.terrain = Some(Arc::new(terrain));
+        self
+    }
+
     /// Consumes the builder and creates a map instance.
     ///
     /// If some of the parameters are not specified before calling `build`, they will be set to the
@@ -216,6 +226,7 @@ impl MapBuilder {
             crs,
             layers,
             messenger,
+            terrain,
         } = self;
         let crs = crs.unwrap_or(Crs::EPSG3857);
 
@@ -235,7 +246,12 @@ impl MapBuilder {
             MapView::new_projected_with_crs(&projected_position, resolution, crs)
         };
 
-        Map::new(view, layers, messenger)
+        let mut map = Map::new(view, layers, messenger);
+        if terrain.is_some() {
+            map.set_terrain(terrain);
+        }
+
+        map
     }
 }
 
@@ -482,4 +498,24 @@ mod tests {
 
         assert!(map.messenger.is_some());
     }
+
+    #[test]
+    fn with_terrain_sets_terrain_to_map_and_view() {
+        struct FlatTerrain;
+        impl Terrain for FlatTerrain {
+            fn elevation(&self, _point: &Point2) -> Option<f64> {
+                Some(100.0)
+            }
+            fn prepare(&self, _view: &MapView) {}
+            fn set_messenger(&self, _messenger: Box<dyn Messenger>) {}
+        }
+
+        let map = MapBuilder::default().build();
+        assert!(map.terrain().is_none());
+        assert_eq!(map.view().elevation(&Point2::new(0.0, 0.0)), 0.0);
+
+        let map = MapBuilder::default().with_terrain(FlatTerrain).build();
+        assert!(map.terrain().is_some());
+        assert_eq!(map.view().elevation(&Point2::new(0.0, 0.0)), 100.0);
+    }
 }
@@ -1,6 +1,11 @@
 use std::ops::{Index, IndexMut, RangeBounds};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use crate::layer::Layer;
+use maybe_sync::{MaybeSend, MaybeSync};
+
+use crate::layer::{Layer, LayerGroup};
+use crate::render::BlendMode;
+use crate::view::MapView;
 
 /// Collection of layers with some meta-information.
 ///
@@ -9,6 +14,25 @@ use crate::layer::Layer;
 /// [`LayerCollection::show_by`] methods. These layers will be ignored by the renderer, but
 /// retain their place in the collection.
 ///
+/// A layer can also be displayed only in a range of map resolutions set by its
+/// [`VisibilityRange`]. Outside of this range the layer is skipped both when the map is rendered
+/// and when the layers are loaded.
+///
+/// Each layer is drawn with its own opacity and [`BlendMode`], set with the
+/// [`LayerCollection::set_opacity`] and [`LayerCollection::set_blend_mode`] methods. A layer with
+/// opacity less than `1.0` or non-normal blend mode is first drawn to a separate image, which is
+/// then combined with the layers below it, so overlapping objects of the layer do not blend with
+/// each other.
+///
+/// Layers can be organized into nested [`LayerGroup`]s. The opacity, blend mode and visibility of
+/// a group apply to all its children. A layer inside groups can be addressed by the path of
+/// indices with the [`LayerCollection::get_by_path`] method.
+///
+/// Every layer added to the collection is assigned a unique [`LayerId`], which does not change when
+/// other layers are inserted, removed or reordered. Layers can also be given names. Application
+/// code can use ids or names to find layers instead of relying on their indices. To be notified of
+/// changes in the collection, set a callback with [`LayerCollection::set_change_callback`].
+///
 /// Since a map should be able to render anything implementing the [`Layer`] trait, this
 /// collection stores layers as trait objects. You can use downcasting through `Any` trait
 /// to obtain a concrete layer type you work with.
@@ -31,11 +55,133 @@ use crate::layer::Layer;
 /// # Ok::<(), galileo::error::GalileoError>(())
 /// ```
 #[derive(Default)]
-pub struct LayerCollection(Vec<LayerEntry>);
+pub struct LayerCollection {
+    layers: Vec<LayerEntry>,
+    on_change: Option<Box<ChangeCallback>>,
+}
+
+type ChangeCallback = dyn Fn(&LayerCollectionChange) + MaybeSend + MaybeSync;
+
+/// Unique identifier of a layer in a [`LayerCollection`].
+///
+/// The id is assigned to a layer when it is added to the collection and does not change while the
+/// layer stays in it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LayerId(u64);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+impl LayerId {
+    fn next() -> Self {
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Change in a [`LayerCollection`] reported to the callback set with
+/// [`LayerCollection::set_change_callback`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LayerCollectionChange {
+    /// Layer was added to the collection.
+    Added(LayerId),
+    /// Layer was removed from the collection.
+    Removed(LayerId),
+    /// Position of the layer in the collection was changed.
+    Moved(LayerId),
+    /// Layer was hidden or shown.
+    VisibilityChanged(LayerId),
+    /// Name, opacity, blend mode or visibility range of the layer was changed.
+    PropertiesChanged(LayerId),
+}
 
 struct LayerEntry {
+    id: LayerId,
+    name: Option<String>,
     layer: Box<dyn Layer>,
     is_hidden: bool,
+    opacity: f32,
+    blend_mode: BlendMode,
+    visibility_range: VisibilityRange,
+}
+
+/// Range of map resolutions in which a layer is displayed.
+///
+/// Resolution is the size of a screen pixel in map units, so the larger the resolution, the more
+/// zoomed out the map is. The layer is displayed if the view resolution is larger than
+/// `min_resolution` and smaller than `max_resolution`.
+///
+/// ```
+/// use galileo::VisibilityRange;
+///
+/// // Buildings are displayed when zoomed in further than 10 meters per pixel, and fade out
+/// // between 5 and 10 m/px.
+/// let range = VisibilityRange::default()
+///     .with_max_resolution(10.0)
+///     .with_fade(1.0);
+///
+/// assert_eq!(range.opacity(2.0), 1.0);
+/// assert!(range.opacity(7.0) > 0.0 && range.opacity(7.0) < 1.0);
+/// assert_eq!(range.opacity(10.0), 0.0);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VisibilityRange {
+    /// Resolution at and below which the layer is hidden. `None` means no lower limit.
+    pub min_resolution: Option<f64>,
+    /// Resolution at and above which the layer is hidden. `None` means no upper limit.
+    pub max_resolution: Option<f64>,
+    /// Width of the zones at the range boundaries, in which the layer gradually fades out,
+    /// relative to the boundary resolution. For example, with the value of `1.0` the layer fades
+    /// out between the resolutions of `max_resolution / 2` and `max_resolution`, and between
+    /// `min_resolution` and `min_resolution * 2`. The value of `0.0` turns off the fading.
+    pub fade: f64,
+}
+
+impl VisibilityRange {
+    /// Sets the resolution at and below which the layer is hidden.
+    pub fn with_min_resolution(mut self, resolution: f64) -> Self {
+        self.min_resolution = Some(resolution);
+        self
+    }
+
+    /// Sets the resolution at and above which the layer is hidden.
+    pub fn with_max_resolution(mut self, resolution: f64) -> Self {
+        self.max_resolution = Some(resolution);
+        self
+    }
+
+    /// Sets the relative width of the fade zones at the range boundaries.
+    pub fn with_fade(mut self, fade: f64) -> Self {
+        self.fade = fade;
+        self
+    }
+
+    /// Returns true if the layer is displayed at the given resolution.
+    pub fn contains(&self, resolution: f64) -> bool {
+        self.opacity(resolution) > 0.0
+    }
+
+    /// Returns the opacity multiplier of the layer at the given resolution: `0.0` outside the
+    /// range, `1.0` inside it, and the value between them in the fade zones.
+    pub fn opacity(&self, resolution: f64) -> f32 {
+        let fade_scale = (1.0 + self.fade.max(0.0)).ln();
+        let boundary_opacity = |ratio: f64| {
+            if ratio <= 1.0 {
+                0.0
+            } else if fade_scale <= 0.0 {
+                1.0
+            } else {
+                (ratio.ln() / fade_scale).min(1.0)
+            }
+        };
+
+        let min_opacity = self
+            .min_resolution
+            .map_or(1.0, |min| boundary_opacity(resolution / min));
+        let max_opacity = self
+            .max_resolution
+            .map_or(1.0, |max| boundary_opacity(max / resolution));
+
+        min_opacity.min(max_opacity) as f32
+    }
 }
 
 impl LayerCollection {
@@ -60,7 +206,9 @@ impl LayerCollection {
     /// assert_eq!(collection[0].as_any().downcast_ref(), Some(&TestLayer("Layer A")));
     /// ```
     pub fn truncate(&mut self, length: usize) {
-        self.0.truncate(length)
+        if length < self.layers.len() {
+            self.drain(length..).for_each(drop);
+        }
     }
 
     /// Removes all layers from the collection.
@@ -80,7 +228,7 @@ impl LayerCollection {
     /// assert_eq!(collection.len(), 0);
     /// ```
     pub fn clear(&mut self) {
-        self.0.clear()
+        self.drain(..).for_each(drop);
     }
 
     /// Removes a layer from the collection and returns it. The removed element is replaced by the
@@ -107,7 +255,13 @@ impl LayerCollection {
     /// assert_eq!(collection[0].as_any().downcast_ref(), Some(&TestLayer("Layer C")));
     /// ```
     pub fn swap_remove(&mut self, index: usize) -> Box<dyn Layer> {
-        self.0.swap_remove(index).layer
+        let entry = self.layers.swap_remove(index);
+        self.notify(LayerCollectionChange::Removed(entry.id));
+        if let Some(moved) = self.layers.get(index) {
+            self.notify(LayerCollectionChange::Moved(moved.id));
+        }
+
+        entry.layer
     }
 
     /// Inserts a layer at position `index`, shifting all layers after it to the right.
@@ -131,8 +285,14 @@ impl LayerCollection {
     /// assert_eq!(collection.len(), 3);
     /// assert_eq!(collection[1].as_any().downcast_ref(), Some(&TestLayer("Layer C")));
     /// assert_eq!(collection[2].as_any().downcast_ref(), Some(&TestLayer("Layer B")));
-    pub fn insert(&mut self, index: usize, layer: impl Layer + 'static) {
-        self.0.insert(index, layer.into());
+    /// ```
+    pub fn insert(&mut self, index: usize, layer: impl Layer + 'static) -> LayerId {
+        let entry = LayerEntry::from(layer);
+        let id = entry.id;
+        self.layers.insert(index, entry);
+        self.notify(LayerCollectionChange::Added(id));
+
+        id
     }
 
     /// Removes a layer at `index`, shifting all layers after it to the left and returning the
@@ -160,7 +320,10 @@ impl LayerCollection {
     /// assert_eq!(collection[1].as_any().downcast_ref(), Some(&TestLayer("Layer C")));
     /// ```
     pub fn remove(&mut self, index: usize) -> Box<dyn Layer> {
-        self.0.remove(index).layer
+        let entry = self.layers.remove(index);
+        self.notify(LayerCollectionChange::Removed(entry.id));
+
+        entry.layer
     }
 
     /// Retains only the layers specified by the predicate. In other words, remove all layers `l`
@@ -188,10 +351,22 @@ impl LayerCollection {
     where
         F: FnMut(&dyn Layer) -> bool,
     {
-        self.0.retain(|entry| f(&*entry.layer))
+        let mut removed = vec![];
+        self.layers.retain(|entry| {
+            let retain = f(&*entry.layer);
+            if !retain {
+                removed.push(entry.id);
+            }
+
+            retain
+        });
+
+        for id in removed {
+            self.notify(LayerCollectionChange::Removed(id));
+        }
     }
 
-    /// Adds the layer to the end of the collection.
+    /// Adds the layer to the end of the collection and returns the id assigned to it.
     ///
     /// # Examples
     ///
@@ -209,8 +384,13 @@ impl LayerCollection {
     /// assert_eq!(collection.len(), 3);
     /// assert_eq!(collection[2].as_any().downcast_ref(), Some(&TestLayer("Layer C")));
     /// ```
-    pub fn push(&mut self, layer: impl Layer + 'static) {
-        self.0.push(layer.into())
+    pub fn push(&mut self, layer: impl Layer + 'static) -> LayerId {
+        let entry = LayerEntry::from(layer);
+        let id = entry.id;
+        self.layers.push(entry);
+        self.notify(LayerCollectionChange::Added(id));
+
+        id
     }
 
     /// Removes the last layer from the collection and returns it. Returns `None` if the collection
@@ -234,7 +414,10 @@ impl LayerCollection {
     /// assert_eq!(removed.unwrap().as_any().downcast_ref(), Some(&TestLayer("Layer C")));
     /// ```
     pub fn pop(&mut self) -> Option<Box<dyn Layer>> {
-        self.0.pop().map(|entry| entry.layer)
+        let entry = self.layers.pop()?;
+        self.notify(LayerCollectionChange::Removed(entry.id));
+
+        Some(entry.layer)
     }
 
     /// Removes the specified range of layers from the collection in bulk, returning all removed
@@ -269,7 +452,14 @@ impl LayerCollection {
     where
         R: RangeBounds<usize>,
     {
-        self.0.drain(range).map(|entry| entry.layer)
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        if let Some(on_change) = &self.on_change {
+            for entry in &self.layers[range] {
+                on_change(&LayerCollectionChange::Removed(entry.id));
+            }
+        }
+
+        self.layers.drain(range).map(|entry| entry.layer)
     }
 
     /// Returns the count of layers in the collection.
@@ -288,7 +478,7 @@ impl LayerCollection {
     /// assert_eq!(collection.len(), 2);
     /// ```
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.layers.len()
     }
 
     /// Returns `true` if the collection contains zero layers.
@@ -306,7 +496,7 @@ impl LayerCollection {
     /// assert!(!collection.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.layers.is_empty()
     }
 
     /// Returns a layer at `index`, or `None` if index is out of bounds.
@@ -326,7 +516,7 @@ impl LayerCollection {
     /// assert!(collection.get(2).is_none());
     /// ```
     pub fn get(&self, index: usize) -> Option<&dyn Layer> {
-        self.0.get(index).map(|entry| &*entry.layer)
+        self.layers.get(index).map(|entry| &*entry.layer)
     }
 
     /// Returns a mutable reference to a layer at `index`, or `None` if index is out of bounds.
@@ -346,7 +536,7 @@ impl LayerCollection {
     /// assert!(collection.get(2).is_none());
     /// ```
     pub fn get_mut(&mut self, index: usize) -> Option<&mut Box<dyn Layer>> {
-        self.0.get_mut(index).map(|entry| &mut entry.layer)
+        self.layers.get_mut(index).map(|entry| &mut entry.layer)
     }
 
     /// Swaps two layers in the collection.
@@ -373,7 +563,11 @@ impl LayerCollection {
     /// assert_eq!(collection[2].as_any().downcast_ref(), Some(&TestLayer("Layer B")));
     /// ```
     pub fn swap(&mut self, a: usize, b: usize) {
-        self.0.swap(a, b)
+        self.layers.swap(a, b);
+        if a != b {
+            self.notify(LayerCollectionChange::Moved(self.layers[a].id));
+            self.notify(LayerCollectionChange::Moved(self.layers[b].id));
+        }
     }
 
     /// Iterates over all layers in the collection.
@@ -393,7 +587,7 @@ impl LayerCollection {
     /// assert!(iterator.next().is_none());
     /// ```
     pub fn iter(&self) -> impl Iterator<Item = &dyn Layer> + '_ {
-        self.0.iter().map(|entry| &*entry.layer)
+        self.layers.iter().map(|entry| &*entry.layer)
     }
 
     /// Iterates over mutable references to all layers in the collection.
@@ -413,7 +607,7 @@ impl LayerCollection {
     /// assert!(iterator.next().is_none());
     /// ```
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Layer>> + '_ {
-        self.0.iter_mut().map(|entry| &mut entry.layer)
+        self.layers.iter_mut().map(|entry| &mut entry.layer)
     }
 
     /// Sets the layer at `index` as invisible. The hidden layer can be later shown with
@@ -440,7 +634,7 @@ impl LayerCollection {
     /// assert!(!collection.is_visible(1));
     /// ```
     pub fn hide(&mut self, index: usize) {
-        self.0[index].is_hidden = true;
+        self.set_hidden(index, true);
     }
 
     /// Sets the layer at `index` as visible.
@@ -467,7 +661,7 @@ impl LayerCollection {
     /// assert!(collection.is_visible(1));
     /// ```
     pub fn show(&mut self, index: usize) {
-        self.0[index].is_hidden = false;
+        self.set_hidden(index, false);
     }
 
     /// Sets all layers for which the predicate returns true as visible. The rest of layers are set
@@ -494,8 +688,18 @@ impl LayerCollection {
     where
         F: FnMut(&dyn Layer) -> bool,
     {
-        for entry in &mut self.0 {
-            entry.is_hidden = !f(&*entry.layer);
+        for index in 0..self.layers.len() {
+            let is_hidden = !f(&*self.layers[index].layer);
+            self.set_hidden(index, is_hidden);
+        }
+    }
+
+    fn set_hidden(&mut self, index: usize, is_hidden: bool) {
+        let entry = &mut self.layers[index];
+        if entry.is_hidden != is_hidden {
+            entry.is_hidden = is_hidden;
+            let id = entry.id;
+            self.notify(LayerCollectionChange::VisibilityChanged(id));
         }
     }
 
@@ -525,11 +729,15 @@ impl LayerCollection {
     /// assert!(collection.is_visible(1));
     /// ```
     pub fn is_visible(&self, index: usize) -> bool {
-        !self.0[index].is_hidden
+        !self.layers[index].is_hidden
     }
 
     /// Iterates over all visible layers in the collection.
     ///
+    /// Only the layers hidden with [`LayerCollection::hide`] are skipped. To also skip the layers
+    /// which visibility range does not contain the map resolution, use
+    /// [`LayerCollection::iter_visible_at`].
+    ///
     /// # Examples
     ///
     /// ```
@@ -550,54 +758,624 @@ impl LayerCollection {
     /// assert!(iterator.next().is_none());
     /// ```
     pub fn iter_visible(&self) -> impl Iterator<Item = &dyn Layer> + '_ {
-        self.0
+        self.layers
             .iter()
             .filter(|entry| !entry.is_hidden)
             .map(|entry| &*entry.layer)
     }
 
+    /// Iterates over all layers in the collection that are visible with the given map view. These
+    /// are the layers that are not hidden and which visibility range contains the view resolution.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::{LayerCollection, MapView, VisibilityRange};
+    /// use galileo::layer::TestLayer;
+    /// use galileo::galileo_types::cartesian::Point2;
+    ///
+    /// let mut collection = LayerCollection::from(vec![
+    ///     TestLayer("Layer A"),
+    ///     TestLayer("Layer B"),
+    ///     TestLayer("Layer C"),
+    ///     TestLayer("Layer D"),
+    /// ]);
+    ///
+    /// collection.hide(1);
+    /// collection.set_visibility_range(3, VisibilityRange::default().with_max_resolution(10.0));
+    ///
+    /// let view = MapView::new_projected(&Point2::new(0.0, 0.0), 100.0);
+    /// let mut iterator = collection.iter_visible_at(&view);
+    /// assert_eq!(iterator.next().and_then(|layer| layer.as_any().downcast_ref()), Some(&TestLayer("Layer A")));
+    /// assert_eq!(iterator.next().and_then(|layer| layer.as_any().downcast_ref()), Some(&TestLayer("Layer C")));
+    /// assert!(iterator.next().is_none());
+    /// ```
+    pub fn iter_visible_at<'a>(
+        &'a self,
+        view: &MapView,
+    ) -> impl Iterator<Item = &'a dyn Layer> + 'a {
+        let resolution = view.resolution();
+        self.layers
+            .iter()
+            .filter(move |entry| !entry.is_hidden && entry.visibility_range.contains(resolution))
+            .map(|entry| &*entry.layer)
+    }
+
+    /// Sets the opacity of the layer at `index`. The value is clamped into `0.0..=1.0` range, `1.0`
+    /// being fully opaque.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::TestLayer;
+    ///
+    /// let mut collection = LayerCollection::from(vec![
+    ///     TestLayer("Layer A"),
+    ///     TestLayer("Layer B"),
+    /// ]);
+    ///
+    /// assert_eq!(collection.opacity(1), 1.0);
+    /// collection.set_opacity(1, 0.5);
+    /// assert_eq!(collection.opacity(1), 0.5);
+    /// collection.set_opacity(1, 2.0);
+    /// assert_eq!(collection.opacity(1), 1.0);
+    /// ```
+    pub fn set_opacity(&mut self, index: usize, opacity: f32) {
+        self.layers[index].opacity = opacity.clamp(0.0, 1.0);
+        self.notify(LayerCollectionChange::PropertiesChanged(
+            self.layers[index].id,
+        ));
+    }
+
+    /// Returns the opacity of the layer at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn opacity(&self, index: usize) -> f32 {
+        self.layers[index].opacity
+    }
+
+    /// Sets the way the colors of the layer at `index` are combined with the layers below it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::TestLayer;
+    /// use galileo::render::BlendMode;
+    ///
+    /// let mut collection = LayerCollection::from(vec![
+    ///     TestLayer("Basemap"),
+    ///     TestLayer("Hillshade"),
+    /// ]);
+    ///
+    /// collection.set_blend_mode(1, BlendMode::Multiply);
+    /// assert_eq!(collection.blend_mode(0), BlendMode::Normal);
+    /// assert_eq!(collection.blend_mode(1), BlendMode::Multiply);
+    /// ```
+    pub fn set_blend_mode(&mut self, index: usize, blend_mode: BlendMode) {
+        self.layers[index].blend_mode = blend_mode;
+        self.notify(LayerCollectionChange::PropertiesChanged(
+            self.layers[index].id,
+        ));
+    }
+
+    /// Returns the blend mode of the layer at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn blend_mode(&self, index: usize) -> BlendMode {
+        self.layers[index].blend_mode
+    }
+
+    /// Sets the range of map resolutions in which the layer at `index` is displayed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::{LayerCollection, VisibilityRange};
+    /// use galileo::layer::TestLayer;
+    ///
+    /// let mut collection = LayerCollection::from(vec![
+    ///     TestLayer("Basemap"),
+    ///     TestLayer("Buildings"),
+    /// ]);
+    ///
+    /// let range = VisibilityRange::default().with_max_resolution(10.0).with_fade(0.5);
+    /// collection.set_visibility_range(1, range);
+    /// assert_eq!(collection.visibility_range(1), range);
+    /// ```
+    pub fn set_visibility_range(&mut self, index: usize, range: VisibilityRange) {
+        self.layers[index].visibility_range = range;
+        self.notify(LayerCollectionChange::PropertiesChanged(
+            self.layers[index].id,
+        ));
+    }
+
+    /// Returns the range of map resolutions in which the layer at `index` is displayed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn visibility_range(&self, index: usize) -> VisibilityRange {
+        self.layers[index].visibility_range
+    }
+
+    /// Iterates over all layers visible with the given view together with their opacity and blend
+    /// mode. The opacity includes the fading at the visibility range boundaries.
+    ///
+    /// Layer groups are returned as single layers with their own opacity and blend mode. The
+    /// renderer draws the children of a group into a separate image and combines it with the
+    /// layers below the group once.
+    pub(crate) fn iter_visible_with_compositing<'a>(
+        &'a self,
+        view: &MapView,
+    ) -> impl Iterator<Item = (&'a dyn Layer, f32, BlendMode)> + 'a {
+        let resolution = view.resolution();
+        self.layers
+            .iter()
+            .filter(|entry| !entry.is_hidden)
+            .filter_map(move |entry| {
+                let range_opacity = entry.visibility_range.opacity(resolution);
+                (range_opacity > 0.0).then_some((
+                    &*entry.layer,
+                    entry.opacity * range_opacity,
+                    entry.blend_mode,
+                ))
+            })
+    }
+
+    /// Returns a layer by its path in the tree of [`LayerGroup`]s, or `None` if there is no such
+    /// layer. The last element of the path is the index of the layer in its group, and the
+    /// previous ones are the indices of the groups containing it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::{LayerGroup, TestLayer};
+    ///
+    /// let collection = LayerCollection::from(vec![
+    ///     LayerGroup::new(vec![TestLayer("Layer A")]),
+    ///     LayerGroup::new(vec![TestLayer("Layer B"), TestLayer("Layer C")]),
+    /// ]);
+    ///
+    /// assert_eq!(collection.get_by_path(&[1, 1]).and_then(|layer| layer.as_any().downcast_ref()), Some(&TestLayer("Layer C")));
+    /// assert!(collection.get_by_path(&[0, 1]).is_none());
+    /// assert!(collection.get_by_path(&[0, 0, 0]).is_none());
+    /// assert!(collection.get_by_path(&[]).is_none());
+    /// ```
+    pub fn get_by_path(&self, path: &[usize]) -> Option<&dyn Layer> {
+        let (&index, group_path) = path.split_last()?;
+        self.group_layers(group_path)?.get(index)
+    }
+
+    /// Returns a mutable reference to a layer by its path in the tree of [`LayerGroup`]s. See
+    /// [`LayerCollection::get_by_path`].
+    pub fn get_by_path_mut(&mut self, path: &[usize]) -> Option<&mut Box<dyn Layer>> {
+        let (&index, group_path) = path.split_last()?;
+        self.group_layers_mut(group_path)?.get_mut(index)
+    }
+
+    /// Returns the layers of the [`LayerGroup`] at the given path, or `None` if there is no group
+    /// at the path. Empty path returns the collection itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::{LayerGroup, TestLayer};
+    ///
+    /// let collection = LayerCollection::from(vec![
+    ///     LayerGroup::new(vec![TestLayer("Layer A"), TestLayer("Layer B")]),
+    /// ]);
+    ///
+    /// assert_eq!(collection.group_layers(&[0]).map(|layers| layers.len()), Some(2));
+    /// assert!(collection.group_layers(&[0, 1]).is_none());
+    /// ```
+    pub fn group_layers(&self, path: &[usize]) -> Option<&LayerCollection> {
+        path.iter().try_fold(self, |collection, &index| {
+            collection
+                .get_typed::<LayerGroup>(index)
+                .map(LayerGroup::layers)
+        })
+    }
+
+    /// Returns a mutable reference to the layers of the [`LayerGroup`] at the given path. See
+    /// [`LayerCollection::group_layers`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::{LayerGroup, TestLayer};
+    ///
+    /// let mut collection = LayerCollection::from(vec![
+    ///     LayerGroup::new(vec![TestLayer("Layer A"), TestLayer("Layer B")]),
+    /// ]);
+    ///
+    /// collection.group_layers_mut(&[0]).unwrap().hide(1);
+    /// assert!(!collection.group_layers(&[0]).unwrap().is_visible(1));
+    /// ```
+    pub fn group_layers_mut(&mut self, path: &[usize]) -> Option<&mut LayerCollection> {
+        path.iter().try_fold(self, |collection, &index| {
+            collection
+                .layers
+                .get_mut(index)?
+                .layer
+                .as_any_mut()
+                .downcast_mut::<LayerGroup>()
+                .map(LayerGroup::layers_mut)
+        })
+    }
+
     /// Returns the layer converted to its original type if it was `T`.
     pub fn get_typed<T: Layer + 'static>(&self, index: usize) -> Option<&T> {
-        self.0
+        self.layers
             .get(index)
             .and_then(|layer| layer.layer.as_any().downcast_ref::<T>())
     }
+
+    /// Returns the id of the layer at `index`, or `None` if index is out of bounds.
+    pub fn id(&self, index: usize) -> Option<LayerId> {
+        self.layers.get(index).map(|entry| entry.id)
+    }
+
+    /// Returns the index of the layer with the given id, or `None` if there is no such layer in
+    /// the collection.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::TestLayer;
+    ///
+    /// let mut collection = LayerCollection::default();
+    /// let id_a = collection.push(TestLayer("Layer A"));
+    /// let id_b = collection.push(TestLayer("Layer B"));
+    ///
+    /// collection.insert(0, TestLayer("Layer C"));
+    /// assert_eq!(collection.index_of(id_a), Some(1));
+    /// assert_eq!(collection.index_of(id_b), Some(2));
+    ///
+    /// collection.remove(2);
+    /// assert_eq!(collection.index_of(id_b), None);
+    /// ```
+    pub fn index_of(&self, id: LayerId) -> Option<usize> {
+        self.layers.iter().position(|entry| entry.id == id)
+    }
+
+    /// Returns the layer with the given id, or `None` if there is no such layer in the collection.
+    pub fn get_by_id(&self, id: LayerId) -> Option<&dyn Layer> {
+        self.get(self.index_of(id)?)
+    }
+
+    /// Returns a mutable reference to the layer with the given id, or `None` if there is no such
+    /// layer in the collection.
+    pub fn get_by_id_mut(&mut self, id: LayerId) -> Option<&mut Box<dyn Layer>> {
+        let index = self.index_of(id)?;
+        self.get_mut(index)
+    }
+
+    /// Returns the layer with the given id converted to its original type if it was `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::TestLayer;
+    ///
+    /// let mut collection = LayerCollection::default();
+    /// let id = collection.push(TestLayer("Layer A"));
+    ///
+    /// assert_eq!(collection.get_typed_by_id::<TestLayer>(id), Some(&TestLayer("Layer A")));
+    /// ```
+    pub fn get_typed_by_id<T: Layer + 'static>(&self, id: LayerId) -> Option<&T> {
+        self.get_typed(self.index_of(id)?)
+    }
+
+    /// Removes the layer with the given id and returns it. Returns `None` if there is no such layer
+    /// in the collection.
+    pub fn remove_by_id(&mut self, id: LayerId) -> Option<Box<dyn Layer>> {
+        let index = self.index_of(id)?;
+        Some(self.remove(index))
+    }
+
+    /// Sets the name of the layer with the given id. Returns `false` if there is no such layer in
+    /// the collection.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::TestLayer;
+    ///
+    /// let mut collection = LayerCollection::default();
+    /// collection.push(TestLayer("Layer A"));
+    /// let id = collection.push(TestLayer("Layer B"));
+    ///
+    /// collection.set_name(id, "Roads");
+    /// assert_eq!(collection.name(id), Some("Roads"));
+    /// assert_eq!(collection.find_by_name("Roads"), Some(id));
+    /// assert_eq!(collection.find_by_name("Buildings"), None);
+    /// ```
+    pub fn set_name(&mut self, id: LayerId, name: impl Into<String>) -> bool {
+        let Some(index) = self.index_of(id) else {
+            return false;
+        };
+
+        self.layers[index].name = Some(name.into());
+        self.notify(LayerCollectionChange::PropertiesChanged(id));
+        true
+    }
+
+    /// Returns the name of the layer with the given id, if the layer exists and has a name.
+    pub fn name(&self, id: LayerId) -> Option<&str> {
+        self.layers[self.index_of(id)?].name.as_deref()
+    }
+
+    /// Returns the id of the first layer with the given name.
+    pub fn find_by_name(&self, name: &str) -> Option<LayerId> {
+        self.layers
+            .iter()
+            .find(|entry| entry.name.as_deref() == Some(name))
+            .map(|entry| entry.id)
+    }
+
+    /// Sets the layer with the given id as invisible. Returns `false` if there is no such layer in
+    /// the collection.
+    pub fn hide_by_id(&mut self, id: LayerId) -> bool {
+        let Some(index) = self.index_of(id) else {
+            return false;
+        };
+
+        self.hide(index);
+        true
+    }
+
+    /// Sets the layer with the given id as visible. Returns `false` if there is no such layer in
+    /// the collection.
+    pub fn show_by_id(&mut self, id: LayerId) -> bool {
+        let Some(index) = self.index_of(id) else {
+            return false;
+        };
+
+        self.show(index);
+        true
+    }
+
+    /// Moves the layer with id `id` right above the layer with id `target`, so that it is drawn
+    /// on top of it. Returns `false` if any of the layers is not in the collection.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use galileo::LayerCollection;
+    /// use galileo::layer::TestLayer;
+    ///
+    /// let mut collection = LayerCollection::default();
+    /// let id_a = collection.push(TestLayer("Layer A"));
+    /// let id_b = collection.push(TestLayer("Layer B"));
+    /// let id_c = collection.push(TestLayer("Layer C"));
+    ///
+    /// assert!(collection.move_above(id_a, id_b));
+    /// assert_eq!(collection.index_of(id_a), Some(1));
+    /// assert_eq!(collection.index_of(id_b), Some(0));
+    ///
+    /// assert!(collection.move_below(id_c, id_b));
+    /// assert_eq!(collection.index_of(id_c), Some(0));
+    /// ```
+    pub fn move_above(&mut self, id: LayerId, target: LayerId) -> bool {
+        self.move_relative(id, target, 1)
+    }
+
+    /// Moves the layer with id `id` right below the layer with id `target`, so that it is drawn
+    /// under it. Returns `false` if any of the layers is not in the collection.
+    pub fn move_below(&mut self, id: LayerId, target: LayerId) -> bool {
+        self.move_relative(id, target, 0)
+    }
+
+    fn move_relative(&mut self, id: LayerId, target: LayerId, offset: usize) -> bool {
+        let (Some(from), Some(_)) = (self.index_of(id), self.index_of(target)) else {
+            return false;
+        };
+        if id == target {
+            return true;
+        }
+
+        let entry = self.layers.remove(from);
+        let to = self.index_of(target).expect("target layer was not removed") + offset;
+        self.layers.insert(to, entry);
+
+        if from != to {
+            self.notify(LayerCollectionChange::Moved(id));
+        }
+
+        true
+    }
+
+    /// Sets the function called every time the collection is changed.
+    ///
+    /// Changes made to the layers themselves (through [`LayerCollection::get_mut`] for example)
+    /// are not reported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use galileo::{LayerCollection, LayerCollectionChange};
+    /// use galileo::layer::TestLayer;
+    ///
+    /// let changes = Arc::new(Mutex::new(vec![]));
+    /// let mut collection = LayerCollection::default();
+    /// let changes_clone = changes.clone();
+    /// collection.set_change_callback(move |change| changes_clone.lock().unwrap().push(*change));
+    ///
+    /// let id = collection.push(TestLayer("Layer A"));
+    /// collection.hide_by_id(id);
+    /// collection.remove_by_id(id);
+    ///
+    /// assert_eq!(*changes.lock().unwrap(), vec![
+    ///     LayerCollectionChange::Added(id),
+    ///     LayerCollectionChange::VisibilityChanged(id),
+    ///     LayerCollectionChange::Removed(id),
+    /// ]);
+    /// ```
+    pub fn set_change_callback(
+        &mut self,
+        callback: impl Fn(&LayerCollectionChange) + MaybeSend + MaybeSync + 'static,
+    ) {
+        self.on_change = Some(Box::new(callback));
+    }
+
+    /// Removes the callback set with [`LayerCollection::set_change_callback`].
+    pub fn remove_change_callback(&mut self) {
+        self.on_change = None;
+    }
+
+    fn notify(&self, change: LayerCollectionChange) {
+        if let Some(on_change) = &self.on_change {
+            on_change(&change);
+        }
+    }
 }
 
 impl Index<usize> for LayerCollection {
     type Output = dyn Layer;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &*self.0[index].layer
+        &*self.layers[index].layer
     }
 }
 
 impl IndexMut<usize> for LayerCollection {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut *self.0[index].layer
+        &mut *self.layers[index].layer
     }
 }
 
 impl<L: Into<LayerEntry>, T: IntoIterator<Item = L>> From<T> for LayerCollection {
     fn from(value: T) -> Self {
-        Self(value.into_iter().map(|layer| layer.into()).collect())
+        Self {
+            layers: value.into_iter().map(|layer| layer.into()).collect(),
+            on_change: None,
+        }
     }
 }
 
-impl<T: Layer + 'static> From<T> for LayerEntry {
-    fn from(value: T) -> Self {
+impl LayerEntry {
+    fn new(layer: Box<dyn Layer>) -> Self {
         Self {
-            layer: Box::new(value),
+            id: LayerId::next(),
+            name: None,
+            layer,
             is_hidden: false,
+            opacity: 1.0,
+            blend_mode: BlendMode::default(),
+            visibility_range: VisibilityRange::default(),
         }
     }
 }
 
+impl<T: Layer + 'static> From<T> for LayerEntry {
+    fn from(value: T) -> Self {
+        Self::new(Box::new(value))
+    }
+}
+
 impl From<Box<dyn Layer>> for LayerEntry {
     fn from(value: Box<dyn Layer>) -> Self {
-        Self {
-            layer: value,
-            is_hidden: false,
-        }
+        Self::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visibility_range_opacity() {
+        let range = VisibilityRange::default()
+            .with_min_resolution(1.0)
+            .with_max_resolution(100.0);
+        assert_eq!(range.opacity(0.5), 0.0);
+        assert_eq!(range.opacity(1.0), 0.0);
+        assert_eq!(range.opacity(1.5), 1.0);
+        assert_eq!(range.opacity(99.0), 1.0);
+        assert_eq!(range.opacity(100.0), 0.0);
+
+        let range = range.with_fade(1.0);
+        assert_eq!(range.opacity(1.0), 0.0);
+        assert!((range.opacity(2.0_f64.sqrt()) - 0.5).abs() < 1e-6);
+        assert_eq!(range.opacity(2.0), 1.0);
+        assert_eq!(range.opacity(50.0), 1.0);
+        assert!((range.opacity(50.0 * 2.0_f64.sqrt()) - 0.5).abs() < 1e-6);
+        assert_eq!(range.opacity(100.0), 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "_tests")]
+    fn group_is_composited_as_a_whole() {
+        use galileo_types::cartesian::Point2;
+
+        use crate::layer::TestLayer;
+
+        let mut group = LayerGroup::new(vec![TestLayer("A"), TestLayer("B"), TestLayer("C")]);
+        group.layers_mut().hide(1);
+        group.layers_mut().set_opacity(2, 0.5);
+        group.layers_mut().set_blend_mode(2, BlendMode::Screen);
+
+        let mut collection = LayerCollection::from(vec![TestLayer("D")]);
+        collection.push(group);
+        collection.set_opacity(1, 0.5);
+        collection.set_blend_mode(1, BlendMode::Multiply);
+
+        let view = MapView::new_projected(&Point2::new(0.0, 0.0), 1.0);
+        let settings = |layers: &LayerCollection| -> Vec<_> {
+            layers
+                .iter_visible_with_compositing(&view)
+                .map(|(layer, opacity, blend_mode)| {
+                    let name = layer.as_any().downcast_ref::<TestLayer>().map(|l| l.0);
+                    (name, opacity, blend_mode)
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            settings(&collection),
+            vec![
+                (Some("D"), 1.0, BlendMode::Normal),
+                (None, 0.5, BlendMode::Multiply),
+            ]
+        );
+
+        // Group settings are not applied to the children, as the group is composited once.
+        let group_layers = collection.group_layers(&[1]).unwrap();
+        assert_eq!(
+            settings(group_layers),
+            vec![
+                (Some("A"), 1.0, BlendMode::Normal),
+                (Some("C"), 0.5, BlendMode::Screen),
+            ]
+        );
+
+        collection.set_visibility_range(1, VisibilityRange::default().with_max_resolution(0.5));
+        assert_eq!(collection.iter_visible_with_compositing(&view).count(), 1);
     }
 }
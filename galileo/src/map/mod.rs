@@ -1,3 +1,4 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use galileo_types::cartesian::Size;
@@ -5,13 +6,14 @@ use web_time::SystemTime;
 
 use crate::layer::Layer;
 use crate::messenger::Messenger;
+use crate::terrain::Terrain;
 use crate::view::MapView;
 
 mod builder;
 mod layer_collection;
 
 pub use builder::MapBuilder;
-pub use layer_collection::LayerCollection;
+pub use layer_collection::{LayerCollection, LayerCollectionChange, LayerId, VisibilityRange};
 
 const FRAME_DURATION: Duration = Duration::from_millis(16);
 
@@ -21,6 +23,7 @@ pub struct Map {
     layers: LayerCollection,
     messenger: Option<Box<dyn Messenger>>,
     animation: Option<AnimationParameters>,
+    terrain: Option<Arc<dyn Terrain>>,
 }
 
 struct AnimationParameters {
@@ -42,6 +45,7 @@ impl Map {
             layers: layers.into(),
             messenger,
             animation: None,
+            terrain: None,
         }
     }
 
@@ -61,17 +65,23 @@ impl Map {
     }
 
     /// Changes the view of the map to the given one.
+    ///
+    /// The terrain of the map (if any) replaces the terrain of the given view.
     pub fn set_view(&mut self, view: MapView) {
-        self.view = view;
+        self.view = view.with_terrain(self.terrain.clone());
         if let Some(messenger) = &self.messenger {
             messenger.request_redraw();
         }
     }
 
-    /// Calls [`Layer::prepare`] method on all the layers with the current map view. Used to preload layer data before
-    /// the map is rendered.
+    /// Calls [`Layer::prepare`] method on all the layers visible with the current map view. Used to preload layer data
+    /// before the map is rendered.
     pub fn load_layers(&self) {
-        for layer in self.layers.iter_visible() {
+        if let Some(terrain) = &self.terrain {
+            terrain.prepare(&self.view);
+        }
+
+        for layer in self.layers.iter_visible_at(&self.view) {
             layer.prepare(&self.view);
         }
     }
@@ -120,12 +130,31 @@ impl Map {
     pub fn animate_to(&mut self, target: MapView, duration: Duration) {
         self.animation = Some(AnimationParameters {
             start_view: self.view.clone(),
-            end_view: target,
+            end_view: target.with_terrain(self.terrain.clone()),
             start_time: SystemTime::now() - FRAME_DURATION,
             duration,
         });
     }
 
+    /// Terrain that sets the elevation of the map surface.
+    pub fn terrain(&self) -> Option<&Arc<dyn Terrain>> {
+        self.terrain.as_ref()
+    }
+
+    /// Sets the terrain of the map. If `None` is given, the map is displayed as a flat surface.
+    ///
+    /// See [`terrain`](crate::terrain) module for details.
+    pub fn set_terrain(&mut self, terrain: Option<Arc<dyn Terrain>>) {
+        self.terrain = terrain;
+        self.view = self.view.with_terrain(self.terrain.clone());
+        if let Some(animation) = &mut self.animation {
+            animation.start_view = animation.start_view.with_terrain(self.terrain.clone());
+            animation.end_view = animation.end_view.with_terrain(self.terrain.clone());
+        }
+
+        self.redraw();
+    }
+
     /// Set the size of the map.
     pub fn set_size(&mut self, new_size: Size) {
         self.view = self.view.with_size(new_size);
@@ -1,5 +1,6 @@
 //! Provides platform specific logic and [`PlatformService`] to access it.
 
+use std::ops::Range;
 use std::sync::LazyLock;
 
 use async_trait::async_trait;
@@ -7,6 +8,33 @@ use bytes::Bytes;
 
 use crate::decoded_image::DecodedImage;
 use crate::error::GalileoError;
+use crate::layer::data_provider::{CacheMetadata, HttpRequest};
+
+/// Response to a request made with [`PlatformService::load_bytes_conditional`].
+#[derive(Debug, Clone)]
+pub enum ConditionalResponse {
+    /// The resource was loaded from the source.
+    Modified {
+        /// Loaded data.
+        data: Bytes,
+        /// Caching information of the response.
+        metadata: CacheMetadata,
+    },
+    /// The source confirmed that the cached version of the resource is still valid.
+    NotModified {
+        /// Caching information of the response.
+        metadata: CacheMetadata,
+    },
+}
+
+/// Response to a request made with [`PlatformService::load_bytes_partial`].
+#[derive(Debug, Clone)]
+pub enum PartialResponse {
+    /// The source responded with the requested part of the resource (`206 Partial Content`).
+    Partial(Bytes),
+    /// The source ignored the `Range` header and responded with the whole resource.
+    Full(Bytes),
+}
 
 /// Service providing some platform specific functions in a generic way.
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -20,6 +48,70 @@ pub trait PlatformService {
     /// Loads a byte array from the given url.
     async fn load_bytes_from_url(&self, url: &str) -> Result<bytes::Bytes, GalileoError>;
 
+    /// Loads a byte array with the given request, sending its headers.
+    ///
+    /// The default implementation ignores the headers and the timeout of the request and loads
+    /// the data with [`PlatformService::load_bytes_from_url`].
+    async fn load_bytes(&self, request: &HttpRequest) -> Result<bytes::Bytes, GalileoError> {
+        self.load_bytes_from_url(&request.url).await
+    }
+
+    /// Loads a byte array with the given request together with the caching headers of the
+    /// response.
+    ///
+    /// If `cached` metadata is given, the request is made conditional on its `ETag` and
+    /// `Last-Modified` values, so the source can respond with
+    /// [`ConditionalResponse::NotModified`] instead of sending the data again.
+    ///
+    /// The default implementation always loads the data with [`PlatformService::load_bytes`].
+    async fn load_bytes_conditional(
+        &self,
+        request: &HttpRequest,
+        _cached: Option<&CacheMetadata>,
+    ) -> Result<ConditionalResponse, GalileoError> {
+        let data = self.load_bytes(request).await?;
+        Ok(ConditionalResponse::Modified {
+            data,
+            metadata: CacheMetadata::new(web_time::SystemTime::now()),
+        })
+    }
+
+    /// Loads a byte array with the given request that has a `Range` header, telling whether the
+    /// source responded with the requested part of the resource or with the whole of it.
+    ///
+    /// The default implementation loads the data with [`PlatformService::load_bytes`] and treats
+    /// it as the whole resource. Implementations that send the request headers must override this
+    /// method, otherwise the requested range is cut out of the already partial data.
+    async fn load_bytes_partial(
+        &self,
+        request: &HttpRequest,
+    ) -> Result<PartialResponse, GalileoError> {
+        Ok(PartialResponse::Full(self.load_bytes(request).await?))
+    }
+
+    /// Loads the given range of bytes of a resource using an HTTP `Range` header.
+    ///
+    /// The returned data can be shorter than requested if the range goes beyond the end of the
+    /// resource. If the source ignores the `Range` header and responds with the whole resource,
+    /// the requested range is cut out of the response.
+    async fn load_bytes_range(
+        &self,
+        request: &HttpRequest,
+        range: Range<u64>,
+    ) -> Result<bytes::Bytes, GalileoError> {
+        if range.is_empty() {
+            return Ok(Bytes::new());
+        }
+
+        let request = request
+            .clone()
+            .with_header("Range", format!("bytes={}-{}", range.start, range.end - 1));
+        match self.load_bytes_partial(&request).await? {
+            PartialResponse::Partial(data) => Ok(data),
+            PartialResponse::Full(data) => Ok(slice_range(&data, range)),
+        }
+    }
+
     /// Decodes an image from raw byte data
     ///
     /// Raw bytes may contain in any supported format. The list of formats depends on the platform.
@@ -43,9 +135,59 @@ pub mod web;
 /// Default implementation of the [`PlatformService`] for the current platform.
 pub type PlatformServiceImpl = web::WebPlatformService;
 
+/// Cuts the given range out of the data. Parts of the range beyond the end of the data are
+/// ignored.
+pub(crate) fn slice_range(data: &Bytes, range: Range<u64>) -> Bytes {
+    let len = data.len() as u64;
+    data.slice(range.start.min(len) as usize..range.end.min(len) as usize)
+}
+
 static SERVICE: LazyLock<PlatformServiceImpl> = LazyLock::new(PlatformServiceImpl::new);
 
 /// Returns the singleton instance of the platform service
 pub fn instance() -> &'static PlatformServiceImpl {
     &SERVICE
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Service of a server that ignores the `Range` header.
+    struct FullResponseService(Bytes);
+
+    #[async_trait]
+    impl PlatformService for FullResponseService {
+        fn new() -> Self {
+            Self(Bytes::new())
+        }
+
+        async fn load_image_url(&self, _url: &str) -> Result<DecodedImage, GalileoError> {
+            Err(GalileoError::IO)
+        }
+
+        async fn load_bytes_from_url(&self, _url: &str) -> Result<Bytes, GalileoError> {
+            Ok(self.0.clone())
+        }
+
+        async fn decode_image(&self, _image_data: Bytes) -> Result<DecodedImage, GalileoError> {
+            Err(GalileoError::IO)
+        }
+    }
+
+    #[tokio::test]
+    async fn range_is_cut_out_of_small_full_response() {
+        let data: Vec<u8> = (0..100).collect();
+        let service = FullResponseService(data.into());
+        let request = HttpRequest::new("https://example.com/small.tif");
+
+        let tail = service.load_bytes_range(&request, 60..160).await.unwrap();
+        assert_eq!(&tail[..], &(60..100).collect::<Vec<u8>>()[..]);
+
+        let middle = service.load_bytes_range(&request, 10..20).await.unwrap();
+        assert_eq!(&middle[..], &(10..20).collect::<Vec<u8>>()[..]);
+
+        let beyond = service.load_bytes_range(&request, 200..300).await.unwrap();
+        assert!(beyond.is_empty());
+    }
+}
@@ -6,7 +6,8 @@ use log::info;
 
 use crate::decoded_image::DecodedImage;
 use crate::error::GalileoError;
-use crate::platform::PlatformService;
+use crate::layer::data_provider::{CacheMetadata, HttpRequest};
+use crate::platform::{ConditionalResponse, PartialResponse, PlatformService};
 
 pub mod vt_processor;
 
@@ -36,14 +37,95 @@ impl PlatformService for NativePlatformService {
         self.load_from_web(url).await
     }
 
+    async fn load_bytes(&self, request: &HttpRequest) -> Result<Bytes, GalileoError> {
+        let response = self.request_builder(request).send().await?;
+        Self::read_response(&request.url, response).await
+    }
+
+    async fn load_bytes_conditional(
+        &self,
+        request: &HttpRequest,
+        cached: Option<&CacheMetadata>,
+    ) -> Result<ConditionalResponse, GalileoError> {
+        use reqwest::header::{
+            CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        };
+
+        let mut builder = self.request_builder(request);
+        if let Some(cached) = cached {
+            if let Some(etag) = &cached.etag {
+                builder = builder.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = builder.send().await?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let metadata =
+            CacheMetadata::from_headers(header(ETAG), header(LAST_MODIFIED), header(CACHE_CONTROL));
+
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(ConditionalResponse::NotModified { metadata });
+        }
+
+        let data = Self::read_response(&request.url, response).await?;
+        Ok(ConditionalResponse::Modified { data, metadata })
+    }
+
+    async fn load_bytes_partial(
+        &self,
+        request: &HttpRequest,
+    ) -> Result<PartialResponse, GalileoError> {
+        let response = self.request_builder(request).send().await?;
+        let is_partial = response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+            && response
+                .headers()
+                .contains_key(reqwest::header::CONTENT_RANGE);
+
+        let data = Self::read_response(&request.url, response).await?;
+        if is_partial {
+            Ok(PartialResponse::Partial(data))
+        } else {
+            Ok(PartialResponse::Full(data))
+        }
+    }
+
     async fn decode_image(&self, image_data: Bytes) -> Result<DecodedImage, GalileoError> {
         DecodedImage::decode(&image_data)
     }
 }
 
 impl NativePlatformService {
+    fn request_builder(&self, request: &HttpRequest) -> reqwest::RequestBuilder {
+        let mut builder = self.http_client.get(&request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        builder
+    }
+
     async fn load_from_web(&self, url: &str) -> Result<Bytes, GalileoError> {
         let response = self.http_client.get(url).send().await?;
+        Self::read_response(url, response).await
+    }
+
+    async fn read_response(url: &str, response: reqwest::Response) -> Result<Bytes, GalileoError> {
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            info!("Failed to load {url}: resource does not exist");
+            return Err(GalileoError::NotFound);
+        }
+
         if !response.status().is_success() {
             info!(
                 "Failed to load {url}: {}, {:?}",
@@ -5,6 +5,7 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use galileo_mvt::MvtTile;
+use galileo_types::geo::Crs;
 use parking_lot::RwLock;
 
 use crate::layer::vector_tile_layer::style::VectorTileStyle;
@@ -55,6 +56,28 @@ impl VectorTileProcessor for ThreadVtProcessor {
         tile: Arc<MvtTile>,
         index: TileIndex,
         style_id: VtStyleId,
+    ) -> Result<RenderBundle, TileProcessingError> {
+        self.process(tile, index, style_id, None).await
+    }
+
+    async fn process_tile_in_crs(
+        &self,
+        tile: Arc<MvtTile>,
+        index: TileIndex,
+        style_id: VtStyleId,
+        target_crs: Crs,
+    ) -> Result<RenderBundle, TileProcessingError> {
+        self.process(tile, index, style_id, Some(target_crs)).await
+    }
+}
+
+impl ThreadVtProcessor {
+    async fn process(
+        &self,
+        tile: Arc<MvtTile>,
+        index: TileIndex,
+        style_id: VtStyleId,
+        target_crs: Option<Crs>,
     ) -> Result<RenderBundle, TileProcessingError> {
         // todo: remove clone here
         let Some(style) = self.styles.read().get(&style_id).cloned() else {
@@ -71,8 +94,18 @@ impl VectorTileProcessor for ThreadVtProcessor {
                 "Added worker: {}",
                 COUNTER.fetch_add(1, Ordering::Relaxed) + 1
             );
-            let result = match VtProcessor::prepare(&tile, &mut bundle, index, &style, &tile_schema)
-            {
+            let prepared = match &target_crs {
+                Some(crs) => VtProcessor::prepare_in_crs(
+                    &tile,
+                    &mut bundle,
+                    index,
+                    &style,
+                    &tile_schema,
+                    crs,
+                ),
+                None => VtProcessor::prepare(&tile, &mut bundle, index, &style, &tile_schema),
+            };
+            let result = match prepared {
                 Ok(()) => Ok(bundle),
                 Err(_) => Err(TileProcessingError::Rendering),
             };
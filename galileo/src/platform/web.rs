@@ -14,12 +14,14 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    Blob, HtmlImageElement, Request, RequestInit, RequestMode, Response, WorkerGlobalScope,
+    AbortController, Blob, HtmlImageElement, Request, RequestInit, RequestMode, Response,
+    WorkerGlobalScope,
 };
 
 use crate::decoded_image::{DecodedImage, DecodedImageType};
 use crate::error::GalileoError;
-use crate::platform::PlatformService;
+use crate::layer::data_provider::{CacheMetadata, HttpRequest};
+use crate::platform::{ConditionalResponse, PartialResponse, PlatformService};
 
 pub mod vt_processor;
 pub mod web_workers;
@@ -63,36 +65,145 @@ impl PlatformService for WebPlatformService {
     }
 
     async fn load_bytes_from_url(&self, url: &str) -> Result<bytes::Bytes, GalileoError> {
-        let opts = RequestInit::new();
-        opts.set_method("GET");
-        opts.set_mode(RequestMode::Cors);
-
-        let request =
-            Request::new_with_str_and_init(url, &opts).expect("failed to create a request object");
-        request
-            .headers()
-            .set("Accept", "application/vnd.mapbox-vector-tile")?;
-
-        use wasm_bindgen::JsCast;
-        let resp_value = {
-            if let Some(window) = web_sys::window() {
-                JsFuture::from(window.fetch_with_request(&request)).await?
-            } else if let Ok(global) = js_sys::global().dyn_into::<WorkerGlobalScope>() {
-                JsFuture::from(global.fetch_with_request(&request)).await?
-            } else {
-                return Err(GalileoError::Wasm(Some(
-                    "Global object is not available".into(),
-                )));
+        self.load_bytes(&HttpRequest::new(url)).await
+    }
+
+    async fn load_bytes(&self, request: &HttpRequest) -> Result<Bytes, GalileoError> {
+        let resp = fetch(request, &[]).await?;
+        read_response(resp).await
+    }
+
+    async fn load_bytes_conditional(
+        &self,
+        request: &HttpRequest,
+        cached: Option<&CacheMetadata>,
+    ) -> Result<ConditionalResponse, GalileoError> {
+        let mut headers = vec![];
+        if let Some(cached) = cached {
+            if let Some(etag) = &cached.etag {
+                headers.push(("If-None-Match", etag.as_str()));
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                headers.push(("If-Modified-Since", last_modified.as_str()));
+            }
+        }
+
+        let resp = fetch(request, &headers).await?;
+        let header = |name| resp.headers().get(name).ok().flatten();
+        let etag = header("ETag");
+        let last_modified = header("Last-Modified");
+        let cache_control = header("Cache-Control");
+        let metadata = CacheMetadata::from_headers(
+            etag.as_deref(),
+            last_modified.as_deref(),
+            cache_control.as_deref(),
+        );
+
+        if resp.status() == 304 {
+            return Ok(ConditionalResponse::NotModified { metadata });
+        }
+
+        let data = read_response(resp).await?;
+        Ok(ConditionalResponse::Modified { data, metadata })
+    }
+
+    async fn load_bytes_partial(
+        &self,
+        request: &HttpRequest,
+    ) -> Result<PartialResponse, GalileoError> {
+        let resp = fetch(request, &[]).await?;
+        // `Content-Range` is not available to scripts for cross-origin requests unless the server
+        // exposes it, so only the status is checked.
+        let is_partial = resp.status() == 206;
+
+        let data = read_response(resp).await?;
+        if is_partial {
+            Ok(PartialResponse::Partial(data))
+        } else {
+            Ok(PartialResponse::Full(data))
+        }
+    }
+}
+
+async fn fetch(
+    http_request: &HttpRequest,
+    extra_headers: &[(&str, &str)],
+) -> Result<Response, GalileoError> {
+    let opts = RequestInit::new();
+    opts.set_method("GET");
+    opts.set_mode(RequestMode::Cors);
+
+    // Browsers do not allow setting the `Referer` header directly, but it can be set as the
+    // referrer of the request.
+    if let Some(referrer) = http_request.header("Referer") {
+        opts.set_referrer(referrer);
+    }
+
+    let abort_controller = match http_request.timeout {
+        Some(_) => {
+            let controller = AbortController::new()?;
+            opts.set_signal(Some(&controller.signal()));
+            Some(controller)
+        }
+        None => None,
+    };
+
+    let request = Request::new_with_str_and_init(&http_request.url, &opts)
+        .expect("failed to create a request object");
+    request
+        .headers()
+        .set("Accept", "application/vnd.mapbox-vector-tile")?;
+    for (name, value) in &http_request.headers {
+        if !name.eq_ignore_ascii_case("Referer") {
+            request.headers().set(name, value)?;
+        }
+    }
+    for (name, value) in extra_headers {
+        request.headers().set(name, value)?;
+    }
+
+    let promise = if let Some(window) = web_sys::window() {
+        window.fetch_with_request(&request)
+    } else if let Ok(global) = js_sys::global().dyn_into::<WorkerGlobalScope>() {
+        global.fetch_with_request(&request)
+    } else {
+        return Err(GalileoError::Wasm(Some(
+            "Global object is not available".into(),
+        )));
+    };
+
+    let resp_value = match (http_request.timeout, abort_controller) {
+        (Some(timeout), Some(controller)) => {
+            let fetch = std::pin::pin!(JsFuture::from(promise));
+            let timer = std::pin::pin!(crate::async_runtime::sleep(timeout));
+            match futures::future::select(fetch, timer).await {
+                futures::future::Either::Left((result, _)) => result?,
+                futures::future::Either::Right(_) => {
+                    controller.abort();
+                    log::info!("Request to {} timed out", http_request.url);
+                    return Err(GalileoError::IO);
+                }
             }
-        };
+        }
+        _ => JsFuture::from(promise).await?,
+    };
 
-        assert!(resp_value.is_instance_of::<Response>());
-        let resp: Response = resp_value.dyn_into()?;
+    assert!(resp_value.is_instance_of::<Response>());
+    Ok(resp_value.dyn_into()?)
+}
+
+async fn read_response(resp: Response) -> Result<Bytes, GalileoError> {
+    if resp.status() == 404 {
+        return Err(GalileoError::NotFound);
+    }
 
-        let bytes_val = JsFuture::from(resp.array_buffer()?).await?;
-        let array = Uint8Array::new(&bytes_val);
-        Ok(array.to_vec().into())
+    if !resp.ok() {
+        return Err(GalileoError::IO);
     }
+
+    let bytes_val = JsFuture::from(resp.array_buffer()?).await?;
+    let array = Uint8Array::new(&bytes_val);
+    Ok(array.to_vec().into())
 }
 
 /// Future for getting image with browser API
@@ -7,6 +7,7 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use galileo_mvt::MvtTile;
+use galileo_types::geo::Crs;
 
 use crate::layer::vector_tile_layer::style::VectorTileStyle;
 use crate::layer::vector_tile_layer::tile_provider::processor::{
@@ -59,13 +60,35 @@ impl VectorTileProcessor for WebWorkerVtProcessor {
         tile: Arc<MvtTile>,
         index: TileIndex,
         style_id: VtStyleId,
+    ) -> Result<RenderBundle, TileProcessingError> {
+        self.process(tile, index, style_id, None).await
+    }
+
+    async fn process_tile_in_crs(
+        &self,
+        tile: Arc<MvtTile>,
+        index: TileIndex,
+        style_id: VtStyleId,
+        target_crs: Crs,
+    ) -> Result<RenderBundle, TileProcessingError> {
+        self.process(tile, index, style_id, Some(target_crs)).await
+    }
+}
+
+impl WebWorkerVtProcessor {
+    async fn process(
+        &self,
+        tile: Arc<MvtTile>,
+        index: TileIndex,
+        style_id: VtStyleId,
+        target_crs: Option<Crs>,
     ) -> Result<RenderBundle, TileProcessingError> {
         let Some(style) = self.get_style(style_id) else {
             return Err(TileProcessingError::InvalidStyle);
         };
 
         self.ww_service
-            .process_vt_tile(tile, index, style, self.tile_schema.clone())
+            .process_vt_tile(tile, index, style, self.tile_schema.clone(), target_crs)
             .await
     }
 }
@@ -11,6 +11,7 @@ use bytes::Bytes;
 use futures::channel::oneshot;
 use futures::channel::oneshot::Sender;
 use galileo_mvt::MvtTile;
+use galileo_types::geo::Crs;
 use serde::{Deserialize, Serialize};
 use tokio::sync::watch::Receiver;
 use wasm_bindgen::closure::Closure;
@@ -82,6 +83,7 @@ enum WebWorkerRequestPayload {
         index: TileIndex,
         style: VectorTileStyle,
         tile_schema: TileSchema,
+        target_crs: Option<Crs>,
     },
     LoadFont {
         font_data: Bytes,
@@ -153,6 +155,7 @@ impl WebWorkerService {
         index: TileIndex,
         style: Arc<VectorTileStyle>,
         tile_schema: TileSchema,
+        target_crs: Option<Crs>,
     ) -> Result<RenderBundle, TileProcessingError> {
         let response = self
             .request_operation(
@@ -161,6 +164,7 @@ impl WebWorkerService {
                     index,
                     style: (*style).clone(),
                     tile_schema,
+                    target_crs,
                 },
                 self.next_worker(),
             )
@@ -338,6 +342,7 @@ mod worker {
 
     use bytes::Bytes;
     use galileo_mvt::MvtTile;
+    use galileo_types::geo::Crs;
     use serde_bytes::ByteBuf;
     use wasm_bindgen::prelude::wasm_bindgen;
     use wasm_bindgen::{JsCast, JsValue};
@@ -439,7 +444,8 @@ mod worker {
                 index,
                 style,
                 tile_schema,
-            } => process_vt_tile(tile, index, style, tile_schema),
+                target_crs,
+            } => process_vt_tile(tile, index, style, tile_schema, target_crs),
             WebWorkerRequestPayload::LoadFont { font_data } => load_font(font_data),
         }
     }
@@ -465,9 +471,16 @@ mod worker {
         index: TileIndex,
         style: VectorTileStyle,
         tile_schema: TileSchema,
+        target_crs: Option<Crs>,
     ) -> WebWorkerResponsePayload {
         let mut bundle = RenderBundle::default();
-        let result = match VtProcessor::prepare(&tile, &mut bundle, index, &style, &tile_schema) {
+        let prepared = match &target_crs {
+            Some(crs) => {
+                VtProcessor::prepare_in_crs(&tile, &mut bundle, index, &style, &tile_schema, crs)
+            }
+            None => VtProcessor::prepare(&tile, &mut bundle, index, &style, &tile_schema),
+        };
+        let result = match prepared {
             Ok(()) => Ok(bundle),
             Err(_) => Err(TileProcessingError::Rendering),
         };
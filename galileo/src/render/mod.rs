@@ -6,7 +6,7 @@
 
 use std::any::Any;
 
-use galileo_types::cartesian::{Size, Vector2};
+use galileo_types::cartesian::{Point3, Size, Vector2};
 use maybe_sync::{MaybeSend, MaybeSync};
 use render_bundle::RenderBundle;
 use serde::{Deserialize, Serialize};
@@ -41,10 +41,34 @@ pub trait Canvas {
     fn pack_bundle(&self, bundle: &RenderBundle) -> Box<dyn PackedBundle>;
     /// Render the bundles.
     fn draw_bundles(&mut self, bundles: &[BundleToDraw], options: RenderOptions);
+    /// Render the bundles with the color adjustment applied to their images.
+    ///
+    /// The [`ColorAdjustment::opacity`] value is not applied by the canvas, it should be multiplied
+    /// into the bundle opacity by the caller. By default the adjustment is ignored and the bundles
+    /// are drawn with [`Canvas::draw_bundles`].
+    fn draw_bundles_with_color_adjustment(
+        &mut self,
+        bundles: &[BundleToDraw],
+        options: RenderOptions,
+        _color_adjustment: &ColorAdjustment,
+    ) {
+        self.draw_bundles(bundles, options);
+    }
     /// Render screen sets that were added previously by the `draw_bundles` calls.
     ///
     /// Returns `true` if canvas requires further animation (fading in or out some of the objects).
     fn draw_screen_sets(&mut self) -> bool;
+    /// Packs heatmap points to make them ready to be drawn with [`Canvas::draw_heatmap`] method.
+    ///
+    /// Returns `None` if the canvas cannot draw heatmaps, which is the default.
+    fn pack_heatmap(&self, _points: &HeatmapPoints) -> Option<Box<dyn PackedBundle>> {
+        None
+    }
+    /// Draws a heatmap of the points packed with [`Canvas::pack_heatmap`].
+    ///
+    /// The density of the points is accumulated in an offscreen buffer of the canvas size, and
+    /// then converted into colors with the color ramp of the `paint`. By default nothing is drawn.
+    fn draw_heatmap(&mut self, _points: &dyn PackedBundle, _paint: HeatmapPaint) {}
 }
 
 /// Packed render bundle ready to be drawn.
@@ -80,6 +104,27 @@ impl<'a> BundleToDraw<'a> {
     }
 }
 
+/// Way the colors of a layer are combined with the colors of the layers below it.
+///
+/// The formulas follow the W3C compositing specification. Colors of the layer are first combined
+/// with the colors below it using the blend mode, and the result is then drawn over the layers
+/// below with the layer opacity.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlendMode {
+    /// The layer is drawn over the layers below it.
+    #[default]
+    Normal,
+    /// Colors are multiplied. The result is always darker, white color does not change the colors
+    /// below. Useful for drawing hillshade over a basemap.
+    Multiply,
+    /// Inverted colors are multiplied. The result is always lighter, black color does not change
+    /// the colors below.
+    Screen,
+    /// Multiply for dark colors below the layer and screen for light colors. Preserves highlights
+    /// and shadows of the layers below.
+    Overlay,
+}
+
 /// Rendering options.
 #[derive(Debug, Copy, Clone)]
 pub struct RenderOptions {
@@ -140,3 +185,189 @@ pub struct ImagePaint {
     /// opacity and this value represented in percents.
     pub opacity: u8,
 }
+
+/// Color transformations applied to images when they are rendered.
+///
+/// The adjustments are applied by the GPU on every frame, so changing them does not require
+/// re-creating render bundles. The default value leaves the images unchanged.
+///
+/// The adjustments are applied in the following order: grayscale and saturation, hue rotation,
+/// contrast, brightness, invert, tint.
+///
+/// ```
+/// use galileo::render::ColorAdjustment;
+/// use galileo::Color;
+///
+/// // Muted basemap.
+/// let adjustment = ColorAdjustment {
+///     grayscale: 1.0,
+///     brightness: 1.2,
+///     contrast: 0.8,
+///     tint: Some(Color::rgba(200, 220, 255, 128)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorAdjustment {
+    /// Opacity multiplier in range `0.0..=1.0`.
+    pub opacity: f32,
+    /// Brightness multiplier. `1.0` leaves colors unchanged, `0.0` makes the image black.
+    pub brightness: f32,
+    /// Contrast multiplier. `1.0` leaves colors unchanged, `0.0` makes the image uniform gray.
+    pub contrast: f32,
+    /// Saturation multiplier. `1.0` leaves colors unchanged, `0.0` removes all colors, values
+    /// larger than `1.0` make colors more vivid.
+    pub saturation: f32,
+    /// Rotation of the hue in degrees.
+    pub hue_rotation: f32,
+    /// Amount of conversion to grayscale in range `0.0..=1.0`.
+    pub grayscale: f32,
+    /// Amount of color inversion in range `0.0..=1.0`. The value of `0.5` makes the image uniform
+    /// gray.
+    pub invert: f32,
+    /// Color the image is multiplied by. The alpha channel of the color sets the strength of the
+    /// tint.
+    pub tint: Option<Color>,
+}
+
+impl ColorAdjustment {
+    /// Adjustment that leaves the images unchanged.
+    pub const IDENTITY: Self = Self {
+        opacity: 1.0,
+        brightness: 1.0,
+        contrast: 1.0,
+        saturation: 1.0,
+        hue_rotation: 0.0,
+        grayscale: 0.0,
+        invert: 0.0,
+        tint: None,
+    };
+}
+
+impl Default for ColorAdjustment {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Points of a heatmap to be packed with [`Canvas::pack_heatmap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeatmapPoints {
+    /// Origin of the points in the map CRS. Positions of the points are stored as offsets from it,
+    /// so they stay precise in `f32` when the map coordinates are large.
+    pub origin: Point3,
+    /// Points of the heatmap.
+    pub points: Vec<HeatmapPoint>,
+}
+
+/// Point of [`HeatmapPoints`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HeatmapPoint {
+    /// Offset of the point from the [`HeatmapPoints::origin`] in map units.
+    pub position: [f32; 3],
+    /// Weight of the point. The density of a point with the weight of `1.0` is `1.0` in its
+    /// center.
+    pub weight: f32,
+}
+
+/// Parameters to draw a heatmap with.
+#[derive(Debug, Clone, Copy)]
+pub struct HeatmapPaint<'a> {
+    /// Radius of the kernel of a point in pixels. The density of a point decreases from its center
+    /// following the Gaussian function and becomes zero at this distance.
+    pub radius: f32,
+    /// Multiplier applied to the accumulated density before it is converted into a color.
+    pub intensity: f32,
+    /// Colors of the density values in range `0.0..=1.0`. Higher densities have the color of
+    /// `1.0`.
+    pub ramp: &'a ColorRamp,
+}
+
+/// Maps values in range `0.0..=1.0` to colors, interpolating linearly between the color stops.
+///
+/// ```
+/// use galileo::render::ColorRamp;
+/// use galileo::Color;
+///
+/// let ramp = ColorRamp::new([(0.0, Color::BLACK), (1.0, Color::WHITE)]);
+/// assert_eq!(ramp.color_at(0.5), Color::rgba(128, 128, 128, 255));
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorRamp {
+    stops: Vec<(f32, Color)>,
+}
+
+impl ColorRamp {
+    /// Creates a new ramp with the given `(value, color)` stops. Values below the first stop have
+    /// the color of the first stop, and values above the last stop have the color of the last one.
+    pub fn new(stops: impl IntoIterator<Item = (f32, Color)>) -> Self {
+        let mut stops: Vec<_> = stops.into_iter().collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self { stops }
+    }
+
+    /// Color stops of the ramp.
+    pub fn stops(&self) -> &[(f32, Color)] {
+        &self.stops
+    }
+
+    /// Returns the color of the value.
+    pub fn color_at(&self, value: f32) -> Color {
+        let index = self.stops.partition_point(|(stop, _)| *stop <= value);
+        if index == 0 {
+            return self
+                .stops
+                .first()
+                .map_or(Color::TRANSPARENT, |(_, color)| *color);
+        }
+        if index == self.stops.len() {
+            return self.stops[index - 1].1;
+        }
+
+        let (from_value, from_color) = self.stops[index - 1];
+        let (to_value, to_color) = self.stops[index];
+        let k = (value - from_value) / (to_value - from_value);
+        let from = from_color.to_u8_array();
+        let to = to_color.to_u8_array();
+        let channel =
+            |i: usize| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * k).round() as u8;
+
+        Color::rgba(channel(0), channel(1), channel(2), channel(3))
+    }
+}
+
+impl Default for ColorRamp {
+    /// Transparent for zero density, then blue, cyan, green, yellow and red for the highest
+    /// density.
+    fn default() -> Self {
+        Self::new([
+            (0.0, Color::rgba(0, 0, 255, 0)),
+            (0.2, Color::rgba(65, 105, 225, 255)),
+            (0.4, Color::rgba(0, 255, 255, 255)),
+            (0.6, Color::rgba(0, 255, 0, 255)),
+            (0.8, Color::rgba(255, 255, 0, 255)),
+            (1.0, Color::rgba(255, 0, 0, 255)),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_ramp_interpolates_between_stops() {
+        let ramp = ColorRamp::new([
+            (1.0, Color::rgba(0, 0, 255, 255)),
+            (0.0, Color::rgba(0, 0, 0, 0)),
+        ]);
+
+        assert_eq!(ramp.color_at(-1.0), Color::rgba(0, 0, 0, 0));
+        assert_eq!(ramp.color_at(0.25), Color::rgba(0, 0, 64, 64));
+        assert_eq!(ramp.color_at(1.0), Color::rgba(0, 0, 255, 255));
+        assert_eq!(ramp.color_at(2.0), Color::rgba(0, 0, 255, 255));
+    }
+}
@@ -44,7 +44,24 @@ impl RenderBundle {
     ) {
         self.world_set.add_image(image, vertices, paint);
     }
+
+    /// Adds an image to the bundle, stretching it over a mesh of points instead of a flat
+    /// quadrangle. This allows drawing an image in a projection different from the map projection
+    /// without visible distortions.
     ///
+    /// The `position` function is called for the nodes of a regular grid over the image. It
+    /// receives relative coordinates of the node in the image (`0.0..=1.0`, starting from the top
+    /// left corner) and returns the position of the node on the map. If it returns `None` for any
+    /// node, the image is not added and `false` is returned.
+    pub fn add_warped_image(
+        &mut self,
+        image: Arc<DecodedImage>,
+        paint: ImagePaint,
+        position: impl Fn(f64, f64) -> Option<Point2>,
+    ) -> bool {
+        self.world_set.add_warped_image(image, paint, position)
+    }
+
     /// Adds an image to the bundle.
     pub fn add_image_owned(
         &mut self,
@@ -34,11 +34,22 @@ pub(crate) struct WorldRenderSet {
     dpi_scale_factor: f32,
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+/// Number of cells along each side of the mesh of a warped image.
+pub(crate) const IMAGE_MESH_SIZE: u16 = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct ImageInfo {
     pub(crate) store_index: usize,
-    pub(crate) vertices: [ImageVertex; 4],
+    pub(crate) vertices: ImageVertices,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum ImageVertices {
+    /// Corners of the image in the order: bottom-left, top-left, bottom-right, top-right.
+    Quad([ImageVertex; 4]),
+    /// Vertices of the `IMAGE_MESH_SIZE * IMAGE_MESH_SIZE` cells mesh, row by row starting from
+    /// the top left corner.
+    Mesh(Vec<ImageVertex>),
 }
 
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
@@ -141,10 +152,45 @@ impl WorldRenderSet {
             },
         ];
 
-        self.add_image_info(index, vertices);
+        self.add_image_info(index, ImageVertices::Quad(vertices));
+    }
+
+    pub fn add_warped_image(
+        &mut self,
+        image: Arc<DecodedImage>,
+        paint: ImagePaint,
+        position: impl Fn(f64, f64) -> Option<Point2>,
+    ) -> bool {
+        let opacity = paint.opacity as f32 / 255.0;
+        let size = IMAGE_MESH_SIZE as usize + 1;
+
+        let mut vertices = Vec::with_capacity(size * size);
+        for row in 0..size {
+            let v = row as f64 / IMAGE_MESH_SIZE as f64;
+            for column in 0..size {
+                let u = column as f64 / IMAGE_MESH_SIZE as f64;
+                let Some(position) = position(u, v) else {
+                    return false;
+                };
+
+                vertices.push(ImageVertex {
+                    position: [position.x() as f32, position.y() as f32],
+                    opacity,
+                    tex_coords: [u as f32, v as f32],
+                    offset: [0.0, 0.0],
+                });
+            }
+        }
+
+        self.buffer_size += image.byte_size() + size_of::<ImageVertex>() * vertices.len();
+
+        let index = self.add_image_to_store(image);
+        self.add_image_info(index, ImageVertices::Mesh(vertices));
+
+        true
     }
 
-    fn add_image_info(&mut self, image_store_index: usize, vertices: [ImageVertex; 4]) -> usize {
+    fn add_image_info(&mut self, image_store_index: usize, vertices: ImageVertices) -> usize {
         let index = self.images.len();
         self.images.push(ImageInfo {
             store_index: image_store_index,
@@ -8,30 +8,37 @@ use std::time::Duration;
 use ahash::HashMap;
 use cfg_if::cfg_if;
 use effects::horizon::HorizonPipeline;
-use galileo_types::cartesian::{Rect, Size, Vector2};
+use galileo_types::cartesian::{CartesianPoint3d, Point2, Rect, Size, Vector2};
 use lyon::tessellation::VertexBuffers;
 use nalgebra::{Point4, Rotation3, Vector3};
 use parking_lot::Mutex;
 use wgpu::util::DeviceExt;
 use wgpu::{
-    Adapter, BindGroup, Buffer, BufferAddress, BufferDescriptor, BufferUsages, Device, Extent3d,
-    Origin3d, Queue, RenderPassDepthStencilAttachment, StoreOp, Surface, SurfaceConfiguration,
-    SurfaceError, SurfaceTexture, TexelCopyBufferInfo, TexelCopyBufferLayout, TexelCopyTextureInfo,
-    Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-    TextureView, TextureViewDescriptor, WasmNotSendSync, COPY_BYTES_PER_ROW_ALIGNMENT,
+    Adapter, BindGroup, Buffer, BufferAddress, BufferDescriptor, BufferUsages, CommandEncoder,
+    Device, Extent3d, Origin3d, Queue, RenderPassDepthStencilAttachment, StoreOp, Surface,
+    SurfaceConfiguration, SurfaceError, SurfaceTexture, TexelCopyBufferInfo, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor, WasmNotSendSync,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
 };
 
 use super::render_bundle::screen_set::{RenderSetState, ScreenSetData};
-use super::{BundleToDraw, Canvas, PackedBundle, RenderOptions};
+use super::{
+    BlendMode, BundleToDraw, Canvas, ColorAdjustment, HeatmapPaint, HeatmapPoints, PackedBundle,
+    RenderOptions,
+};
 use crate::decoded_image::DecodedImage;
 use crate::error::GalileoError;
+use crate::layer::{Layer, LayerGroup};
 use crate::map::Map;
 use crate::render::render_bundle::world_set::{PointInstance, PolyVertex, WorldRenderSet};
 use crate::render::render_bundle::RenderBundle;
+use crate::render::wgpu::pipelines::heatmap::DENSITY_TEXTURE_FORMAT;
 use crate::render::wgpu::pipelines::image::WgpuImage;
 use crate::render::wgpu::pipelines::Pipelines;
 use crate::view::MapView;
 use crate::Color;
+use crate::LayerCollection;
 
 mod effects;
 mod pipelines;
@@ -61,6 +68,27 @@ struct RendererTargets {
     stencil_view_multisample: TextureView,
     stencil_view: TextureView,
     horizon_effect: Option<HorizonPipeline>,
+    /// Targets for each level of nested composited layers.
+    layer_targets: Mutex<Vec<LayerTargets>>,
+    heatmap_targets: Mutex<Option<HeatmapTargets>>,
+}
+
+/// Textures a layer is rendered to before it is composited with the layers below it. Created when
+/// a layer with opacity or blend mode is rendered for the first time. Nested composited layers
+/// (like a composited layer inside a composited group) use separate targets.
+struct LayerTargets {
+    size: Size<u32>,
+    multisampling_view: TextureView,
+    layer_view: TextureView,
+    /// Resolved image of the layers below the composited layer.
+    backdrop_view: TextureView,
+}
+
+/// Texture the density of heatmap points is accumulated in. Created when a heatmap is drawn for
+/// the first time.
+struct HeatmapTargets {
+    size: Size<u32>,
+    density_view: TextureView,
 }
 
 enum RenderTarget {
@@ -189,12 +217,19 @@ impl WgpuRenderer {
                 stencil_view_multisample,
                 stencil_view,
                 horizon_effect,
+                layer_targets,
+                heatmap_targets,
             }) if new_target.size() == render_target.size() => {
-                let pipelines = if new_target.format() == render_target.format() {
-                    pipelines
-                } else {
-                    Pipelines::create(&self.device, new_target.format())
-                };
+                let (pipelines, layer_targets, heatmap_targets) =
+                    if new_target.format() == render_target.format() {
+                        (pipelines, layer_targets, heatmap_targets)
+                    } else {
+                        (
+                            Pipelines::create(&self.device, new_target.format()),
+                            Mutex::default(),
+                            Mutex::default(),
+                        )
+                    };
 
                 self.renderer_targets = Some(RendererTargets {
                     render_target: new_target,
@@ -203,6 +238,8 @@ impl WgpuRenderer {
                     stencil_view_multisample,
                     stencil_view,
                     horizon_effect,
+                    layer_targets,
+                    heatmap_targets,
                 })
             }
             _ => self.renderer_targets = Some(self.create_renderer_targets(new_target)),
@@ -235,9 +272,64 @@ impl WgpuRenderer {
             stencil_view_multisample,
             stencil_view,
             horizon_effect,
+            layer_targets: Mutex::default(),
+            heatmap_targets: Mutex::default(),
+        }
+    }
+
+    fn create_layer_targets(&self, renderer_targets: &RendererTargets) -> LayerTargets {
+        let size = renderer_targets.render_target.size();
+        let format = renderer_targets.render_target.format();
+        let create_texture = |label| {
+            self.device
+                .create_texture(&TextureDescriptor {
+                    label: Some(label),
+                    size: Extent3d {
+                        width: size.width(),
+                        height: size.height(),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&TextureViewDescriptor::default())
+        };
+
+        LayerTargets {
+            size,
+            multisampling_view: Self::create_multisample_texture(&self.device, size, format),
+            layer_view: create_texture("Layer texture"),
+            backdrop_view: create_texture("Backdrop texture"),
         }
     }
 
+    fn create_heatmap_targets(&self, renderer_targets: &RendererTargets) -> HeatmapTargets {
+        let size = renderer_targets.render_target.size();
+        let density_view = self
+            .device
+            .create_texture(&TextureDescriptor {
+                label: Some("Heatmap density texture"),
+                size: Extent3d {
+                    width: size.width(),
+                    height: size.height(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: DENSITY_TEXTURE_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&TextureViewDescriptor::default());
+
+        HeatmapTargets { size, density_view }
+    }
+
     /// Creates a new wgpu renderer that renders the map to the given window. The given size must be equal to the
     /// window size.
     ///
@@ -515,6 +607,8 @@ impl WgpuRenderer {
             renderer_targets.stencil_view_multisample =
                 Self::create_stencil_texture(&self.device, new_size, 4);
             renderer_targets.stencil_view = Self::create_stencil_texture(&self.device, new_size, 1);
+            renderer_targets.layer_targets.get_mut().clear();
+            *renderer_targets.heatmap_targets.get_mut() = None;
         }
     }
 
@@ -701,17 +795,25 @@ impl WgpuRenderer {
             return;
         };
 
+        let depth = Self::compositing_depth(map.layers(), view);
+        let mut layer_targets = renderer_targets.layer_targets.lock();
+        let size = renderer_targets.render_target.size();
+        layer_targets.retain(|targets| targets.size == size);
+        while layer_targets.len() < depth {
+            let targets = self.create_layer_targets(renderer_targets);
+            layer_targets.push(targets);
+        }
+
         let Some(mut canvas) = WgpuCanvas::new(self, renderer_targets, texture_view, view.clone())
         else {
             log::warn!("Layer cannot be rendered to the map view.");
             return;
         };
 
-        for layer in map.layers().iter_visible() {
-            layer.render(view, &mut canvas);
-        }
+        Self::render_layers(map.layers(), view, &mut canvas, &layer_targets);
 
         let needs_animation = canvas.draw_screen_sets();
+        canvas.finish();
         if needs_animation {
             map.redraw();
         }
@@ -719,6 +821,66 @@ impl WgpuRenderer {
         self.draw_horizon(view, renderer_targets, texture_view);
     }
 
+    /// Renders the visible layers of the collection. The children of the groups are rendered in
+    /// place of the group. A layer or a group with opacity or blend mode is rendered to the first
+    /// of the `layer_targets`, and its nested layers use the following ones.
+    fn render_layers<'a>(
+        layers: &LayerCollection,
+        view: &MapView,
+        canvas: &mut WgpuCanvas<'a>,
+        layer_targets: &'a [LayerTargets],
+    ) {
+        for (layer, opacity, blend_mode) in layers.iter_visible_with_compositing(view) {
+            if opacity <= 0.0 {
+                continue;
+            }
+
+            match layer_targets.split_first() {
+                Some((targets, nested_targets)) if Self::needs_compositing(opacity, blend_mode) => {
+                    canvas.begin_layer(targets);
+                    Self::render_layer(layer, view, canvas, nested_targets);
+                    canvas.end_layer(opacity, blend_mode);
+                }
+                _ => Self::render_layer(layer, view, canvas, layer_targets),
+            }
+        }
+    }
+
+    fn render_layer<'a>(
+        layer: &dyn Layer,
+        view: &MapView,
+        canvas: &mut WgpuCanvas<'a>,
+        layer_targets: &'a [LayerTargets],
+    ) {
+        match layer.as_any().downcast_ref::<LayerGroup>() {
+            Some(group) => Self::render_layers(group.layers(), view, canvas, layer_targets),
+            None => layer.render(view, canvas),
+        }
+    }
+
+    /// Returns the largest number of nested layers that must be composited, which is the number of
+    /// layer targets needed to render the collection.
+    fn compositing_depth(layers: &LayerCollection, view: &MapView) -> usize {
+        layers
+            .iter_visible_with_compositing(view)
+            .filter(|(_, opacity, _)| *opacity > 0.0)
+            .map(|(layer, opacity, blend_mode)| {
+                let nested = layer
+                    .as_any()
+                    .downcast_ref::<LayerGroup>()
+                    .map_or(0, |group| Self::compositing_depth(group.layers(), view));
+                nested + usize::from(Self::needs_compositing(opacity, blend_mode))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns true if the layer must be rendered to a separate texture and then composited with
+    /// the layers below it.
+    fn needs_compositing(opacity: f32, blend_mode: BlendMode) -> bool {
+        opacity < 1.0 || blend_mode != BlendMode::Normal
+    }
+
     /// Returns options of the horizon effect used by the renderer.
     pub fn horizon_options(&self) -> &Option<HorizonOptions> {
         &self.horizon_options
@@ -838,8 +1000,18 @@ struct WgpuCanvas<'a> {
     renderer_targets: &'a RendererTargets,
     view: &'a TextureView,
     map_view: MapView,
+    /// Targets of the layers that are being rendered to separate textures, starting from the
+    /// outermost one.
+    layer_targets: Vec<&'a LayerTargets>,
+    /// All draw calls of the frame are recorded into this encoder and submitted together by
+    /// [`WgpuCanvas::finish`].
+    encoder: CommandEncoder,
 
     screen_sets: Vec<(Arc<Mutex<WgpuScreenSet>>, f32, Vector2<f32>)>,
+    /// Bounding boxes of the screen sets already displayed in this frame. Screen sets of the
+    /// following layers are hidden if they overlap these boxes.
+    displayed_screen_sets: Vec<Rect<f32>>,
+    is_animating: bool,
 }
 
 impl<'a> WgpuCanvas<'a> {
@@ -870,131 +1042,140 @@ impl<'a> WgpuCanvas<'a> {
             }]),
         );
 
+        if renderer_targets
+            .pipelines
+            .replace_terrain_key(TerrainKey::new(&map_view))
+        {
+            let (terrain, heightmap) =
+                terrain_heightmap(&map_view).unwrap_or((TerrainUniform::DISABLED, vec![]));
+            renderer_targets
+                .pipelines
+                .write_terrain(&renderer.queue, terrain, &heightmap);
+        }
+
+        let encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
         Some(Self {
             renderer,
             renderer_targets,
             view,
             map_view,
+            layer_targets: vec![],
+            encoder,
             screen_sets: vec![],
+            displayed_screen_sets: vec![],
+            is_animating: false,
         })
     }
-}
-
-impl Canvas for WgpuCanvas<'_> {
-    fn size(&self) -> Size {
-        self.renderer.size()
-    }
 
-    fn pack_bundle(&self, bundle: &RenderBundle) -> Box<dyn PackedBundle> {
-        Box::new(WgpuPackedBundle::new(
-            bundle,
-            self.renderer,
-            self.renderer_targets,
-        ))
+    /// Submits all draw calls recorded by the canvas.
+    fn finish(self) {
+        self.renderer
+            .queue
+            .submit(std::iter::once(self.encoder.finish()));
     }
 
-    fn draw_bundles(&mut self, bundles: &[super::BundleToDraw], options: RenderOptions) {
-        if bundles.is_empty() {
-            log::debug!("Requested drawing of 0 bundles");
-            return;
+    /// Returns the multisampled view and the resolve target the draw calls currently go to.
+    fn target_views(&self) -> (&'a TextureView, &'a TextureView) {
+        match self.layer_targets.last() {
+            Some(targets) => (&targets.multisampling_view, &targets.layer_view),
+            None => (&self.renderer_targets.multisampling_view, self.view),
         }
+    }
 
-        let mut encoder =
-            self.renderer
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
-                });
+    /// Starts rendering of a layer to the given separate targets. All following draw calls are
+    /// done to these targets until [`WgpuCanvas::end_layer`] is called.
+    fn begin_layer(&mut self, targets: &'a LayerTargets) {
+        self.draw_pending_screen_sets();
+
+        let _ = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Layer Clear Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &targets.multisampling_view,
+                resolve_target: Some(&targets.layer_view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
 
-        {
-            let (view, resolve_target, depth_view) = if options.antialias {
-                (
-                    &self.renderer_targets.multisampling_view,
-                    Some(self.view),
-                    &self.renderer_targets.stencil_view_multisample,
-                )
-            } else {
-                (self.view, None, &self.renderer_targets.stencil_view)
-            };
+        self.layer_targets.push(targets);
+    }
 
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view,
-                    resolve_target,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: depth_view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: StoreOp::Discard,
-                    }),
-                    stencil_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(0),
-                        store: StoreOp::Discard,
-                    }),
-                }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+    /// Finishes rendering of the layer started with [`WgpuCanvas::begin_layer`] and draws the
+    /// layer image over the layers below it.
+    ///
+    /// Screen sets of the layer are drawn into the layer image before it is composited, so they
+    /// get the opacity and the blend mode of the layer too.
+    fn end_layer(&mut self, opacity: f32, blend_mode: BlendMode) {
+        self.draw_pending_screen_sets();
 
-            let display_instances: Vec<_> = bundles
-                .iter()
-                .map(
-                    |BundleToDraw {
-                         opacity, offset, ..
-                     }| DisplayInstance {
-                        opacity: *opacity,
-                        offset: [offset.dx(), offset.dy(), 0.0],
-                    },
-                )
-                .collect();
+        let Some(targets) = self.layer_targets.pop() else {
+            return;
+        };
+        let (multisampling_view, target_view) = self.target_views();
+
+        // Resolving the multisampled target gives the image of the layers below.
+        let _ = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Backdrop Resolve Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: multisampling_view,
+                resolve_target: Some(&targets.backdrop_view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
 
-            let display_buffer =
-                self.renderer
-                    .device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: None,
-                        usage: wgpu::BufferUsages::VERTEX,
-                        contents: bytemuck::cast_slice(&display_instances),
-                    });
-            render_pass.set_vertex_buffer(1, display_buffer.slice(..));
+        let pipeline = self.renderer_targets.pipelines.composite_pipeline();
+        let binding = pipeline.create_bind_group(
+            &self.renderer.device,
+            &targets.layer_view,
+            &targets.backdrop_view,
+            opacity,
+            blend_mode,
+        );
 
-            for (
-                index,
-                BundleToDraw {
-                    bundle,
-                    opacity,
-                    offset,
+        let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Layer Composite Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: multisampling_view,
+                resolve_target: Some(target_view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
                 },
-            ) in bundles.iter().enumerate()
-            {
-                if let Some(cast) = bundle.as_any().downcast_ref::<WgpuPackedBundle>() {
-                    self.renderer_targets.pipelines.render(
-                        &mut render_pass,
-                        cast,
-                        options,
-                        index as u32,
-                    );
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
 
-                    for screen_set in &cast.screen_sets {
-                        self.screen_sets
-                            .push((screen_set.clone(), *opacity, *offset));
-                    }
-                }
-            }
-        }
+        pipeline.render(&mut render_pass, &binding);
+    }
 
-        self.renderer
-            .queue
-            .submit(std::iter::once(encoder.finish()));
+    /// Draws the screen sets collected since the last call to the current target.
+    fn draw_pending_screen_sets(&mut self) {
+        if self.render_screen_sets() {
+            self.is_animating = true;
+        }
     }
 
-    fn draw_screen_sets(&mut self) -> bool {
+    /// Draws the screen sets collected by `draw_bundles` calls to the current target. Returns true
+    /// if some of the sets are being animated.
+    fn render_screen_sets(&mut self) -> bool {
         if self.screen_sets.is_empty() {
             return false;
         }
@@ -1044,7 +1225,7 @@ impl Canvas for WgpuCanvas<'_> {
         });
 
         let now = web_time::Instant::now();
-        let mut displayed: Vec<Rect<f32>> = vec![];
+        let displayed = &mut self.displayed_screen_sets;
         let mut filtered_sets: Vec<_> = sets
             .into_iter()
             .filter_map(|(mut set, anchor, offset)| {
@@ -1105,19 +1286,13 @@ impl Canvas for WgpuCanvas<'_> {
             .collect();
 
         let mut is_animating = false;
-        let mut encoder =
-            self.renderer
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
-                });
+        let (view, resolve_target) = self.target_views();
 
         {
-            let view = &self.renderer_targets.multisampling_view;
-            let resolve_target = Some(self.view);
+            let resolve_target = Some(resolve_target);
             let depth_view = &self.renderer_targets.stencil_view_multisample;
 
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view,
@@ -1217,14 +1392,252 @@ impl Canvas for WgpuCanvas<'_> {
             }
         }
 
-        self.renderer
-            .queue
-            .submit(std::iter::once(encoder.finish()));
-
         is_animating
     }
 }
 
+impl Canvas for WgpuCanvas<'_> {
+    fn size(&self) -> Size {
+        self.renderer.size()
+    }
+
+    fn pack_bundle(&self, bundle: &RenderBundle) -> Box<dyn PackedBundle> {
+        Box::new(WgpuPackedBundle::new(
+            bundle,
+            self.renderer,
+            self.renderer_targets,
+        ))
+    }
+
+    fn draw_bundles(&mut self, bundles: &[super::BundleToDraw], options: RenderOptions) {
+        self.draw_bundles_with_color_adjustment(bundles, options, &ColorAdjustment::IDENTITY);
+    }
+
+    fn draw_bundles_with_color_adjustment(
+        &mut self,
+        bundles: &[BundleToDraw],
+        options: RenderOptions,
+        color_adjustment: &ColorAdjustment,
+    ) {
+        if bundles.is_empty() {
+            log::debug!("Requested drawing of 0 bundles");
+            return;
+        }
+
+        let color_adjustment_binding =
+            (*color_adjustment != ColorAdjustment::IDENTITY).then(|| {
+                self.renderer_targets
+                    .pipelines
+                    .create_color_adjustment_binding(&self.renderer.device, color_adjustment)
+            });
+        let color_adjustment_binding = color_adjustment_binding
+            .as_ref()
+            .unwrap_or(self.renderer_targets.pipelines.identity_color_adjustment());
+
+        {
+            let (multisampling_view, target_view) = self.target_views();
+            let (view, resolve_target, depth_view) = if options.antialias {
+                (
+                    multisampling_view,
+                    Some(target_view),
+                    &self.renderer_targets.stencil_view_multisample,
+                )
+            } else {
+                (target_view, None, &self.renderer_targets.stencil_view)
+            };
+
+            let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: StoreOp::Discard,
+                    }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: StoreOp::Discard,
+                    }),
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            let display_instances: Vec<_> = bundles
+                .iter()
+                .map(
+                    |BundleToDraw {
+                         opacity, offset, ..
+                     }| DisplayInstance {
+                        opacity: *opacity,
+                        offset: [offset.dx(), offset.dy(), 0.0],
+                    },
+                )
+                .collect();
+
+            let display_buffer =
+                self.renderer
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: None,
+                        usage: wgpu::BufferUsages::VERTEX,
+                        contents: bytemuck::cast_slice(&display_instances),
+                    });
+            render_pass.set_vertex_buffer(1, display_buffer.slice(..));
+
+            for (
+                index,
+                BundleToDraw {
+                    bundle,
+                    opacity,
+                    offset,
+                    ..
+                },
+            ) in bundles.iter().enumerate()
+            {
+                if let Some(cast) = bundle.as_any().downcast_ref::<WgpuPackedBundle>() {
+                    self.renderer_targets.pipelines.render(
+                        &mut render_pass,
+                        cast,
+                        options,
+                        color_adjustment_binding,
+                        index as u32,
+                    );
+
+                    for screen_set in &cast.screen_sets {
+                        self.screen_sets
+                            .push((screen_set.clone(), *opacity, *offset));
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw_screen_sets(&mut self) -> bool {
+        self.draw_pending_screen_sets();
+        self.is_animating
+    }
+
+    fn pack_heatmap(&self, points: &HeatmapPoints) -> Option<Box<dyn PackedBundle>> {
+        let buffer = self
+            .renderer
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Heatmap point buffer"),
+                usage: wgpu::BufferUsages::VERTEX,
+                contents: bytemuck::cast_slice(&points.points),
+            });
+
+        Some(Box::new(WgpuPackedHeatmap {
+            origin: [
+                points.origin.x() as f32,
+                points.origin.y() as f32,
+                points.origin.z() as f32,
+            ],
+            buffer,
+            point_count: points.points.len() as u32,
+        }))
+    }
+
+    fn draw_heatmap(&mut self, points: &dyn PackedBundle, paint: HeatmapPaint) {
+        let Some(points) = points.as_any().downcast_ref::<WgpuPackedHeatmap>() else {
+            log::warn!("Heatmap points were not packed by the wgpu canvas");
+            return;
+        };
+        if points.point_count == 0 {
+            return;
+        }
+
+        let mut heatmap_targets = self.renderer_targets.heatmap_targets.lock();
+        if heatmap_targets
+            .as_ref()
+            .is_none_or(|targets| targets.size != self.renderer_targets.render_target.size())
+        {
+            *heatmap_targets = Some(self.renderer.create_heatmap_targets(self.renderer_targets));
+        }
+        let Some(targets) = heatmap_targets.as_ref() else {
+            return;
+        };
+
+        let pipeline = self.renderer_targets.pipelines.heatmap_pipeline();
+        let binding = pipeline.create_binding(
+            &self.renderer.device,
+            &self.renderer.queue,
+            &paint,
+            points.origin,
+            &targets.density_view,
+        );
+
+        {
+            let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Heatmap Density Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &targets.density_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.renderer_targets
+                .pipelines
+                .set_bindings(&mut render_pass);
+            pipeline.render_density(
+                &mut render_pass,
+                &binding,
+                &points.buffer,
+                points.point_count,
+            );
+        }
+
+        {
+            let (multisampling_view, target_view) = self.target_views();
+            let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Heatmap Color Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: multisampling_view,
+                    resolve_target: Some(target_view),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pipeline.render_colors(&mut render_pass, &binding);
+        }
+    }
+}
+
+/// Heatmap points packed into a vertex buffer.
+struct WgpuPackedHeatmap {
+    origin: [f32; 3],
+    buffer: Buffer,
+    point_count: u32,
+}
+
+impl PackedBundle for WgpuPackedHeatmap {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 struct WgpuPackedBundle {
     clip_area_buffers: Option<WgpuVertexBuffers>,
     map_ref_buffers: WgpuVertexBuffers,
@@ -1426,6 +1839,122 @@ struct ViewUniform {
     _padding: [f32; 1],
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TerrainUniform {
+    bounds: [f32; 4],
+    size: [u32; 2],
+    enabled: u32,
+    _padding: [u32; 1],
+}
+
+/// Color adjustment of the images of a layer, shared by all bundles drawn in one
+/// [`Canvas::draw_bundles_with_color_adjustment`] call.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorAdjustmentUniform {
+    /// Brightness, contrast, saturation and hue rotation (in radians).
+    transform: [f32; 4],
+    /// Tint color with the tint strength as alpha.
+    tint: [f32; 4],
+    invert: f32,
+    _padding: [f32; 3],
+}
+
+impl From<&ColorAdjustment> for ColorAdjustmentUniform {
+    fn from(adjustment: &ColorAdjustment) -> Self {
+        Self {
+            transform: [
+                adjustment.brightness,
+                adjustment.contrast,
+                adjustment.saturation * (1.0 - adjustment.grayscale.clamp(0.0, 1.0)),
+                adjustment.hue_rotation.to_radians(),
+            ],
+            tint: adjustment
+                .tint
+                .map_or([1.0, 1.0, 1.0, 0.0], |tint| tint.to_f32_array()),
+            invert: adjustment.invert.clamp(0.0, 1.0),
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+impl TerrainUniform {
+    const DISABLED: Self = Self {
+        bounds: [0.0; 4],
+        size: [1, 1],
+        enabled: 0,
+        _padding: [0],
+    };
+}
+
+/// Identifies the terrain heightmap written for a view.
+///
+/// The heightmap is sampled again only when the terrain, its revision or the view bounding box
+/// changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TerrainKey {
+    terrain: usize,
+    revision: u64,
+    bbox: Option<Rect>,
+}
+
+impl TerrainKey {
+    /// Returns `None` if the terrain of the view does not report its revision, so the heightmap
+    /// must be sampled every frame.
+    fn new(map_view: &MapView) -> Option<Self> {
+        let Some(terrain) = map_view.terrain() else {
+            return Some(Self {
+                terrain: 0,
+                revision: 0,
+                bbox: None,
+            });
+        };
+
+        Some(Self {
+            terrain: Arc::as_ptr(terrain) as *const () as usize,
+            revision: terrain.revision()?,
+            bbox: map_view.get_bbox(),
+        })
+    }
+}
+
+/// Samples the elevation of the view terrain into a heightmap covering the view bounding box.
+///
+/// Returns `None` if the view has no terrain.
+fn terrain_heightmap(map_view: &MapView) -> Option<(TerrainUniform, Vec<f32>)> {
+    let terrain = map_view.terrain()?;
+    let bbox = map_view.get_bbox()?;
+
+    let size = pipelines::TERRAIN_HEIGHTMAP_SIZE;
+    let step_x = bbox.width() / (size - 1) as f64;
+    let step_y = bbox.height() / (size - 1) as f64;
+
+    let mut heightmap = Vec::with_capacity((size * size) as usize);
+    for row in 0..size {
+        let y = bbox.y_max() - row as f64 * step_y;
+        for column in 0..size {
+            let x = bbox.x_min() + column as f64 * step_x;
+            let elevation = terrain.elevation(&Point2::new(x, y)).unwrap_or(0.0);
+            heightmap.push(elevation as f32);
+        }
+    }
+
+    let uniform = TerrainUniform {
+        bounds: [
+            bbox.x_min() as f32,
+            bbox.y_min() as f32,
+            bbox.x_max() as f32,
+            bbox.y_max() as f32,
+        ],
+        size: [size, size],
+        enabled: 1,
+        _padding: [0],
+    };
+
+    Some((uniform, heightmap))
+}
+
 impl PointInstance {
     fn wgpu_desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
@@ -23,7 +23,8 @@ impl ClipPipeline {
         map_view_layout: &BindGroupLayout,
     ) -> Self {
         let buffers = [PolyVertex::wgpu_desc(), DisplayInstance::wgpu_desc()];
-        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/map_ref.wgsl"));
+        let shader =
+            device.create_shader_module(include_wgsl_with_terrain!("./shaders/map_ref.wgsl"));
 
         let clip_stencil_state = StencilFaceState {
             compare: CompareFunction::Never,
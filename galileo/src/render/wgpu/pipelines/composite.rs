@@ -0,0 +1,163 @@
+use wgpu::util::DeviceExt;
+use wgpu::{
+    BindGroup, BindGroupLayout, Device, RenderPass, RenderPipeline, TextureFormat, TextureView,
+};
+
+use crate::render::BlendMode;
+
+/// Draws the image of a layer rendered to a separate texture over the layers below it, using the
+/// opacity and the blend mode of the layer.
+pub struct CompositePipeline {
+    wgpu_pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+}
+
+impl CompositePipeline {
+    pub fn create(device: &Device, format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/composite.wgsl"));
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Composite bind group layout"),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // The result replaces the content of the target, as it is already mixed with the backdrop
+        // by the shader.
+        let targets = [Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        })];
+        let wgpu_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Composite pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &targets,
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 4,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: Default::default(),
+        });
+
+        Self {
+            wgpu_pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// Creates the binding of the texture with the layer image, the texture with the image of the
+    /// layers below it and the compositing parameters.
+    ///
+    /// A new binding is created for every composited layer, since all layers of a frame are
+    /// recorded before any of them is drawn.
+    pub fn create_bind_group(
+        &self,
+        device: &Device,
+        layer_view: &TextureView,
+        backdrop_view: &TextureView,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> BindGroup {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Composite uniform buffer"),
+            contents: bytemuck::cast_slice(&[CompositeUniform::new(opacity, blend_mode)]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(layer_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(backdrop_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Composite bind group"),
+        })
+    }
+
+    /// Draws the layer image over the whole render target. The render pass must be attached to
+    /// the multisampled render target.
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>, bind_group: &'a BindGroup) {
+        render_pass.set_pipeline(&self.wgpu_pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CompositeUniform {
+    opacity: f32,
+    blend_mode: u32,
+    _padding: [u32; 2],
+}
+
+impl CompositeUniform {
+    fn new(opacity: f32, blend_mode: BlendMode) -> Self {
+        let blend_mode = match blend_mode {
+            BlendMode::Normal => 0,
+            BlendMode::Multiply => 1,
+            BlendMode::Screen => 2,
+            BlendMode::Overlay => 3,
+        };
+
+        Self {
+            opacity,
+            blend_mode,
+            _padding: [0; 2],
+        }
+    }
+}
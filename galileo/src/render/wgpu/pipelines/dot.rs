@@ -24,7 +24,7 @@ impl DotPipeline {
         desc.step_mode = VertexStepMode::Vertex;
 
         let buffers = [desc, DisplayInstance::wgpu_desc()];
-        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/dot.wgsl"));
+        let shader = device.create_shader_module(include_wgsl_with_terrain!("./shaders/dot.wgsl"));
 
         let targets = default_targets(format);
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -0,0 +1,323 @@
+use std::mem::size_of;
+
+use wgpu::util::DeviceExt;
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, Device, Queue, RenderPass, RenderPipeline, TextureFormat,
+    TextureView,
+};
+
+use crate::render::wgpu::pipelines::default_targets;
+use crate::render::{HeatmapPaint, HeatmapPoint};
+
+/// Format of the texture the density of heatmap points is accumulated in. Unlike 32-bit float
+/// textures, half float ones support blending without additional device features.
+pub const DENSITY_TEXTURE_FORMAT: TextureFormat = TextureFormat::R16Float;
+
+/// Number of colors in the color ramp texture.
+const RAMP_SIZE: u32 = 256;
+
+/// Draws heatmaps in two passes. First the Gaussian kernels of the points are summed up in a
+/// density texture, and then the density is converted into colors with a color ramp and drawn
+/// over the render target.
+pub struct HeatmapPipeline {
+    density_pipeline: RenderPipeline,
+    color_pipeline: RenderPipeline,
+    uniform_bind_group_layout: BindGroupLayout,
+    color_bind_group_layout: BindGroupLayout,
+}
+
+/// Parameters of a single heatmap: the kernel radius and intensity, and the color ramp.
+pub struct HeatmapBinding {
+    uniform_binding: BindGroup,
+    color_binding: BindGroup,
+}
+
+impl HeatmapPipeline {
+    pub fn create(
+        device: &Device,
+        format: TextureFormat,
+        map_view_layout: &BindGroupLayout,
+    ) -> Self {
+        let uniform_entry = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX)],
+                label: Some("Heatmap uniform bind group layout"),
+            });
+
+        let color_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    texture_entry(0),
+                    texture_entry(1),
+                    uniform_entry(2, wgpu::ShaderStages::FRAGMENT),
+                ],
+                label: Some("Heatmap color bind group layout"),
+            });
+
+        let density_pipeline =
+            Self::create_density_pipeline(device, map_view_layout, &uniform_bind_group_layout);
+        let color_pipeline = Self::create_color_pipeline(device, format, &color_bind_group_layout);
+
+        Self {
+            density_pipeline,
+            color_pipeline,
+            uniform_bind_group_layout,
+            color_bind_group_layout,
+        }
+    }
+
+    fn create_density_pipeline(
+        device: &Device,
+        map_view_layout: &BindGroupLayout,
+        uniform_layout: &BindGroupLayout,
+    ) -> RenderPipeline {
+        let shader =
+            device.create_shader_module(include_wgsl_with_terrain!("./shaders/heatmap.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[map_view_layout, uniform_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Kernels of all points are summed up.
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let targets = [Some(wgpu::ColorTargetState {
+            format: DENSITY_TEXTURE_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+            write_mask: wgpu::ColorWrites::RED,
+        })];
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Heatmap density pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[HeatmapPoint::wgpu_desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &targets,
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: Default::default(),
+        })
+    }
+
+    fn create_color_pipeline(
+        device: &Device,
+        format: TextureFormat,
+        color_layout: &BindGroupLayout,
+    ) -> RenderPipeline {
+        let shader =
+            device.create_shader_module(wgpu::include_wgsl!("./shaders/heatmap_color.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[color_layout],
+            push_constant_ranges: &[],
+        });
+
+        let targets = default_targets(format);
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Heatmap color pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &targets,
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 4,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: Default::default(),
+        })
+    }
+
+    /// Creates the binding of the heatmap parameters and the density texture.
+    ///
+    /// A new binding is created for every drawn heatmap, since all layers of a frame are recorded
+    /// before any of them is drawn.
+    pub fn create_binding(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        paint: &HeatmapPaint,
+        origin: [f32; 3],
+        density_view: &TextureView,
+    ) -> HeatmapBinding {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Heatmap uniform buffer"),
+            contents: bytemuck::cast_slice(&[HeatmapUniform {
+                origin,
+                radius: paint.radius,
+                intensity: paint.intensity,
+                _padding: [0.0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let colors: Vec<[u8; 4]> = (0..RAMP_SIZE)
+            .map(|i| {
+                paint
+                    .ramp
+                    .color_at(i as f32 / (RAMP_SIZE - 1) as f32)
+                    .to_u8_array()
+            })
+            .collect();
+        let ramp_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Heatmap color ramp"),
+                size: wgpu::Extent3d {
+                    width: RAMP_SIZE,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&colors),
+        );
+        let ramp_view = ramp_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let uniform_binding = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("Heatmap uniform bind group"),
+        });
+        let color_binding = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.color_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(density_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&ramp_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Heatmap color bind group"),
+        });
+
+        HeatmapBinding {
+            uniform_binding,
+            color_binding,
+        }
+    }
+
+    /// Adds the kernels of the points to the density texture. The render pass must be attached to
+    /// the density texture, and the map view bindings must be set.
+    pub fn render_density<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        binding: &'a HeatmapBinding,
+        points: &'a Buffer,
+        point_count: u32,
+    ) {
+        render_pass.set_pipeline(&self.density_pipeline);
+        render_pass.set_bind_group(1, &binding.uniform_binding, &[]);
+        render_pass.set_vertex_buffer(0, points.slice(..));
+        render_pass.draw(0..6, 0..point_count);
+    }
+
+    /// Draws the colored density over the whole render target. The render pass must be attached
+    /// to the multisampled render target.
+    pub fn render_colors<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        binding: &'a HeatmapBinding,
+    ) {
+        render_pass.set_pipeline(&self.color_pipeline);
+        render_pass.set_bind_group(0, &binding.color_binding, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct HeatmapUniform {
+    origin: [f32; 3],
+    radius: f32,
+    intensity: f32,
+    _padding: [f32; 3],
+}
+
+impl HeatmapPoint {
+    fn wgpu_desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<HeatmapPoint>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+}
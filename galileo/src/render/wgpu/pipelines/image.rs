@@ -6,12 +6,15 @@ use wgpu::{
     TextureFormat,
 };
 
-use crate::render::render_bundle::world_set::ImageVertex;
+use crate::render::render_bundle::world_set::{ImageVertex, ImageVertices, IMAGE_MESH_SIZE};
 use crate::render::wgpu::pipelines::default_targets;
 use crate::render::wgpu::{pipelines, DisplayInstance};
 use crate::render::RenderOptions;
 
-const INDICES: &[u16] = &[1, 0, 2, 1, 2, 3];
+/// Number of cells along each side of the grid the image quad is split into. The grid allows
+/// the image to follow the terrain surface. Warped images are given as meshes of the same size.
+const GRID_SIZE: u16 = IMAGE_MESH_SIZE;
+const INDEX_COUNT: u32 = GRID_SIZE as u32 * GRID_SIZE as u32 * 6;
 
 pub struct WgpuImage {
     pub texture_bind_group: Arc<BindGroup>,
@@ -30,13 +33,19 @@ impl ImagePipeline {
         format: TextureFormat,
         map_view_layout: &BindGroupLayout,
         texture_bind_group_layout: &BindGroupLayout,
+        color_adjustment_bind_group_layout: &BindGroupLayout,
     ) -> Self {
-        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/image.wgsl"));
+        let shader =
+            device.create_shader_module(include_wgsl_with_terrain!("./shaders/image.wgsl"));
         let buffers = [ImageVertex::wgpu_desc(), DisplayInstance::wgpu_desc()];
 
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[map_view_layout, texture_bind_group_layout],
+            bind_group_layouts: &[
+                map_view_layout,
+                texture_bind_group_layout,
+                color_adjustment_bind_group_layout,
+            ],
             push_constant_ranges: &[],
         });
 
@@ -52,7 +61,7 @@ impl ImagePipeline {
 
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Image index buffer"),
-            contents: bytemuck::cast_slice(INDICES),
+            contents: bytemuck::cast_slice(&grid_indices()),
             usage: wgpu::BufferUsages::INDEX,
         });
 
@@ -67,8 +76,17 @@ impl ImagePipeline {
         &self,
         device: &Device,
         texture: Arc<BindGroup>,
-        vertices: &[ImageVertex; 4],
+        vertices: &ImageVertices,
     ) -> WgpuImage {
+        let grid;
+        let vertices = match vertices {
+            ImageVertices::Quad(corners) => {
+                grid = grid_vertices(corners);
+                &grid
+            }
+            ImageVertices::Mesh(vertices) => vertices,
+        };
+
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Image vertex buffer"),
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
@@ -86,6 +104,7 @@ impl ImagePipeline {
         buffers: &'a WgpuImage,
         render_pass: &mut RenderPass<'a>,
         render_options: RenderOptions,
+        color_adjustment: &'a BindGroup,
         bundle_index: u32,
     ) {
         if render_options.antialias {
@@ -96,10 +115,70 @@ impl ImagePipeline {
 
         let bind_group: &BindGroup = &buffers.texture_bind_group;
         render_pass.set_bind_group(1, bind_group, &[]);
+        render_pass.set_bind_group(2, color_adjustment, &[]);
         render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(0..INDICES.len() as u32, 0, bundle_index..(bundle_index + 1));
+        render_pass.draw_indexed(0..INDEX_COUNT, 0, bundle_index..(bundle_index + 1));
+    }
+}
+
+/// Splits the image quad into the grid of `GRID_SIZE * GRID_SIZE` cells. The vertices of the quad
+/// are expected in the order: bottom-left, top-left, bottom-right, top-right (by texture
+/// coordinates).
+fn grid_vertices(corners: &[ImageVertex; 4]) -> Vec<ImageVertex> {
+    let [bottom_left, top_left, bottom_right, top_right] = corners;
+    let lerp = |a: f32, b: f32, k: f32| a + (b - a) * k;
+    let lerp2 = |a: [f32; 2], b: [f32; 2], k: f32| [lerp(a[0], b[0], k), lerp(a[1], b[1], k)];
+    let bilinear = |get: fn(&ImageVertex) -> [f32; 2], u: f32, v: f32| {
+        lerp2(
+            lerp2(get(top_left), get(top_right), u),
+            lerp2(get(bottom_left), get(bottom_right), u),
+            v,
+        )
+    };
+
+    let mut vertices = Vec::with_capacity((GRID_SIZE as usize + 1).pow(2));
+    for row in 0..=GRID_SIZE {
+        let v = row as f32 / GRID_SIZE as f32;
+        for column in 0..=GRID_SIZE {
+            let u = column as f32 / GRID_SIZE as f32;
+            vertices.push(ImageVertex {
+                position: bilinear(|vertex| vertex.position, u, v),
+                opacity: lerp(
+                    lerp(top_left.opacity, top_right.opacity, u),
+                    lerp(bottom_left.opacity, bottom_right.opacity, u),
+                    v,
+                ),
+                tex_coords: bilinear(|vertex| vertex.tex_coords, u, v),
+                offset: bilinear(|vertex| vertex.offset, u, v),
+            });
+        }
     }
+
+    vertices
+}
+
+fn grid_indices() -> Vec<u16> {
+    let index = |row: u16, column: u16| row * (GRID_SIZE + 1) + column;
+    let mut indices = Vec::with_capacity(INDEX_COUNT as usize);
+    for row in 0..GRID_SIZE {
+        for column in 0..GRID_SIZE {
+            let top_left = index(row, column);
+            let bottom_left = index(row + 1, column);
+            let bottom_right = index(row + 1, column + 1);
+            let top_right = index(row, column + 1);
+            indices.extend([
+                top_left,
+                bottom_left,
+                bottom_right,
+                top_left,
+                bottom_right,
+                top_right,
+            ]);
+        }
+    }
+
+    indices
 }
 
 impl ImageVertex {
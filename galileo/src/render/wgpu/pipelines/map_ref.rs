@@ -17,7 +17,8 @@ impl MapRefPipeline {
         map_view_layout: &BindGroupLayout,
     ) -> Self {
         let buffers = [PolyVertex::wgpu_desc(), DisplayInstance::wgpu_desc()];
-        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/map_ref.wgsl"));
+        let shader =
+            device.create_shader_module(include_wgsl_with_terrain!("./shaders/map_ref.wgsl"));
 
         let targets = default_targets(format);
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -1,6 +1,7 @@
 use std::mem::size_of;
 use std::sync::Arc;
 
+use parking_lot::Mutex;
 use screen_set_image::ScreenSetImagePipeline;
 use screen_set_vertex::ScreenSetPipeline;
 use wgpu::util::{DeviceExt, TextureDataOrder};
@@ -13,14 +14,36 @@ use wgpu::{
 use super::WgpuScreenSetData;
 use crate::decoded_image::{DecodedImage, DecodedImageType};
 use crate::render::wgpu::pipelines::clip::ClipPipeline;
+use crate::render::wgpu::pipelines::composite::CompositePipeline;
 use crate::render::wgpu::pipelines::dot::DotPipeline;
+use crate::render::wgpu::pipelines::heatmap::HeatmapPipeline;
 use crate::render::wgpu::pipelines::image::ImagePipeline;
 use crate::render::wgpu::pipelines::map_ref::MapRefPipeline;
-use crate::render::wgpu::{ViewUniform, WgpuPackedBundle, DEPTH_FORMAT};
-use crate::render::RenderOptions;
+use crate::render::wgpu::{
+    ColorAdjustmentUniform, TerrainKey, TerrainUniform, ViewUniform, WgpuPackedBundle, DEPTH_FORMAT,
+};
+use crate::render::{ColorAdjustment, RenderOptions};
+
+/// Creates a shader module descriptor from the given file with the terrain elevation functions
+/// (`shaders/terrain.wgsl`) prepended to it.
+macro_rules! include_wgsl_with_terrain {
+    ($path:literal) => {
+        wgpu::ShaderModuleDescriptor {
+            label: Some($path),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("./shaders/terrain.wgsl"), include_str!($path)).into(),
+            ),
+        }
+    };
+}
+
+/// Number of elevation samples along each side of the terrain heightmap.
+pub(crate) const TERRAIN_HEIGHTMAP_SIZE: u32 = 128;
 
 mod clip;
+mod composite;
 mod dot;
+pub mod heatmap;
 pub mod image;
 mod map_ref;
 mod screen_set_image;
@@ -29,8 +52,13 @@ mod screen_set_vertex;
 pub struct Pipelines {
     map_view_binding: BindGroup,
     map_view_buffer: Buffer,
+    terrain_buffer: Buffer,
+    terrain_heightmap: wgpu::Texture,
+    terrain_key: Mutex<Option<TerrainKey>>,
     pub(crate) map_view_bind_group_layout: BindGroupLayout,
     texture_bind_group_layout: BindGroupLayout,
+    color_adjustment_bind_group_layout: BindGroupLayout,
+    identity_color_adjustment: BindGroup,
 
     image: ImagePipeline,
     map_ref: MapRefPipeline,
@@ -38,6 +66,8 @@ pub struct Pipelines {
     dot: DotPipeline,
     screen_set: ScreenSetPipeline,
     screen_set_image: ScreenSetImagePipeline,
+    composite: CompositePipeline,
+    heatmap: HeatmapPipeline,
 }
 
 impl Pipelines {
@@ -49,27 +79,83 @@ impl Pipelines {
             mapped_at_creation: false,
         });
 
+        let terrain_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Terrain buffer"),
+            size: size_of::<TerrainUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let terrain_heightmap = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Terrain heightmap"),
+            size: wgpu::Extent3d {
+                width: TERRAIN_HEIGHTMAP_SIZE,
+                height: TERRAIN_HEIGHTMAP_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
         let map_view_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                ],
                 label: None,
             });
 
+        let terrain_heightmap_view =
+            terrain_heightmap.create_view(&wgpu::TextureViewDescriptor::default());
         let map_view_binding = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &map_view_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: map_view_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: map_view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: terrain_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&terrain_heightmap_view),
+                },
+            ],
             label: Some("view_bind_group"),
         });
 
@@ -96,9 +182,32 @@ impl Pipelines {
                 label: Some("texture_bind_group_label"),
             });
 
+        let color_adjustment_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("color_adjustment_bind_group_layout"),
+            });
+        let identity_color_adjustment = create_color_adjustment_binding(
+            device,
+            &color_adjustment_bind_group_layout,
+            &ColorAdjustment::IDENTITY,
+        );
+
         Self {
             map_view_binding,
             map_view_buffer,
+            terrain_buffer,
+            terrain_heightmap,
+            terrain_key: Mutex::new(None),
             map_view_bind_group_layout: map_view_bind_group_layout.clone(),
             texture_bind_group_layout: texture_bind_group_layout.clone(),
             image: ImagePipeline::create(
@@ -106,7 +215,10 @@ impl Pipelines {
                 format,
                 &map_view_bind_group_layout,
                 &texture_bind_group_layout,
+                &color_adjustment_bind_group_layout,
             ),
+            color_adjustment_bind_group_layout,
+            identity_color_adjustment,
             map_ref: MapRefPipeline::create(device, format, &map_view_bind_group_layout),
             clip: ClipPipeline::create(device, format, &map_view_bind_group_layout),
             dot: DotPipeline::create(device, format, &map_view_bind_group_layout),
@@ -117,6 +229,8 @@ impl Pipelines {
                 &map_view_bind_group_layout,
                 &texture_bind_group_layout,
             ),
+            composite: CompositePipeline::create(device, format),
+            heatmap: HeatmapPipeline::create(device, format, &map_view_bind_group_layout),
         }
     }
 
@@ -125,6 +239,7 @@ impl Pipelines {
         render_pass: &mut RenderPass<'a>,
         bundle: &'a WgpuPackedBundle,
         render_options: RenderOptions,
+        color_adjustment: &'a BindGroup,
         bundle_index: u32,
     ) {
         self.set_bindings(render_pass);
@@ -135,8 +250,13 @@ impl Pipelines {
         }
 
         for image in &bundle.image_buffers {
-            self.image
-                .render(image, render_pass, render_options, bundle_index);
+            self.image.render(
+                image,
+                render_pass,
+                render_options,
+                color_adjustment,
+                bundle_index,
+            );
         }
 
         if bundle.map_ref_buffers.index_count > 0 {
@@ -163,6 +283,61 @@ impl Pipelines {
         &self.map_view_buffer
     }
 
+    /// Stores the key of the terrain heightmap to be written. Returns `false` if the heightmap
+    /// with the same key is already written, so it does not need to be sampled again.
+    pub(crate) fn replace_terrain_key(&self, key: Option<TerrainKey>) -> bool {
+        let mut stored = self.terrain_key.lock();
+        if key.is_some() && *stored == key {
+            return false;
+        }
+
+        *stored = key;
+        true
+    }
+
+    /// Writes the terrain parameters and the heightmap. The heightmap is ignored if the terrain
+    /// is disabled.
+    pub fn write_terrain(&self, queue: &Queue, uniform: TerrainUniform, heightmap: &[f32]) {
+        queue.write_buffer(&self.terrain_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        if uniform.enabled == 0 {
+            return;
+        }
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.terrain_heightmap,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(heightmap),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(size_of::<f32>() as u32 * TERRAIN_HEIGHTMAP_SIZE),
+                rows_per_image: Some(TERRAIN_HEIGHTMAP_SIZE),
+            },
+            self.terrain_heightmap.size(),
+        );
+    }
+
+    /// Creates the binding of the color adjustment uniform used by the image pipeline.
+    pub fn create_color_adjustment_binding(
+        &self,
+        device: &Device,
+        color_adjustment: &ColorAdjustment,
+    ) -> BindGroup {
+        create_color_adjustment_binding(
+            device,
+            &self.color_adjustment_bind_group_layout,
+            color_adjustment,
+        )
+    }
+
+    /// Binding of the color adjustment that leaves images unchanged.
+    pub fn identity_color_adjustment(&self) -> &BindGroup {
+        &self.identity_color_adjustment
+    }
+
     pub fn image_pipeline(&self) -> &ImagePipeline {
         &self.image
     }
@@ -171,6 +346,14 @@ impl Pipelines {
         &self.screen_set_image
     }
 
+    pub fn composite_pipeline(&self) -> &CompositePipeline {
+        &self.composite
+    }
+
+    pub fn heatmap_pipeline(&self) -> &HeatmapPipeline {
+        &self.heatmap
+    }
+
     pub fn set_bindings<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
         render_pass.set_bind_group(0, &self.map_view_binding, &[]);
     }
@@ -291,6 +474,27 @@ impl Pipelines {
     }
 }
 
+fn create_color_adjustment_binding(
+    device: &Device,
+    layout: &BindGroupLayout,
+    color_adjustment: &ColorAdjustment,
+) -> BindGroup {
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Color adjustment buffer"),
+        contents: bytemuck::cast_slice(&[ColorAdjustmentUniform::from(color_adjustment)]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+        label: Some("color_adjustment_bind_group"),
+    })
+}
+
 pub(crate) fn default_targets(format: TextureFormat) -> [Option<wgpu::ColorTargetState>; 1] {
     [Some(wgpu::ColorTargetState {
         format,
@@ -47,8 +47,9 @@ impl ScreenSetImagePipeline {
             ScreenSetImageVertex::wgpu_desc(),
             DisplayInstance::wgpu_desc(),
         ];
-        let shader =
-            device.create_shader_module(wgpu::include_wgsl!("./shaders/screen_set_image.wgsl"));
+        let shader = device.create_shader_module(include_wgsl_with_terrain!(
+            "./shaders/screen_set_image.wgsl"
+        ));
 
         let targets = default_targets(format);
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -38,7 +38,8 @@ impl ScreenSetPipeline {
         map_view_layout: &BindGroupLayout,
     ) -> Self {
         let buffers = [ScreenSetVertex::wgpu_desc(), DisplayInstance::wgpu_desc()];
-        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/screen_set.wgsl"));
+        let shader =
+            device.create_shader_module(include_wgsl_with_terrain!("./shaders/screen_set.wgsl"));
 
         let targets = default_targets(format);
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -0,0 +1,71 @@
+// Composites the image of a layer with the image of the layers below it (backdrop). Both images
+// contain premultiplied colors.
+
+struct CompositeUniform {
+    opacity: f32,
+    // 0 - normal, 1 - multiply, 2 - screen, 3 - overlay
+    blend_mode: u32,
+}
+
+@group(0) @binding(0)
+var layer_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var backdrop_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> composite: CompositeUniform;
+
+// Vertex shader
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole render target.
+    let x = f32(i32(index & 1u) * 4 - 1);
+    let y = f32(i32(index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+// Fragment shader
+
+fn blend(backdrop: vec3<f32>, source: vec3<f32>) -> vec3<f32> {
+    switch composite.blend_mode {
+        case 1u: {
+            return backdrop * source;
+        }
+        case 2u: {
+            return backdrop + source - backdrop * source;
+        }
+        case 3u: {
+            let multiply = 2.0 * backdrop * source;
+            let screen = 1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source);
+            return select(screen, multiply, backdrop <= vec3<f32>(0.5));
+        }
+        default: {
+            return source;
+        }
+    }
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(position.xy);
+    let layer = textureLoad(layer_texture, coords, 0);
+    let backdrop = textureLoad(backdrop_texture, coords, 0);
+
+    let alpha = layer.a * composite.opacity;
+    if alpha <= 0.0 {
+        return backdrop;
+    }
+
+    let source = layer.rgb / layer.a;
+    var backdrop_color = vec3<f32>(0.0);
+    if backdrop.a > 0.0 {
+        backdrop_color = backdrop.rgb / backdrop.a;
+    }
+
+    // Where the backdrop is transparent, the source color is used as is.
+    let color = mix(source, blend(backdrop_color, source), backdrop.a);
+    return vec4<f32>(
+        color * alpha + backdrop.rgb * (1.0 - alpha),
+        alpha + backdrop.a * (1.0 - alpha),
+    );
+}
@@ -30,7 +30,9 @@ fn vs_main(
     var color = vec4<f32>(model.color) / 255.0;
     color[3] = color[3] * model.bundle_opacity;
     out.color = color;
-    out.clip_position = transform.view_proj * vec4<f32>(model.position + model.bundle_offset, 1.0);
+    let position = model.position + model.bundle_offset;
+    let elevation = terrain_elevation(position.xy);
+    out.clip_position = transform.view_proj * vec4<f32>(position.xy, position.z + elevation, 1.0);
 
     return out;
 }
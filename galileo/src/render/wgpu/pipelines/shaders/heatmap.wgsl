@@ -0,0 +1,74 @@
+// Accumulates the density of heatmap points into a single channel float texture.
+
+struct ViewUniform {
+    view_proj: mat4x4<f32>,
+    view_rotation: mat4x4<f32>,
+    inv_screen_size: vec2<f32>,
+    resolution: f32,
+}
+
+struct HeatmapUniform {
+    origin: vec3<f32>,
+    radius: f32,
+    intensity: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> transform: ViewUniform;
+
+@group(1) @binding(0)
+var<uniform> heatmap: HeatmapUniform;
+
+struct PointInput {
+    // Offset of the point from the heatmap origin.
+    @location(0) position: vec3<f32>,
+    @location(1) weight: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Position inside the kernel square, from -1 to 1 along each axis.
+    @location(0) offset: vec2<f32>,
+    @location(1) weight: f32,
+};
+
+// Vertex shader
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, point: PointInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[index];
+
+    let position = point.position + heatmap.origin;
+    let elevation = terrain_elevation(position.xy);
+    let center = transform.view_proj * vec4<f32>(position.xy, position.z + elevation, 1.0);
+    let center_normalized = center / center.w;
+
+    var out: VertexOutput;
+    out.clip_position = center_normalized + vec4<f32>(corner * heatmap.radius * transform.inv_screen_size * 2.0, 0.0, 0.0);
+    out.offset = corner;
+    out.weight = point.weight;
+
+    return out;
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance_squared = dot(in.offset, in.offset);
+    if distance_squared > 1.0 {
+        discard;
+    }
+
+    // Gaussian kernel with the standard deviation of one third of the radius, cut at the radius.
+    let density = in.weight * exp(-4.5 * distance_squared);
+    return vec4<f32>(density, 0.0, 0.0, 0.0);
+}
@@ -0,0 +1,38 @@
+// Converts the accumulated heatmap density into colors using the color ramp texture.
+
+struct HeatmapUniform {
+    origin: vec3<f32>,
+    radius: f32,
+    intensity: f32,
+}
+
+@group(0) @binding(0)
+var density_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var ramp_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> heatmap: HeatmapUniform;
+
+// Vertex shader
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole render target.
+    let x = f32(i32(index & 1u) * 4 - 1);
+    let y = f32(i32(index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let density = textureLoad(density_texture, vec2<i32>(position.xy), 0).r * heatmap.intensity;
+    if density <= 0.0 {
+        discard;
+    }
+
+    let ramp_size = textureDimensions(ramp_texture).x;
+    let index = u32(clamp(density, 0.0, 1.0) * f32(ramp_size - 1u) + 0.5);
+    return textureLoad(ramp_texture, vec2<u32>(index, 0u), 0);
+}
@@ -32,7 +32,9 @@ fn vs_main(
     var out: VertexOutput;
     out.tex_coord = model.tex_coord;
 
-    var point_position = transform.view_proj * vec4<f32>(model.position + model.bundle_offset.xy, model.bundle_offset.z, 1.0);
+    let position = model.position + model.bundle_offset.xy;
+    let elevation = terrain_elevation(position);
+    var point_position = transform.view_proj * vec4<f32>(position, model.bundle_offset.z + elevation, 1.0);
     var vertex_delta = vec4<f32>(model.offset * transform.inv_screen_size * point_position[3] * 2.0, 0.0, 0.0);
 
     out.clip_position = point_position + vertex_delta;
@@ -49,10 +51,21 @@ var t_diffuse: texture_2d<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
 
+struct ColorAdjustment {
+    // Brightness, contrast, saturation and hue rotation (in radians).
+    transform: vec4<f32>,
+    // Tint color with the tint strength as alpha.
+    tint: vec4<f32>,
+    invert: f32,
+}
+
+@group(2) @binding(0)
+var<uniform> color_adjustment: ColorAdjustment;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     var color = textureSample(t_diffuse, s_diffuse, in.tex_coord);
-    color[3] = color[3] * in.opacity;
+    color = vec4<f32>(adjust_color(color.rgb, color_adjustment.transform, color_adjustment.invert, color_adjustment.tint), color[3] * in.opacity);
 
     if color[3] == 0.0 {
         discard;
@@ -60,3 +73,47 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
     return color;
 }
+
+// Color adjustments are done in sRGB space to match the usual image editing tools.
+fn adjust_color(linear: vec3<f32>, transform: vec4<f32>, invert: f32, tint: vec4<f32>) -> vec3<f32> {
+    if all(transform == vec4<f32>(1.0, 1.0, 1.0, 0.0)) && invert == 0.0 && tint.a == 0.0 {
+        return linear;
+    }
+
+    var color = linear_to_srgb(linear);
+
+    let luma = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    color = mix(vec3<f32>(luma), color, transform.z);
+
+    let hue = transform.w;
+    if hue != 0.0 {
+        let c = cos(hue);
+        let s = sin(hue);
+        let rotation = mat3x3<f32>(
+            vec3<f32>(0.213 + c * 0.787 - s * 0.213, 0.213 - c * 0.213 + s * 0.143, 0.213 - c * 0.213 - s * 0.787),
+            vec3<f32>(0.715 - c * 0.715 - s * 0.715, 0.715 + c * 0.285 + s * 0.140, 0.715 - c * 0.715 + s * 0.715),
+            vec3<f32>(0.072 - c * 0.072 + s * 0.928, 0.072 - c * 0.072 - s * 0.283, 0.072 + c * 0.928 + s * 0.072),
+        );
+        color = rotation * color;
+    }
+
+    color = (color - 0.5) * transform.y + 0.5;
+    color = color * transform.x;
+    color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    color = mix(color, vec3<f32>(1.0) - color, invert);
+    color = mix(color, color * tint.rgb, tint.a);
+
+    return srgb_to_linear(color);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
@@ -42,7 +42,8 @@ fn vs_main(
 
     let norm = model.norm * norm_limit * transform.resolution;
     let position = model.position + model.bundle_offset;
-    let vertex_position = transform.view_proj * vec4<f32>(position.xy + norm, model.position.z, 1.0);
+    let elevation = terrain_elevation(position.xy);
+    let vertex_position = transform.view_proj * vec4<f32>(position.xy + norm, model.position.z + elevation, 1.0);
 
     out.clip_position = vertex_position;
 
@@ -35,7 +35,8 @@ fn vs_main(
     out.tex_coord = vertex.tex_coord;
     out.opacity = screen_set.opacity;
 
-    var point_position = transform.view_proj * vec4<f32>(screen_set.anchor, 1.0);
+    let elevation = terrain_elevation(screen_set.anchor.xy);
+    var point_position = transform.view_proj * vec4<f32>(screen_set.anchor.xy, screen_set.anchor.z + elevation, 1.0);
     var position_normalized = point_position / point_position[3];
 
     var vertex_delta = vec4<f32>(vertex.position * transform.inv_screen_size * 2.0, 0.0, 0.0);
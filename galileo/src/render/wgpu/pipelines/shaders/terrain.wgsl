@@ -0,0 +1,43 @@
+// Terrain elevation. This file is prepended to the shaders of the pipelines that draw on the map surface.
+
+struct TerrainUniform {
+    // x_min, y_min, x_max, y_max of the heightmap in map coordinates
+    bounds: vec4<f32>,
+    size: vec2<u32>,
+    enabled: u32,
+}
+
+@group(0) @binding(1)
+var<uniform> terrain: TerrainUniform;
+
+// Elevations in map units. Row 0 corresponds to the top (y_max) side of the bounds.
+@group(0) @binding(2)
+var terrain_heights: texture_2d<f32>;
+
+fn terrain_elevation(position: vec2<f32>) -> f32 {
+    if terrain.enabled == 0u {
+        return 0.0;
+    }
+
+    let last = vec2<f32>(terrain.size - vec2<u32>(1u));
+    let relative = (position - terrain.bounds.xy) / (terrain.bounds.zw - terrain.bounds.xy);
+    let pixel = clamp(vec2<f32>(relative.x, 1.0 - relative.y) * last, vec2<f32>(0.0), last);
+
+    let p0 = vec2<u32>(floor(pixel));
+    let p1 = min(p0 + vec2<u32>(1u), terrain.size - vec2<u32>(1u));
+    let k = pixel - floor(pixel);
+
+    let top = mix(
+        textureLoad(terrain_heights, vec2<u32>(p0.x, p0.y), 0).r,
+        textureLoad(terrain_heights, vec2<u32>(p1.x, p0.y), 0).r,
+        k.x,
+    );
+    let bottom = mix(
+        textureLoad(terrain_heights, vec2<u32>(p0.x, p1.y), 0).r,
+        textureLoad(terrain_heights, vec2<u32>(p1.x, p1.y), 0).r,
+        k.x,
+    );
+
+    return mix(top, bottom, k.y);
+}
+
@@ -0,0 +1,100 @@
+//! Conversion of coordinates between different coordinate systems.
+
+use galileo_types::cartesian::{CartesianPoint2d, Point2};
+use galileo_types::geo::impls::GeoPoint2d;
+use galileo_types::geo::{Crs, GeoPoint, NewGeoPoint, Projection};
+
+type GeoProjection = Box<dyn Projection<InPoint = GeoPoint2d, OutPoint = Point2>>;
+
+/// Converts points from one CRS into another through geographic coordinates.
+///
+/// Coordinates of [`Crs::WGS84`] are treated as longitude (x) and latitude (y) in degrees.
+pub(crate) struct CrsTransform {
+    source: Option<GeoProjection>,
+    target: Option<GeoProjection>,
+    is_identity: bool,
+}
+
+impl CrsTransform {
+    /// Creates a new transformation. Returns `None` if coordinates of one of the CRSs cannot be
+    /// converted into geographic coordinates.
+    pub(crate) fn new(source: &Crs, target: &Crs) -> Option<Self> {
+        Some(Self {
+            source: Self::geo_projection(source)?,
+            target: Self::geo_projection(target)?,
+            is_identity: source == target,
+        })
+    }
+
+    fn geo_projection(crs: &Crs) -> Option<Option<GeoProjection>> {
+        if *crs == Crs::WGS84 {
+            Some(None)
+        } else {
+            crs.get_projection().map(Some)
+        }
+    }
+
+    /// Converts a point from the source CRS into the target CRS.
+    pub(crate) fn transform(&self, point: &Point2) -> Option<Point2> {
+        if self.is_identity {
+            return Some(*point);
+        }
+
+        let geo = Self::to_geo(self.source.as_deref(), point)?;
+        Self::from_geo(self.target.as_deref(), &geo)
+    }
+
+    fn to_geo(
+        projection: Option<&dyn Projection<InPoint = GeoPoint2d, OutPoint = Point2>>,
+        point: &Point2,
+    ) -> Option<GeoPoint2d> {
+        match projection {
+            Some(projection) => projection.unproject(point),
+            None => Some(GeoPoint2d::latlon(point.y(), point.x())),
+        }
+    }
+
+    fn from_geo(
+        projection: Option<&dyn Projection<InPoint = GeoPoint2d, OutPoint = Point2>>,
+        point: &GeoPoint2d,
+    ) -> Option<Point2> {
+        let point = match projection {
+            Some(projection) => projection.project(point)?,
+            None => Point2::new(point.lon(), point.lat()),
+        };
+
+        (point.x().is_finite() && point.y().is_finite()).then_some(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn transform_between_wgs84_and_web_mercator() {
+        let transform = CrsTransform::new(&Crs::WGS84, &Crs::EPSG3857).unwrap();
+
+        let projected = transform.transform(&Point2::new(180.0, 0.0)).unwrap();
+        assert_abs_diff_eq!(projected.x(), 20037508.34, epsilon = 0.01);
+        assert_abs_diff_eq!(projected.y(), 0.0, epsilon = 0.01);
+
+        let back = CrsTransform::new(&Crs::EPSG3857, &Crs::WGS84)
+            .unwrap()
+            .transform(&projected)
+            .unwrap();
+        assert_abs_diff_eq!(back.x(), 180.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(back.y(), 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn identity_transform() {
+        let transform = CrsTransform::new(&Crs::EPSG3857, &Crs::EPSG3857).unwrap();
+        assert_eq!(
+            transform.transform(&Point2::new(1.0, 2.0)),
+            Some(Point2::new(1.0, 2.0))
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use galileo_types::cartesian::{CartesianPoint2d, Point2};
+use galileo_types::geo::Crs;
+use parking_lot::Mutex;
+use quick_cache::sync::Cache;
+use web_time::Instant;
+
+use super::Terrain;
+use crate::error::GalileoError;
+use crate::layer::raster_tile_layer::dem::{web_mercator_scale, METERS_PER_DEGREE};
+use crate::layer::raster_tile_layer::{DemEncoding, DemTile, RasterTileLoader, RestTileLoader};
+use crate::layer::tiles::{
+    RequestSlot, RequestSourceId, RetryPolicy, TilePriority, TileRequest, TileRequestQueue,
+};
+use crate::messenger::Messenger;
+use crate::tile_schema::TileIndex;
+use crate::view::MapView;
+use crate::TileSchema;
+
+/// Number of decoded DEM tiles kept in memory.
+const TILE_CACHE_SIZE: usize = 128;
+
+/// Terrain with elevations loaded from DEM tiles (terrain-RGB or Terrarium encoded images).
+///
+/// Tiles are loaded for the z-level of the displayed view. If a tile for the point is not loaded
+/// yet, the elevation is taken from the loaded tiles of lower z-levels. Tiles are requested
+/// through a [`TileRequestQueue`], and failed requests are retried according to the
+/// [`RetryPolicy`].
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use galileo::layer::raster_tile_layer::{DemEncoding, RestTileLoader};
+/// use galileo::terrain::DemTerrain;
+/// use galileo::{MapBuilder, TileSchema};
+///
+/// let loader = RestTileLoader::new(
+///     |index| {
+///         format!(
+///             "https://s3.amazonaws.com/elevation-tiles-prod/terrarium/{}/{}/{}.png",
+///             index.z, index.x, index.y
+///         )
+///     },
+///     None,
+///     false,
+/// );
+/// let terrain = DemTerrain::new(loader, DemEncoding::Terrarium, TileSchema::web(15))
+///     .with_exaggeration(1.5);
+///
+/// let mut map = MapBuilder::default().build();
+/// map.set_terrain(Some(Arc::new(terrain)));
+/// ```
+pub struct DemTerrain {
+    dem_loader: Arc<RestTileLoader>,
+    encoding: DemEncoding,
+    tile_schema: TileSchema,
+    /// Z-levels of the tile schema from the highest to the lowest.
+    z_levels: Vec<u32>,
+    exaggeration: f64,
+    store: Arc<TileStore>,
+    current_z: AtomicU32,
+    request_queue: TileRequestQueue,
+    request_source: RequestSourceId,
+    retry_policy: RetryPolicy,
+}
+
+/// Loaded tiles shared with the loading tasks.
+struct TileStore {
+    tiles: Cache<TileIndex, TileState>,
+    revision: AtomicU64,
+    messenger: Mutex<Option<Box<dyn Messenger>>>,
+}
+
+#[derive(Clone)]
+enum TileState {
+    Loading,
+    Loaded(Arc<DemTile>),
+    Empty,
+    Error(Instant),
+}
+
+impl DemTerrain {
+    /// Creates a new terrain. The tile schema must be the schema of the DEM tiles.
+    pub fn new(dem_loader: RestTileLoader, encoding: DemEncoding, tile_schema: TileSchema) -> Self {
+        let mut z_levels: Vec<_> = tile_schema.lods.iter().map(|lod| lod.z_index()).collect();
+        z_levels.sort_unstable_by(|a, b| b.cmp(a));
+
+        Self {
+            dem_loader: Arc::new(dem_loader),
+            encoding,
+            tile_schema,
+            z_levels,
+            exaggeration: 1.0,
+            store: Arc::new(TileStore {
+                tiles: Cache::new(TILE_CACHE_SIZE),
+                revision: AtomicU64::new(0),
+                messenger: Mutex::new(None),
+            }),
+            current_z: AtomicU32::new(0),
+            request_queue: TileRequestQueue::default(),
+            request_source: RequestSourceId::next_id(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets the queue the DEM tiles are requested through.
+    ///
+    /// By default the terrain creates its own [`TileRequestQueue`]. Setting the queue of the
+    /// layers that load tiles from the same server makes them share the limit of concurrent
+    /// requests per host.
+    pub fn with_request_queue(mut self, request_queue: TileRequestQueue) -> Self {
+        self.request_queue.cancel(self.request_source);
+        self.request_queue = request_queue;
+        self
+    }
+
+    /// Sets the way the terrain handles tiles that failed to load.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the multiplier of the elevations. Defaults to 1.0.
+    pub fn with_exaggeration(mut self, exaggeration: f64) -> Self {
+        self.exaggeration = exaggeration;
+        self
+    }
+
+    /// Returns the elevation in meters at the given point, or `None` if no tile containing the
+    /// point is loaded.
+    pub fn elevation_meters(&self, point: &Point2) -> Option<f64> {
+        let max_z = self.current_z.load(Ordering::Relaxed);
+        let z_levels = self.z_levels.iter().filter(|z| **z <= max_z);
+
+        z_levels.into_iter().find_map(|&z| {
+            let index = self.tile_schema.tile_index_at(point, z)?;
+            let TileState::Loaded(tile) = self.store.tiles.get(&index)? else {
+                return None;
+            };
+
+            self.sample(&tile, index, point)
+        })
+    }
+
+    fn sample(&self, tile: &DemTile, index: TileIndex, point: &Point2) -> Option<f64> {
+        let bbox = self.tile_schema.tile_bbox(index.into_wrapping())?;
+        let (width, height) = (tile.width() as f64, tile.height() as f64);
+
+        // The point may be in one of the wrapped copies of the world.
+        let world_width = self.tile_schema.bounds.width();
+        let x = bbox.x_min() + (point.x() - bbox.x_min()).rem_euclid(world_width);
+
+        let px = ((x - bbox.x_min()) / bbox.width() * width - 0.5).clamp(0.0, width - 1.0);
+        let py =
+            ((bbox.y_max() - point.y()) / bbox.height() * height - 0.5).clamp(0.0, height - 1.0);
+
+        let (x0, y0) = (px.floor() as u32, py.floor() as u32);
+        let (x1, y1) = (
+            (x0 + 1).min(tile.width() - 1),
+            (y0 + 1).min(tile.height() - 1),
+        );
+        let (fx, fy) = (px - x0 as f64, py - y0 as f64);
+
+        let e = |x, y| tile.elevation(x, y) as f64;
+        let top = e(x0, y0) * (1.0 - fx) + e(x1, y0) * fx;
+        let bottom = e(x0, y1) * (1.0 - fx) + e(x1, y1) * fx;
+
+        Some(top * (1.0 - fy) + bottom * fy)
+    }
+
+    /// Number of map units in one meter of elevation at the given point.
+    fn map_units_per_meter(&self, point: &Point2) -> f64 {
+        if self.tile_schema.crs == Crs::EPSG3857 {
+            1.0 / web_mercator_scale(point.y())
+        } else if self.tile_schema.crs == Crs::WGS84 {
+            1.0 / METERS_PER_DEGREE
+        } else {
+            1.0
+        }
+    }
+
+    fn needs_loading(&self, index: TileIndex) -> bool {
+        match self.store.tiles.get(&index) {
+            None => true,
+            Some(TileState::Error(failed_at)) => self.retry_policy.is_error_expired(failed_at),
+            Some(_) => false,
+        }
+    }
+
+    async fn load_tile(
+        index: TileIndex,
+        dem_loader: Arc<RestTileLoader>,
+        encoding: DemEncoding,
+        retry_policy: RetryPolicy,
+        store: Arc<TileStore>,
+        slot: RequestSlot,
+    ) {
+        if matches!(store.tiles.get(&index), Some(TileState::Loading)) {
+            return;
+        }
+
+        store.tiles.insert(index, TileState::Loading);
+
+        let result = retry_policy
+            .run(
+                || DemTile::load(&dem_loader, index, encoding),
+                |err| !matches!(err, GalileoError::NotFound | GalileoError::Offline),
+                Some(&slot),
+            )
+            .await;
+
+        let state = match result {
+            Ok(Some(tile)) => TileState::Loaded(Arc::new(tile)),
+            Ok(None) => TileState::Empty,
+            Err(err) => {
+                log::debug!("Failed to load DEM tile {index:?}: {err}");
+                store.tiles.insert(index, TileState::Error(Instant::now()));
+                return;
+            }
+        };
+
+        store.tiles.insert(index, state);
+        store.revision.fetch_add(1, Ordering::Relaxed);
+        if let Some(messenger) = &*store.messenger.lock() {
+            messenger.request_redraw();
+        }
+    }
+}
+
+impl Drop for DemTerrain {
+    fn drop(&mut self) {
+        self.request_queue.cancel(self.request_source);
+    }
+}
+
+impl Terrain for DemTerrain {
+    fn elevation(&self, point: &Point2) -> Option<f64> {
+        let elevation = self.elevation_meters(point)?;
+        Some(elevation * self.exaggeration * self.map_units_per_meter(point))
+    }
+
+    fn prepare(&self, view: &MapView) {
+        let Some(tiles) = self.tile_schema.iter_tiles(view) else {
+            return;
+        };
+
+        let mut tiles = tiles.peekable();
+        if let Some(index) = tiles.peek() {
+            let prev_z = self.current_z.swap(index.z, Ordering::Relaxed);
+            if prev_z != index.z {
+                self.store.revision.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let requests = tiles.filter_map(|index| {
+            let tile_index = TileIndex::from(index);
+            if !self.needs_loading(tile_index) {
+                return None;
+            }
+
+            Some(TileRequest {
+                index: tile_index,
+                priority: TilePriority::new(index, &self.tile_schema, view),
+                host: self.dem_loader.request_host(tile_index),
+            })
+        });
+
+        let dem_loader = self.dem_loader.clone();
+        let encoding = self.encoding;
+        let retry_policy = self.retry_policy;
+        let store = self.store.clone();
+        self.request_queue
+            .request_tiles(self.request_source, requests, move |index, slot| {
+                Self::load_tile(
+                    index,
+                    dem_loader.clone(),
+                    encoding,
+                    retry_policy,
+                    store.clone(),
+                    slot,
+                )
+            });
+    }
+
+    fn revision(&self) -> Option<u64> {
+        Some(self.store.revision.load(Ordering::Relaxed))
+    }
+
+    fn set_messenger(&self, messenger: Box<dyn Messenger>) {
+        *self.store.messenger.lock() = Some(messenger);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use galileo_types::cartesian::Rect;
+
+    use super::*;
+    use crate::decoded_image::DecodedImage;
+    use crate::lod::Lod;
+    use crate::tile_schema::VerticalDirection;
+
+    fn terrain() -> DemTerrain {
+        let tile_schema = TileSchema {
+            origin: Point2::new(0.0, 400.0),
+            bounds: Rect::new(0.0, 0.0, 400.0, 400.0),
+            lods: [Lod::new(2.0, 0).unwrap(), Lod::new(1.0, 1).unwrap()].into(),
+            tile_width: 200,
+            tile_height: 200,
+            y_direction: VerticalDirection::TopToBottom,
+            crs: Crs::new(
+                galileo_types::geo::Datum::WGS84,
+                galileo_types::geo::ProjectionType::Other("local".into()),
+            ),
+        };
+
+        DemTerrain::new(
+            RestTileLoader::new(|_: &TileIndex| String::new(), None, false),
+            DemEncoding::Terrarium,
+            tile_schema,
+        )
+        .with_exaggeration(2.0)
+    }
+
+    fn dem_tile(elevation: impl Fn(u32, u32) -> f32) -> DemTile {
+        let mut bytes = vec![];
+        for y in 0..2 {
+            for x in 0..2 {
+                let value = elevation(x, y) + 32768.0;
+                bytes.extend([(value / 256.0) as u8, (value % 256.0) as u8, 0, 255]);
+            }
+        }
+
+        let image = DecodedImage::from_raw(bytes, galileo_types::cartesian::Size::new(2, 2))
+            .expect("valid image");
+        DemTile::from_image(&image, DemEncoding::Terrarium).expect("valid tile")
+    }
+
+    #[test]
+    fn elevation_is_interpolated_and_exaggerated() {
+        let terrain = terrain();
+        terrain.current_z.store(1, Ordering::Relaxed);
+        terrain.store.tiles.insert(
+            TileIndex::new(0, 0, 0),
+            TileState::Loaded(Arc::new(dem_tile(|x, _| x as f32 * 100.0))),
+        );
+
+        // Pixel centers of the 2x2 tile covering 400x400 are at 100 and 300.
+        assert_eq!(
+            terrain.elevation_meters(&Point2::new(100.0, 300.0)),
+            Some(0.0)
+        );
+        assert_eq!(
+            terrain.elevation_meters(&Point2::new(300.0, 300.0)),
+            Some(100.0)
+        );
+        assert_abs_diff_eq!(
+            terrain
+                .elevation_meters(&Point2::new(200.0, 100.0))
+                .unwrap(),
+            50.0
+        );
+        assert_abs_diff_eq!(
+            terrain.elevation(&Point2::new(200.0, 100.0)).unwrap(),
+            100.0
+        );
+    }
+
+    #[test]
+    fn higher_z_tiles_are_preferred() {
+        let terrain = terrain();
+        terrain.current_z.store(1, Ordering::Relaxed);
+        terrain.store.tiles.insert(
+            TileIndex::new(0, 0, 0),
+            TileState::Loaded(Arc::new(dem_tile(|_, _| 10.0))),
+        );
+        terrain.store.tiles.insert(
+            TileIndex::new(1, 0, 1),
+            TileState::Loaded(Arc::new(dem_tile(|_, _| 20.0))),
+        );
+
+        assert_eq!(
+            terrain.elevation_meters(&Point2::new(300.0, 300.0)),
+            Some(20.0)
+        );
+        assert_eq!(
+            terrain.elevation_meters(&Point2::new(100.0, 300.0)),
+            Some(10.0)
+        );
+        assert_eq!(terrain.elevation_meters(&Point2::new(100.0, -100.0)), None);
+    }
+
+    #[tokio::test]
+    async fn revision_changes_with_z_level() {
+        let terrain = terrain();
+        let revision = terrain.revision().unwrap();
+        assert_eq!(terrain.z_levels, vec![1, 0]);
+
+        let view = MapView::new_projected_with_crs(
+            &Point2::new(200.0, 200.0),
+            1.0,
+            terrain.tile_schema.crs.clone(),
+        )
+        .with_size(galileo_types::cartesian::Size::new(400.0, 400.0));
+        terrain.prepare(&view);
+        assert_ne!(terrain.revision().unwrap(), revision);
+
+        let revision = terrain.revision().unwrap();
+        terrain.prepare(&view);
+        assert_eq!(terrain.revision().unwrap(), revision);
+    }
+}
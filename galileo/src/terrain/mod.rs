@@ -0,0 +1,56 @@
+//! Elevation of the map surface.
+//!
+//! By default the map is drawn as a flat plane. If a [`Terrain`] is set for the map with
+//! [`Map::set_terrain`](crate::Map::set_terrain), the surface is displaced by the terrain
+//! elevation: all layers are draped on top of it, and [`MapView::screen_to_map`] finds the point
+//! where the line of sight hits the terrain.
+//!
+//! The renderer samples the terrain into a heightmap covering the visible area when the view or
+//! the terrain [revision](Terrain::revision) changes, and displaces the vertices of the drawn
+//! geometries by it. Note that the geometries are displaced only at their vertices, so large
+//! polygons with few vertices do not follow the relief, and elevated surfaces are not occluded by
+//! the ones in front of them.
+
+use galileo_types::cartesian::Point2;
+use maybe_sync::{MaybeSend, MaybeSync};
+
+use crate::messenger::Messenger;
+use crate::view::MapView;
+
+#[cfg(feature = "image")]
+mod dem;
+#[cfg(feature = "image")]
+pub use dem::DemTerrain;
+
+/// Source of the elevation of the map surface.
+pub trait Terrain: MaybeSend + MaybeSync {
+    /// Returns the elevation of the surface at the given point in map units.
+    ///
+    /// The value must already include exaggeration and the scale of the map projection at the
+    /// point. Returns `None` if the elevation is not known (e.g. data is not loaded yet). Such
+    /// points are displayed at zero elevation.
+    fn elevation(&self, point: &Point2) -> Option<f64>;
+
+    /// Loads the data required to display the given view.
+    ///
+    /// Called every time the map prepares its layers, so the terrain should skip the data that is
+    /// already loaded or being loaded.
+    fn prepare(&self, view: &MapView);
+
+    /// Sets the messenger used to request redraw of the map when new data is loaded.
+    fn set_messenger(&self, messenger: Box<dyn Messenger>);
+
+    /// Returns a number that changes every time the elevations returned by the terrain change.
+    ///
+    /// The renderer resamples the terrain only if the revision or the view changes. If `None` is
+    /// returned, the terrain is resampled every frame.
+    fn revision(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl std::fmt::Debug for dyn Terrain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Terrain")
+    }
+}
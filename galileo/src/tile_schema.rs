@@ -1,17 +1,25 @@
 //! [`TileSchema`] is used by tile layers to calculate [tile indices](TileIndex) needed for a given ['MapView'].
 
 use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
 
-use galileo_types::cartesian::{CartesianPoint2d, Point2, Rect};
+use galileo_types::cartesian::{CartesianPoint2d, CartesianPoint3d, Point2, Rect};
 use galileo_types::geo::Crs;
 #[cfg(target_arch = "wasm32")]
 use js_sys::wasm_bindgen::prelude::wasm_bindgen;
 use serde::{Deserialize, Serialize};
 
 use crate::lod::Lod;
+use crate::reprojection::CrsTransform;
 use crate::view::MapView;
 
 const RESOLUTION_TOLERANCE: f64 = 0.01;
+/// Number of segments each side of the view is split into to find the area the view covers in
+/// the schema CRS.
+const FOOTPRINT_SEGMENTS: usize = 8;
+/// Maximum number of tiles selected for a view in a CRS different from the schema CRS. If the
+/// view covers more tiles of the z-level matching its scale, a lower z-level is used.
+const MAX_REPROJECTED_TILES: f64 = 256.0;
 
 /// Direction of the Y index of tiles.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -158,13 +166,171 @@ impl TileSchema {
         self.iter_tiles_over_bbox(resolution, bounding_box)
     }
 
+    /// Iterate over tile indices that should be displayed for the given map view with a CRS
+    /// different from the schema CRS.
+    ///
+    /// The tiles cover the area the view occupies when projected into the schema CRS. The z-level
+    /// is selected by the scale of the view in its center. Only tiles inside the schema bounds are
+    /// returned. Returns `None` if the view cannot be projected into the schema CRS.
+    pub fn iter_tiles_reprojected(
+        &self,
+        view: &MapView,
+    ) -> Option<impl Iterator<Item = WrappingTileIndex>> {
+        let (_, resolution) = self.view_center_and_resolution(view)?;
+        let footprint = self.view_footprint(view)?;
+
+        let mut lods: Vec<_> = self.lods.iter().copied().collect();
+        lods.sort_by(|a, b| b.resolution().total_cmp(&a.resolution()));
+
+        let selected = self.select_lod(resolution)?;
+        let position = lods.iter().position(|lod| *lod == selected)?;
+        let lod = lods[..=position]
+            .iter()
+            .rev()
+            .find(|lod| {
+                let tile_w = lod.resolution() * self.tile_width as f64;
+                let tile_h = lod.resolution() * self.tile_height as f64;
+                let count = ((footprint.width() / tile_w).ceil() + 1.0)
+                    * ((footprint.height() / tile_h).ceil() + 1.0);
+                count <= MAX_REPROJECTED_TILES
+            })
+            .unwrap_or(&lods[0]);
+
+        Some(self.iter_lod_tiles(*lod, footprint))
+    }
+
+    /// Returns the position of the view center and the size of a view pixel, both in the schema
+    /// CRS.
+    pub(crate) fn view_center_and_resolution(&self, view: &MapView) -> Option<(Point2, f64)> {
+        let position = view.projected_position()?;
+        let center = Point2::new(position.x(), position.y());
+        if *view.crs() == self.crs {
+            return Some((center, view.resolution()));
+        }
+
+        let transform = CrsTransform::new(view.crs(), &self.crs)?;
+        let step = view.resolution();
+        let projected_center = transform.transform(&center)?;
+        let dx = transform.transform(&Point2::new(center.x() + step, center.y()))?;
+        let dy = transform.transform(&Point2::new(center.x(), center.y() + step))?;
+
+        // Geometric mean of the scales along the axes keeps the area of a pixel the same.
+        let resolution =
+            (dx.distance_sq(&projected_center) * dy.distance_sq(&projected_center)).powf(0.25);
+        if resolution.is_nan() || resolution <= 0.0 {
+            return None;
+        }
+
+        Some((projected_center, resolution))
+    }
+
+    /// Returns the bounding box of the area the view covers in the schema CRS, limited to the
+    /// schema bounds.
+    fn view_footprint(&self, view: &MapView) -> Option<Rect> {
+        let bbox = view.get_bbox()?;
+        let transform = CrsTransform::new(view.crs(), &self.crs)?;
+
+        let segments = FOOTPRINT_SEGMENTS as f64;
+        let points = (0..=FOOTPRINT_SEGMENTS).flat_map(|i| {
+            (0..=FOOTPRINT_SEGMENTS).map(move |j| {
+                Point2::new(
+                    bbox.x_min() + bbox.width() * i as f64 / segments,
+                    bbox.y_min() + bbox.height() * j as f64 / segments,
+                )
+            })
+        });
+
+        let footprint = points
+            .filter_map(|point| transform.transform(&point))
+            .fold(None, |acc: Option<Rect>, point| {
+                let point_rect = Rect::new(point.x(), point.y(), point.x(), point.y());
+                Some(match acc {
+                    Some(rect) => rect.merge(point_rect),
+                    None => point_rect,
+                })
+            })?;
+
+        footprint
+            .intersects(self.bounds)
+            .then(|| footprint.limit(self.bounds))
+    }
+
+    /// Iterate over indices of the tiles of the given z-level that intersect the given bounding box.
+    ///
+    /// The bounding box must be in the CRS of the tile schema. Only tiles inside the schema bounds
+    /// are returned. Returns `None` if the schema has no z-level with the given index.
+    pub fn iter_tiles_in_bbox(
+        &self,
+        z: u32,
+        bounding_box: Rect,
+    ) -> Option<impl Iterator<Item = TileIndex>> {
+        let lod = *self.lods.iter().find(|lod| lod.z_index() == z)?;
+        let tiles = if bounding_box.intersects(self.bounds) {
+            Some(self.iter_lod_tiles(lod, bounding_box.limit(self.bounds)))
+        } else {
+            None
+        };
+
+        Some(tiles.into_iter().flatten().map(TileIndex::from))
+    }
+
+    /// Number of tiles of the given z-level that intersect with the given bounding box.
+    ///
+    /// The tiles are counted from their index ranges, without enumerating them. Returns `None` if
+    /// the schema does not have the given z-level.
+    pub fn count_tiles_in_bbox(&self, z: u32, bounding_box: Rect) -> Option<usize> {
+        let lod = *self.lods.iter().find(|lod| lod.z_index() == z)?;
+        if !bounding_box.intersects(self.bounds) {
+            return Some(0);
+        }
+
+        let (x_range, y_range) = self.lod_index_ranges(lod, bounding_box.limit(self.bounds));
+        let range_len = |range: RangeInclusive<i32>| {
+            (*range.end() as i64 - *range.start() as i64 + 1).max(0) as usize
+        };
+
+        Some(range_len(x_range) * range_len(y_range))
+    }
+
     fn iter_tiles_over_bbox(
         &self,
         resolution: f64,
         bounding_box: Rect,
     ) -> Option<impl Iterator<Item = WrappingTileIndex>> {
         let lod = self.select_lod(resolution)?;
+        Some(self.iter_lod_tiles(lod, bounding_box))
+    }
 
+    fn iter_lod_tiles(
+        &self,
+        lod: Lod,
+        bounding_box: Rect,
+    ) -> impl Iterator<Item = WrappingTileIndex> {
+        let (x_range, y_range) = self.lod_index_ranges(lod, bounding_box);
+
+        let schema_x_min = self.min_x_index(lod.resolution());
+        let schema_x_max = self.max_x_index(lod.resolution());
+        let index_range = schema_x_max - schema_x_min + 1;
+
+        let actual_x =
+            move |display_x: i32| (display_x - schema_x_min).rem_euclid(index_range) + schema_x_min;
+
+        x_range.flat_map(move |x| {
+            y_range.clone().map(move |y| WrappingTileIndex {
+                x: actual_x(x),
+                y,
+                z: lod.z_index(),
+                display_x: x,
+            })
+        })
+    }
+
+    /// Ranges of displayed X indices and Y indices of the tiles of the LOD covering the bounding box.
+    fn lod_index_ranges(
+        &self,
+        lod: Lod,
+        bounding_box: Rect,
+    ) -> (RangeInclusive<i32>, RangeInclusive<i32>) {
         let tile_w = lod.resolution() * self.tile_width as f64;
         let tile_h = lod.resolution() * self.tile_height as f64;
 
@@ -192,21 +358,7 @@ impl TileSchema {
         let y_max = (y_max_adj / tile_h) as i32 + y_add_one;
         let y_max = y_max.min(self.max_y_index(lod.resolution()));
 
-        let schema_x_min = self.min_x_index(lod.resolution());
-        let schema_x_max = self.max_x_index(lod.resolution());
-        let index_range = schema_x_max - schema_x_min + 1;
-
-        let actual_x =
-            move |display_x: i32| (display_x - schema_x_min).rem_euclid(index_range) + schema_x_min;
-
-        Some((x_min..=x_max).flat_map(move |x| {
-            (y_min..=y_max).map(move |y| WrappingTileIndex {
-                x: actual_x(x),
-                y,
-                z: lod.z_index(),
-                display_x: x,
-            })
-        }))
+        (x_min..=x_max, y_min..=y_max)
     }
 
     fn x_adj(&self, x: f64) -> f64 {
@@ -276,6 +428,58 @@ impl TileSchema {
         ))
     }
 
+    /// Returns the Y index of the tile counted in the direction opposite to the schema's
+    /// `y_direction`, e.g. to convert XYZ tile indices into TMS ones.
+    pub(crate) fn flip_y(&self, index: TileIndex) -> Option<i32> {
+        let resolution = self.lod_resolution(index.z)?;
+        Some(self.min_y_index(resolution) + self.max_y_index(resolution) - index.y)
+    }
+
+    /// Returns the index of the tile at the given z-level that contains the point. The X index is
+    /// wrapped into the schema bounds if the schema wraps along the X axis.
+    pub(crate) fn tile_index_at(
+        &self,
+        point: &impl CartesianPoint2d<Num = f64>,
+        z: u32,
+    ) -> Option<TileIndex> {
+        let resolution = self.lod_resolution(z)?;
+        let tile_width = self.tile_width as f64 * resolution;
+        let tile_height = self.tile_height as f64 * resolution;
+
+        let x = ((point.x() - self.origin.x()) / tile_width).floor() as i32;
+        let y = match self.y_direction {
+            VerticalDirection::TopToBottom => ((self.origin.y() - point.y()) / tile_height).floor(),
+            VerticalDirection::BottomToTop => ((point.y() - self.origin.y()) / tile_height).floor(),
+        } as i32;
+
+        self.neighbor(TileIndex::new(x, y, z), 0, 0)
+    }
+
+    /// Returns the index of the tile shifted by `dx` columns and `dy` rows from the given one.
+    ///
+    /// Rows are counted from top to bottom regardless of the schema's `y_direction`. Columns wrap
+    /// around the antimeridian if the schema wraps along the X axis. Returns `None` if the tile is
+    /// outside of the schema bounds.
+    pub(crate) fn neighbor(&self, index: TileIndex, dx: i32, dy: i32) -> Option<TileIndex> {
+        let resolution = self.lod_resolution(index.z)?;
+
+        let min_x = self.min_x_index(resolution);
+        let max_x = self.max_x_index(resolution);
+        let mut x = index.x + dx;
+        if self.wrap_x() {
+            x = min_x + (x - min_x).rem_euclid(max_x - min_x + 1);
+        }
+
+        let y = match self.y_direction {
+            VerticalDirection::TopToBottom => index.y + dy,
+            VerticalDirection::BottomToTop => index.y - dy,
+        };
+
+        let x_range = min_x..=max_x;
+        let y_range = self.min_y_index(resolution)..=self.max_y_index(resolution);
+        (x_range.contains(&x) && y_range.contains(&y)).then_some(TileIndex::new(x, y, index.z))
+    }
+
     fn wrap_x(&self) -> bool {
         // TODO: https://github.com/Maximkaaa/galileo/issues/221
         true
@@ -312,10 +516,16 @@ impl TileSchema {
         }
     }
 
+    /// Y index of the first row of tiles inside the schema bounds.
+    ///
+    /// Rows are counted from the origin in the `y_direction`, so for top-to-bottom schemas the
+    /// first row is at the top edge of the bounds, and the distance to it is `origin.y - y_max`.
+    /// The bounds don't have to be symmetric around zero, and the origin doesn't have to lie on
+    /// their edge (e.g. WMTS tile matrix sets that cover only a part of the CRS area).
     fn min_y_index(&self, resolution: f64) -> i32 {
         match self.y_direction {
             VerticalDirection::TopToBottom => {
-                ((self.bounds.y_min() + self.origin.y()) / resolution / self.tile_height as f64)
+                ((self.origin.y() - self.bounds.y_max()) / resolution / self.tile_height as f64)
                     .floor() as i32
             }
             VerticalDirection::BottomToTop => {
@@ -325,9 +535,10 @@ impl TileSchema {
         }
     }
 
+    /// Y index of the last row of tiles inside the schema bounds. See [`TileSchema::min_y_index`].
     fn max_y_index(&self, resolution: f64) -> i32 {
         let pix_bound = match self.y_direction {
-            VerticalDirection::TopToBottom => (self.bounds.y_max() + self.origin.y()) / resolution,
+            VerticalDirection::TopToBottom => (self.origin.y() - self.bounds.y_min()) / resolution,
             VerticalDirection::BottomToTop => (self.bounds.y_max() - self.origin.y()) / resolution,
         };
         let floored = pix_bound.floor();
@@ -369,6 +580,54 @@ mod tests {
         ))
     }
 
+    fn asymmetric_schema(y_direction: VerticalDirection) -> TileSchema {
+        // Bounds cover 2x4 tiles of the single LOD, and start 4 tile rows away from the origin.
+        let bounds = Rect::new(100.0, 24.0, 612.0, 1048.0);
+        let origin = match y_direction {
+            VerticalDirection::TopToBottom => Point2::new(100.0, 2072.0),
+            VerticalDirection::BottomToTop => Point2::new(100.0, -1000.0),
+        };
+
+        TileSchema {
+            origin,
+            bounds,
+            lods: [Lod::new(1.0, 0).unwrap()].into(),
+            tile_width: 256,
+            tile_height: 256,
+            y_direction,
+            crs: Crs::EPSG3857,
+        }
+    }
+
+    #[test]
+    fn y_index_range_with_asymmetric_bounds() {
+        for y_direction in [
+            VerticalDirection::TopToBottom,
+            VerticalDirection::BottomToTop,
+        ] {
+            let schema = asymmetric_schema(y_direction);
+            assert_eq!(schema.min_y_index(1.0), 4, "{y_direction:?}");
+            assert_eq!(schema.max_y_index(1.0), 7, "{y_direction:?}");
+
+            let tiles: Vec<_> = schema
+                .iter_tiles_in_bbox(0, schema.bounds)
+                .unwrap()
+                .collect();
+            assert_eq!(tiles.len(), 8, "{y_direction:?}");
+
+            let mut covered: Option<Rect> = None;
+            for index in tiles {
+                let tile_bbox = schema.tile_bbox(index.into_wrapping()).unwrap();
+                assert!(
+                    schema.bounds.contains(&tile_bbox.center()),
+                    "{y_direction:?}: {index:?}"
+                );
+                covered = Some(covered.map_or(tile_bbox, |rect| rect.merge(tile_bbox)));
+            }
+            assert_eq!(covered, Some(schema.bounds), "{y_direction:?}");
+        }
+    }
+
     #[test]
     fn select_lod() {
         let schema = simple_schema();
@@ -383,6 +642,48 @@ mod tests {
         assert_eq!(schema.select_lod(1.0).unwrap().z_index(), 2);
     }
 
+    #[test]
+    fn iter_tiles_in_bbox() {
+        let schema = simple_schema();
+        let full = Rect::new(-1000.0, -1000.0, 5000.0, 5000.0);
+        assert_eq!(schema.iter_tiles_in_bbox(0, full).unwrap().count(), 1);
+        assert_eq!(schema.iter_tiles_in_bbox(1, full).unwrap().count(), 4);
+        assert_eq!(schema.iter_tiles_in_bbox(2, full).unwrap().count(), 16);
+        assert!(schema.iter_tiles_in_bbox(3, full).is_none());
+
+        let part = Rect::new(100.0, 100.0, 600.0, 400.0);
+        let mut tiles: Vec<_> = schema.iter_tiles_in_bbox(2, part).unwrap().collect();
+        tiles.sort_by_key(|index| (index.x, index.y));
+        assert_eq!(
+            tiles,
+            vec![TileIndex::new(0, 0, 2), TileIndex::new(1, 0, 2)]
+        );
+
+        let outside = Rect::new(3000.0, 3000.0, 4000.0, 4000.0);
+        assert_eq!(schema.iter_tiles_in_bbox(2, outside).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn count_tiles_in_bbox() {
+        let schema = simple_schema();
+        let boxes = [
+            Rect::new(-1000.0, -1000.0, 5000.0, 5000.0),
+            Rect::new(100.0, 100.0, 600.0, 400.0),
+            Rect::new(0.0, 0.0, 1024.0, 1024.0),
+            Rect::new(3000.0, 3000.0, 4000.0, 4000.0),
+        ];
+        for bbox in boxes {
+            for z in 0..3 {
+                assert_eq!(
+                    schema.count_tiles_in_bbox(z, bbox),
+                    Some(schema.iter_tiles_in_bbox(z, bbox).unwrap().count())
+                );
+            }
+        }
+
+        assert!(schema.count_tiles_in_bbox(3, boxes[0]).is_none());
+    }
+
     #[test]
     fn iter_indices_full_bbox() {
         let schema = simple_schema();
@@ -472,4 +773,76 @@ mod tests {
             4
         );
     }
+
+    #[test]
+    fn tile_index_at() {
+        let schema = simple_schema();
+        assert_eq!(
+            schema.tile_index_at(&Point2::new(1500.0, 100.0), 1),
+            Some(TileIndex::new(1, 0, 1))
+        );
+        assert_eq!(schema.tile_index_at(&Point2::new(100.0, -100.0), 1), None);
+
+        let schema = TileSchema::web(4);
+        assert_eq!(
+            schema.tile_index_at(&Point2::new(-1000.0, 1000.0), 1),
+            Some(TileIndex::new(0, 0, 1))
+        );
+    }
+
+    #[test]
+    fn neighbor() {
+        let schema = TileSchema::web(4);
+        let index = TileIndex::new(0, 0, 1);
+        assert_eq!(schema.neighbor(index, 1, 1), Some(TileIndex::new(1, 1, 1)));
+        assert_eq!(schema.neighbor(index, -1, 0), Some(TileIndex::new(1, 0, 1)));
+        assert_eq!(schema.neighbor(index, 0, -1), None);
+
+        let schema = simple_schema();
+        assert_eq!(
+            schema.neighbor(TileIndex::new(0, 0, 1), 0, -1),
+            Some(TileIndex::new(0, 1, 1))
+        );
+    }
+
+    fn wgs84_view(lon: f64, lat: f64, resolution: f64, size: Size) -> MapView {
+        MapView::new_projected_with_crs(&Point2::new(lon, lat), resolution, Crs::WGS84)
+            .with_size(size)
+    }
+
+    #[test]
+    fn view_center_and_resolution_in_other_crs() {
+        let schema = TileSchema::web(18);
+        let view = wgs84_view(0.0, 0.0, 0.01, Size::new(256.0, 256.0));
+
+        let (center, resolution) = schema.view_center_and_resolution(&view).unwrap();
+        assert!(center.x().abs() < 1e-6 && center.y().abs() < 1e-6);
+        assert!((resolution - 1113.19).abs() < 1.0, "{resolution}");
+    }
+
+    #[test]
+    fn iter_tiles_reprojected_covers_view_footprint() {
+        let schema = TileSchema::web(18);
+        let view = wgs84_view(0.0, 0.0, 0.01, Size::new(256.0, 256.0));
+
+        assert!(schema.iter_tiles(&view).is_none());
+
+        let tiles: Vec<_> = schema.iter_tiles_reprojected(&view).unwrap().collect();
+        // Resolution of z-level 8 is 611 m, which is the closest one finer than 1113 m.
+        assert!(tiles.iter().all(|index| index.z == 8));
+        assert_eq!(tiles.len(), 4);
+        for (x, y) in [(127, 127), (127, 128), (128, 127), (128, 128)] {
+            assert!(tiles.contains(&WrappingTileIndex::new(x, y, 8)));
+        }
+    }
+
+    #[test]
+    fn iter_tiles_reprojected_limits_tile_count() {
+        let schema = TileSchema::web(18);
+        let view = wgs84_view(0.0, 0.0, 0.01, Size::new(36000.0, 16000.0));
+
+        let tiles: Vec<_> = schema.iter_tiles_reprojected(&view).unwrap().collect();
+        assert!(tiles.len() as f64 <= MAX_REPROJECTED_TILES);
+        assert!(tiles.iter().all(|index| index.z < 8));
+    }
 }
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use galileo_types::cartesian::{
     CartesianPoint2d, CartesianPoint3d, Point2, Point3, Rect, Size, Vector2, Vector3,
 };
@@ -5,6 +7,8 @@ use galileo_types::geo::impls::GeoPoint2d;
 use galileo_types::geo::{Crs, GeoPoint};
 use nalgebra::{Matrix4, OMatrix, Perspective3, Rotation3, Scale3, Translation3, U4};
 
+use crate::terrain::Terrain;
+
 /// Map view specifies the area of the map that should be drawn. In other words, it sets the position of "camera" that
 /// looks at the map.
 ///
@@ -16,7 +20,8 @@ use nalgebra::{Matrix4, OMatrix, Perspective3, Rotation3, Scale3, Translation3,
 ///   displayed in. Note, that currently geographic CRSs are not supported, and a map with such a view will not be
 ///   drawn.
 ///
-/// The view can also specify rotation along *x* (tilt) and *z* (rotation) axis.
+/// The view can also specify rotation along *x* (tilt) and *z* (rotation) axis, and the [`Terrain`] that sets the
+/// elevation of the map surface.
 #[derive(Debug, Clone)]
 pub struct MapView {
     projected_position: Option<Point3<f64>>,
@@ -26,6 +31,7 @@ pub struct MapView {
     size: Size,
     crs: Crs,
     dpi_scale_factor: f32,
+    terrain: Option<Arc<dyn Terrain>>,
 }
 
 impl MapView {
@@ -48,6 +54,7 @@ impl MapView {
             size: Default::default(),
             crs,
             dpi_scale_factor: 1.0,
+            terrain: None,
         }
     }
 
@@ -70,6 +77,7 @@ impl MapView {
             size: Default::default(),
             crs,
             dpi_scale_factor: 1.0,
+            terrain: None,
         }
     }
 
@@ -107,6 +115,7 @@ impl MapView {
         Self {
             projected_position,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
             ..*self
         }
     }
@@ -121,6 +130,7 @@ impl MapView {
         Self {
             resolution: resolution / self.dpi_scale_factor as f64,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
             ..*self
         }
     }
@@ -135,6 +145,7 @@ impl MapView {
         Self {
             size: new_size,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
             ..*self
         }
     }
@@ -223,9 +234,10 @@ impl MapView {
         // Get the map to scene transformation matrix
         let transform = self.map_to_scene_transform()?;
 
-        // Convert 2D map position to homogeneous coordinates (add z=0, w=1)
+        // Convert 2D map position to homogeneous coordinates (add elevation as z, w=1)
+        let z = self.elevation(&map_pos);
         let map_point_homogeneous =
-            nalgebra::Point3::new(map_pos.x(), map_pos.y(), 0.0).to_homogeneous();
+            nalgebra::Point3::new(map_pos.x(), map_pos.y(), z).to_homogeneous();
 
         // Transform map coordinates to scene coordinates
         let scene_point = transform * map_point_homogeneous;
@@ -305,6 +317,7 @@ impl MapView {
         Self {
             rotation_x,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
             ..*self
         }
     }
@@ -314,6 +327,7 @@ impl MapView {
         Self {
             rotation_z,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
             ..*self
         }
     }
@@ -324,10 +338,36 @@ impl MapView {
             rotation_x,
             rotation_z,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
+            ..*self
+        }
+    }
+
+    /// Terrain that sets the elevation of the map surface, if any.
+    pub fn terrain(&self) -> Option<&Arc<dyn Terrain>> {
+        self.terrain.as_ref()
+    }
+
+    /// Creates a new view, same as the current one, but with the given terrain.
+    ///
+    /// Usually there is no need to call this method directly, as the terrain is set to the view by the
+    /// [`Map`](crate::Map) (see [`Map::set_terrain`](crate::Map::set_terrain)).
+    pub fn with_terrain(&self, terrain: Option<Arc<dyn Terrain>>) -> Self {
+        Self {
+            terrain,
+            crs: self.crs.clone(),
             ..*self
         }
     }
 
+    /// Elevation of the map surface at the given point in map units.
+    pub fn elevation(&self, map_pos: &Point2) -> f64 {
+        self.terrain
+            .as_ref()
+            .and_then(|terrain| terrain.elevation(map_pos))
+            .unwrap_or(0.0)
+    }
+
     /// DPI scale factor.
     pub fn dpi_scale_factor(&self) -> f32 {
         self.dpi_scale_factor
@@ -338,15 +378,73 @@ impl MapView {
         Self {
             dpi_scale_factor,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
             ..*self
         }
     }
 
-    /// Projects the given screen point into map coordinates at the 0 elevation.
+    /// Projects the given screen point into map coordinates on the map surface.
+    ///
+    /// If the view has a [`Terrain`], the returned point is where the line of sight hits the terrain surface,
+    /// otherwise the point is at the 0 elevation.
     ///
     /// Returns `None` if the point is outside of map (this can be possible, if the map is tilted and the point is
     /// above the horizon, or if the point is outside the projection bounds).
     pub fn screen_to_map(&self, px_position: Point2) -> Option<Point2> {
+        let flat = self.screen_to_map_flat(px_position)?;
+        match &self.terrain {
+            Some(terrain) => Some(self.screen_to_terrain(px_position, terrain.as_ref(), flat)),
+            None => Some(flat),
+        }
+    }
+
+    /// Finds the point where the line of sight through the given screen point hits the terrain.
+    ///
+    /// The intersection is found iteratively: the ray is intersected with the horizontal plane at the elevation of the
+    /// previous approximation, starting with the intersection at the 0 elevation.
+    fn screen_to_terrain(
+        &self,
+        px_position: Point2,
+        terrain: &dyn Terrain,
+        flat: Point2,
+    ) -> Point2 {
+        const ITERATIONS: usize = 8;
+
+        let Some(inverse) = self
+            .map_to_screen_center_transform()
+            .and_then(|transform| transform.try_inverse())
+        else {
+            return flat;
+        };
+
+        let ndc_x = 2.0 * px_position.x() / self.size.width() - 1.0;
+        let ndc_y = 1.0 - 2.0 * px_position.y() / self.size.height();
+        let unproject = |ndc_z: f64| {
+            let p = inverse * nalgebra::Vector4::new(ndc_x, ndc_y, ndc_z, 1.0);
+            p.xyz() / p.w
+        };
+        let near = unproject(-1.0);
+        let direction = unproject(1.0) - near;
+        if direction.z.abs() < f64::EPSILON {
+            return flat;
+        }
+
+        let mut point = flat;
+        for _ in 0..ITERATIONS {
+            let elevation = terrain.elevation(&point).unwrap_or(0.0);
+            let t = (elevation - near.z) / direction.z;
+            if t < 0.0 {
+                break;
+            }
+
+            let next = near + direction * t;
+            point = Point2::new(next.x, next.y);
+        }
+
+        point
+    }
+
+    fn screen_to_map_flat(&self, px_position: Point2) -> Option<Point2> {
         // todo: this must be calculated with matrices somehow but I'm not bright enough
         // to figure out how to do it...
         let x = px_position.x();
@@ -378,8 +476,8 @@ impl MapView {
         Some(Point2::new(transformed.x, transformed.y))
     }
 
-    /// Projects the given screen point into map coordinates at the 0 elevation, and then projects them into
-    /// geographic coordinates.
+    /// Projects the given screen point into map coordinates on the map surface (see [`MapView::screen_to_map`]), and
+    /// then projects them into geographic coordinates.
     ///
     /// Returns `None` if the point is outside of map (this can be possible, if the map is tilted and the point is
     /// above the horizon, or if the point is outside the projection bounds).
@@ -422,11 +520,13 @@ impl MapView {
                 Self {
                     projected_position: Some(projected_position),
                     crs: self.crs.clone(),
+                    terrain: self.terrain.clone(),
                     ..*self
                 }
             }
             None => Self {
                 crs: self.crs.clone(),
+                terrain: self.terrain.clone(),
                 ..*self
             },
         }
@@ -448,6 +548,7 @@ impl MapView {
             projected_position: new_position,
             resolution,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
             ..*self
         }
     }
@@ -465,6 +566,7 @@ impl MapView {
             projected_position: Some(projected_position),
             resolution: self.resolution + (target.resolution - self.resolution) * k,
             crs: self.crs.clone(),
+            terrain: self.terrain.clone(),
             ..*self
         }
     }
@@ -671,4 +773,41 @@ mod tests {
         let recovered_geo_point = view.screen_to_map_geo(screen_point).unwrap();
         assert_abs_diff_eq!(original_geo_point, recovered_geo_point, epsilon = 0.0001);
     }
+
+    struct PlateauTerrain;
+    impl Terrain for PlateauTerrain {
+        fn elevation(&self, point: &Point2) -> Option<f64> {
+            Some(if point.y() > 0.0 { 20.0 } else { 0.0 })
+        }
+        fn prepare(&self, _view: &MapView) {}
+        fn set_messenger(&self, _messenger: Box<dyn crate::Messenger>) {}
+    }
+
+    #[test]
+    fn screen_to_map_with_terrain() {
+        let view = test_view()
+            .with_size(Size::new(100.0, 100.0))
+            .with_rotation_x(std::f64::consts::FRAC_PI_4)
+            .with_terrain(Some(Arc::new(PlateauTerrain)));
+
+        // Elevated point is displayed higher on the screen than the same point without terrain.
+        let elevated = Point2::new(10.0, 10.0);
+        let screen_point = view.map_to_screen(elevated).unwrap();
+        let flat_screen_point = view.with_terrain(None).map_to_screen(elevated).unwrap();
+        assert!(screen_point.y() < flat_screen_point.y());
+
+        assert_abs_diff_eq!(
+            view.screen_to_map(screen_point).unwrap(),
+            elevated,
+            epsilon = 0.0001
+        );
+
+        let flat = Point2::new(10.0, -10.0);
+        let screen_point = view.map_to_screen(flat).unwrap();
+        assert_abs_diff_eq!(
+            view.screen_to_map(screen_point).unwrap(),
+            flat,
+            epsilon = 0.0001
+        );
+    }
 }